@@ -0,0 +1,107 @@
+// Flat layer of scrolling clouds at a fixed height, made of value noise and faded into the sky with distance
+
+struct Clouds {
+    inv_view_proj: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    // Where the noise starts at the origin of the chunk the camera is in, wrapped to NOISE_PERIOD blocks
+    offset: vec2<f32>,
+    // Height of the layer relative to the chunk the camera is in
+    height: f32,
+    coverage: f32,
+    max_distance: f32,
+    _padding_0: f32,
+    _padding_1: f32,
+    _padding_2: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> clouds: Clouds;
+
+// Blocks along each side of a noise cell at the coarsest octave, and cells before the noise repeats. The period in
+// blocks must match NOISE_PERIOD in renderer.rs
+const CELL_SIZE: f32 = 64.0;
+const CELL_PERIOD: u32 = 64u;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+struct FragmentOutput {
+    @builtin(frag_depth) depth: f32,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pos, 0.0, 1.0);
+    out.ndc = pos;
+    return out;
+}
+
+fn hash(cell: vec2<u32>) -> f32 {
+    var h = cell.x * 374761393u + cell.y * 668265263u;
+    h = (h ^ (h >> 13u)) * 1274126177u;
+    return f32(h ^ (h >> 16u)) / 4294967295.0;
+}
+
+// Smoothly interpolated random values at the corners of a grid that wraps every period cells
+fn value_noise(pos: vec2<f32>, period: u32) -> f32 {
+    let cell = floor(pos);
+    let t = fract(pos);
+    let s = t * t * (3.0 - 2.0 * t);
+
+    let base = vec2<u32>(vec2<i32>(cell) % i32(period) + i32(period));
+    let a = hash((base + vec2<u32>(0u, 0u)) % period);
+    let b = hash((base + vec2<u32>(1u, 0u)) % period);
+    let c = hash((base + vec2<u32>(0u, 1u)) % period);
+    let d = hash((base + vec2<u32>(1u, 1u)) % period);
+
+    return mix(mix(a, b, s.x), mix(c, d, s.x), s.y);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // The view ray of the pixel, from unprojecting it at the near and far plane
+    let near_h = clouds.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far_h = clouds.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let near = near_h.xyz / near_h.w;
+    let direction = normalize(far_h.xyz / far_h.w - near);
+
+    let distance = (clouds.height - near.y) / direction.y;
+    if distance <= 0.0 || distance > clouds.max_distance {
+        discard;
+    }
+    let hit = near + direction * distance;
+
+    var noise = 0.0;
+    var amplitude = 0.5;
+    var scale = 1.0;
+    var period = CELL_PERIOD;
+    for (var octave = 0; octave < 3; octave++) {
+        noise += value_noise((hit.xz + clouds.offset) * scale / CELL_SIZE, period) * amplitude;
+        amplitude *= 0.5;
+        scale *= 2.0;
+        period *= 2u;
+    }
+    noise /= 0.875;
+
+    let density = smoothstep(1.0 - clouds.coverage, 1.0 - clouds.coverage + 0.15, noise);
+    let fade = smoothstep(clouds.max_distance, clouds.max_distance * 0.5, distance);
+    if density * fade <= 0.0 {
+        discard;
+    }
+
+    let clip = clouds.view_proj * vec4<f32>(hit, 1.0);
+
+    var out: FragmentOutput;
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    out.color = vec4<f32>(mix(clouds.horizon_color.rgb, clouds.color.rgb, fade), clouds.color.a * density * fade);
+    return out;
+}
@@ -0,0 +1,38 @@
+// Debug view drawing the depth buffer in grayscale, near is black and far is white
+
+struct DepthView {
+    znear: f32,
+    zfar: f32,
+    _padding_0: f32,
+    _padding_1: f32,
+}
+
+@group(0) @binding(0)
+var t_depth: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> depth_view: DepthView;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pos * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = textureLoad(t_depth, vec2<i32>(in.clip_position.xy), 0);
+
+    // Perspective depth is packed close to 1, so it's linearized and shown on a log scale
+    let linear_depth = depth_view.znear * depth_view.zfar / (depth_view.zfar - depth * (depth_view.zfar - depth_view.znear));
+    let gray = clamp(log(linear_depth / depth_view.znear) / log(depth_view.zfar / depth_view.znear), 0.0, 1.0);
+
+    return vec4<f32>(gray, gray, gray, 1.0);
+}
@@ -0,0 +1,39 @@
+// Translucent boxes drawn over the world, used for both their faces and their edges
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    world_pos_and_chunk_size: vec4<i32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = camera.view_proj * vec4<f32>(vertex.pos, 1.0);
+    out.color = vertex.color;
+
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
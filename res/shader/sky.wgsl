@@ -0,0 +1,36 @@
+// Vertical gradient behind the world, from the horizon color straight ahead to the zenith color straight up
+
+struct Sky {
+    inv_view_proj: mat4x4<f32>,
+    horizon_color: vec4<f32>,
+    zenith_color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pos, 0.0, 1.0);
+    out.ndc = pos;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The view direction of the pixel, from unprojecting it at the near and far plane
+    let near = sky.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = sky.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    return mix(sky.horizon_color, sky.zenith_color, clamp(direction.y, 0.0, 1.0));
+}
@@ -11,8 +11,28 @@ struct Settings {
     base_light_value: f32,
     light_power_factor: f32,
     tile_size: f32,
+    min_brightness: f32,
+    fog_start: f32,
+    fog_end: f32,
+    mipmaps: u32,
+    fog_color: vec4<f32>,
+    sun_color: vec4<f32>,
+    time: f32,
+    _padding_0: f32,
+    _padding_1: f32,
+    _padding_2: f32,
 }
 
+// Fluid surfaces sit FLUID_SURFACE_DEPTH below the top of the block and move up and down by FLUID_WAVE_HEIGHT, in
+// waves FLUID_WAVE_LENGTH blocks long. Waves repeat every FLUID_WAVE_PERIOD blocks so the phase stays exact far out
+const FLUID_SURFACE_FLAG: u32 = 16u;
+const FLUID_SURFACE_DEPTH: f32 = 0.125;
+const FLUID_WAVE_HEIGHT: f32 = 0.05;
+const FLUID_WAVE_LENGTH: f32 = 8.0;
+const FLUID_WAVE_SPEED: f32 = 1.5;
+const FLUID_WAVE_PERIOD: i32 = 64;
+const TAU: f32 = 6.28318530718;
+
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
@@ -24,6 +44,10 @@ var<uniform> camera: Camera;
 @group(2) @binding(0)
 var<uniform> settings: Settings;
 
+// Offset from the first to the current frame of each animated texture
+@group(2) @binding(1)
+var<uniform> animation_offsets: array<vec4<f32>, 32>;
+
 @group(3) @binding(0)
 var<uniform> world_pos: vec4<i32>;
 
@@ -44,6 +68,8 @@ struct VertexOutput {
     @location(4) axis2: vec3<f32>,
     @location(5) brightness: f32,
     @location(6) transparency: f32,
+    @location(7) view_offset: vec3<f32>,
+    @location(8) @interpolate(flat) cutout: u32,
 }
 
 @vertex
@@ -63,10 +89,21 @@ fn vs_main(
         let block_pos = vec3(i32(block.pos.x), i32(block.pos.y), i32(block.pos.z));
 
         let pos_i32 = block_pos + chunk_offset;
-        let pos_f32 = vec3(f32(pos_i32.x), f32(pos_i32.y), f32(pos_i32.z));
+        // Corners of partial blocks halfway through a block have the axes they are rounded up on flagged in w
+        let half_block_offset = vec3(f32(block.pos.w & 1u), f32((block.pos.w >> 1u) & 1u), f32((block.pos.w >> 2u) & 1u)) * 0.5;
+        let pos_f32 = vec3(f32(pos_i32.x), f32(pos_i32.y), f32(pos_i32.z)) - half_block_offset;
+
+        // Only the position the face is drawn at moves, the texture stays where the block is
+        var drawn_pos = pos_f32;
+        if (block.brightness_transparency.x & FLUID_SURFACE_FLAG) != 0u {
+            let world_block_pos = block_pos + chunk_world_pos * chunk_size;
+            let phase = f32((world_block_pos.x + world_block_pos.z) % FLUID_WAVE_PERIOD) * TAU / FLUID_WAVE_LENGTH + settings.time * FLUID_WAVE_SPEED;
+            drawn_pos.y -= FLUID_SURFACE_DEPTH + sin(phase) * FLUID_WAVE_HEIGHT;
+        }
 
         out.pos = pos_f32;
-        out.clip_position = camera.view_proj * vec4<f32>(pos_f32, 1.0);
+        out.view_offset = drawn_pos - camera.view_pos.xyz;
+        out.clip_position = camera.view_proj * vec4<f32>(drawn_pos, 1.0);
     }
 
     {
@@ -76,6 +113,10 @@ fn vs_main(
         if e1.x == 0.0 && e1.y == 0.0 && e1.z == 0.0 {
             e1 = cross(normal, vec3(0.0, 0.0, 1.0));
         }
+        // The diagonal quads of cross blocks span one block along x, so the texture follows x alone to fit them
+        if normal.x != 0.0 && normal.z != 0.0 {
+            e1 = vec3(e1.x, 0.0, 0.0);
+        }
         let e2 = cross(normal, e1);
     
         out.axis1 = e1;
@@ -83,34 +124,25 @@ fn vs_main(
     }
 
     {
-        var color_raw = vec3(block.color.x, block.color.y, block.color.z);
+        var color = vec3(f32(block.color.x), f32(block.color.y), f32(block.color.z)) / 16.0;
 
+        // Each channel is lit by whichever is brighter of the block light and the sunlight tinted by the sun color
         if block.color.w > 0u {
             let relative_sunlight_strength = i32(block.color.w) - (15 - i32(settings.sunlight_intensity));
-            
+
             if relative_sunlight_strength > 0 {
-                let relative_sunlight_strength = u32(relative_sunlight_strength);
-
-                if color_raw.x < relative_sunlight_strength {
-                    color_raw.x = relative_sunlight_strength;
-                }
-                if color_raw.y < relative_sunlight_strength {
-                    color_raw.y = relative_sunlight_strength;
-                }
-                if color_raw.z < relative_sunlight_strength {
-                    color_raw.z = relative_sunlight_strength;
-                }
+                color = max(color, f32(relative_sunlight_strength) / 16.0 * settings.sun_color.xyz);
             }
         }
 
-        let color = vec3(f32(color_raw.x) / 16.0, f32(color_raw.y) / 16.0, f32(color_raw.z) / 16.0);
         out.color = vec3(settings.base_light_value + pow(color.x, settings.light_power_factor), settings.base_light_value + pow(color.y, settings.light_power_factor), settings.base_light_value + pow(color.z, settings.light_power_factor));
+        out.color = max(out.color, vec3(settings.min_brightness));
     }
 
     {
         var brightness: f32;
         
-        switch block.brightness_transparency.x {
+        switch block.brightness_transparency.x & 3u {
             default {
                 brightness = 1.0;
             }
@@ -125,14 +157,15 @@ fn vs_main(
             }
         }
 
-        out.brightness = brightness;
+        // Ambient occlusion level from 0 to 3 is stored above the face brightness
+        out.brightness = brightness * (1.0 - 0.2 * f32((block.brightness_transparency.x >> 2u) & 3u));
     }
 
 
     {
         var transparency: f32;
     
-        switch block.brightness_transparency.y {
+        switch block.brightness_transparency.y & 1u {
             default {
                 transparency = 1.0;
             }
@@ -142,6 +175,14 @@ fn vs_main(
         }
 
         out.transparency = transparency;
+        out.cutout = (block.brightness_transparency.y >> 1u) & 1u;
+    }
+
+    {
+        let animation = block.brightness_transparency.y >> 2u;
+        if animation != 0u {
+            out.texture_atlas_pos += animation_offsets[animation - 1u].xy;
+        }
     }
 
     return out;
@@ -152,6 +193,30 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let tile_uv = vec2(dot(in.axis1, in.pos), dot(in.axis2, in.pos));
     let tex_coord = in.texture_atlas_pos + fract(tile_uv) * settings.tile_size;
 
-    let color = in.brightness * textureSample(t_diffuse, s_diffuse, tex_coord);
-    return vec4<f32>(in.color.x * color.x, in.color.y * color.y, in.color.z * color.z, in.transparency);
+    // Gradients are taken before wrapping the coordinates into the tile, so the mip level doesn't jump at block edges
+    let tex_coord_dx = dpdx(tile_uv) * settings.tile_size;
+    let tex_coord_dy = dpdy(tile_uv) * settings.tile_size;
+
+    var texture_color: vec4<f32>;
+    if settings.mipmaps != 0u {
+        texture_color = textureSampleGrad(t_diffuse, s_diffuse, tex_coord, tex_coord_dx, tex_coord_dy);
+    } else {
+        texture_color = textureSampleLevel(t_diffuse, s_diffuse, tex_coord, 0.0);
+    }
+
+    // Only faces with a texture that has transparent pixels are alpha tested
+    if in.cutout != 0u && texture_color.a < 0.5 {
+        discard;
+    }
+
+    let color = in.brightness * texture_color;
+    var lit_color = vec3<f32>(in.color.x * color.x, in.color.y * color.y, in.color.z * color.z);
+
+    // Fog is off unless it ends after it starts
+    if settings.fog_end > settings.fog_start {
+        let fog = clamp((length(in.view_offset) - settings.fog_start) / (settings.fog_end - settings.fog_start), 0.0, 1.0);
+        lit_color = mix(lit_color, settings.fog_color.xyz, fog);
+    }
+
+    return vec4<f32>(lit_color, in.transparency);
 }
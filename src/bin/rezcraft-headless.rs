@@ -0,0 +1,17 @@
+// Usage: rezcraft-headless [seed] [frames] [render distance]
+fn main() {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1).map(|arg| arg.parse::<u32>());
+    let mut next_arg = |default: u32| match args.next() {
+        Some(Ok(value)) => value,
+        Some(Err(e)) => {
+            eprintln!("Arguments are seed, frames and render distance as whole numbers - {e}");
+            std::process::exit(1)
+        }
+        None => default,
+    };
+    let (seed, frames, render_distance) = (next_arg(0), next_arg(3), next_arg(4));
+
+    rezcraft::run_headless(seed, frames, render_distance);
+}
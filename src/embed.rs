@@ -0,0 +1,135 @@
+// Facade for running the world inside another application, which keeps its own window and event loop and calls `step`
+// every frame instead of `run`
+//
+// Threading: `Game` is neither Send nor Sync and has to stay on the thread that created it. Chunk generation, lighting
+// and meshing still run on the worker threads of the terrain, `step` only picks up their results and sends new
+// requests. New chunks around the camera are requested by `meshes_to_render`, or by `load_chunks` when nothing is drawn,
+// so one of them has to be called after every step for chunks to load. Blocks can only be read and set in loaded
+// chunks, elsewhere `get_block` returns None and `set_block` does nothing
+
+use cgmath::Vector3;
+
+pub use crate::{
+    engine::{
+        resource::{Draw, Material, Vertex},
+        TextureAtlas,
+    },
+    game::{
+        world::{Block, BlockManager, BlockVertex, Terrain, TerrainGeneratorKind},
+        State,
+    },
+    misc::{pos::Pos, Settings},
+};
+
+pub struct Game {
+    state: State,
+    settings: Settings,
+}
+
+impl Game {
+    // Starts a new world with a random seed, the player stands at the origin
+    pub async fn new(generator_kind: TerrainGeneratorKind, settings: Settings) -> Self {
+        let block_manager = BlockManager::new();
+        let texture_atlas = TextureAtlas::new(
+            block_manager.all_texture_names(),
+            block_manager.texture_animations(),
+            &"texture",
+        )
+        .await;
+
+        Self {
+            state: State::new(&texture_atlas, block_manager, false, generator_kind),
+            settings,
+        }
+    }
+
+    // Advances the simulation by dt, moving the player and the time of day and handling finished chunk requests
+    pub fn step(&mut self, dt: instant::Duration) {
+        self.state.update(true, dt, &self.settings)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    // Block of the block file with the given name, like "Stone"
+    pub fn block(&self, name: &str) -> Option<Block> {
+        let block_manager = self.state.block_manager();
+        block_manager
+            .get(name)
+            .is_some()
+            .then(|| Block::new_with_default(name, &block_manager))
+    }
+
+    pub fn get_block(&mut self, pos: &Pos) -> Option<Block> {
+        self.terrain_mut().get_block(pos)
+    }
+
+    pub fn set_block(&mut self, pos: &Pos, block: Block) {
+        self.terrain_mut().set_block(pos, block)
+    }
+
+    // Like `get_block` for the block at an absolute block coordinate, see `Pos::from_abs_block`
+    pub fn get_block_abs(&mut self, abs_block_pos: Vector3<i64>) -> Option<Block> {
+        self.terrain_mut().get_block_abs(abs_block_pos)
+    }
+
+    pub fn set_block_abs(&mut self, abs_block_pos: Vector3<i64>, block: Block) {
+        self.terrain_mut().set_block_abs(abs_block_pos, block)
+    }
+
+    // Sets every block in between and including both corners, relighting the chunks once at the end
+    pub fn fill_region(&mut self, min: &Pos, max: &Pos, block: Block) -> usize {
+        self.terrain_mut().fill_region(min, max, block)
+    }
+
+    // Chunks are loaded and meshed around the camera
+    pub fn camera_pos(&self) -> Pos {
+        self.state.camera().pos
+    }
+
+    pub fn teleport(&mut self, pos: Pos) {
+        self.state.player_mut().teleport(pos)
+    }
+
+    // Requests the chunks in render distance without uploading their meshes, instead of `meshes_to_render`
+    pub fn load_chunks(&mut self) {
+        self.state.load_chunks(&self.settings)
+    }
+
+    // Chunk requests that haven't come back from the worker threads yet
+    pub fn loading_chunks(&self) -> u32 {
+        self.state.loading_chunks()
+    }
+
+    // Solid, transparent and double sided meshes of the chunks in render distance, uploaded to the GPU first if they
+    // changed. They are drawn with the pipeline layout of `Renderer`, vertices are laid out by `BlockVertex::desc`
+    pub fn meshes_to_render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (Vec<&impl Draw>, Vec<&impl Draw>, Vec<&impl Draw>) {
+        self.state.meshes_to_render(device, queue, &self.settings)
+    }
+
+    // Atlas of all block textures, the texture coordinates of the vertices point into it
+    pub fn texture_atlas(&self) -> &TextureAtlas {
+        self.state.texture_atlas()
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    pub fn terrain_mut(&mut self) -> &mut Terrain {
+        self.state.terrain_mut()
+    }
+}
@@ -59,4 +59,6 @@ pub trait Projection: Sized + Default {
     fn calc_matrix(&self) -> Matrix4<f32>;
     fn resize(&mut self, new_size: Vector2<u32>);
     fn set_vfov(&mut self, val: Rad<f32>, display_size: Vector2<u32>);
+    fn znear(&self) -> f32;
+    fn zfar(&self) -> f32;
 }
@@ -68,6 +68,17 @@ impl FaceDirection {
         }
     }
 
+    pub fn opposite(&self) -> Self {
+        match self {
+            FaceDirection::Top => FaceDirection::Bottom,
+            FaceDirection::Bottom => FaceDirection::Top,
+            FaceDirection::West => FaceDirection::East,
+            FaceDirection::East => FaceDirection::West,
+            FaceDirection::North => FaceDirection::South,
+            FaceDirection::South => FaceDirection::North,
+        }
+    }
+
     pub fn brightness(&self) -> u8 {
         match self {
             FaceDirection::Top => 0,
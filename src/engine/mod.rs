@@ -1,8 +1,10 @@
 pub mod camera;
 pub mod face;
+mod overlay;
 mod renderer;
 pub mod resource;
 mod texture_atlas;
 
-pub use renderer::{Renderer, GUI};
+pub use overlay::{BoxOverlay, GroundPlane};
+pub use renderer::{CloudLayer, RenderStats, Renderer, GUI};
 pub use texture_atlas::TextureAtlas;
@@ -0,0 +1,168 @@
+use std::mem;
+
+use cgmath::Vector3;
+use wgpu::{vertex_attr_array, BufferAddress, VertexBufferLayout, VertexStepMode};
+
+use crate::{engine::resource::Vertex, misc::pos::Pos};
+
+// Keeps the faces of the box just outside of the blocks it covers, so they don't z-fight with them
+const OVERLAY_INFLATE: f32 = 0.005;
+
+// Translucent box drawn over the world, covering every block between and including both corners
+#[derive(Clone, Copy, Debug)]
+pub struct BoxOverlay {
+    pub corners: (Pos, Pos),
+    pub color: [f32; 4],
+    pub outline_color: [f32; 4],
+    // Width of the edges in blocks, a thickness of 0 draws them as single pixel lines
+    pub outline_thickness: f32,
+}
+
+impl BoxOverlay {
+    pub fn new(corners: (Pos, Pos), color: [f32; 4], outline_color: [f32; 4], outline_thickness: f32) -> Self {
+        Self {
+            corners,
+            color,
+            outline_color,
+            outline_thickness,
+        }
+    }
+
+    // Returns vertices of the faces as a triangle list and of the edges as a line list, thick edges are instead added to
+    // the triangle list as thin boxes. Like chunk meshes they are relative to the chunk the camera is in
+    pub fn vertices(&self, camera_pos: &Pos) -> (Vec<OverlayVertex>, Vec<OverlayVertex>) {
+        let origin = Pos::new(*camera_pos.chunk_pos(), Vector3::new(0.0, 0.0, 0.0)).abs_pos();
+        let block_pos = |pos: &Pos| {
+            let abs_pos = pos.abs_pos();
+            Vector3::new(
+                (abs_pos.x.floor() - origin.x) as f32,
+                (abs_pos.y.floor() - origin.y) as f32,
+                (abs_pos.z.floor() - origin.z) as f32,
+            )
+        };
+
+        let (first, second) = (block_pos(&self.corners.0), block_pos(&self.corners.1));
+        let min = Vector3::new(first.x.min(second.x), first.y.min(second.y), first.z.min(second.z))
+            - Vector3::new(OVERLAY_INFLATE, OVERLAY_INFLATE, OVERLAY_INFLATE);
+        let max = Vector3::new(first.x.max(second.x), first.y.max(second.y), first.z.max(second.z))
+            + Vector3::new(1.0 + OVERLAY_INFLATE, 1.0 + OVERLAY_INFLATE, 1.0 + OVERLAY_INFLATE);
+
+        // Fully transparent faces are left out, the box is then only its edges
+        let mut faces = if self.color[3] > 0.0 {
+            box_triangles(min, max, self.color)
+        } else {
+            Vec::new()
+        };
+        let edge_indices = (0..8)
+            .flat_map(|index| [1, 2, 4].map(move |axis| (index, index | axis)))
+            .filter(|(from, to)| from != to);
+
+        let edges = if self.outline_thickness > 0.0 {
+            let half_thickness = Vector3::new(1.0, 1.0, 1.0) * (self.outline_thickness / 2.0);
+            for (from, to) in edge_indices {
+                let (from, to) = (
+                    Vector3::from(box_corner(min, max, from)),
+                    Vector3::from(box_corner(min, max, to)),
+                );
+                faces.extend(box_triangles(
+                    from - half_thickness,
+                    to + half_thickness,
+                    self.outline_color,
+                ));
+            }
+            Vec::new()
+        } else {
+            edge_indices
+                .flat_map(|(from, to)| [from, to])
+                .map(|index| OverlayVertex::new(box_corner(min, max, index), self.outline_color))
+                .collect()
+        };
+
+        (faces, edges)
+    }
+}
+
+// Bits 0, 1 and 2 of the index pick the max instead of the min on the x, y and z axis
+fn box_corner(min: Vector3<f32>, max: Vector3<f32>, index: usize) -> [f32; 3] {
+    [
+        if index & 1 == 0 { min.x } else { max.x },
+        if index & 2 == 0 { min.y } else { max.y },
+        if index & 4 == 0 { min.z } else { max.z },
+    ]
+}
+
+fn box_triangles(min: Vector3<f32>, max: Vector3<f32>, color: [f32; 4]) -> Vec<OverlayVertex> {
+    [
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+    ]
+    .iter()
+    .flat_map(|[a, b, c, d]| [a, b, c, a, c, d])
+    .map(|index| OverlayVertex::new(box_corner(min, max, *index), color))
+    .collect()
+}
+
+// Flat plane at a fixed height drawn behind all chunks, so it only shows where nothing is loaded yet
+#[derive(Clone, Copy, Debug)]
+pub struct GroundPlane {
+    pub height: f32,
+    pub color: [f32; 4],
+}
+
+impl GroundPlane {
+    pub fn new(height: f32, color: [f32; 4]) -> Self {
+        Self { height, color }
+    }
+
+    // A square reaching extent blocks away from the camera in every direction, as a triangle list relative to the
+    // chunk the camera is in
+    pub fn vertices(&self, camera_pos: &Pos, extent: f32) -> Vec<OverlayVertex> {
+        let origin = Pos::new(*camera_pos.chunk_pos(), Vector3::new(0.0, 0.0, 0.0)).abs_pos();
+        let center = camera_pos.in_chunk_pos_f32();
+        let y = (self.height as f64 - origin.y) as f32;
+
+        let corner = |x: f32, z: f32| OverlayVertex::new([center.x + x * extent, y, center.z + z * extent], self.color);
+        vec![
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OverlayVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+impl OverlayVertex {
+    fn new(pos: [f32; 3], color: [f32; 4]) -> Self {
+        Self { pos, color }
+    }
+}
+
+impl Vertex for OverlayVertex {
+    fn desc<'a>() -> VertexBufferLayout<'a> {
+        use wgpu::VertexAttribute;
+
+        static ATTRIBUTES: [VertexAttribute; 2] = vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x4,
+        ];
+
+        VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
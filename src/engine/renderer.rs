@@ -3,9 +3,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use cgmath::{Rad, Vector2};
+use cgmath::{Matrix4, Rad, SquareMatrix, Vector2, Vector3};
 use egui::{Context, FontDefinitions, Style};
 use egui_winit_platform::{Platform, PlatformDescriptor};
+use rustc_hash::FxHashMap;
 use wgpu::{
     util::DeviceExt, LoadOp, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
     VertexBufferLayout,
@@ -15,10 +16,12 @@ use winit::window::Window;
 use crate::{
     engine::{
         camera::{Camera, CameraUniform, Projection},
-        resource::{Draw, Material, Texture},
-        texture_atlas::TextureAtlas,
+        overlay::{BoxOverlay, GroundPlane, OverlayVertex},
+        resource::{Draw, Material, Texture, Vertex},
+        texture_atlas::{TextureAtlas, MAX_ANIMATED_TEXTURES},
     },
-    misc::{loader::load_resource_string, Settings},
+    game::world::TextureAnimation,
+    misc::{loader::load_resource_string, pos::Pos, profiling::profile_span, PresentMode, Settings},
 };
 
 #[repr(C)]
@@ -28,6 +31,15 @@ struct SettingsUniform {
     base_light_value: f32,
     light_power_factor: f32,
     tile_size: f32,
+    min_brightness: f32,
+    fog_start: f32,
+    fog_end: f32,
+    mipmaps: u32,
+    fog_color: [f32; 4],
+    sun_color: [f32; 4],
+    // Seconds since the renderer was created, moves the surfaces of fluids
+    time: f32,
+    _padding: [f32; 3],
 }
 
 impl SettingsUniform {
@@ -37,36 +49,189 @@ impl SettingsUniform {
             base_light_value: settings.base_light_value,
             light_power_factor: settings.light_power_factor,
             tile_size,
+            min_brightness: settings.min_brightness,
+            fog_start: settings.fog_start,
+            fog_end: settings.fog_end,
+            mipmaps: settings.mipmaps as u32,
+            fog_color: [settings.sky_color[0], settings.sky_color[1], settings.sky_color[2], 1.0],
+            sun_color: [settings.sun_color[0], settings.sun_color[1], settings.sun_color[2], 1.0],
+            time: 0.0,
+            _padding: [0.0; 3],
         }
     }
 
-    fn update_self(&mut self, settings: &Settings) {
-        self.sunlight_intensity = settings.sunlight_intensity as u32;
+    fn update_self(
+        &mut self,
+        settings: &Settings,
+        sunlight_intensity: u8,
+        sun_color: [f32; 3],
+        fog_color: [f32; 3],
+        time: f64,
+    ) {
+        self.sunlight_intensity = sunlight_intensity as u32;
         self.base_light_value = settings.base_light_value;
         self.light_power_factor = settings.light_power_factor;
+        self.min_brightness = settings.min_brightness;
+        self.fog_start = settings.fog_start;
+        self.fog_end = settings.fog_end;
+        self.mipmaps = settings.mipmaps as u32;
+        self.fog_color = [fog_color[0], fog_color[1], fog_color[2], 1.0];
+        self.sun_color = [sun_color[0], sun_color[1], sun_color[2], 1.0];
+        self.time = time as f32;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthViewUniform {
+    znear: f32,
+    zfar: f32,
+    _padding: [f32; 2],
+}
+
+impl DepthViewUniform {
+    fn new(projection: &impl Projection) -> Self {
+        Self {
+            znear: projection.znear(),
+            zfar: projection.zfar(),
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    horizon_color: [f32; 4],
+    zenith_color: [f32; 4],
+}
+
+impl SkyUniform {
+    fn new(view_proj: Matrix4<f32>, horizon_color: [f32; 3], zenith_color: [f32; 3]) -> Self {
+        Self {
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+            horizon_color: [horizon_color[0], horizon_color[1], horizon_color[2], 1.0],
+            zenith_color: [zenith_color[0], zenith_color[1], zenith_color[2], 1.0],
+        }
     }
 }
 
+// Blocks after which the cloud noise repeats, must match CELL_SIZE * CELL_PERIOD in clouds.wgsl. The noise offset is
+// wrapped to it so it stays precise far from the origin
+const NOISE_PERIOD: f64 = 4096.0;
+
+// Settings of the cloud layer for the current frame, with the color already adjusted for the time of day
+#[derive(Clone, Copy, Debug)]
+pub struct CloudLayer {
+    pub height: f32,
+    // Blocks per second the clouds drift along the x axis
+    pub speed: f32,
+    // Part of the sky covered, in 0..=1
+    pub coverage: f32,
+    pub color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CloudUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    view_proj: [[f32; 4]; 4],
+    color: [f32; 4],
+    horizon_color: [f32; 4],
+    offset: [f32; 2],
+    height: f32,
+    coverage: f32,
+    max_distance: f32,
+    _padding: [f32; 3],
+}
+
+impl CloudUniform {
+    fn new(
+        view_proj: Matrix4<f32>,
+        camera_pos: &Pos,
+        cloud_layer: &CloudLayer,
+        horizon_color: [f32; 3],
+        time: f64,
+        max_distance: f32,
+    ) -> Self {
+        // Chunk meshes and the camera are relative to the chunk the camera is in
+        let origin = Pos::new(*camera_pos.chunk_pos(), Vector3::new(0.0, 0.0, 0.0)).abs_pos();
+
+        Self {
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+            view_proj: view_proj.into(),
+            color: cloud_layer.color,
+            horizon_color: [horizon_color[0], horizon_color[1], horizon_color[2], 1.0],
+            offset: [
+                (origin.x + time * cloud_layer.speed as f64).rem_euclid(NOISE_PERIOD) as f32,
+                origin.z.rem_euclid(NOISE_PERIOD) as f32,
+            ],
+            height: (cloud_layer.height as f64 - origin.y) as f32,
+            coverage: cloud_layer.coverage.clamp(0.0, 1.0),
+            max_distance,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+// Counted for the chunk meshes of the last rendered frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u32,
+}
+
 pub struct Renderer<P> {
     block_material: Material,
     camera_bind_group: wgpu::BindGroup,
     camera_buffer: wgpu::Buffer,
+    camera_pos: Option<Pos>,
     camera_uniform: CameraUniform,
+    // Whether the cloud layer is drawn
+    clouds: bool,
+    clouds_bind_group: wgpu::BindGroup,
+    clouds_buffer: wgpu::Buffer,
+    clouds_pipeline: wgpu::RenderPipeline,
     projection: P,
     config: wgpu::SurfaceConfiguration,
     depth_texture: Texture,
+    depth_view_bind_group: wgpu::BindGroup,
+    depth_view_bind_group_layout: wgpu::BindGroupLayout,
+    depth_view_buffer: wgpu::Buffer,
+    depth_view_pipeline: wgpu::RenderPipeline,
     device: wgpu::Device,
+    double_sided_pipeline: wgpu::RenderPipeline,
     egui_platform: Platform,
     egui_rpass: egui_wgpu_backend::RenderPass,
+    // The block texture atlas as egui sees it, for drawing blocks in the UI
+    egui_atlas_texture_id: egui::TextureId,
+    overlay_edges_pipeline: wgpu::RenderPipeline,
+    // The setting the surface was last configured for and the modes it supports
+    present_mode: PresentMode,
+    present_modes: Vec<wgpu::PresentMode>,
+    overlay_faces_pipeline: wgpu::RenderPipeline,
     queue: wgpu::Queue,
     render_pipeline: wgpu::RenderPipeline,
+    render_stats: RenderStats,
+    animation_buffer: wgpu::Buffer,
     settings_bind_group: wgpu::BindGroup,
     settings_buffer: wgpu::Buffer,
     settings_uniform: SettingsUniform,
+    show_depth_view: bool,
     size: winit::dpi::PhysicalSize<u32>,
+    // Whether the gradient sky is drawn, otherwise the background is cleared to a flat color
+    sky: bool,
+    sky_bind_group: wgpu::BindGroup,
+    sky_buffer: wgpu::Buffer,
+    sky_pipeline: wgpu::RenderPipeline,
+    start_time: instant::Instant,
     surface: wgpu::Surface,
     texture_atlas: TextureAtlas,
+    transparent_backfaces: bool,
     window: Window,
+    wireframe: bool,
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
 }
 
 impl<P: Projection + Sized + Default> Renderer<P> {
@@ -74,6 +239,7 @@ impl<P: Projection + Sized + Default> Renderer<P> {
         window: Window,
         vertex_desc: VertexBufferLayout<'a>,
         texture_names: &[String],
+        texture_animations: &FxHashMap<String, TextureAnimation>,
         texture_folder: &impl AsRef<Path>,
         settings: &Settings,
     ) -> Self {
@@ -94,11 +260,13 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             })
             .await
             .unwrap();
+        // Only needed for the wireframe debug view, which isn't available without it
+        let features = adapter.features() & wgpu::Features::POLYGON_MODE_LINE;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    features,
                     limits: if cfg!(target_arch = "wasm32") {
                         wgpu::Limits::downlevel_webgl2_defaults()
                     } else {
@@ -122,7 +290,7 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: supported_present_mode(settings.present_mode, &surface_caps.present_modes),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
@@ -183,32 +351,56 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             label: Some("camera_bind_group"),
         });
 
-        let texture_atlas = TextureAtlas::new(texture_names, texture_folder).await;
+        let texture_atlas = TextureAtlas::new(texture_names, texture_animations, texture_folder).await;
         let settings_uniform = SettingsUniform::new(settings, texture_atlas.tile_size().0);
         let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Settings Buffer"),
             contents: bytemuck::cast_slice(&[settings_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        // Written every frame by render, as the offsets depend on the time
+        let animation_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Animation Buffer"),
+            contents: bytemuck::cast_slice(&[[0.0f32; 4]; MAX_ANIMATED_TEXTURES]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
         let settings_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
             label: Some("settings_bind_group_layout"),
         });
         let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &settings_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: settings_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: animation_buffer.as_entire_binding(),
+                },
+            ],
             label: Some("settings_bind_group"),
         });
 
@@ -246,50 +438,315 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+        let create_render_pipeline = |label, cull_mode, polygon_mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: std::slice::from_ref(&vertex_desc),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent::OVER,
+                            alpha: wgpu::BlendComponent::OVER,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode,
+                    polygon_mode,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let render_pipeline =
+            create_render_pipeline("Render Pipeline", Some(wgpu::Face::Back), wgpu::PolygonMode::Fill);
+        // Lets the inside faces of transparent blocks be seen, e.g. when under water
+        let double_sided_pipeline = create_render_pipeline("Double Sided Pipeline", None, wgpu::PolygonMode::Fill);
+        let wireframe_pipeline = if features.contains(wgpu::Features::POLYGON_MODE_LINE) {
+            Some(create_render_pipeline(
+                "Wireframe Pipeline",
+                Some(wgpu::Face::Back),
+                wgpu::PolygonMode::Line,
+            ))
+        } else {
+            log::info!("Line polygon mode is not supported, wireframe rendering is unavailable");
+            None
+        };
+
+        let depth_view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Depth View Buffer"),
+            contents: bytemuck::cast_slice(&[DepthViewUniform::new(&projection)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let depth_view_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("depth_view_bind_group_layout"),
+        });
+        let depth_view_bind_group = create_depth_view_bind_group(
+            &device,
+            &depth_view_bind_group_layout,
+            &depth_texture,
+            &depth_view_buffer,
+        );
+
+        let depth_view_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_view.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                load_resource_string(PathBuf::new().join("shader").join("depth_view.wgsl"))
+                    .expect("Failed to load shader 'resource/shader/depth_view.wgsl'")
+                    .into(),
+            ),
+        });
+        let depth_view_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth View Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth View Pipeline Layout"),
+                bind_group_layouts: &[&depth_view_bind_group_layout],
+                push_constant_ranges: &[],
+            })),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: &depth_view_shader,
                 entry_point: "vs_main",
-                buffers: &[vertex_desc],
+                buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: &depth_view_shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::OVER,
-                        alpha: wgpu::BlendComponent::OVER,
-                    }),
+                    blend: None,
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sky_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[SkyUniform::new(
+                Matrix4::identity(),
+                settings.sky_color,
+                settings.sky_zenith_color,
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sky_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("sky_bind_group_layout"),
+        });
+        let sky_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &sky_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: sky_buffer.as_entire_binding(),
+            }],
+            label: Some("sky_bind_group"),
+        });
+
+        let sky_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sky.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                load_resource_string(PathBuf::new().join("shader").join("sky.wgsl"))
+                    .expect("Failed to load shader 'resource/shader/sky.wgsl'")
+                    .into(),
+            ),
+        });
+        let sky_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sky Pipeline Layout"),
+                bind_group_layouts: &[&sky_bind_group_layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: wgpu::VertexState {
+                module: &sky_shader,
+                entry_point: "vs_main",
+                buffers: &[],
             },
+            fragment: Some(wgpu::FragmentState {
+                module: &sky_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let clouds_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Clouds Buffer"),
+            size: std::mem::size_of::<CloudUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let clouds_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &sky_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: clouds_buffer.as_entire_binding(),
+            }],
+            label: Some("clouds_bind_group"),
+        });
+
+        let clouds_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("clouds.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                load_resource_string(PathBuf::new().join("shader").join("clouds.wgsl"))
+                    .expect("Failed to load shader 'resource/shader/clouds.wgsl'")
+                    .into(),
+            ),
+        });
+        // Tested against the depth of the world so terrain in front hides the clouds, but translucent so it doesn't
+        // write depth
+        let clouds_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Clouds Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Clouds Pipeline Layout"),
+                bind_group_layouts: &[&sky_bind_group_layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: wgpu::VertexState {
+                module: &clouds_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &clouds_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: false,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+            multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
 
+        let overlay_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("overlay.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                load_resource_string(PathBuf::new().join("shader").join("overlay.wgsl"))
+                    .expect("Failed to load shader 'resource/shader/overlay.wgsl'")
+                    .into(),
+            ),
+        });
+        let overlay_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        // Overlays are tested against the depth of the world but don't write to it, so they don't hide each other
+        let create_overlay_pipeline = |label, topology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&overlay_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &overlay_shader,
+                    entry_point: "vs_main",
+                    buffers: &[OverlayVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &overlay_shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let overlay_faces_pipeline =
+            create_overlay_pipeline("Overlay Faces Pipeline", wgpu::PrimitiveTopology::TriangleList);
+        let overlay_edges_pipeline =
+            create_overlay_pipeline("Overlay Edges Pipeline", wgpu::PrimitiveTopology::LineList);
+
         let atlas_texture = texture_atlas.load_texture(&device, &queue);
         let block_material = Material {
             name: "BlockMaterial".into(),
@@ -319,28 +776,59 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             font_definitions: FontDefinitions::default(),
             style: Style::default(),
         });
-        let egui_rpass = egui_wgpu_backend::RenderPass::new(&device, surface_format, 1);
+        let mut egui_rpass = egui_wgpu_backend::RenderPass::new(&device, surface_format, 1);
+        let egui_atlas_texture_id = egui_rpass.egui_texture_from_wgpu_texture(
+            &device,
+            &block_material.diffuse_texture.view,
+            wgpu::FilterMode::Nearest,
+        );
 
         Self {
             block_material,
             camera_bind_group,
             camera_buffer,
+            camera_pos: None,
             camera_uniform,
+            clouds: false,
+            clouds_bind_group,
+            clouds_buffer,
+            clouds_pipeline,
             config,
             depth_texture,
+            depth_view_bind_group,
+            depth_view_bind_group_layout,
+            depth_view_buffer,
+            depth_view_pipeline,
             device,
+            double_sided_pipeline,
             egui_platform,
             egui_rpass,
+            egui_atlas_texture_id,
+            overlay_edges_pipeline,
+            present_mode: settings.present_mode,
+            present_modes: surface_caps.present_modes,
+            overlay_faces_pipeline,
             projection,
             queue,
             render_pipeline,
+            render_stats: RenderStats::default(),
+            animation_buffer,
             settings_bind_group,
             settings_buffer,
             settings_uniform,
+            show_depth_view: false,
             size,
+            sky: settings.sky_gradient,
+            sky_bind_group,
+            sky_buffer,
+            sky_pipeline,
+            start_time: instant::Instant::now(),
             surface,
             texture_atlas,
+            transparent_backfaces: settings.transparent_backfaces,
             window,
+            wireframe: settings.wireframe,
+            wireframe_pipeline,
         }
     }
 
@@ -354,6 +842,22 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
             self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.depth_view_bind_group = create_depth_view_bind_group(
+                &self.device,
+                &self.depth_view_bind_group_layout,
+                &self.depth_texture,
+                &self.depth_view_buffer,
+            );
+        }
+    }
+
+    // Reconfigures the surface if the mode changed, the config keeps it for later resizes
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if present_mode != self.present_mode {
+            log::info!("Switching present mode to {present_mode:?}");
+            self.present_mode = present_mode;
+            self.config.present_mode = supported_present_mode(present_mode, &self.present_modes);
+            self.surface.configure(&self.device, &self.config);
         }
     }
 
@@ -362,23 +866,96 @@ impl<P: Projection + Sized + Default> Renderer<P> {
             .set_vfov(val, Vector2::new(self.size.width, self.size.height))
     }
 
-    pub fn update(&mut self, camera: &impl Camera, settings: &Settings) {
+    pub fn toggle_depth_view(&mut self) {
+        self.show_depth_view = !self.show_depth_view
+    }
+
+    // fog_color should match the sky at the horizon, so distant terrain and clouds fade into it. Without a zenith color
+    // the sky is left flat
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        camera: &impl Camera,
+        settings: &Settings,
+        sunlight_intensity: u8,
+        sun_color: [f32; 3],
+        fog_color: [f32; 3],
+        zenith_color: Option<[f32; 3]>,
+        cloud_layer: Option<CloudLayer>,
+    ) {
         self.camera_uniform.update_view_proj(camera, &self.projection);
-        self.settings_uniform.update_self(settings);
+        self.camera_pos = Some(camera.pos());
+        self.sky = zenith_color.is_some();
+        if let Some(zenith_color) = zenith_color {
+            self.queue.write_buffer(
+                &self.sky_buffer,
+                0,
+                bytemuck::cast_slice(&[SkyUniform::new(
+                    self.projection.calc_matrix() * camera.calc_matrix(),
+                    fog_color,
+                    zenith_color,
+                )]),
+            );
+        }
+        self.clouds = cloud_layer.is_some();
+        if let Some(cloud_layer) = cloud_layer {
+            self.queue.write_buffer(
+                &self.clouds_buffer,
+                0,
+                bytemuck::cast_slice(&[CloudUniform::new(
+                    self.projection.calc_matrix() * camera.calc_matrix(),
+                    &camera.pos(),
+                    &cloud_layer,
+                    fog_color,
+                    self.start_time.elapsed().as_secs_f64(),
+                    self.projection.zfar(),
+                )]),
+            );
+        }
+        self.transparent_backfaces = settings.transparent_backfaces;
+        self.wireframe = settings.wireframe;
+        self.set_present_mode(settings.present_mode);
+        self.settings_uniform.update_self(
+            settings,
+            sunlight_intensity,
+            sun_color,
+            fog_color,
+            self.start_time.elapsed().as_secs_f64(),
+        );
 
         self.queue
             .write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
         self.queue
             .write_buffer(&self.settings_buffer, 0, bytemuck::cast_slice(&[self.settings_uniform]));
+        self.queue.write_buffer(
+            &self.depth_view_buffer,
+            0,
+            bytemuck::cast_slice(&[DepthViewUniform::new(&self.projection)]),
+        );
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn render<'a>(
         &mut self,
         meshes: Vec<&impl Draw>,
+        transparent_meshes: Vec<&impl Draw>,
+        double_sided_meshes: Vec<&impl Draw>,
         background_color: Option<(f32, f32, f32)>,
+        overlays: &[BoxOverlay],
+        ground_plane: Option<GroundPlane>,
         ui: &mut impl GUI,
     ) -> Result<(), wgpu::SurfaceError> {
+        profile_span!("render");
         self.egui_platform.update_time(ui.elapsed_secs());
+        self.queue.write_buffer(
+            &self.animation_buffer,
+            0,
+            bytemuck::cast_slice(
+                &self
+                    .texture_atlas
+                    .animation_offsets(self.start_time.elapsed().as_secs_f64()),
+            ),
+        );
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
@@ -387,6 +964,70 @@ impl<P: Projection + Sized + Default> Renderer<P> {
         let output = self.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        let overlay_buffers = match self.camera_pos {
+            Some(camera_pos) if !overlays.is_empty() => {
+                let (mut faces, mut edges) = (Vec::new(), Vec::new());
+                for overlay in overlays {
+                    let (overlay_faces, overlay_edges) = overlay.vertices(&camera_pos);
+                    faces.extend(overlay_faces);
+                    edges.extend(overlay_edges);
+                }
+
+                let create_buffer = |label, vertices: &[OverlayVertex]| {
+                    (
+                        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some(label),
+                            contents: bytemuck::cast_slice(vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        }),
+                        vertices.len() as u32,
+                    )
+                };
+                Some((
+                    create_buffer("Overlay Faces Buffer", &faces),
+                    create_buffer("Overlay Edges Buffer", &edges),
+                ))
+            }
+            _ => None,
+        };
+        let ground_plane_buffer = match (self.camera_pos, ground_plane) {
+            (Some(camera_pos), Some(ground_plane)) => {
+                let vertices = ground_plane.vertices(&camera_pos, self.projection.zfar());
+                Some((
+                    self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Ground Plane Buffer"),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                    vertices.len() as u32,
+                ))
+            }
+            _ => None,
+        };
+
+        let mut render_stats = RenderStats::default();
+
+        // Covers the whole screen, so the chunks are drawn over it instead of clearing
+        let draw_sky = self.sky && background_color.is_some();
+        if draw_sky {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Sky Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.sky_pipeline);
+            render_pass.set_bind_group(0, &self.sky_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -394,7 +1035,7 @@ impl<P: Projection + Sized + Default> Renderer<P> {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: if let Some((r, g, b)) = background_color {
+                        load: if let (Some((r, g, b)), false) = (background_color, draw_sky) {
                             LoadOp::Clear(wgpu::Color {
                                 r: r as f64,
                                 g: g as f64,
@@ -417,9 +1058,33 @@ impl<P: Projection + Sized + Default> Renderer<P> {
                 }),
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
+            // Drawn first and without writing depth, so every chunk covers it
+            if let Some((ground_plane_buffer, ground_plane_count)) = &ground_plane_buffer {
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_pipeline(&self.overlay_faces_pipeline);
+                render_pass.set_vertex_buffer(0, ground_plane_buffer.slice(..));
+                render_pass.draw(0..*ground_plane_count, 0..1);
+            }
+
+            // Falls back to filled faces when the wireframe pipeline couldn't be created
+            let wireframe_pipeline = self.wireframe_pipeline.as_ref().filter(|_| self.wireframe);
+            render_pass.set_pipeline(wireframe_pipeline.unwrap_or(&self.render_pipeline));
 
             for mesh in meshes {
+                render_stats.draw_calls += 1;
+                render_stats.triangles += mesh.element_count() / 3;
+                mesh.draw(
+                    &self.block_material,
+                    &self.camera_bind_group,
+                    &self.settings_bind_group,
+                    &mut render_pass,
+                )
+            }
+
+            render_pass.set_pipeline(wireframe_pipeline.unwrap_or(&self.double_sided_pipeline));
+            for mesh in double_sided_meshes {
+                render_stats.draw_calls += 1;
+                render_stats.triangles += mesh.element_count() / 3;
                 mesh.draw(
                     &self.block_material,
                     &self.camera_bind_group,
@@ -427,6 +1092,60 @@ impl<P: Projection + Sized + Default> Renderer<P> {
                     &mut render_pass,
                 )
             }
+
+            // Transparent meshes stay on the double sided pipeline when their backfaces are shown
+            if !self.transparent_backfaces && wireframe_pipeline.is_none() {
+                render_pass.set_pipeline(&self.render_pipeline);
+            }
+            for mesh in transparent_meshes {
+                render_stats.draw_calls += 1;
+                render_stats.triangles += mesh.element_count() / 3;
+                mesh.draw(
+                    &self.block_material,
+                    &self.camera_bind_group,
+                    &self.settings_bind_group,
+                    &mut render_pass,
+                )
+            }
+
+            if self.clouds {
+                render_pass.set_pipeline(&self.clouds_pipeline);
+                render_pass.set_bind_group(0, &self.clouds_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+
+            if let Some(((faces_buffer, faces_count), (edges_buffer, edges_count))) = &overlay_buffers {
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+
+                render_pass.set_pipeline(&self.overlay_faces_pipeline);
+                render_pass.set_vertex_buffer(0, faces_buffer.slice(..));
+                render_pass.draw(0..*faces_count, 0..1);
+
+                render_pass.set_pipeline(&self.overlay_edges_pipeline);
+                render_pass.set_vertex_buffer(0, edges_buffer.slice(..));
+                render_pass.draw(0..*edges_count, 0..1);
+            }
+        }
+
+        self.render_stats = render_stats;
+
+        if self.show_depth_view {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Depth View Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.depth_view_pipeline);
+            render_pass.set_bind_group(0, &self.depth_view_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
         }
 
         self.egui_platform.begin_frame();
@@ -460,6 +1179,10 @@ impl<P: Projection + Sized + Default> Renderer<P> {
         Ok(())
     }
 
+    pub fn render_stats(&self) -> RenderStats {
+        self.render_stats
+    }
+
     pub fn window(&self) -> &Window {
         &self.window
     }
@@ -468,10 +1191,18 @@ impl<P: Projection + Sized + Default> Renderer<P> {
         &self.device
     }
 
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
     pub fn texture_atlas(&self) -> &TextureAtlas {
         &self.texture_atlas
     }
 
+    pub fn egui_atlas_texture_id(&self) -> egui::TextureId {
+        self.egui_atlas_texture_id
+    }
+
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
         self.size
     }
@@ -481,6 +1212,40 @@ impl<P: Projection + Sized + Default> Renderer<P> {
     }
 }
 
+// AutoVsync is supported everywhere, wgpu picks a vsync mode the surface has for it
+fn supported_present_mode(present_mode: PresentMode, present_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    let wanted = present_mode.into();
+
+    if wanted == wgpu::PresentMode::AutoVsync || present_modes.contains(&wanted) {
+        wanted
+    } else {
+        log::warn!("Present mode {wanted:?} is not supported, falling back to AutoVsync");
+        wgpu::PresentMode::AutoVsync
+    }
+}
+
+fn create_depth_view_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    depth_texture: &Texture,
+    depth_view_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: depth_view_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("depth_view_bind_group"),
+    })
+}
+
 pub trait GUI {
     fn elapsed_secs(&self) -> f64;
     fn show_ui(&mut self, ctx: &Context);
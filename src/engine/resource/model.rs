@@ -20,4 +20,6 @@ pub trait Draw {
         settings_bind_group: &'a BindGroup,
         render_pass: &mut RenderPass<'a>,
     );
+    // Number of indices drawn, three per triangle
+    fn element_count(&self) -> u32;
 }
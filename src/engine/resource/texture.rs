@@ -48,6 +48,7 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        mip_level_count: u32,
     ) -> Result<Self, ()> {
         let dimensions = img.dimensions();
         let rgba = img.to_rgba8();
@@ -61,7 +62,7 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
@@ -69,21 +70,32 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        let mut level = rgba;
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                level = half_size(&level);
+            }
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                wgpu::Extent3d {
+                    width: level.width(),
+                    height: level.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
@@ -92,10 +104,27 @@ impl Texture {
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Nearest,
             min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
         Ok(Self { texture, view, sampler })
     }
 }
+
+// Averages every 2x2 block of pixels, so a texel of the next mip level only covers pixels of the texel above it
+fn half_size(image: &image::RgbaImage) -> image::RgbaImage {
+    image::RgbaImage::from_fn((image.width() / 2).max(1), (image.height() / 2).max(1), |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel(
+                (x * 2 + dx).min(image.width() - 1),
+                (y * 2 + dy).min(image.height() - 1),
+            );
+            for (channel, value) in sum.iter_mut().zip(pixel.0) {
+                *channel += value as u32;
+            }
+        }
+        image::Rgba(sum.map(|channel| (channel / 4) as u8))
+    })
+}
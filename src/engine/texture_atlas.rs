@@ -1,52 +1,138 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
-use image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use image::{imageops, DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use rustc_hash::FxHashMap;
 
-use crate::{engine::resource::Texture, game::world::TextureID, misc::loader::load_resource_binary};
+use crate::{
+    engine::resource::Texture,
+    game::world::{TextureAnimation, TextureID},
+    misc::loader::{is_url, load_resource_binary, load_url_binary},
+};
 
 pub struct TextureAtlas {
-    texture_buffer: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    texture_buffer: ImageBuffer<Rgba<u8>, Vec<u8>>,
     offset: HashMap<TextureID, (u32, u32)>,
+    // Textures with at least one transparent pixel, their blocks need fragments below the alpha threshold discarded
+    cutout: HashSet<TextureID>,
+    // Mean color of the first frame of every texture, for drawing blocks as single pixels
+    average_colors: HashMap<TextureID, [u8; 3]>,
+    // Slots of every frame of animated textures after the first, which is their offset, indexed by animation_index
+    animations: Vec<(Vec<(u32, u32)>, TextureAnimation)>,
+    animation_index: HashMap<TextureID, u8>,
     atlas_size: (u32, u32),
+    // Size in pixels of each texture and of the gutter of repeated edge pixels around it
+    tile_pixels: (u32, u32),
+    gutter: (u32, u32),
 }
 
 impl TextureAtlas {
-    pub async fn new(texture_names: &[String], texture_folder: &impl AsRef<Path>) -> Self {
-        let mut images: HashMap<&str, ImageBuffer<Rgb<u8>, Vec<u8>>> = HashMap::default();
+    pub async fn new(
+        texture_names: &[String],
+        texture_animations: &FxHashMap<String, TextureAnimation>,
+        texture_folder: &impl AsRef<Path>,
+    ) -> Self {
+        let mut images: HashMap<&str, Vec<RgbaImage>> = HashMap::default();
+        let mut animated = Vec::new();
 
+        let mut missing = Vec::new();
         let (mut last_width, mut last_height) = (0, 0);
         for texture_name in texture_names {
-            let img = load_image(texture_name.clone(), texture_folder).await;
+            let Some(img) = load_image(texture_name.clone(), texture_folder).await else {
+                missing.push(texture_name);
+                continue;
+            };
+
+            let frames = match texture_animations.get(texture_name) {
+                Some(animation) if animation.frames > 1 && img.height() % animation.frames == 0 => {
+                    animated.push((texture_name.as_str(), *animation));
+                    animation.frames
+                }
+                Some(animation) => {
+                    log::error!(
+                        "Texture {texture_name:?} is {:} pixels high, which can't be split into {:} frames",
+                        img.height(),
+                        animation.frames
+                    );
+                    1
+                }
+                None => 1,
+            };
+            let frame_height = img.height() / frames;
 
             if last_width != 0 && last_height != 0 {
                 assert!(
-                    (last_width == img.width()) && (last_height == img.height()),
+                    (last_width == img.width()) && (last_height == frame_height),
                     "All textures must have same size"
                 );
             }
 
             last_width = img.width();
-            last_height = img.height();
+            last_height = frame_height;
 
-            images.insert(texture_name, img);
+            images.insert(
+                texture_name,
+                (0..frames)
+                    .map(|frame| {
+                        imageops::crop_imm(&img, 0, frame * frame_height, img.width(), frame_height).to_image()
+                    })
+                    .collect(),
+            );
         }
 
-        let texture_width = (images.len() as f32).sqrt().ceil() as u32;
+        if !missing.is_empty() {
+            if last_width == 0 || last_height == 0 {
+                (last_width, last_height) = MISSING_TEXTURE_SIZE;
+            }
+            for texture_name in missing {
+                images.insert(texture_name, vec![missing_texture(last_width, last_height)]);
+            }
+        }
+
+        if animated.len() > MAX_ANIMATED_TEXTURES {
+            log::warn!("Only {MAX_ANIMATED_TEXTURES:} textures can be animated, the rest only show their first frame");
+        }
+        animated.sort_by_key(|(texture_name, _)| *texture_name);
+        animated.truncate(MAX_ANIMATED_TEXTURES);
+
+        let slot_count = images.values().map(|frames| frames.len()).sum::<usize>();
+        let texture_width = (slot_count as f32).sqrt().ceil() as u32;
         let texture_height = texture_width;
 
         let mut offset = HashMap::default();
-        let mut images_iter = images.into_iter();
-        let mut texture_buffer = RgbImage::new(texture_width * last_width, texture_height * last_height);
+        let mut cutout = HashSet::default();
+        let mut average_colors = HashMap::default();
+        let mut frame_slots: HashMap<&str, Vec<(u32, u32)>> = HashMap::default();
+        let mut images_iter = images
+            .into_iter()
+            .flat_map(|(texture_name, frames)| frames.into_iter().map(move |frame| (texture_name, frame)));
+        let gutter = (
+            (last_width / GUTTER_DIVISOR).max(1),
+            (last_height / GUTTER_DIVISOR).max(1),
+        );
+        let slot = (last_width + 2 * gutter.0, last_height + 2 * gutter.1);
+        let mut texture_buffer = RgbaImage::new(texture_width * slot.0, texture_height * slot.1);
 
         for x in 0..texture_width {
             for y in 0..texture_height {
                 if let Some((texture_name, image)) = images_iter.next() {
-                    offset.insert(texture_name.into(), (x, y));
-                    for image_x in 0..image.width() {
-                        for image_y in 0..image.height() {
+                    offset.entry(texture_name.into()).or_insert((x, y));
+                    average_colors
+                        .entry(texture_name.into())
+                        .or_insert_with(|| average_color(&image));
+                    frame_slots.entry(texture_name).or_default().push((x, y));
+                    if image.pixels().any(|pixel| pixel.0[3] < u8::MAX) {
+                        cutout.insert(texture_name.into());
+                    }
+                    for slot_x in 0..slot.0 {
+                        for slot_y in 0..slot.1 {
+                            let image_x = slot_x.saturating_sub(gutter.0).min(image.width() - 1);
+                            let image_y = slot_y.saturating_sub(gutter.1).min(image.height() - 1);
                             texture_buffer.put_pixel(
-                                image_x + x * image.width(),
-                                image_y + y * image.height(),
+                                slot_x + x * slot.0,
+                                slot_y + y * slot.1,
                                 *image.get_pixel(image_x, image_y),
                             )
                         }
@@ -55,10 +141,26 @@ impl TextureAtlas {
             }
         }
 
+        let animation_index = animated
+            .iter()
+            .enumerate()
+            .map(|(index, (texture_name, _))| (TextureID::from(*texture_name), index as u8))
+            .collect();
+        let animations = animated
+            .into_iter()
+            .map(|(texture_name, animation)| (frame_slots.remove(texture_name).unwrap_or_default(), animation))
+            .collect();
+
         Self {
             texture_buffer,
             offset,
+            cutout,
+            average_colors,
+            animations,
+            animation_index,
             atlas_size: (texture_width, texture_height),
+            tile_pixels: (last_width, last_height),
+            gutter,
         }
     }
 
@@ -66,45 +168,146 @@ impl TextureAtlas {
         Texture::from_image(
             device,
             queue,
-            &DynamicImage::ImageRgb8(self.texture_buffer.clone()),
+            &DynamicImage::ImageRgba8(self.texture_buffer.clone()),
             Some("TextureAtlas"),
+            self.mip_level_count(),
         )
         .expect("Failed creating TextureAtlas")
     }
 
+    pub fn save_image(&self, path: impl AsRef<Path>) -> Result<(), ()> {
+        let path = path.as_ref();
+
+        self.texture_buffer.save(path).map_err(|e| {
+            log::warn!("Failed writing texture atlas to {} - {}", path.display(), e);
+        })
+    }
+
     pub fn texture_coordinates(&self, texture: &TextureID) -> (f32, f32) {
         let coords = self.offset[texture];
+        let (slot, pixels) = (self.slot_pixels(), self.atlas_pixels());
         (
-            coords.0 as f32 / self.atlas_size.0 as f32,
-            coords.1 as f32 / self.atlas_size.1 as f32,
+            (coords.0 * slot.0 + self.gutter.0) as f32 / pixels.0 as f32,
+            (coords.1 * slot.1 + self.gutter.1) as f32 / pixels.1 as f32,
         )
     }
 
-    pub fn atlas_size(&self) -> (f32, f32) {
-        (self.atlas_size.0 as f32, self.atlas_size.1 as f32)
+    pub fn is_cutout(&self, texture: &TextureID) -> bool {
+        self.cutout.contains(texture)
+    }
+
+    pub fn average_color(&self, texture: &TextureID) -> [u8; 3] {
+        self.average_colors.get(texture).copied().unwrap_or_default()
+    }
+
+    pub fn animation_index(&self, texture: &TextureID) -> Option<u8> {
+        self.animation_index.get(texture).copied()
+    }
+
+    // Offset from the first to the current frame of every animated texture, to be added to its texture coordinates
+    pub fn animation_offsets(&self, time_secs: f64) -> [[f32; 4]; MAX_ANIMATED_TEXTURES] {
+        let pixels = self.atlas_pixels();
+        let slot = self.slot_pixels();
+        let mut out = [[0.0; 4]; MAX_ANIMATED_TEXTURES];
+
+        for ((frames, animation), offset) in self.animations.iter().zip(out.iter_mut()) {
+            if let Some(first_frame) = frames.first() {
+                let frame = (time_secs * 1000.0 / animation.frame_duration_ms.max(1) as f64) as usize % frames.len();
+                let current_frame = frames[frame];
+                offset[0] = (current_frame.0 as f32 - first_frame.0 as f32) * slot.0 as f32 / pixels.0 as f32;
+                offset[1] = (current_frame.1 as f32 - first_frame.1 as f32) * slot.1 as f32 / pixels.1 as f32;
+            }
+        }
+
+        out
     }
 
     pub fn tile_size(&self) -> (f32, f32) {
-        let atlas_size = self.atlas_size();
-        (1.0 / atlas_size.0, 1.0 / atlas_size.1)
+        let pixels = self.atlas_pixels();
+        (
+            self.tile_pixels.0 as f32 / pixels.0 as f32,
+            self.tile_pixels.1 as f32 / pixels.1 as f32,
+        )
+    }
+
+    // A mip level can be used as long as its texels don't cover pixels of two slots and aren't bigger than a texture,
+    // with the default gutter of a quarter of a 16 pixel texture that gives 4 levels
+    pub fn mip_level_count(&self) -> u32 {
+        let slot = self.slot_pixels();
+        let levels = |slot: u32, tile: u32| slot.trailing_zeros().min(tile.max(1).ilog2());
+        1 + levels(slot.0, self.tile_pixels.0).min(levels(slot.1, self.tile_pixels.1))
+    }
+
+    fn slot_pixels(&self) -> (u32, u32) {
+        (
+            self.tile_pixels.0 + 2 * self.gutter.0,
+            self.tile_pixels.1 + 2 * self.gutter.1,
+        )
+    }
+
+    fn atlas_pixels(&self) -> (u32, u32) {
+        let slot = self.slot_pixels();
+        (self.atlas_size.0 * slot.0, self.atlas_size.1 * slot.1)
     }
 
     pub fn clone_without_image(&self) -> Self {
         Self {
             texture_buffer: ImageBuffer::new(1, 1),
             offset: self.offset.clone(),
+            cutout: self.cutout.clone(),
+            average_colors: self.average_colors.clone(),
+            animations: self.animations.clone(),
+            animation_index: self.animation_index.clone(),
             atlas_size: self.atlas_size,
+            tile_pixels: self.tile_pixels,
+            gutter: self.gutter,
         }
     }
 }
 
-async fn load_image(texture_name: String, texture_folder: &impl AsRef<Path>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let path = texture_folder.as_ref().join(texture_name.clone()).with_extension("png");
+// Size of the array of animation offsets in voxel.wgsl
+pub const MAX_ANIMATED_TEXTURES: usize = 32;
+// Every texture is surrounded by a gutter of its edge pixels this many times smaller than it. Without it smaller mip
+// levels would blend neighbouring textures of the atlas together, at the cost of some unused atlas space
+const GUTTER_DIVISOR: u32 = 4;
+// Textures that fail to load are replaced by a checkerboard, which is used at this size when no texture loaded at all
+const MISSING_TEXTURE_SIZE: (u32, u32) = (16, 16);
+
+// Pixels that are fully transparent are left out, so cutout textures aren't darkened by them
+fn average_color(image: &RgbaImage) -> [u8; 3] {
+    let (sum, count) = image
+        .pixels()
+        .filter(|pixel| pixel.0[3] > 0)
+        .fold(([0u64; 3], 0u64), |(sum, count), pixel| {
+            ([0, 1, 2].map(|i| sum[i] + pixel.0[i] as u64), count + 1)
+        });
+    sum.map(|channel| (channel / count.max(1)) as u8)
+}
+
+fn missing_texture(width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(width, height, |x, y| {
+        if (x * 2 / width == 0) == (y * 2 / height == 0) {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
 
-    let bytes =
-        load_resource_binary(&path).unwrap_or_else(|_| panic!("Failed to load texture: {texture_name:?} - {path:?}"));
+async fn load_image(texture_name: String, texture_folder: &impl AsRef<Path>) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let bytes = if is_url(&texture_name) {
+        load_url_binary(&texture_name).await
+    } else {
+        let path = texture_folder.as_ref().join(texture_name.clone()).with_extension("png");
+        load_resource_binary(&path).map_err(|_| log::error!("Failed to load texture: {texture_name:?} - {path:?}"))
+    }
+    .ok()?;
 
-    image::load_from_memory(&bytes)
-        .unwrap_or_else(|_| panic!("Failed to parse {texture_name:?} - {path:?} as image"))
-        .to_rgb8()
+    match image::load_from_memory(&bytes) {
+        Ok(image) => Some(image.to_rgba8()),
+        Err(e) => {
+            log::error!("Failed to parse {texture_name:?} as image - {e:?}");
+            None
+        }
+    }
 }
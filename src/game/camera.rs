@@ -1,4 +1,4 @@
-use std::{f32::consts::FRAC_PI_2, num::NonZeroI32};
+use std::{f32::consts::FRAC_PI_2, num::NonZeroI32, ops::RangeInclusive};
 
 use cgmath::{perspective, Angle, Deg, InnerSpace, Matrix4, Rad, Vector2, Vector3};
 use instant::Duration;
@@ -10,7 +10,7 @@ use crate::{
         move_pos,
         world::{Terrain, CHUNK_SIZE},
     },
-    misc::{pos::Pos, Settings},
+    misc::{pos::Pos, Keybinds, Settings},
 };
 
 #[rustfmt::skip]
@@ -21,6 +21,15 @@ use crate::{
     0.0, 0.0, 0.5, 1.0,
 );
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
+// Mouse movement past the deadzone, in counts per event, that an acceleration of 1.0 doubles
+const LOOK_ACCELERATION_REFERENCE: f32 = 20.0;
+// In blocks per second (squared), a jump reaches a bit over a block high
+const GRAVITY: f32 = 25.0;
+const JUMP_VELOCITY: f32 = 8.0;
+const TERMINAL_VELOCITY: f32 = 50.0;
+// Each step of the scroll wheel multiplies or divides the fly speed by FLY_SPEED_STEP
+const FLY_SPEED_MULTIPLIER_RANGE: RangeInclusive<f32> = 0.125..=8.0;
+const FLY_SPEED_STEP: f32 = 1.25;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Camera {
@@ -133,6 +142,14 @@ impl crate::engine::camera::Projection for Projection {
     fn set_vfov(&mut self, val: Rad<f32>, display_size: Vector2<u32>) {
         *self = Self::new(display_size, val, self.znear, self.zfar);
     }
+
+    fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    fn zfar(&self) -> f32 {
+        self.zfar
+    }
 }
 
 impl Default for Projection {
@@ -141,7 +158,7 @@ impl Default for Projection {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct CameraController {
     amount_left: f32,
     amount_right: f32,
@@ -151,6 +168,14 @@ pub struct CameraController {
     amount_down: f32,
     rotate_horizontal: f32,
     rotate_vertical: f32,
+    sprinting: bool,
+    fly_speed_multiplier: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraController {
@@ -164,37 +189,54 @@ impl CameraController {
             amount_down: 0.0,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
+            sprinting: false,
+            fly_speed_multiplier: 1.0,
         }
     }
 
-    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+    pub fn sprinting(&self) -> bool {
+        self.sprinting
+    }
+
+    // Positive steps speed up, returns the new multiplier
+    pub fn change_fly_speed(&mut self, steps: i32) -> f32 {
+        self.fly_speed_multiplier = (self.fly_speed_multiplier * FLY_SPEED_STEP.powi(steps))
+            .clamp(*FLY_SPEED_MULTIPLIER_RANGE.start(), *FLY_SPEED_MULTIPLIER_RANGE.end());
+        self.fly_speed_multiplier
+    }
+
+    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState, keybinds: &Keybinds) -> bool {
         let amount = if state == ElementState::Pressed { 1.0 } else { 0.0 };
 
         match key {
-            VirtualKeyCode::W | VirtualKeyCode::Up => {
+            key if key == keybinds.forward || key == VirtualKeyCode::Up => {
                 self.amount_forward = amount;
                 true
             }
-            VirtualKeyCode::S | VirtualKeyCode::Down => {
+            key if key == keybinds.back || key == VirtualKeyCode::Down => {
                 self.amount_backward = amount;
                 true
             }
-            VirtualKeyCode::A | VirtualKeyCode::Left => {
+            key if key == keybinds.left || key == VirtualKeyCode::Left => {
                 self.amount_left = amount;
                 true
             }
-            VirtualKeyCode::D | VirtualKeyCode::Right => {
+            key if key == keybinds.right || key == VirtualKeyCode::Right => {
                 self.amount_right = amount;
                 true
             }
-            VirtualKeyCode::Space | VirtualKeyCode::K => {
+            key if key == keybinds.up || key == VirtualKeyCode::K => {
                 self.amount_up = amount;
                 true
             }
-            VirtualKeyCode::LShift | VirtualKeyCode::J => {
+            key if key == keybinds.down || key == VirtualKeyCode::J => {
                 self.amount_down = amount;
                 true
             }
+            key if key == keybinds.sprint => {
+                self.sprinting = state == ElementState::Pressed;
+                true
+            }
             _ => false,
         }
     }
@@ -204,20 +246,78 @@ impl CameraController {
         self.rotate_vertical = mouse_dy as f32;
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration, terrain: &mut Terrain, settings: &Settings) {
+    // Given a vertical velocity the camera walks, falling with gravity instead of moving up and down, and moving up
+    // jumps while it stands on something. Without collision there is nothing to stand on, so it flies anyway
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        dt: Duration,
+        terrain: &mut Terrain,
+        settings: &Settings,
+        vertical_velocity: Option<&mut f32>,
+    ) {
         let dt = dt.as_secs_f32();
-
-        let motion = self.motion_amount(camera, settings.camera_speed * dt);
-        if settings.collision {
-            camera.pos = move_pos(camera.pos, motion, terrain)
-        } else {
-            camera.pos.in_chunk_pos += motion;
-            camera.pos.check_in_chunk_overflow();
+        let speed = settings.camera_speed
+            * if self.sprinting {
+                settings.sprint_multiplier
+            } else {
+                1.0
+            };
+
+        match vertical_velocity {
+            Some(vertical_velocity) if settings.collision => {
+                // Chunks that aren't loaded yet have nothing to land on
+                *vertical_velocity = if terrain.get_block(&camera.pos).is_some() {
+                    (*vertical_velocity - GRAVITY * dt).max(-TERMINAL_VELOCITY)
+                } else {
+                    0.0
+                };
+
+                let mut motion = self.motion_amount(camera, speed * dt);
+                motion.y = *vertical_velocity * dt;
+                let pos = move_pos(
+                    camera.pos,
+                    motion,
+                    settings.player_half_extent.into(),
+                    terrain,
+                    settings.auto_jump,
+                );
+
+                // Landing on or bumping into a block stops the fall or the jump
+                let moved_y = (pos.abs_pos().y - camera.pos.abs_pos().y) as f32;
+                if (moved_y - motion.y).abs() > 0.0001 {
+                    let grounded = motion.y < 0.0;
+                    *vertical_velocity = if grounded && self.amount_up > 0.0 {
+                        JUMP_VELOCITY
+                    } else {
+                        0.0
+                    };
+                }
+                camera.pos = pos;
+            }
+            _ => {
+                let speed = speed * self.fly_speed_multiplier;
+                let motion = self.motion_amount(camera, speed * dt)
+                    + Vector3::new(0.0, 1.0, 0.0) * (self.amount_up - self.amount_down) * speed * dt;
+                if settings.collision {
+                    camera.pos = move_pos(camera.pos, motion, settings.player_half_extent.into(), terrain, false)
+                } else {
+                    camera.pos.in_chunk_pos += motion;
+                    camera.pos.check_in_chunk_overflow();
+                }
+            }
         }
 
-        camera.yaw += Rad(self.rotate_horizontal) * settings.camera_sensitivity * dt;
+        let (rotate_horizontal, rotate_vertical) = look_curve(
+            self.rotate_horizontal,
+            self.rotate_vertical,
+            settings.look_deadzone,
+            settings.look_acceleration,
+        );
 
-        camera.pitch += Rad(-self.rotate_vertical) * settings.camera_sensitivity * dt;
+        camera.yaw += Rad(rotate_horizontal) * settings.camera_sensitivity * dt;
+
+        camera.pitch += Rad(-rotate_vertical) * settings.camera_sensitivity * dt;
 
         self.rotate_horizontal = 0.0;
         self.rotate_vertical = 0.0;
@@ -230,10 +330,22 @@ impl CameraController {
         camera.yaw = camera.yaw.normalize_signed()
     }
 
+    // Only the horizontal part, moving up and down depends on whether the camera is walking
     fn motion_amount(&mut self, camera: &mut Camera, by: f32) -> Vector3<f32> {
         ((camera.forward_vec_xz() * (self.amount_forward - self.amount_backward))
-            + (camera.right_vec() * (self.amount_right - self.amount_left))
-            + (Vector3::new(0.0, 1.0, 0.0) * (self.amount_up - self.amount_down)))
+            + (camera.right_vec() * (self.amount_right - self.amount_left)))
             * by
     }
 }
+
+// Drops movements inside the deadzone and speeds up fast ones, keeping the direction of the movement
+fn look_curve(dx: f32, dy: f32, deadzone: f32, acceleration: f32) -> (f32, f32) {
+    let magnitude = dx.hypot(dy);
+    if magnitude <= deadzone {
+        return (0.0, 0.0);
+    }
+
+    let past_deadzone = magnitude - deadzone;
+    let scale = past_deadzone * (1.0 + acceleration.max(0.0) * past_deadzone / LOOK_ACCELERATION_REFERENCE) / magnitude;
+    (dx * scale, dy * scale)
+}
@@ -0,0 +1,11 @@
+use crate::{game::world::Block, misc::pos::Pos};
+
+// Changes to the world that systems outside of it, like an inventory, can react to through `State::add_block_listener`
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum BlockEvent {
+    // The player broke `block` at `pos`, it could be turned into a dropped item
+    Broken { block: Block, pos: Pos },
+}
+
+pub type BlockListener = Box<dyn FnMut(&BlockEvent)>;
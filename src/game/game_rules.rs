@@ -0,0 +1,49 @@
+use std::num::NonZeroI32;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game::world::CHUNK_SIZE, misc::pos::Pos};
+
+// Per-world rules, stored in the save next to the seed, unlike Settings which belong to the client
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameRules {
+    pub allow_flight: bool,
+    pub gravity: bool,
+    // Stops the time of day from moving in the day night cycle
+    pub time_paused: bool,
+    // Distance from the origin in chunks the player can not move past horizontally
+    pub world_border: Option<u32>,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            allow_flight: true,
+            gravity: false,
+            time_paused: false,
+            world_border: None,
+        }
+    }
+}
+
+impl GameRules {
+    pub fn clamp_to_world_border(&self, pos: &mut Pos) {
+        if let Some(world_border) = self.world_border {
+            let world_border = world_border.clamp(1, i32::MAX as u32) as i32;
+
+            clamp_axis(&mut pos.chunk_pos.x, &mut pos.in_chunk_pos.x, world_border);
+            clamp_axis(&mut pos.chunk_pos.z, &mut pos.in_chunk_pos.z, world_border);
+        }
+    }
+}
+
+fn clamp_axis(chunk_pos: &mut NonZeroI32, in_chunk_pos: &mut f32, world_border: i32) {
+    if chunk_pos.get() > world_border {
+        *chunk_pos = NonZeroI32::new(world_border).unwrap();
+        *in_chunk_pos = CHUNK_SIZE as f32 - 0.01;
+    } else if chunk_pos.get() < -world_border {
+        *chunk_pos = NonZeroI32::new(-world_border).unwrap();
+        *in_chunk_pos = 0.0;
+    }
+}
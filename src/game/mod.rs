@@ -1,10 +1,14 @@
 mod camera;
+mod event;
+mod game_rules;
 mod player;
 mod ray;
 mod state;
+pub mod time_of_day;
 pub mod world;
 
 pub use camera::{Camera, CameraController, Projection};
-pub use player::Player;
-pub use ray::move_pos;
+pub use game_rules::GameRules;
+pub use player::{Player, DEFAULT_PLAYER_REACH, HOTBAR_SLOTS};
+pub use ray::{move_pos, DEFAULT_MAX_RAY_STEPS};
 pub use state::State;
@@ -1,26 +1,48 @@
-use std::num::NonZeroI32;
+use std::{fmt, num::NonZeroI32};
 
 use instant::{Duration, Instant};
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use winit::event::{ElementState, VirtualKeyCode};
 
 use crate::{
     game::{
         world::{Block, BlockManager, Terrain},
-        Camera, CameraController,
+        Camera, CameraController, GameRules,
     },
-    misc::Settings,
+    misc::{pos::Pos, Keybinds, Settings},
 };
 
-pub const PLAYER_REACH: f32 = 20.0;
+pub const DEFAULT_PLAYER_REACH: f32 = 20.0;
 pub const BLOCK_UPDATE_MIN_DELAY: f64 = 0.05;
+pub const HOTBAR_SLOTS: usize = 9;
+
+// Walking falls with gravity and jumps, flying moves freely up and down like a spectator
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhysicsMode {
+    #[default]
+    Fly,
+    Walk,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Player {
-    pub selected_block: Block,
+    #[serde(alias = "selected_block", deserialize_with = "deserialize_hotbar")]
+    pub hotbar: [Block; HOTBAR_SLOTS],
+    #[serde(default)]
+    pub active_slot: usize,
+    #[serde(default)]
+    pub palette: Vec<Block>,
     pub camera: Camera,
+    #[serde(default)]
+    pub physics_mode: PhysicsMode,
     #[serde(skip)]
     pub camera_controller: CameraController,
+    // In blocks per second, only used while walking
+    #[serde(skip)]
+    vertical_velocity: f32,
     #[serde(skip)]
     last_block_update_time: Option<Instant>,
 }
@@ -28,12 +50,19 @@ pub struct Player {
 impl Player {
     pub fn new(block_manager: &BlockManager) -> Self {
         Self {
-            selected_block: Block::new(
-                block_manager.all_rendered_block_names()[0].as_str(),
-                block_manager,
-                None,
-                false,
-            ),
+            hotbar: {
+                let block_names = block_manager.all_rendered_block_names();
+                std::array::from_fn(|slot| {
+                    Block::new(
+                        block_names[slot % block_names.len()].as_str(),
+                        block_manager,
+                        None,
+                        false,
+                    )
+                })
+            },
+            active_slot: 0,
+            palette: Vec::new(),
             camera: {
                 Camera::new(
                     (
@@ -45,26 +74,70 @@ impl Player {
                     cgmath::Deg(0.0),
                 )
             },
+            physics_mode: PhysicsMode::default(),
             camera_controller: CameraController::new(),
+            vertical_velocity: 0.0,
             last_block_update_time: None,
         }
     }
 
-    pub fn update(&mut self, dt: Duration, terrain: &mut Terrain, settings: &Settings) {
-        self.camera_controller
-            .update_camera(&mut self.camera, dt, terrain, settings);
+    pub fn update(&mut self, dt: Duration, terrain: &mut Terrain, settings: &Settings, game_rules: &GameRules) {
+        let walking = self.is_walking(game_rules);
+        self.camera_controller.update_camera(
+            &mut self.camera,
+            dt,
+            terrain,
+            settings,
+            walking.then_some(&mut self.vertical_velocity),
+        );
+        game_rules.clamp_to_world_border(&mut self.camera.pos);
+    }
+
+    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState, keybinds: &Keybinds) -> bool {
+        self.camera_controller.process_keyboard(key, state, keybinds)
+    }
+
+    // Without flight allowed, or with gravity on, the player always walks
+    pub fn is_walking(&self, game_rules: &GameRules) -> bool {
+        self.physics_mode == PhysicsMode::Walk || !game_rules.allow_flight || game_rules.gravity
     }
 
-    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
-        self.camera_controller.process_keyboard(key, state)
+    pub fn toggle_physics_mode(&mut self) {
+        self.physics_mode = match self.physics_mode {
+            PhysicsMode::Fly => PhysicsMode::Walk,
+            PhysicsMode::Walk => PhysicsMode::Fly,
+        };
+        self.vertical_velocity = 0.0;
     }
 
     pub fn input_mouse(&mut self, delta: (f64, f64)) {
         self.camera_controller.process_mouse(delta.0, delta.1)
     }
 
+    pub fn teleport(&mut self, pos: Pos) {
+        self.camera.pos = pos;
+        self.camera.pos.check_in_chunk_overflow();
+    }
+
+    pub fn selected_block(&self) -> &Block {
+        &self.hotbar[self.active_slot % HOTBAR_SLOTS]
+    }
+
     pub fn selected_block_mut(&mut self) -> &mut Block {
-        &mut self.selected_block
+        &mut self.hotbar[self.active_slot % HOTBAR_SLOTS]
+    }
+
+    pub fn select_slot(&mut self, slot: usize) {
+        self.active_slot = slot % HOTBAR_SLOTS
+    }
+
+    // Positive steps move to the right, wrapping around at either end
+    pub fn scroll_slot(&mut self, steps: i32) {
+        self.active_slot = (self.active_slot as i32 + steps).rem_euclid(HOTBAR_SLOTS as i32) as usize
+    }
+
+    pub fn palette_mut(&mut self) -> &mut Vec<Block> {
+        &mut self.palette
     }
 
     pub fn set_last_block_update_time(&mut self) {
@@ -79,3 +152,59 @@ impl Player {
         }
     }
 }
+
+// Saves from before the hotbar only had the one selected block, which ends up in every slot
+fn deserialize_hotbar<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[Block; HOTBAR_SLOTS], D::Error> {
+    struct HotbarVisitor;
+
+    impl<'de> Visitor<'de> for HotbarVisitor {
+        type Value = [Block; HOTBAR_SLOTS];
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a list of {HOTBAR_SLOTS} blocks or a single block")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut hotbar = Vec::with_capacity(HOTBAR_SLOTS);
+            while let Some(block) = seq.next_element()? {
+                hotbar.push(block);
+            }
+            hotbar
+                .try_into()
+                .map_err(|hotbar: Vec<Block>| de::Error::invalid_length(hotbar.len(), &self))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            let block = Block::deserialize(de::value::MapAccessDeserializer::new(map))?;
+            Ok(std::array::from_fn(|_| block.clone()))
+        }
+    }
+
+    deserializer.deserialize_any(HotbarVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gravity_and_no_flight_force_walking() {
+        let mut player = Player::new(&BlockManager::new());
+        assert_eq!(player.physics_mode, PhysicsMode::Fly);
+        assert!(!player.is_walking(&GameRules::default()));
+
+        let no_flight = GameRules {
+            allow_flight: false,
+            ..Default::default()
+        };
+        let gravity = GameRules {
+            gravity: true,
+            ..Default::default()
+        };
+        assert!(player.is_walking(&no_flight));
+        assert!(player.is_walking(&gravity));
+
+        player.toggle_physics_mode();
+        assert!(player.is_walking(&GameRules::default()));
+    }
+}
@@ -1,11 +1,16 @@
-use cgmath::{InnerSpace, Vector3};
+use cgmath::{InnerSpace, Rad, Vector3};
 
 use crate::{game::world::Terrain, misc::pos::Pos};
 
+// Plenty for any ray a player can aim, a ray crosses at most three block boundaries per block of length
+pub const DEFAULT_MAX_RAY_STEPS: u32 = 1024;
+
 pub struct Ray {
     from: Pos,
     dir: Vector3<f32>,
     length: f32,
+    // Block boundaries the ray crosses before giving up, bounds the cost of very long rays
+    max_steps: u32,
 }
 
 impl Ray {
@@ -14,25 +19,48 @@ impl Ray {
             from,
             dir: dir.normalize(),
             length: length.unwrap_or(1.0),
+            max_steps: DEFAULT_MAX_RAY_STEPS,
         }
     }
 
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
     pub fn intersect(&self, terrain: &mut Terrain) -> Option<(Pos, Option<Pos>, Pos)> {
         let mut out = None;
         let mut current_pos = self.from;
         let mut last_pos = None::<Pos>;
 
-        voxel_raycast(
+        let finished = voxel_raycast(
             self.from.in_chunk_pos_f32(),
             self.dir,
             self.length,
-            |index, intersect_pos, _| {
-                last_pos = Some(current_pos);
+            self.max_steps,
+            |index, intersect_pos, hit_norm| {
+                let previous_pos = current_pos;
 
                 current_pos.chunk_pos = self.from.chunk_pos;
                 current_pos.in_chunk_pos = Vector3::new(index.x as f32, index.y as f32, index.z as f32);
                 current_pos.check_in_chunk_overflow();
 
+                // The block in front of the face the ray entered through, which isn't always the previously visited
+                // one when the ray passes exactly through an edge or corner
+                last_pos = Some(if hit_norm == Vector3::new(0, 0, 0) {
+                    previous_pos
+                } else {
+                    let mut pos_tmp = current_pos;
+                    pos_tmp.in_chunk_pos += hit_norm.map(|val| val as f32);
+                    pos_tmp.check_in_chunk_overflow();
+                    pos_tmp
+                });
+
                 let mut done = false;
 
                 if let Some(block) = terrain.get_block(&current_pos) {
@@ -54,73 +82,195 @@ impl Ray {
             },
         );
 
-        out
+        if finished {
+            out
+        } else {
+            log::debug!("Ray gave up after {} steps", self.max_steps);
+            None
+        }
+    }
+
+    // When the center ray misses, tries rays in rings around it up to cone_angle and returns the first hit, inner rings
+    // first so the target stays as close to the crosshair as possible
+    pub fn intersect_with_assist(
+        &self,
+        terrain: &mut Terrain,
+        cone_angle: Rad<f32>,
+    ) -> Option<(Pos, Option<Pos>, Pos)> {
+        const RINGS: u32 = 2;
+        const RAYS_PER_RING: u32 = 8;
+
+        if let Some(out) = self.intersect(terrain) {
+            return Some(out);
+        }
+        if cone_angle.0 <= 0.0 {
+            return None;
+        }
+
+        let right = {
+            let right = self.dir.cross(Vector3::unit_y());
+            if right.magnitude2() > f32::EPSILON {
+                right.normalize()
+            } else {
+                Vector3::unit_x()
+            }
+        };
+        let up = right.cross(self.dir).normalize();
+
+        for ring in 1..=RINGS {
+            let angle = cone_angle.0 * ring as f32 / RINGS as f32;
+
+            for ray_index in 0..RAYS_PER_RING {
+                let around = std::f32::consts::TAU * ray_index as f32 / RAYS_PER_RING as f32;
+                let dir = self.dir * angle.cos() + (right * around.cos() + up * around.sin()) * angle.sin();
+
+                if let Some(out) = Ray::new(self.from, dir, Some(self.length))
+                    .with_max_steps(self.max_steps)
+                    .intersect(terrain)
+                {
+                    return Some(out);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// Moves a box with the given half extent around pos, stopping it MIN_DISTANCE_FROM_BLOCK short of the solid part of any
+// block in the way. With `auto_jump`, moving into a horizontal obstacle one block high steps on top of it, if there is
+// room above
+pub fn move_pos(
+    pos: Pos,
+    motion: Vector3<f32>,
+    half_extent: Vector3<f32>,
+    terrain: &mut Terrain,
+    auto_jump: bool,
+) -> Pos {
+    let mut block_box = |index: Vector3<i32>| {
+        let mut block_pos = pos;
+        block_pos.in_chunk_pos = index.map(|val| val as f32);
+        block_pos.check_in_chunk_overflow();
+
+        let corner = index.map(|val| val as f32);
+        terrain
+            .get_block(&block_pos)
+            .and_then(|block| block.collision_box())
+            .map(|(min, max)| (corner + min, corner + max))
+    };
+
+    let center = pos.in_chunk_pos;
+    let (mut offset, blocked_horizontally) = sweep_box(center, half_extent, motion, &mut block_box);
+
+    if auto_jump && blocked_horizontally {
+        let step = Vector3::new(0.0, 1.0, 0.0);
+        if sweep_box(center, half_extent, step, &mut block_box).0 == step {
+            let (stepped_offset, stepped_blocked) = sweep_box(center + step, half_extent, motion, &mut block_box);
+            if !stepped_blocked {
+                offset = step + stepped_offset;
+            }
+        }
     }
+
+    let mut pos_out = pos;
+    pos_out.in_chunk_pos += offset;
+    pos_out.check_in_chunk_overflow();
+    pos_out
 }
 
-pub fn move_pos(pos: Pos, motion: Vector3<f32>, terrain: &mut Terrain) -> Pos {
-    const MIN_DISTANCE_FROM_BLOCK: f32 = 0.2;
+// Whether the box with the given half extent around pos overlaps the full block at block_pos, touching it doesn't count
+pub fn box_overlaps_block(pos: Pos, half_extent: Vector3<f32>, block_pos: &Pos) -> bool {
+    let center = pos.abs_pos();
+    let block_min = block_pos.abs_block_pos().map(|val| val as f64);
+    (0..3).all(|a| {
+        let (min, max) = (center[a] - half_extent[a] as f64, center[a] + half_extent[a] as f64);
+        block_min[a] < max && block_min[a] + 1.0 > min
+    })
+}
+
+// Moves the box along one axis after the other, y first so walking along the ground doesn't catch on it. On each axis
+// every block the box overlaps on the other two is tested, from its leading face to where the motion ends, so fast
+// motion can't skip over thin walls. Blocks the box is already inside of are ignored, so it can always move out of
+// them. Returns the motion that is left, and whether it was cut short on a horizontal axis
+fn sweep_box(
+    center: Vector3<f32>,
+    half_extent: Vector3<f32>,
+    motion: Vector3<f32>,
+    mut block_box: impl FnMut(Vector3<i32>) -> Option<(Vector3<f32>, Vector3<f32>)>,
+) -> (Vector3<f32>, bool) {
+    const MIN_DISTANCE_FROM_BLOCK: f32 = 0.01;
 
-    let min_motion = motion.normalize_to(MIN_DISTANCE_FROM_BLOCK);
+    let mut offset = Vector3::new(0.0, 0.0, 0.0);
+    let mut blocked_horizontally = false;
 
-    let mut pos_out = pos.clone();
-    pos_out.in_chunk_pos += Vector3::new(0, 1, 2).map(|idx: usize| {
-        let mut offset = motion[idx];
+    for axis in [1, 0, 2] {
+        let distance = motion[axis];
+        if distance == 0.0 || !distance.is_finite() {
+            continue;
+        }
 
-        let trying_pos = {
-            let mut pos_tmp = pos.clone();
-            pos_tmp.in_chunk_pos[idx] += offset;
-            pos_tmp.check_in_chunk_overflow();
-            pos_tmp
+        let (min, max) = (center - half_extent + offset, center + half_extent + offset);
+        let (lead, reach) = if distance > 0.0 {
+            (max[axis], max[axis] + distance + MIN_DISTANCE_FROM_BLOCK)
+        } else {
+            (min[axis], min[axis] + distance - MIN_DISTANCE_FROM_BLOCK)
         };
+        let [first, second] = [(axis + 1) % 3, (axis + 2) % 3];
 
-        if let Some(block) = terrain.get_block(&trying_pos) {
-            if block.is_solid() {
-                offset = min_motion[idx];
-                let trying_pos = {
-                    let mut pos_tmp = pos.clone();
-                    pos_tmp.in_chunk_pos[idx] += offset;
-                    pos_tmp.check_in_chunk_overflow();
-                    pos_tmp
-                };
+        let mut allowed = distance;
+        for along in (lead.min(reach).floor() as i32 - 1)..=(lead.max(reach).floor() as i32) {
+            for first_index in (min[first].floor() as i32)..=(max[first].floor() as i32) {
+                for second_index in (min[second].floor() as i32)..=(max[second].floor() as i32) {
+                    let mut index = Vector3::new(0, 0, 0);
+                    index[axis] = along;
+                    index[first] = first_index;
+                    index[second] = second_index;
+
+                    let Some((block_min, block_max)) = block_box(index) else {
+                        continue;
+                    };
+                    let overlaps = |other: usize| block_min[other] < max[other] && block_max[other] > min[other];
+                    if !overlaps(first) || !overlaps(second) {
+                        continue;
+                    }
 
-                if let Some(block) = terrain.get_block(&trying_pos) {
-                    if block.is_solid() {
-                        offset = 0.0
+                    if distance > 0.0 && block_min[axis] >= lead {
+                        allowed = allowed.min((block_min[axis] - lead - MIN_DISTANCE_FROM_BLOCK).max(0.0));
+                    } else if distance < 0.0 && block_max[axis] <= lead {
+                        allowed = allowed.max((block_max[axis] - lead + MIN_DISTANCE_FROM_BLOCK).min(0.0));
                     }
                 }
             }
         }
 
-        if offset.is_finite() {
-            offset
-        } else {
-            0.0
-        }
-    });
-    pos_out.check_in_chunk_overflow();
+        blocked_horizontally |= axis != 1 && allowed != distance;
+        offset[axis] = allowed;
+    }
 
-    pos_out
+    (offset, blocked_horizontally)
+}
+
+// Boundaries crossed at the same time, within rounding, are stepped over together
+const TIE_EPSILON: f32 = 1e-5;
+
+// Bitmask of the axes whose next boundary is the closest one
+fn voxel_stepped_axes(t_max: Vector3<f32>) -> u8 {
+    let t_min = t_max.x.min(t_max.y).min(t_max.z);
+    (0..3)
+        .filter(|axis| t_max[*axis] - t_min <= TIE_EPSILON * t_min.max(1.0))
+        .fold(0, |axes, axis| axes | (1 << axis))
 }
 
+// Returns false if the ray crossed max_steps block boundaries before func stopped it or it reached max_dir
 fn voxel_raycast(
     origin: Vector3<f32>,
     dir: Vector3<f32>,
     max_dir: f32,
+    max_steps: u32,
     mut func: impl FnMut(Vector3<i32>, Vector3<f32>, Vector3<i32>) -> bool,
-) {
+) -> bool {
     // Based on https://docs.rs/voxel-tile-raycast/latest/voxel_tile_raycast/fn.tile_raycast.html
 
-    fn voxel_stepped_index(t_max: Vector3<f32>) -> usize {
-        if t_max.x < t_max.y && t_max.x < t_max.z {
-            0
-        } else if t_max.y < t_max.z {
-            1
-        } else {
-            2
-        }
-    }
-
     let dir = dir.normalize();
     let mut t = 0.0;
     let mut index = origin.map(|val| val.floor() as i32);
@@ -145,18 +295,303 @@ fn voxel_raycast(
         (Vector3::new(0, 1, 2)).map(|val| origin[val] + t * dir[val]),
         Vector3::new(0, 0, 0),
     ) {
-        while t < max_dir {
-            let stepped_index = voxel_stepped_index(t_max);
-            index[stepped_index] += step[stepped_index];
-            t = t_max[stepped_index];
-            t_max[stepped_index] += t_delta[stepped_index];
-            if func(index, (Vector3::new(0, 1, 2)).map(|val| origin[val] + t * dir[val]), {
+        let mut steps = 0;
+        'outer: while t < max_dir {
+            if steps == max_steps {
+                return false;
+            }
+            steps += 1;
+
+            let stepped_axes = voxel_stepped_axes(t_max);
+            t = (0..3)
+                .filter(|axis| stepped_axes & (1 << axis) != 0)
+                .map(|axis| t_max[axis])
+                .fold(f32::INFINITY, f32::min);
+            let intersect_pos = (Vector3::new(0, 1, 2)).map(|val| origin[val] + t * dir[val]);
+
+            // A ray through an edge or corner touches every block around it, they are visited fewest steps first and
+            // then in x, y, z order, so the same ray always targets the same block
+            let mut subsets = (1..=stepped_axes)
+                .filter(|subset| subset & !stepped_axes == 0)
+                .collect::<Vec<_>>();
+            subsets.sort_by_key(|subset| (subset.count_ones(), *subset));
+
+            for subset in subsets {
+                let mut subset_index = index;
                 let mut hit_norm = Vector3::new(0, 0, 0);
-                hit_norm[stepped_index] = -step[stepped_index];
-                hit_norm
-            }) {
-                break;
+                for axis in (0..3).rev().filter(|axis| subset & (1 << axis) != 0) {
+                    subset_index[axis] += step[axis];
+                    hit_norm = Vector3::new(0, 0, 0);
+                    hit_norm[axis] = -step[axis];
+                }
+
+                if func(subset_index, intersect_pos, hit_norm) {
+                    break 'outer;
+                }
+            }
+
+            for axis in (0..3).filter(|axis| stepped_axes & (1 << axis) != 0) {
+                index[axis] += step[axis];
+                t_max[axis] += t_delta[axis];
             }
         }
     }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Blocks the ray goes through with the normal of the face it entered them through, up to the first one it enters at
+    // or past max_dir
+    fn visited(origin: [f32; 3], dir: [f32; 3], max_dir: f32) -> Vec<([i32; 3], [i32; 3])> {
+        let mut out = Vec::new();
+        assert!(voxel_raycast(
+            origin.into(),
+            dir.into(),
+            max_dir,
+            DEFAULT_MAX_RAY_STEPS,
+            |index, _, hit_norm| {
+                out.push((index.into(), hit_norm.into()));
+                false
+            }
+        ));
+        out
+    }
+
+    fn blocks(visited: Vec<([i32; 3], [i32; 3])>) -> Vec<[i32; 3]> {
+        visited.into_iter().map(|(index, _)| index).collect()
+    }
+
+    #[test]
+    fn stepped_axes_ties_within_epsilon() {
+        assert_eq!(voxel_stepped_axes(Vector3::new(1.0, 2.0, 3.0)), 0b001);
+        assert_eq!(voxel_stepped_axes(Vector3::new(2.0, 0.5, f32::INFINITY)), 0b010);
+        assert_eq!(
+            voxel_stepped_axes(Vector3::new(1.0, 1.0 + TIE_EPSILON / 2.0, 3.0)),
+            0b011
+        );
+        assert_eq!(
+            voxel_stepped_axes(Vector3::new(1.0, 1.0 + TIE_EPSILON * 2.0, 3.0)),
+            0b001
+        );
+        assert_eq!(voxel_stepped_axes(Vector3::new(0.7, 0.7, 0.7)), 0b111);
+        // Far along the ray the epsilon grows with the distance, as rounding errors do
+        assert_eq!(voxel_stepped_axes(Vector3::new(1000.0, f32::INFINITY, 1000.005)), 0b101);
+        assert_eq!(voxel_stepped_axes(Vector3::new(1000.0, f32::INFINITY, 1000.02)), 0b001);
+    }
+
+    #[test]
+    fn axis_aligned_rays_visit_a_line() {
+        assert_eq!(
+            visited([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 2.5),
+            [
+                ([0, 0, 0], [0, 0, 0]),
+                ([1, 0, 0], [-1, 0, 0]),
+                ([2, 0, 0], [-1, 0, 0]),
+                ([3, 0, 0], [-1, 0, 0]),
+            ]
+        );
+        assert_eq!(
+            visited([0.5, 0.5, 0.5], [0.0, -1.0, 0.0], 1.5),
+            [([0, 0, 0], [0, 0, 0]), ([0, -1, 0], [0, 1, 0]), ([0, -2, 0], [0, 1, 0])]
+        );
+        assert_eq!(
+            blocks(visited([-3.25, 7.5, 2.75], [0.0, 0.0, -1.0], 2.0)),
+            [[-4, 7, 2], [-4, 7, 1], [-4, 7, 0], [-4, 7, -1]]
+        );
+    }
+
+    #[test]
+    fn rays_through_edges_visit_every_block_around_them() {
+        // Single axis steps first, x before y before z, then both
+        assert_eq!(
+            visited([0.5, 0.5, 0.5], [1.0, 1.0, 0.0], 2.0),
+            [
+                ([0, 0, 0], [0, 0, 0]),
+                ([1, 0, 0], [-1, 0, 0]),
+                ([0, 1, 0], [0, -1, 0]),
+                ([1, 1, 0], [-1, 0, 0]),
+                ([2, 1, 0], [-1, 0, 0]),
+                ([1, 2, 0], [0, -1, 0]),
+                ([2, 2, 0], [-1, 0, 0]),
+            ]
+        );
+        assert_eq!(
+            blocks(visited([0.5, 0.5, 0.5], [0.0, -1.0, 1.0], 0.5)),
+            [[0, 0, 0], [0, -1, 0], [0, 0, 1], [0, -1, 1]]
+        );
+    }
+
+    #[test]
+    fn rays_through_corners_visit_every_block_around_them() {
+        assert_eq!(
+            blocks(visited([0.5, 0.5, 0.5], [1.0, 1.0, 1.0], 0.5)),
+            [
+                [0, 0, 0],
+                [1, 0, 0],
+                [0, 1, 0],
+                [0, 0, 1],
+                [1, 1, 0],
+                [1, 0, 1],
+                [0, 1, 1],
+                [1, 1, 1],
+            ]
+        );
+        assert_eq!(
+            blocks(visited([0.5, 0.5, 0.5], [-1.0, 1.0, -1.0], 0.5)),
+            [
+                [0, 0, 0],
+                [-1, 0, 0],
+                [0, 1, 0],
+                [0, 0, -1],
+                [-1, 1, 0],
+                [-1, 0, -1],
+                [0, 1, -1],
+                [-1, 1, -1],
+            ]
+        );
+    }
+
+    #[test]
+    fn long_rays_keep_hitting_edges() {
+        // The ray meets every edge at x = k, y = 2k. Boundaries along x and y are added up separately, so without the
+        // epsilon rounding would split the crossings of an edge into two steps further along the ray
+        let mut expected = vec![[0, 0, 0]];
+        for k in 0..60 {
+            expected.extend([
+                [k, 2 * k + 1, 0],
+                [k + 1, 2 * k + 1, 0],
+                [k, 2 * k + 2, 0],
+                [k + 1, 2 * k + 2, 0],
+            ]);
+        }
+        let visited = blocks(visited([0.0, 0.0, 0.5], [1.0, 2.0, 0.0], 140.0));
+
+        assert_eq!(visited[..expected.len()], expected);
+    }
+
+    const PLAYER_HALF_EXTENT: [f32; 3] = [0.3, 0.8, 0.3];
+
+    // Sweeps the default player box through full blocks where is_solid is true
+    fn sweep(center: [f32; 3], motion: [f32; 3], is_solid: impl Fn([i32; 3]) -> bool) -> ([f32; 3], bool) {
+        let (offset, blocked_horizontally) = sweep_box(
+            center.into(),
+            PLAYER_HALF_EXTENT.into(),
+            motion.into(),
+            |index: Vector3<i32>| {
+                is_solid(index.into()).then(|| {
+                    let corner = index.map(|val| val as f32);
+                    (corner, corner + Vector3::new(1.0, 1.0, 1.0))
+                })
+            },
+        );
+        (offset.into(), blocked_horizontally)
+    }
+
+    fn assert_close(left: [f32; 3], right: [f32; 3]) {
+        assert!(
+            (0..3).all(|axis| (left[axis] - right[axis]).abs() < 1e-4),
+            "{:?} is not {:?}",
+            left,
+            right
+        );
+    }
+
+    #[test]
+    fn box_slides_along_walls_and_lands() {
+        let floor_and_wall = |[x, y, _]: [i32; 3]| y == -1 || x == 2;
+
+        // Falls onto the floor below the box instead of the point at its center
+        let (offset, blocked) = sweep([0.5, 3.0, 0.5], [0.0, -10.0, 0.0], floor_and_wall);
+        assert_close(offset, [0.0, -2.19, 0.0]);
+        assert!(!blocked);
+
+        // Stops short of the wall on x and keeps moving on z
+        let (offset, blocked) = sweep([0.5, 0.81, 0.5], [3.0, 0.0, 2.0], floor_and_wall);
+        assert_close(offset, [1.19, 0.0, 2.0]);
+        assert!(blocked);
+    }
+
+    #[test]
+    fn fast_box_stops_at_thin_walls() {
+        let walls = |[x, _, _]: [i32; 3]| x == 5 || x == -5;
+
+        let (offset, blocked) = sweep([0.5, 0.5, 0.5], [1000.0, 0.0, 0.0], walls);
+        assert_close(offset, [4.19, 0.0, 0.0]);
+        assert!(blocked);
+        let (offset, blocked) = sweep([0.5, 0.5, 0.5], [-1000.0, 0.0, 0.0], walls);
+        assert_close(offset, [-4.19, 0.0, 0.0]);
+        assert!(blocked);
+    }
+
+    #[test]
+    fn box_moves_out_of_blocks_it_is_inside() {
+        let (offset, blocked) = sweep([0.5, 0.5, 0.5], [2.0, 0.0, 0.0], |[x, _, z]| x == 0 && z == 0);
+        assert_close(offset, [2.0, 0.0, 0.0]);
+        assert!(!blocked);
+    }
+
+    #[test]
+    fn box_only_collides_with_blocks_it_overlaps() {
+        let pillar = |[x, _, z]: [i32; 3]| x == 2 && z == 1;
+
+        // The box reaches up to z 0.8 and passes next to the pillar
+        let (offset, blocked) = sweep([0.5, 0.5, 0.5], [5.0, 0.0, 0.0], pillar);
+        assert_close(offset, [5.0, 0.0, 0.0]);
+        assert!(!blocked);
+
+        // Reaching 0.03 into it on z is enough to be stopped
+        let (offset, blocked) = sweep([0.5, 0.5, 0.73], [5.0, 0.0, 0.0], pillar);
+        assert_close(offset, [1.19, 0.0, 0.0]);
+        assert!(blocked);
+    }
+
+    #[test]
+    fn box_lands_on_slabs() {
+        use crate::game::world::{Block, BlockManager, BlockShape};
+
+        let block_manager = BlockManager::new();
+        let mut slab = Block::new_with_default("Stone", &block_manager);
+        *slab.shape_mut() = BlockShape::Slab { top: false };
+        let (min, max) = slab.collision_box().unwrap();
+
+        let (offset, _) = sweep_box(
+            Vector3::new(0.5, 3.0, 0.5),
+            PLAYER_HALF_EXTENT.into(),
+            Vector3::new(0.0, -10.0, 0.0),
+            |index: Vector3<i32>| {
+                (index.y == 0).then(|| (index.map(|val| val as f32) + min, index.map(|val| val as f32) + max))
+            },
+        );
+        assert_close(offset.into(), [0.0, -1.69, 0.0]);
+    }
+
+    #[test]
+    fn placed_blocks_overlap_the_player_box() {
+        let half_extent = Vector3::new(0.25, 0.75, 0.25);
+        let overlaps = |center: [f64; 3], block: [i64; 3]| {
+            box_overlaps_block(
+                Pos::from_abs(center.into()),
+                half_extent,
+                &Pos::from_abs_block(block.into()).unwrap(),
+            )
+        };
+
+        // Feet, head and the block in between
+        for y in 0..3 {
+            assert!(overlaps([0.5, 1.5, 0.5], [0, y, 0]));
+        }
+        assert!(!overlaps([0.5, 1.5, 0.5], [0, 3, 0]));
+        assert!(!overlaps([0.5, 1.5, 0.5], [1, 1, 0]));
+
+        // Standing near the edge of a block reaches into the next one, but touching it doesn't count
+        assert!(overlaps([0.9, 1.5, 0.5], [1, 1, 0]));
+        assert!(!overlaps([0.75, 1.5, 0.5], [1, 1, 0]));
+        assert!(overlaps([0.5, 1.5, -0.1], [0, 1, -1]));
+        assert!(!overlaps([-0.5, 1.5, 0.5], [0, 1, 0]));
+        assert!(overlaps([-0.5, 1.5, 0.5], [-1, 1, 0]));
+    }
 }
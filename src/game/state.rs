@@ -1,48 +1,98 @@
 use std::rc::Rc;
 
+use cgmath::Deg;
+use rustc_hash::FxHashMap;
 use winit::event::*;
 
-use crate::engine::{resource::Draw, TextureAtlas};
+use crate::engine::{resource::Draw, BoxOverlay, CloudLayer, GroundPlane, TextureAtlas};
 
 const PURGE_ENABLED: bool = false;
 // const PURGE_ENABLED: bool = cfg!(not(target_arch = "wasm32"));
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "request_log")]
+use crate::game::world::RequestLog;
 #[cfg(feature = "save_system")]
-use crate::misc::save_helper::{available_saves, load_player, load_u32, save};
+use crate::misc::save_helper::{
+    available_saves, delete_save, last_played_save, load_game_rules, load_generator_kind, load_palette, load_player,
+    load_time_of_day, load_u32, load_world_meta, save, save_has_chunks, LoadError, WorldMeta,
+    UNVERSIONED_FORMAT_VERSION, WORLD_FORMAT_VERSION,
+};
 use crate::{
     game::{
+        event::{BlockEvent, BlockListener},
         player::Player,
         player::BLOCK_UPDATE_MIN_DELAY,
-        player::PLAYER_REACH,
-        ray::Ray,
-        world::{Block, BlockManager, Terrain, TerrainGenerator},
-        Camera,
+        player::DEFAULT_PLAYER_REACH,
+        ray::{box_overlaps_block, Ray, DEFAULT_MAX_RAY_STEPS},
+        time_of_day,
+        world::{Block, BlockManager, Terrain, TerrainConfig, TerrainGenerator, TerrainGeneratorKind},
+        Camera, GameRules,
     },
-    misc::Settings,
+    misc::{pos::Pos, profiling::profile_span, Keybinds, Settings},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    misc::vox::{load_vox, VoxPalette},
+    EXPORTS_PATH,
 };
 
 const CHUNK_PURGE_INTERVAL: f64 = 120.0;
+// Written next to every export and shared by them
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_ATLAS_NAME: &str = "atlas.png";
+// How bright clouds are at midnight compared to midday
+const NIGHT_CLOUD_BRIGHTNESS: f32 = 0.1;
 
 pub struct State {
     terrain: Terrain,
     block_manager: Rc<BlockManager>,
     player: Player,
+    game_rules: GameRules,
+    // Fraction of the day, see time_of_day
+    time_of_day: f64,
     seed: u32,
     purge_counter: f64,
+    // Debug toggle, not persisted
+    solid_only: bool,
+    aim_assist_cone: Option<Deg<f32>>,
+    max_ray_steps: u32,
+    reach: f32,
+    // Blocks overlapping the box of this half extent around the camera can't be placed
+    player_half_extent: [f32; 3],
+    // Block the player is aiming at, updated every frame
+    target: Option<Pos>,
+    replace_fluids_on_place: bool,
+    // Corners of the selected area, the second one is set by the next selection after the first
+    selection: Option<(Pos, Pos)>,
+    selecting_second_corner: bool,
+    // Feedback on an input, shown in the hud until the next one
+    hud_message: Option<String>,
+    block_listeners: Vec<BlockListener>,
     #[cfg(feature = "save_system")]
     current_save_name: String,
+    #[cfg(feature = "save_system")]
+    save_error: Option<String>,
 }
 
 impl State {
-    pub fn new(texture_atlas: &TextureAtlas, block_manager: BlockManager, load_last_save: bool) -> Self {
+    pub fn new(
+        texture_atlas: &TextureAtlas,
+        block_manager: BlockManager,
+        #[cfg_attr(not(feature = "save_system"), allow(unused_variables))] load_last_save: bool,
+        generator_kind: TerrainGeneratorKind,
+    ) -> Self {
         let seed = TerrainGenerator::generate_seed();
 
         #[cfg(feature = "save_system")]
-        let current_save_name = if load_last_save {
-            available_saves().into_iter().next().unwrap_or(seed.to_string())
-        } else {
-            seed.to_string()
-        };
+        let last_save = if load_last_save { last_played_save() } else { None };
+        #[cfg(feature = "save_system")]
+        let current_save_name = last_save.clone().unwrap_or(seed.to_string());
 
         let mut out = Self {
             terrain: {
@@ -51,6 +101,8 @@ impl State {
                     texture_atlas,
                     seed,
                     block_manager.clone(),
+                    TerrainConfig::load(),
+                    generator_kind,
                 );
                 #[cfg(feature = "save_system")]
                 terrain.set_save_name(current_save_name.clone());
@@ -58,15 +110,30 @@ impl State {
                 terrain
             },
             player: Player::new(&block_manager),
+            game_rules: GameRules::default(),
+            time_of_day: time_of_day::NOON,
             block_manager: Rc::new(block_manager),
             seed,
             #[cfg(feature = "save_system")]
             current_save_name,
+            #[cfg(feature = "save_system")]
+            save_error: None,
             purge_counter: 0.0,
+            solid_only: false,
+            aim_assist_cone: None,
+            max_ray_steps: DEFAULT_MAX_RAY_STEPS,
+            reach: DEFAULT_PLAYER_REACH,
+            player_half_extent: Settings::default().player_half_extent,
+            target: None,
+            replace_fluids_on_place: true,
+            selection: None,
+            selecting_second_corner: false,
+            hud_message: None,
+            block_listeners: Vec::new(),
         };
 
         #[cfg(feature = "save_system")]
-        if load_last_save {
+        if last_save.is_some() {
             out.load();
         }
 
@@ -74,13 +141,38 @@ impl State {
     }
 
     pub fn update(&mut self, game_running: bool, dt: instant::Duration, settings: &Settings) {
+        profile_span!("state_update");
+        self.terrain.set_max_light_sources(settings.max_light_sources);
+        self.terrain.set_sunlight_spread(settings.sunlight_spread);
+        self.terrain.set_smooth_lighting(settings.smooth_lighting);
+        self.terrain.set_save_threads(settings.save_threads);
+        #[cfg(feature = "save_system")]
+        self.terrain.set_max_queued_saves(settings.max_queued_saves);
+        #[cfg(feature = "save_system")]
+        self.terrain
+            .set_pause_generation_on_save(settings.pause_generation_on_save);
+        self.terrain
+            .set_prioritize_look_direction(settings.prioritize_look_direction);
+        self.terrain.set_occlusion_culling(settings.occlusion_culling);
+        self.terrain
+            .set_chunk_request_delay(instant::Duration::from_secs_f32(settings.chunk_request_delay.max(0.0)));
+        self.terrain
+            .set_remesh_coalescing_window(instant::Duration::from_secs_f32(
+                settings.remesh_coalescing_window.max(0.0),
+            ));
+        self.aim_assist_cone = settings.aim_assist.then_some(Deg(settings.aim_assist_cone));
+        self.max_ray_steps = settings.max_ray_steps;
+        self.reach = settings.reach.max(0.0);
+        self.player_half_extent = settings.player_half_extent;
+        self.replace_fluids_on_place = settings.replace_fluids_on_place;
         self.terrain.update();
 
         if PURGE_ENABLED && self.purge_counter >= CHUNK_PURGE_INTERVAL {
             self.terrain.purge(
                 &self.player.camera.pos.chunk_pos(),
                 settings.render_distance_horizontal,
-                settings.render_distance_vertical,
+                settings.render_distance_up.max(settings.render_distance_down),
+                settings.kept_surrounding_chunks,
             );
 
             self.purge_counter = 0.0;
@@ -88,26 +180,35 @@ impl State {
         self.purge_counter += dt.as_secs_f64();
 
         if game_running {
-            self.player.update(dt, &mut self.terrain, settings);
+            self.player.update(dt, &mut self.terrain, settings, &self.game_rules);
+        }
+        if game_running && settings.day_night_cycle && !self.game_rules.time_paused {
+            self.time_of_day = time_of_day::advance(self.time_of_day, dt);
         }
+
+        let ray = self.player_ray();
+        self.target = self.target_block(&ray).map(|(intersect_pos, _, _)| intersect_pos);
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
+    pub fn input(&mut self, event: &WindowEvent, keybinds: &Keybinds) -> bool {
         match event {
             WindowEvent::MouseInput {
                 button: MouseButton::Left,
                 state: ElementState::Pressed,
                 ..
+            } => {
+                self.break_block();
+                true
             }
-            | WindowEvent::KeyboardInput {
+            WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
                         state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::X),
+                        virtual_keycode: Some(key),
                         ..
                     },
                 ..
-            } => {
+            } if *key == keybinds.break_block => {
                 self.break_block();
                 true
             }
@@ -115,16 +216,19 @@ impl State {
                 button: MouseButton::Right,
                 state: ElementState::Pressed,
                 ..
+            } => {
+                self.place_block();
+                true
             }
-            | WindowEvent::KeyboardInput {
+            WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
                         state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::C),
+                        virtual_keycode: Some(key),
                         ..
                     },
                 ..
-            } => {
+            } if *key == keybinds.place_block => {
                 self.place_block();
                 true
             }
@@ -132,19 +236,64 @@ impl State {
                 button: MouseButton::Middle,
                 state: ElementState::Pressed,
                 ..
+            } => {
+                self.pick_block();
+                true
             }
-            | WindowEvent::KeyboardInput {
+            WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
                         state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::V),
+                        virtual_keycode: Some(key),
                         ..
                     },
                 ..
-            } => {
+            } if *key == keybinds.pick_block => {
                 self.pick_block();
                 true
             }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if *key == keybinds.toggle_walk => {
+                if self.game_rules.allow_flight && !self.game_rules.gravity {
+                    self.player.toggle_physics_mode();
+                    log::info!("Physics mode: {:?}", self.player.physics_mode);
+                } else {
+                    log::info!("Flight isn't allowed in this world");
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if *key == keybinds.select_corner => {
+                self.select_corner();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if *key == keybinds.clear_selection => {
+                self.selection = None;
+                self.selecting_second_corner = false;
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -160,6 +309,108 @@ impl State {
                 }
                 true
             }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F6),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.teleport_to_frontier();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F7),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.solid_only = !self.solid_only;
+                log::info!("Rendering only solid meshes: {}", self.solid_only);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F8),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.terrain.set_loading_frozen(!self.terrain.loading_frozen());
+                log::info!("Chunk loading frozen: {}", self.terrain.loading_frozen());
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F10),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.log_block_counts();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F3),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.reload_blocks();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode:
+                            Some(
+                                key @ (VirtualKeyCode::Key1
+                                | VirtualKeyCode::Key2
+                                | VirtualKeyCode::Key3
+                                | VirtualKeyCode::Key4
+                                | VirtualKeyCode::Key5
+                                | VirtualKeyCode::Key6
+                                | VirtualKeyCode::Key7
+                                | VirtualKeyCode::Key8
+                                | VirtualKeyCode::Key9),
+                            ),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.player.select_slot(*key as usize - VirtualKeyCode::Key1 as usize);
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let scroll = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y as f64,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y,
+                };
+                // Scrolling down moves to the next slot, or slows down flying while sprinting
+                if scroll != 0.0 {
+                    let steps = if scroll < 0.0 { 1 } else { -1 };
+                    if self.player.camera_controller.sprinting() && !self.player.is_walking(&self.game_rules) {
+                        let multiplier = self.player.camera_controller.change_fly_speed(-steps);
+                        self.hud_message = Some(format!("Fly speed {multiplier:.2}x"));
+                    } else {
+                        self.player.scroll_slot(steps);
+                    }
+                }
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -168,11 +419,15 @@ impl State {
                         ..
                     },
                 ..
-            } => self.player.process_keyboard(*key, *state),
+            } => self.player.process_keyboard(*key, *state, keybinds),
             _ => false,
         }
     }
 
+    pub fn take_hud_message(&mut self) -> Option<String> {
+        self.hud_message.take()
+    }
+
     pub fn input_mouse(&mut self, delta: (f64, f64)) {
         self.player.input_mouse(delta)
     }
@@ -182,8 +437,28 @@ impl State {
         if self.saving_chunks() == 0 {
             self.terrain.set_save_name(self.current_save_name.clone());
 
+            save(
+                self.current_save_name.clone(),
+                "world",
+                &WorldMeta::new(
+                    &self.current_save_name,
+                    self.terrain.save_format_version(),
+                    self.seed,
+                    self.terrain.generator_kind().clone(),
+                ),
+                false,
+            );
             save(self.current_save_name.clone(), "player", &self.player, false);
+            // Also kept in world.yaml, but still written so older versions can load the save
             save(self.current_save_name.clone(), "seed", &self.seed, false);
+            save(
+                self.current_save_name.clone(),
+                "generator_kind",
+                self.terrain.generator_kind(),
+                false,
+            );
+            save(self.current_save_name.clone(), "game_rules", &self.game_rules, false);
+            save(self.current_save_name.clone(), "time_of_day", &self.time_of_day, false);
             self.terrain.save();
         } else {
             log::warn!("Already saving")
@@ -192,39 +467,186 @@ impl State {
 
     #[cfg(feature = "save_system")]
     pub fn load(&mut self) {
+        let world_meta = match load_world_meta(self.current_save_name.clone(), "world") {
+            Ok(world_meta) if world_meta.format_version > WORLD_FORMAT_VERSION => {
+                let message = format!(
+                    "Refusing to load save {:?}, it has format version {} but only up to {} is supported",
+                    self.current_save_name, world_meta.format_version, WORLD_FORMAT_VERSION
+                );
+                log::error!("{}", message);
+                self.save_error = Some(message);
+                return;
+            }
+            Ok(world_meta) => Some(world_meta),
+            // Saves made before world metadata existed keep the seed and generator kind in their own files
+            Err(LoadError::Missing) => None,
+            Err(e) => {
+                log::warn!(
+                    "Failed loading world metadata from save {:?}, {}",
+                    self.current_save_name,
+                    e
+                );
+                None
+            }
+        };
+        let format_version = world_meta
+            .as_ref()
+            .map_or(UNVERSIONED_FORMAT_VERSION, |world_meta| world_meta.format_version);
+
+        // A missing seed is only acceptable for a save without any chunks, otherwise the chunks on disk would be
+        // surrounded by terrain generated from a different seed
+        let seed = match world_meta.as_ref().map_or_else(
+            || load_u32(self.current_save_name.clone(), "seed"),
+            |world_meta| Ok(world_meta.seed),
+        ) {
+            Ok(seed) => seed,
+            Err(LoadError::Missing) if !save_has_chunks(self.current_save_name.clone()) => {
+                log::warn!("Save {:?} has no seed, keeping current seed", self.current_save_name);
+                self.seed
+            }
+            Err(e) => {
+                let message = format!(
+                    "Refusing to load save {:?}, seed {} - the saved chunks would not match newly generated terrain",
+                    self.current_save_name, e
+                );
+                log::error!("{}", message);
+                self.save_error = Some(message);
+                return;
+            }
+        };
+
+        // Saves made before flat worlds existed were all generated normally
+        let generator_kind = match world_meta.map_or_else(
+            || load_generator_kind(self.current_save_name.clone(), "generator_kind"),
+            |world_meta| Ok(world_meta.generator_kind),
+        ) {
+            Ok(generator_kind) => generator_kind,
+            Err(LoadError::Missing) => TerrainGeneratorKind::Normal,
+            Err(e) => {
+                let message = format!(
+                    "Refusing to load save {:?}, generator kind {} - the saved chunks would not match newly generated terrain",
+                    self.current_save_name, e
+                );
+                log::error!("{}", message);
+                self.save_error = Some(message);
+                return;
+            }
+        };
+
         self.purge_counter = 0.0;
+        self.save_error = None;
+        self.seed = seed;
 
-        self.player = if let Some(player) = load_player(self.current_save_name.clone(), "player") {
-            player
-        } else {
-            log::warn!("Failed loading player from save {:?}", self.current_save_name);
-            Player::new(&self.block_manager)
+        self.player = match load_player(self.current_save_name.clone(), "player") {
+            Ok(player) => player,
+            Err(e) => {
+                log::warn!("Failed loading player from save {:?}, {}", self.current_save_name, e);
+                Player::new(&self.block_manager)
+            }
         };
-        self.seed = if let Some(seed) = load_u32(self.current_save_name.clone(), "seed") {
-            seed
-        } else {
-            log::warn!("Failed loading seed from save {:?}", self.current_save_name);
-            self.seed
+
+        // Saves made before game rules existed get the defaults, which match how those worlds played
+        self.game_rules = match load_game_rules(self.current_save_name.clone(), "game_rules") {
+            Ok(game_rules) => game_rules,
+            Err(LoadError::Missing) => {
+                log::info!("Save {:?} has no game rules, using defaults", self.current_save_name);
+                GameRules::default()
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed loading game rules from save {:?}, {}",
+                    self.current_save_name,
+                    e
+                );
+                GameRules::default()
+            }
         };
 
-        self.terrain = {
-            let mut terrain = Terrain::new(
-                self.terrain.transparency(),
-                self.terrain.texture_atlas(),
-                self.seed,
-                (*self.block_manager).clone(),
-            );
-            terrain.set_save_name(self.current_save_name.to_string());
-            terrain
+        self.time_of_day = match load_time_of_day(self.current_save_name.clone(), "time_of_day") {
+            Ok(time_of_day) => time_of_day,
+            Err(LoadError::Missing) => time_of_day::NOON,
+            Err(e) => {
+                log::warn!(
+                    "Failed loading time of day from save {:?}, {}",
+                    self.current_save_name,
+                    e
+                );
+                time_of_day::NOON
+            }
         };
+
+        self.terrain = self.new_terrain(generator_kind);
+        self.terrain.set_save_format_version(format_version);
+    }
+
+    // Starts a new world in the selected save, its name is used as the seed
+    #[cfg(feature = "save_system")]
+    pub fn new_world(&mut self, generator_kind: TerrainGeneratorKind) {
+        if available_saves().contains(&self.current_save_name) {
+            self.save_error = Some(format!(
+                "Save {:?} already exists, load it or pick another name",
+                self.current_save_name
+            ));
+            return;
+        }
+
+        self.seed = TerrainGenerator::seed_from_str(&self.current_save_name);
+        if self.current_save_name.trim().is_empty() {
+            self.current_save_name = self.seed.to_string();
+        }
+        log::info!(
+            "Starting new world {:?} with seed {}",
+            self.current_save_name,
+            self.seed
+        );
+
+        self.purge_counter = 0.0;
+        self.save_error = None;
+        self.player = Player::new(&self.block_manager);
+        self.game_rules = GameRules::default();
+        self.time_of_day = time_of_day::NOON;
+        self.terrain = self.new_terrain(generator_kind);
     }
 
-    pub fn meshes_to_render(&mut self, device: &wgpu::Device, settings: &Settings) -> Vec<&impl Draw> {
+    #[cfg(feature = "save_system")]
+    fn new_terrain(&self, generator_kind: TerrainGeneratorKind) -> Terrain {
+        let mut terrain = Terrain::new(
+            self.terrain.transparency(),
+            self.terrain.texture_atlas(),
+            self.seed,
+            (*self.block_manager).clone(),
+            self.terrain.terrain_config().clone(),
+            generator_kind,
+        );
+        terrain.set_save_name(self.current_save_name.to_string());
+        terrain
+    }
+
+    // Solid, transparent and double sided meshes, transparent ones are drawn after all others
+    pub fn meshes_to_render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+    ) -> (Vec<&impl Draw>, Vec<&impl Draw>, Vec<&impl Draw>) {
         self.terrain.meshes_to_render(
             &self.player.camera,
             settings.render_distance_horizontal,
-            settings.render_distance_vertical,
+            settings.render_distance_up,
+            settings.render_distance_down,
             device,
+            queue,
+            self.solid_only,
+        )
+    }
+
+    // Loads chunks around the player like `meshes_to_render`, for when nothing is drawn
+    pub fn load_chunks(&mut self, settings: &Settings) {
+        self.terrain.load_chunks(
+            &self.player.camera,
+            settings.render_distance_horizontal,
+            settings.render_distance_up,
+            settings.render_distance_down,
         )
     }
 
@@ -232,17 +654,115 @@ impl State {
         self.terrain.loading_chunks()
     }
 
+    pub fn light_capped_chunks(&self) -> u32 {
+        self.terrain.light_capped_chunks()
+    }
+
+    pub fn skipped_chunk_requests(&self) -> u32 {
+        self.terrain.skipped_chunk_requests()
+    }
+
+    pub fn remeshes(&self) -> u32 {
+        self.terrain.remeshes()
+    }
+
+    pub fn buffer_allocations(&self) -> u64 {
+        self.terrain.buffer_allocations()
+    }
+
+    pub fn loading_frozen(&self) -> bool {
+        self.terrain.loading_frozen()
+    }
+
     pub fn saving_chunks(&self) -> u32 {
         self.terrain.saving_chunks()
     }
 
+    // Logs the most common blocks in the loaded chunks, blocks changed in the block editor are counted together
+    fn log_block_counts(&self) {
+        const SHOWN_BLOCK_COUNTS: usize = 10;
+
+        let mut counts = FxHashMap::<&str, u64>::default();
+        for (block, count) in self.terrain.count_blocks() {
+            *counts
+                .entry(self.block_manager.block_name(&block).unwrap_or("Custom"))
+                .or_default() += count;
+        }
+        let total = counts.values().sum::<u64>();
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts
+            .sort_by(|(first_name, first), (second_name, second)| second.cmp(first).then(first_name.cmp(second_name)));
+
+        log::info!(
+            "{} blocks in {} loaded chunks, most common:",
+            total,
+            self.terrain.chunk_count()
+        );
+        for (name, count) in counts.into_iter().take(SHOWN_BLOCK_COUNTS) {
+            log::info!(
+                "  {:<12} {:>10} ({:.1}%)",
+                name,
+                count,
+                count as f64 / total.max(1) as f64 * 100.0
+            );
+        }
+    }
+
+    // Re-reads the block files, blocks already placed in loaded chunks or held by the player take on the new definitions
+    fn reload_blocks(&mut self) {
+        let mut block_manager = (*self.block_manager).clone();
+        let replacements = block_manager.reload();
+
+        for hotbar_block in self.player.hotbar.iter_mut() {
+            if let Some(block) = hotbar_block.replaced(&replacements) {
+                *hotbar_block = block;
+            }
+        }
+        for palette_block in self.player.palette.iter_mut() {
+            if let Some(block) = palette_block.replaced(&replacements) {
+                *palette_block = block;
+            }
+        }
+        self.terrain.set_block_manager(block_manager.clone(), &replacements);
+        self.block_manager = Rc::new(block_manager);
+
+        log::info!("Reloaded blocks, {} changed", replacements.len());
+    }
+
+    // Ray along the view of the player as far as they can reach
+    fn player_ray(&self) -> Ray {
+        Ray::new(self.camera().pos, self.camera().forward_vec_xyz(), Some(self.reach))
+            .with_max_steps(self.max_ray_steps)
+    }
+
+    fn target_block(&mut self, ray: &Ray) -> Option<(Pos, Option<Pos>, Pos)> {
+        if let Some(aim_assist_cone) = self.aim_assist_cone {
+            ray.intersect_with_assist(&mut self.terrain, aim_assist_cone.into())
+        } else {
+            ray.intersect(&mut self.terrain)
+        }
+    }
+
     fn break_block(&mut self) {
         if self.player.last_block_update_time_dt() >= BLOCK_UPDATE_MIN_DELAY {
-            let ray = Ray::new(self.camera().pos, self.camera().forward_vec_xyz(), Some(PLAYER_REACH));
+            let ray = self.player_ray();
+
+            if let Some((intersect_pos, _, _)) = self.target_block(&ray) {
+                let broken_block = if self.block_listeners.is_empty() {
+                    None
+                } else {
+                    self.terrain.get_block(&intersect_pos)
+                };
 
-            if let Some((intersect_pos, _, _)) = ray.intersect(&mut self.terrain) {
                 self.terrain
-                    .set_block(&intersect_pos, Block::new("Air", &self.block_manager, None, false))
+                    .set_block(&intersect_pos, Block::new("Air", &self.block_manager, None, false));
+
+                if let Some(block) = broken_block {
+                    self.emit_block_event(BlockEvent::Broken {
+                        block,
+                        pos: intersect_pos,
+                    });
+                }
             }
 
             self.player.set_last_block_update_time()
@@ -251,11 +771,23 @@ impl State {
         }
     }
 
+    // Listeners are called in the order they were added, for every event from then on
+    #[allow(dead_code)]
+    pub fn add_block_listener(&mut self, listener: impl FnMut(&BlockEvent) + 'static) {
+        self.block_listeners.push(Box::new(listener))
+    }
+
+    fn emit_block_event(&mut self, event: BlockEvent) {
+        for listener in &mut self.block_listeners {
+            listener(&event)
+        }
+    }
+
     fn place_block(&mut self) {
         if self.player.last_block_update_time_dt() >= BLOCK_UPDATE_MIN_DELAY {
-            let ray = Ray::new(self.camera().pos, self.camera().forward_vec_xyz(), Some(PLAYER_REACH));
+            let ray = self.player_ray();
 
-            let selected_block = self.player.selected_block.clone();
+            let selected_block = self.player.selected_block().clone();
             if let Some(light_source) = selected_block.light_source() {
                 if !light_source.is_valid() {
                     log::warn!("Trying to place invalid light source");
@@ -263,8 +795,11 @@ impl State {
                 }
             }
 
-            if let Some((_, Some(place_pos), _)) = ray.intersect(&mut self.terrain) {
-                if place_pos.in_chunk_pos_i32() != self.player.camera.pos.in_chunk_pos_i32() {
+            if let Some((_, Some(place_pos), _)) = self.target_block(&ray) {
+                let inside_player =
+                    box_overlaps_block(self.player.camera.pos, self.player_half_extent.into(), &place_pos);
+
+                if !inside_player && self.is_replaceable(&place_pos) {
                     self.terrain.set_block(&place_pos, selected_block)
                 }
             }
@@ -274,20 +809,181 @@ impl State {
         }
     }
 
+    // Blocks can only be placed into air, or into fluids (blocks that are rendered but not solid) if enabled
+    fn is_replaceable(&mut self, pos: &Pos) -> bool {
+        match self.terrain.get_block(pos) {
+            Some(block) => !block.is_solid() && (!block.is_rendered() || self.replace_fluids_on_place),
+            None => false,
+        }
+    }
+
     fn pick_block(&mut self) {
-        let ray = Ray::new(self.camera().pos, self.camera().forward_vec_xyz(), Some(PLAYER_REACH));
+        let ray = self.player_ray();
 
-        if let Some((intersect_pos, _, _)) = ray.intersect(&mut self.terrain) {
+        if let Some((intersect_pos, _, _)) = self.target_block(&ray) {
             if let Some(block) = self.terrain.get_block(&intersect_pos) {
-                self.player.selected_block = block
+                *self.player.selected_block_mut() = block
             }
         }
     }
 
+    fn select_corner(&mut self) {
+        let ray = self.player_ray();
+
+        if let Some((intersect_pos, _, _)) = self.target_block(&ray) {
+            match (self.selection, self.selecting_second_corner) {
+                (Some((first_corner, _)), true) => self.selection = Some((first_corner, intersect_pos)),
+                _ => self.selection = Some((intersect_pos, intersect_pos)),
+            }
+            self.selecting_second_corner = !self.selecting_second_corner;
+        }
+    }
+
+    // Debug helper for stress-testing generation, jumps just past the edge of the loaded chunks
+    fn teleport_to_frontier(&mut self) {
+        if let Some(chunk_pos) = self
+            .terrain
+            .nearest_unloaded_frontier(self.player.camera.pos.chunk_pos())
+        {
+            log::info!("Teleporting to unloaded chunk {:?}", chunk_pos);
+
+            let in_chunk_pos = self.player.camera.pos.in_chunk_pos_f32();
+            self.player.teleport(Pos::new(chunk_pos, in_chunk_pos));
+        } else {
+            log::warn!("No unloaded frontier found to teleport to")
+        }
+    }
+
     pub fn player(&self) -> &Player {
         &self.player
     }
 
+    pub fn player_mut(&mut self) -> &mut Player {
+        &mut self.player
+    }
+
+    pub fn terrain_mut(&mut self) -> &mut Terrain {
+        &mut self.terrain
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    #[cfg(feature = "request_log")]
+    pub fn start_request_log(&mut self) {
+        self.terrain.start_request_log()
+    }
+
+    #[cfg(feature = "request_log")]
+    pub fn take_request_log(&mut self) -> Option<RequestLog> {
+        self.terrain.take_request_log().map(|requests| RequestLog {
+            seed: self.seed,
+            requests,
+        })
+    }
+
+    // Swaps in a fresh terrain generated from the recorded seed and replays the requests on it
+    #[cfg(all(feature = "request_log", not(target_arch = "wasm32")))]
+    pub fn replay_requests(&mut self, request_log: &RequestLog, timeout: instant::Duration) -> Result<(), ()> {
+        log::info!(
+            "Replaying {} chunk requests with seed {}",
+            request_log.requests.len(),
+            request_log.seed
+        );
+
+        self.seed = request_log.seed;
+        self.terrain = Terrain::new(
+            self.terrain.transparency(),
+            self.terrain.texture_atlas(),
+            self.seed,
+            (*self.block_manager).clone(),
+            self.terrain.terrain_config().clone(),
+            self.terrain.generator_kind().clone(),
+        );
+        #[cfg(feature = "save_system")]
+        self.terrain.set_save_name(self.current_save_name.clone());
+
+        self.terrain.replay_requests(&request_log.requests, timeout)
+    }
+
+    pub fn game_rules(&self) -> &GameRules {
+        &self.game_rules
+    }
+
+    pub fn set_game_rules(&mut self, game_rules: GameRules) {
+        self.game_rules = game_rules;
+    }
+
+    pub fn time_of_day(&self) -> f64 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time_of_day: f64) {
+        self.time_of_day = time_of_day::wrap(time_of_day);
+    }
+
+    // Sunlight multiplier, without the day night cycle it is always day
+    pub fn daylight(&self, settings: &Settings) -> f32 {
+        if settings.day_night_cycle {
+            time_of_day::daylight(self.time_of_day) as f32
+        } else {
+            1.0
+        }
+    }
+
+    // Sunlight intensity from the settings, scaled by the time of day in the day night cycle, in 0..=15
+    pub fn current_sunlight_intensity(&self, settings: &Settings) -> u8 {
+        if settings.day_night_cycle {
+            time_of_day::sunlight_intensity(self.time_of_day, settings.sunlight_intensity)
+        } else {
+            settings.sunlight_intensity.min(15)
+        }
+    }
+
+    // Blends from the night to the day sky color as the sun rises
+    pub fn sky_color(&self, settings: &Settings) -> [f32; 3] {
+        self.blend_daylight(settings, settings.sky_color, settings.night_sky_color)
+    }
+
+    // Turns to the sunrise color around dawn and dusk, without the day night cycle it is always day
+    pub fn sun_color(&self, settings: &Settings) -> [f32; 3] {
+        let sunrise = if settings.day_night_cycle {
+            time_of_day::sun_horizon(self.time_of_day) as f32
+        } else {
+            0.0
+        };
+        [0, 1, 2].map(|i| settings.sun_color[i] + (settings.sunrise_sun_color[i] - settings.sun_color[i]) * sunrise)
+    }
+
+    // None when the sky is flat
+    pub fn sky_zenith_color(&self, settings: &Settings) -> Option<[f32; 3]> {
+        settings
+            .sky_gradient
+            .then(|| self.blend_daylight(settings, settings.sky_zenith_color, settings.night_sky_zenith_color))
+    }
+
+    pub fn cloud_layer(&self, settings: &Settings) -> Option<CloudLayer> {
+        let [red, green, blue, alpha] = settings.cloud_color;
+        let [red, green, blue] = self.blend_daylight(
+            settings,
+            [red, green, blue],
+            [red, green, blue].map(|channel| channel * NIGHT_CLOUD_BRIGHTNESS),
+        );
+
+        settings.clouds.then_some(CloudLayer {
+            height: settings.cloud_height,
+            speed: settings.cloud_speed,
+            coverage: settings.cloud_coverage,
+            color: [red, green, blue, alpha],
+        })
+    }
+
+    fn blend_daylight(&self, settings: &Settings, day_color: [f32; 3], night_color: [f32; 3]) -> [f32; 3] {
+        let daylight = self.daylight(settings);
+        [0, 1, 2].map(|i| night_color[i] + (day_color[i] - night_color[i]) * daylight)
+    }
+
     pub fn camera(&self) -> &Camera {
         &self.player.camera
     }
@@ -296,6 +992,162 @@ impl State {
         self.player.selected_block_mut()
     }
 
+    pub fn texture_atlas(&self) -> &TextureAtlas {
+        self.terrain.texture_atlas()
+    }
+
+    // Pastes a MagicaVoxel model with its most negative corner at the first selected corner, or at the player without a
+    // selection. Its colors become blocks by `vox_palette.yaml`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn paste_structure(&mut self, path: &str) {
+        let Some(model) = load_vox(path) else {
+            self.hud_message = Some(format!("Failed importing {path:?}"));
+            return;
+        };
+
+        let origin = self
+            .selection
+            .map_or(self.player.camera.pos, |(first_corner, _)| first_corner);
+        let pasted = self
+            .terrain
+            .paste_structure(&origin, &model.to_structure(&VoxPalette::load()));
+        self.hud_message = Some(format!("Pasted {pasted} blocks of {path:?}"));
+    }
+
+    // Writes the chunks of the selection, or the chunk the player is in without one, to EXPORTS_PATH as a Wavefront OBJ
+    // with a material library and the texture atlas it uses. The texture atlas of the terrain has no image, so the
+    // rendered one is passed in
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_obj(&mut self, texture_atlas: &TextureAtlas) -> Result<PathBuf, ()> {
+        profile_span!("export_obj");
+        let corners = self
+            .selection
+            .unwrap_or((self.player.camera.pos, self.player.camera.pos));
+        // Exports made within the same millisecond get a counter, so they don't overwrite each other
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let mut name = format!("export-{time}");
+        let mut copy = 1;
+        while EXPORTS_PATH.join(format!("{name}.obj")).exists() {
+            name = format!("export-{time}-{copy}");
+            copy += 1;
+        }
+
+        if let Err(e) = std::fs::create_dir_all(&*EXPORTS_PATH) {
+            log::warn!("Failed creating {} - {}", EXPORTS_PATH.display(), e);
+            return Err(());
+        }
+        texture_atlas.save_image(EXPORTS_PATH.join(EXPORT_ATLAS_NAME))?;
+
+        let obj_path = EXPORTS_PATH.join(format!("{name}.obj"));
+        let mtl_path = EXPORTS_PATH.join(format!("{name}.mtl"));
+        let obj = self.terrain.export_region_obj(corners, &format!("{name}.mtl"));
+        for (path, text) in [
+            (
+                &mtl_path,
+                format!("newmtl atlas\nKd 1 1 1\nmap_Kd {EXPORT_ATLAS_NAME}\n"),
+            ),
+            (&obj_path, obj),
+        ] {
+            if let Err(e) = std::fs::write(path, text) {
+                log::warn!("Failed writing export to {} - {}", path.display(), e);
+                return Err(());
+            }
+        }
+
+        log::info!("Exported to {}", obj_path.display());
+        Ok(obj_path)
+    }
+
+    pub fn surface_colors(&self, radius: u32, settings: &Settings) -> Vec<Option<[u8; 3]>> {
+        self.terrain.surface_colors(
+            &self.player.camera.pos,
+            radius,
+            settings.render_distance_up,
+            settings.render_distance_down,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn selection(&self) -> Option<(Pos, Pos)> {
+        self.selection
+    }
+
+    #[allow(dead_code)]
+    pub fn target(&self) -> Option<Pos> {
+        self.target
+    }
+
+    pub fn overlays(&self, settings: &Settings) -> Vec<BoxOverlay> {
+        let selection = self.selection.map(|corners| {
+            BoxOverlay::new(
+                corners,
+                settings.selection_color,
+                settings.outline_color,
+                settings.outline_thickness,
+            )
+        });
+        // Only the edges, as single pixel lines
+        let target = self
+            .target
+            .filter(|_| settings.target_outline)
+            .map(|pos| BoxOverlay::new((pos, pos), [0.0; 4], settings.target_outline_color, 0.0));
+
+        selection.into_iter().chain(target).collect()
+    }
+
+    pub fn ground_plane(&self, settings: &Settings) -> Option<GroundPlane> {
+        settings
+            .ground_plane
+            .then(|| GroundPlane::new(settings.ground_plane_height, settings.ground_plane_color))
+    }
+
+    pub fn palette_mut(&mut self) -> &mut Vec<Block> {
+        self.player.palette_mut()
+    }
+
+    // Palettes are kept with the current save, the file can be copied to another save to share it
+    #[cfg(feature = "save_system")]
+    pub fn export_palette(&self, palette_name: &str) {
+        save(
+            self.current_save_name.clone(),
+            format!("palettes/{}", palette_name),
+            &self.player.palette,
+            false,
+        );
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn import_palette(&mut self, palette_name: &str) {
+        match load_palette(self.current_save_name.clone(), format!("palettes/{}", palette_name)) {
+            Ok(palette) => {
+                let loaded_blocks = palette.len();
+                let palette: Vec<Block> = palette
+                    .into_iter()
+                    .filter(|block| self.block_manager.is_valid_block(block))
+                    .collect();
+
+                if palette.len() < loaded_blocks {
+                    let message = format!(
+                        "Skipped {} blocks of palette {:?} with unknown textures or invalid light sources",
+                        loaded_blocks - palette.len(),
+                        palette_name
+                    );
+                    log::warn!("{}", message);
+                    self.save_error = Some(message);
+                }
+
+                self.player.palette = palette;
+            }
+            Err(e) => {
+                let message = format!("Failed importing palette {:?}, {}", palette_name, e);
+                log::warn!("{}", message);
+                self.save_error = Some(message);
+            }
+        }
+    }
+
     #[cfg(feature = "save_system")]
     pub fn selected_save(&self) -> String {
         self.current_save_name.clone()
@@ -306,6 +1158,57 @@ impl State {
         self.current_save_name = save_name;
     }
 
+    // Deletes the selected save and starts a new world under a fresh name, so the deleted world can't be saved into
+    // another existing save
+    #[cfg(feature = "save_system")]
+    pub fn delete_save(&mut self) {
+        if self.saving_chunks() > 0 {
+            self.save_error = Some(format!(
+                "Can't delete save {:?} while chunks are being saved",
+                self.current_save_name
+            ));
+            return;
+        }
+
+        match delete_save(&self.current_save_name) {
+            Ok(()) => {
+                log::info!("Deleted save {:?}", self.current_save_name);
+                self.current_save_name = TerrainGenerator::generate_seed().to_string();
+                self.new_world(self.terrain.generator_kind().clone());
+            }
+            Err(e) => {
+                let message = format!("Failed deleting save {:?} - {}", self.current_save_name, e);
+                log::warn!("{}", message);
+                self.save_error = Some(message);
+            }
+        }
+    }
+
+    // Makes the given save the current one and loads it. If it can't be loaded the current save is kept, so saving
+    // afterwards doesn't write this world into it
+    #[cfg(feature = "save_system")]
+    pub fn switch_save(&mut self, save_name: String) -> Result<(), ()> {
+        let previous_save_name = std::mem::replace(&mut self.current_save_name, save_name);
+        self.load();
+
+        if self.save_error.is_some() {
+            self.current_save_name = previous_save_name;
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn save_error(&self) -> Option<String> {
+        self.save_error.clone()
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn set_save_error(&mut self, save_error: Option<String>) {
+        self.save_error = save_error;
+    }
+
     pub fn cancel_requests(&mut self) {
         self.terrain.reset_chunks(self.seed);
 
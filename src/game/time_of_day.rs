@@ -0,0 +1,88 @@
+use std::f64::consts::TAU;
+
+// Time of day as the fraction of the day, with midnight at 0.0 and noon at 0.5
+
+// Length of a full day in seconds
+pub const DAY_LENGTH: f64 = 1200.0;
+
+pub const MIDNIGHT: f64 = 0.0;
+pub const DAWN: f64 = 0.25;
+pub const NOON: f64 = 0.5;
+pub const DUSK: f64 = 0.75;
+
+// Lowest daylight, so nights are dark but not pitch black
+const MIN_DAYLIGHT: f64 = 0.1;
+// Sun height up to which the sun counts as rising or setting
+const SUN_HORIZON_HEIGHT: f64 = 0.4;
+
+pub fn advance(time_of_day: f64, dt: instant::Duration) -> f64 {
+    wrap(time_of_day + dt.as_secs_f64() / DAY_LENGTH)
+}
+
+pub fn wrap(time_of_day: f64) -> f64 {
+    time_of_day.rem_euclid(1.0)
+}
+
+// -1.0 at midnight, 0.0 at dawn and dusk, 1.0 at noon
+pub fn sun_height(time_of_day: f64) -> f64 {
+    ((time_of_day - DAWN) * TAU).sin()
+}
+
+// 1.0 with the sun at the horizon, falling to 0.0 once it is SUN_HORIZON_HEIGHT above or below it
+pub fn sun_horizon(time_of_day: f64) -> f64 {
+    (1.0 - sun_height(time_of_day).abs() / SUN_HORIZON_HEIGHT).max(0.0)
+}
+
+// Multiplier for sunlight, ramps up quickly around dawn and down around dusk
+pub fn daylight(time_of_day: f64) -> f64 {
+    (0.5 + sun_height(time_of_day) * 2.0).clamp(MIN_DAYLIGHT, 1.0)
+}
+
+// Sunlight intensity in 0..=15, max_intensity at day
+pub fn sunlight_intensity(time_of_day: f64, max_intensity: u8) -> u8 {
+    (max_intensity.min(15) as f64 * daylight(time_of_day)).round() as u8
+}
+
+pub fn hours_minutes(time_of_day: f64) -> (u32, u32) {
+    let minutes = (wrap(time_of_day) * 24.0 * 60.0) as u32;
+    (minutes / 60 % 24, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sunlight_intensity_follows_the_sun() {
+        assert_eq!(sunlight_intensity(NOON, 15), 15);
+        assert_eq!(sunlight_intensity(MIDNIGHT, 15), 2);
+        // Half way at dawn and dusk
+        assert_eq!(sunlight_intensity(DAWN, 12), 6);
+        assert_eq!(sunlight_intensity(DUSK, 12), 6);
+        // The slider still caps and scales the cycle
+        assert_eq!(sunlight_intensity(NOON, 255), 15);
+        assert_eq!(sunlight_intensity(NOON, 7), 7);
+        assert_eq!(sunlight_intensity(NOON, 0), 0);
+
+        // Rises from midnight to noon and falls again the same way after it
+        let steps = 200;
+        let mut last = sunlight_intensity(MIDNIGHT, 15);
+        for step in 1..=steps {
+            let time = NOON * step as f64 / steps as f64;
+            let intensity = sunlight_intensity(time, 15);
+            assert!(intensity >= last, "intensity falls at {time}");
+            assert_eq!(intensity, sunlight_intensity(1.0 - time, 15));
+            last = intensity;
+        }
+    }
+
+    #[test]
+    fn time_wraps_around_midnight() {
+        let second = instant::Duration::from_secs(1);
+        let before_midnight = 1.0 - 0.5 / DAY_LENGTH;
+        assert!((advance(before_midnight, second) - 0.5 / DAY_LENGTH).abs() < 1e-9);
+        assert_eq!(advance(NOON, instant::Duration::from_secs_f64(DAY_LENGTH / 4.0)), DUSK);
+        assert_eq!(hours_minutes(DUSK), (18, 0));
+        assert_eq!(hours_minutes(-0.25), (18, 0));
+    }
+}
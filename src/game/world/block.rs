@@ -10,22 +10,26 @@ use std::{
 use block_mesh::ndshape::ConstShape;
 use cfg_if::cfg_if;
 use cgmath::{Vector2, Vector3};
-use either::Either;
 use rle_vec::RleVec;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 
 #[cfg(feature = "portable")]
 use crate::RESOURCE_DIR;
 #[cfg(not(feature = "portable"))]
 use crate::RESOURCE_PATH;
 use crate::{
-    engine::face::FaceDirection,
-    game::world::{coordinate_in_surrounding_buffers_cube, CacheUpdateActionKind, ChunkShape, LightSource, CHUNK_SIZE},
+    engine::face::{FaceDirection, SideDirection},
+    game::world::{
+        coordinate_in_surrounding_buffers_cube, CacheUpdateActionKind, ChunkShape, LightSource, CHUNK_SIZE,
+        MAX_LIGHT_VAL,
+    },
     misc::{
         index::{index_from_pos_2d, index_from_relative_pos_surrounding_cubes},
         loader::load_resource_string,
+        profiling::profile_span,
     },
 };
 
@@ -50,51 +54,226 @@ impl From<&str> for TextureID {
     }
 }
 
+// Textures of the faces of a block, either one for all of them, one for the top, sides and bottom or one for each
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockTextures<T> {
+    // Serialized like the `Either` these used to be, so blocks in older saves still load
+    #[serde(rename = "Left")]
+    Uniform(T),
+    #[serde(rename = "Right")]
+    TopSideBottom([T; 3]),
+    // Top, bottom, north, south, east and west
+    SixFaces([T; 6]),
+}
+
+impl<T: Clone> BlockTextures<T> {
+    // None for any number of textures other than 1, 3 or 6
+    pub fn from_slice(textures: &[T]) -> Option<Self> {
+        match textures {
+            [texture] => Some(BlockTextures::Uniform(texture.clone())),
+            [top, side, bottom] => Some(BlockTextures::TopSideBottom([
+                top.clone(),
+                side.clone(),
+                bottom.clone(),
+            ])),
+            [top, bottom, north, south, east, west] => Some(BlockTextures::SixFaces([
+                top.clone(),
+                bottom.clone(),
+                north.clone(),
+                south.clone(),
+                east.clone(),
+                west.clone(),
+            ])),
+            _ => None,
+        }
+    }
+}
+
+impl<T> BlockTextures<T> {
+    pub fn face(&self, face_direction: FaceDirection) -> &T {
+        match self {
+            BlockTextures::Uniform(texture) => texture,
+            BlockTextures::TopSideBottom([top, side, bottom]) => match face_direction.into() {
+                SideDirection::Top => top,
+                SideDirection::Side => side,
+                SideDirection::Bottom => bottom,
+            },
+            BlockTextures::SixFaces([top, bottom, north, south, east, west]) => match face_direction {
+                FaceDirection::Top => top,
+                FaceDirection::Bottom => bottom,
+                FaceDirection::North => north,
+                FaceDirection::South => south,
+                FaceDirection::East => east,
+                FaceDirection::West => west,
+            },
+        }
+    }
+
+    // In the order they are given in block files
+    pub fn all(&self) -> &[T] {
+        match self {
+            BlockTextures::Uniform(texture) => std::slice::from_ref(texture),
+            BlockTextures::TopSideBottom(textures) => textures,
+            BlockTextures::SixFaces(textures) => textures,
+        }
+    }
+}
+
+// Which faces between this block and a transparent neighbour get meshed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum InternalFaces {
+    #[default]
+    Hidden,
+    DifferentBlocks,
+    Always,
+}
+
+// Space a block fills in its voxel, anything but a full cube is meshed one block at a time instead of greedily
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockShape {
+    #[default]
+    Full,
+    // Fills the lower or the upper half
+    Slab {
+        top: bool,
+    },
+    // Two diagonal quads crossing in the middle, for plants. Doesn't fill any space, so nothing collides with it
+    Cross,
+}
+
+impl BlockShape {
+    pub const ALL: [BlockShape; 4] = [
+        BlockShape::Full,
+        BlockShape::Slab { top: false },
+        BlockShape::Slab { top: true },
+        BlockShape::Cross,
+    ];
+
+    pub const fn is_full(&self) -> bool {
+        matches!(self, BlockShape::Full)
+    }
+
+    // Lower and upper corner of the box the shape fills, in half blocks from the lower corner of its voxel
+    pub const fn half_block_bounds(&self) -> ([u32; 3], [u32; 3]) {
+        match self {
+            BlockShape::Full | BlockShape::Cross => ([0, 0, 0], [2, 2, 2]),
+            BlockShape::Slab { top: false } => ([0, 0, 0], [2, 1, 2]),
+            BlockShape::Slab { top: true } => ([0, 1, 0], [2, 2, 2]),
+        }
+    }
+}
+
+// An animated texture is a strip of `frames` equally sized frames stacked from top to bottom in one image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TextureAnimation {
+    pub frames: u32,
+    pub frame_duration_ms: u32,
+}
+
+// A texture in a block file is either just its name or its name with the animation of the strip it is
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum TextureDescriptor {
+    Static(String),
+    Animated {
+        name: String,
+        #[serde(flatten)]
+        animation: TextureAnimation,
+    },
+}
+
+impl TextureDescriptor {
+    fn name(&self) -> &str {
+        match self {
+            TextureDescriptor::Static(name) | TextureDescriptor::Animated { name, .. } => name,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 struct BlockDescriptor {
     name: String,
-    texture: Vec<String>,
+    texture: Vec<TextureDescriptor>,
     is_transparent: bool,
     is_solid: bool,
     is_lightsource: bool,
+    // Light given off by light sources, full strength white if left out
+    #[serde(default)]
+    light: Option<LightSource>,
+    #[serde(default)]
+    internal_faces: InternalFaces,
+    #[serde(default)]
+    shape: BlockShape,
+    #[serde(default)]
+    is_double_sided: bool,
+    // Surfaces of fluids sit a little below the top of the block and move up and down
+    #[serde(default)]
+    is_fluid: bool,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Block {
-    texture_id: Option<Either<TextureID, [TextureID; 3]>>,
+    texture_id: Option<BlockTextures<TextureID>>,
     is_transparent: bool,
     is_solid: bool,
     light_source: Option<Box<LightSource>>,
     sunlit: bool,
+    #[serde(default)]
+    internal_faces: InternalFaces,
+    #[serde(default)]
+    shape: BlockShape,
+    #[serde(default)]
+    is_double_sided: bool,
+    #[serde(default)]
+    is_fluid: bool,
 }
 
-impl From<BlockDescriptor> for Block {
-    fn from(val: BlockDescriptor) -> Self {
-        assert!(
-            matches!(val.texture.len(), 0 | 1 | 3),
-            "Attempting to create block `{:}` with invalid number of textures - {:}. Only 0, 1 or 3 textures are valid",
-            val.name,
-            val.texture.len()
-        );
+impl TryFrom<BlockDescriptor> for Block {
+    type Error = String;
 
-        let tmp = Block {
-            texture_id:
-                match val.texture.len() {
-                    0 => None,
-                    1 => Some(Either::Left(TextureID::from(val.texture[0].as_str()))),
-                    3 => Some(Either::Right([TextureID::from(val.texture[0].as_str()), TextureID::from(val.texture[1].as_str()), TextureID::from(val.texture[2].as_str())])),
-                    _ => panic!("Attempting to create block `{:}` with invalid number of textures - {:}. Only 0, 1 or 3 textures are valid", val.name, val.texture.len())
-                },
+    fn try_from(val: BlockDescriptor) -> Result<Self, Self::Error> {
+        if !matches!(val.texture.len(), 0 | 1 | 3 | 6) {
+            return Err(format!(
+                "Block `{:}` has an invalid number of textures - {:}. Only 0, 1, 3 or 6 textures are valid",
+                val.name,
+                val.texture.len()
+            ));
+        }
+        if let Some(light) = &val.light {
+            if light.strength() > MAX_LIGHT_VAL || !light.is_valid() {
+                return Err(format!(
+                    "Block `{:}` has an invalid light {:?}. Channels can be at most {:}, one must be above 0",
+                    val.name, light, MAX_LIGHT_VAL
+                ));
+            }
+            if !val.is_lightsource {
+                log::warn!(
+                    "Block `{:}` has a light but isn't a light source, ignoring it",
+                    val.name
+                );
+            }
+        }
+
+        Ok(Block {
+            texture_id: BlockTextures::from_slice(
+                &val.texture
+                    .iter()
+                    .map(|texture| TextureID::from(texture.name()))
+                    .collect::<Vec<_>>(),
+            ),
             is_transparent: val.is_transparent,
             is_solid: val.is_solid,
             light_source: if val.is_lightsource {
-                Some(Box::default())
+                Some(Box::new(val.light.unwrap_or_default()))
             } else {
                 None
             },
-            sunlit: false
-        };
-        tmp
+            sunlit: false,
+            internal_faces: val.internal_faces,
+            shape: val.shape,
+            is_double_sided: val.is_double_sided,
+            is_fluid: val.is_fluid,
+        })
     }
 }
 
@@ -141,8 +320,14 @@ impl Block {
         &mut self.is_transparent
     }
 
+    // Partial blocks let light through to their neighbours, but still shade everything below them from the sky
     pub const fn is_opaque(&self) -> bool {
-        self.is_rendered() && !self.is_transparent
+        self.casts_shadow() && self.shape.is_full()
+    }
+
+    // Cross blocks are too thin to shade anything
+    pub const fn casts_shadow(&self) -> bool {
+        self.is_rendered() && !self.is_transparent && !matches!(self.shape, BlockShape::Cross)
     }
 
     pub const fn is_sunlit(&self) -> bool {
@@ -157,6 +342,53 @@ impl Block {
         &mut self.is_solid
     }
 
+    pub const fn internal_faces(&self) -> InternalFaces {
+        self.internal_faces
+    }
+
+    pub fn internal_faces_mut(&mut self) -> &mut InternalFaces {
+        &mut self.internal_faces
+    }
+
+    pub const fn shape(&self) -> BlockShape {
+        self.shape
+    }
+
+    pub fn shape_mut(&mut self) -> &mut BlockShape {
+        &mut self.shape
+    }
+
+    // Drawn without backface culling, for thin blocks that have to be seen from both sides
+    pub const fn is_double_sided(&self) -> bool {
+        self.is_double_sided
+    }
+
+    pub fn is_double_sided_mut(&mut self) -> &mut bool {
+        &mut self.is_double_sided
+    }
+
+    pub const fn is_fluid(&self) -> bool {
+        self.is_fluid
+    }
+
+    pub fn is_fluid_mut(&mut self) -> &mut bool {
+        &mut self.is_fluid
+    }
+
+    // Whether a point inside the voxel of the block, relative to its lower corner, is blocked by it
+    // Lower and upper corner of the part of the voxel other boxes collide with, None if nothing collides with the block
+    pub fn collision_box(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        if !self.is_solid || self.shape == BlockShape::Cross {
+            return None;
+        }
+
+        let (min, max) = self.shape.half_block_bounds();
+        Some((
+            Vector3::from(min.map(|val| val as f32 / 2.0)),
+            Vector3::from(max.map(|val| val as f32 / 2.0)),
+        ))
+    }
+
     pub fn light_source(&self) -> Option<&LightSource> {
         self.light_source.as_deref()
     }
@@ -169,22 +401,50 @@ impl Block {
         self.light_source = light_source.map(Box::new);
     }
 
-    pub fn texture_id(&self) -> &Option<Either<TextureID, [TextureID; 3]>> {
+    pub fn texture_id(&self) -> &Option<BlockTextures<TextureID>> {
         &self.texture_id
     }
 
-    pub fn set_texture_id(&mut self, texture_id: Option<Either<TextureID, [TextureID; 3]>>) {
+    pub fn set_texture_id(&mut self, texture_id: Option<BlockTextures<TextureID>>) {
         self.texture_id = texture_id;
     }
+
+    // Sunlight changes with the surroundings of a block, so it is left out when telling kinds of blocks apart
+    fn without_sunlight(&self) -> Block {
+        let mut block = self.clone();
+        block.sunlit = false;
+        block
+    }
+
+    // The block after applying pairs of old and new definitions in order, None if none of them matched
+    pub fn replaced(&self, replacements: &[(Block, Block)]) -> Option<Block> {
+        let mut out = None;
+        for (old_block, new_block) in replacements {
+            if out.as_ref().unwrap_or(self).without_sunlight() == *old_block {
+                let mut block = new_block.to_owned();
+                block.sunlit = self.sunlit;
+                out = Some(block);
+            }
+        }
+
+        out
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct BlockManager {
-    blocks: FxHashMap<String, (Block, Option<Either<String, [String; 3]>>)>,
+    blocks: FxHashMap<String, (Block, Option<BlockTextures<String>>)>,
     all_block_names: Vec<String>,
     all_rendered_block_names: Vec<String>,
     all_texture_names: Vec<String>,
     texture_id_to_name: FxHashMap<TextureID, String>,
+    texture_animations: FxHashMap<String, TextureAnimation>,
+}
+
+impl Default for BlockManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BlockManager {
@@ -195,72 +455,16 @@ impl BlockManager {
             all_rendered_block_names: Vec::new(),
             all_texture_names: Vec::new(),
             texture_id_to_name: FxHashMap::default(),
+            texture_animations: FxHashMap::default(),
         };
 
-        let paths: Vec<String>;
-        cfg_if! {
-            if #[cfg(feature = "portable")] {
-                paths = {
-                    let mut out = Vec::new();
-                    for entry in RESOURCE_DIR.get_dir("block").unwrap().entries() {
-                        if let include_dir::DirEntry::File(_) = entry {
-                            if let Some(file_name) = entry.path().file_name() {
-                                if let Some(name) = file_name.to_str() {
-                                    out.push(name.to_string());
-                                }
-                            }
-                        }
-                    }
-                    out
-                };
-            } else {
-                use std::fs;
-
-                paths = fs::read_dir(RESOURCE_PATH.join("block"))
-                    .unwrap()
-                    .map(|dir| dir.unwrap().file_name().to_string_lossy().to_string())
-                    .collect();
-            }
-        }
-
-        for block_file_name in paths {
-            let path = PathBuf::new().join("block").join(&block_file_name);
-            match load_resource_string(path) {
-                Ok(block_string) => match serde_yaml::from_str::<BlockDescriptor>(block_string.as_str()) {
-                    Ok(block_descriptor) => {
-                        out.blocks
-                            .insert(block_descriptor.name.clone(), (block_descriptor.clone().into(), match block_descriptor.texture.len() {
-                    0 => None,
-                    1 => Some(Either::Left(block_descriptor.texture[0].clone())),
-                    3 => Some(Either::Right([block_descriptor.texture[0].clone(), block_descriptor.texture[1].clone(), block_descriptor.texture[2].clone()])),
-                    _ => panic!("Attempting to create block `{:}` with invalid number of textures - {:}. Only 0, 1 or 3 textures are valid", block_descriptor.name, block_descriptor.texture.len())
-                }));
-                    }
-                    Err(e) => log::error!("Failed parsing `{block_file_name:}` - {e:?}"),
-                },
-                Err(e) => {
-                    log::error!("Attempted to load block `{block_file_name:}` without a block file - {e:?}");
-                }
-            }
-        }
+        out.load_block_files();
 
         out.all_texture_names = {
             let mut tmp = out
                 .blocks
                 .iter()
-                .map(|(_, (_, texture))| {
-                    if let Some(textures) = texture {
-                        match textures {
-                            Either::Left(texture) => vec![texture.clone()],
-                            Either::Right([texture_top, texture_side, texture_bottom]) => {
-                                vec![texture_top.clone(), texture_side.clone(), texture_bottom.clone()]
-                            }
-                        }
-                    } else {
-                        vec![]
-                    }
-                })
-                .flatten()
+                .flat_map(|(_, (_, texture))| texture.iter().flat_map(|textures| textures.all().iter().cloned()))
                 .collect::<Vec<_>>();
 
             let to_extend: Vec<String>;
@@ -280,6 +484,8 @@ impl BlockManager {
                         out
                     };
                 } else {
+                    use std::fs;
+
                     to_extend = fs::read_dir(RESOURCE_PATH.join("texture")).unwrap().filter_map(|dir| {
                         let dir_entry = dir.unwrap();
                         if dir_entry.file_type().unwrap().is_file() {
@@ -305,8 +511,136 @@ impl BlockManager {
             tmp
         };
 
-        out.all_block_names = {
-            let mut tmp = out
+        out.update_block_names();
+
+        out.texture_id_to_name = out
+            .all_texture_names
+            .iter()
+            .map(|texture_name| (TextureID::from(texture_name.as_str()), texture_name.to_owned()))
+            .collect();
+
+        out
+    }
+
+    // Re-reads the block files, so blocks can be changed without a restart. The texture atlas is only built at startup,
+    // so blocks that now use a texture it doesn't have keep their old definition, like blocks whose file is missing or
+    // invalid. Returns the old and new definition of every block that changed
+    pub fn reload(&mut self) -> Vec<(Block, Block)> {
+        let old_blocks = mem::take(&mut self.blocks);
+        let old_texture_animations = mem::take(&mut self.texture_animations);
+        self.load_block_files();
+
+        let texture_names_unknown = self
+            .blocks
+            .iter()
+            .filter(|(_, (_, texture_names))| {
+                texture_names.iter().any(|textures| {
+                    textures
+                        .all()
+                        .iter()
+                        .any(|texture_name| self.all_texture_names.binary_search(texture_name).is_err())
+                })
+            })
+            .map(|(block_name, _)| block_name.to_owned())
+            .collect::<Vec<_>>();
+        for block_name in texture_names_unknown {
+            log::warn!("Block `{block_name:}` uses a texture that wasn't loaded at startup, restart to apply it");
+            match old_blocks.get(&block_name) {
+                Some(old_block) => self.blocks.insert(block_name, old_block.to_owned()),
+                None => self.blocks.remove(&block_name),
+            };
+        }
+
+        for (block_name, old_block) in &old_blocks {
+            if !self.blocks.contains_key(block_name) {
+                log::warn!("Block `{block_name:}` has no valid block file any more, keeping its old definition");
+                self.blocks.insert(block_name.to_owned(), old_block.to_owned());
+            }
+        }
+
+        if self.texture_animations != old_texture_animations {
+            log::warn!("Texture animations changed, restart to apply them");
+        }
+        self.texture_animations = old_texture_animations;
+
+        self.update_block_names();
+
+        self.blocks
+            .iter()
+            .filter_map(|(block_name, (block, _))| {
+                let (old_block, _) = old_blocks.get(block_name)?;
+                (old_block != block).then(|| (old_block.to_owned(), block.to_owned()))
+            })
+            .collect()
+    }
+
+    fn load_block_files(&mut self) {
+        let paths: Vec<String>;
+        cfg_if! {
+            if #[cfg(feature = "portable")] {
+                paths = {
+                    let mut out = Vec::new();
+                    for entry in RESOURCE_DIR.get_dir("block").unwrap().entries() {
+                        if let include_dir::DirEntry::File(_) = entry {
+                            if let Some(file_name) = entry.path().file_name() {
+                                if let Some(name) = file_name.to_str() {
+                                    out.push(name.to_string());
+                                }
+                            }
+                        }
+                    }
+                    out
+                };
+            } else {
+                use std::fs;
+
+                paths = fs::read_dir(RESOURCE_PATH.join("block"))
+                    .unwrap()
+                    .map(|dir| dir.unwrap().file_name().to_string_lossy().to_string())
+                    .collect();
+            }
+        }
+
+        for block_file_name in paths {
+            let path = PathBuf::new().join("block").join(&block_file_name);
+            match load_resource_string(path) {
+                Ok(block_string) => match serde_yaml::from_str::<BlockDescriptor>(block_string.as_str()) {
+                    Ok(block_descriptor) => {
+                        let block_name = block_descriptor.name.clone();
+                        let texture_descriptors = block_descriptor.texture.clone();
+                        let block = match Block::try_from(block_descriptor) {
+                            Ok(block) => block,
+                            Err(e) => {
+                                log::error!("Failed loading `{block_file_name:}` - {e:}");
+                                continue;
+                            }
+                        };
+
+                        for texture in &texture_descriptors {
+                            if let TextureDescriptor::Animated { name, animation } = texture {
+                                self.texture_animations.insert(name.to_owned(), *animation);
+                            }
+                        }
+                        let texture_names = BlockTextures::from_slice(
+                            &texture_descriptors
+                                .iter()
+                                .map(|texture| texture.name().to_owned())
+                                .collect::<Vec<_>>(),
+                        );
+                        self.blocks.insert(block_name, (block, texture_names));
+                    }
+                    Err(e) => log::error!("Failed parsing `{block_file_name:}` - {e:?}"),
+                },
+                Err(e) => {
+                    log::error!("Attempted to load block `{block_file_name:}` without a block file - {e:?}");
+                }
+            }
+        }
+    }
+
+    fn update_block_names(&mut self) {
+        self.all_block_names = {
+            let mut tmp = self
                 .blocks
                 .iter()
                 .map(|(block_name, (_, _))| block_name.to_owned())
@@ -318,12 +652,12 @@ impl BlockManager {
             tmp
         };
 
-        out.all_rendered_block_names = {
-            let mut tmp = out
+        self.all_rendered_block_names = {
+            let mut tmp = self
                 .blocks
                 .iter()
                 .map(|(block_name, (_, _))| block_name.to_owned())
-                .filter(|block_name| out.blocks[block_name].0.is_rendered())
+                .filter(|block_name| self.blocks[block_name].0.is_rendered())
                 .collect::<Vec<_>>();
 
             tmp.sort();
@@ -331,14 +665,6 @@ impl BlockManager {
 
             tmp
         };
-
-        out.texture_id_to_name = out
-            .all_texture_names
-            .iter()
-            .map(|texture_name| (TextureID::from(texture_name.as_str()), texture_name.to_owned()))
-            .collect();
-
-        out
     }
 
     pub fn get(&self, k: &str) -> Option<&Block> {
@@ -360,15 +686,49 @@ impl BlockManager {
         self.all_texture_names.as_ref()
     }
 
+    pub fn texture_animations(&self) -> &FxHashMap<String, TextureAnimation> {
+        &self.texture_animations
+    }
+
     pub fn get_texture_name(&self, k: &TextureID) -> Option<&String> {
         self.texture_id_to_name.get(k)
     }
+
+    // None for blocks that were changed in the block editor
+    pub fn block_name(&self, block: &Block) -> Option<&str> {
+        let block = block.without_sunlight();
+
+        self.blocks
+            .iter()
+            .find(|(_, (known_block, _))| known_block.without_sunlight() == block)
+            .map(|(name, _)| name.as_str())
+    }
+
+    // For blocks that didn't come from this block manager, e.g. loaded from a file
+    #[cfg(feature = "save_system")]
+    pub fn is_valid_block(&self, block: &Block) -> bool {
+        let textures_known = block.texture_id().iter().all(|textures| {
+            textures
+                .all()
+                .iter()
+                .all(|texture_id| self.texture_id_to_name.contains_key(texture_id))
+        });
+        let light_source_valid = block.light_source().is_none_or(|light_source| {
+            light_source.strength() <= crate::game::world::MAX_LIGHT_VAL && light_source.is_valid()
+        });
+
+        textures_known && light_source_valid
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash)]
 pub struct BlockBuffer {
     buffer: RleVec<Block>,
     collum_contains_opaque_blocks: Vec<bool>,
+    // Bit FaceDirection::as_index is set if the layer of blocks on that face of the chunk is all opaque. Not saved, see
+    // `update_opaque_faces`
+    #[serde(skip)]
+    opaque_faces: u8,
     light_source_cache: Option<LightPosCache<0>>,
     sunlight_source_cache: Option<LightPosCache<1>>,
     to_update_cache_later: Vec<(Vector3<i32>, CacheUpdateActionKind)>,
@@ -378,14 +738,23 @@ impl BlockBuffer {
     pub fn new(blocks: Vec<Block>) -> Self {
         debug_assert!(blocks.len() == (CHUNK_SIZE as usize).pow(3));
 
+        let opaque_faces = FaceDirection::iter()
+            .filter(|face| {
+                face_layer(*face).all(|pos| {
+                    blocks[ChunkShape::linearize([pos.x as u32, pos.y as u32, pos.z as u32]) as usize].is_opaque()
+                })
+            })
+            .fold(0, |opaque_faces, face| opaque_faces | 1 << face.as_index());
+
         Self {
+            opaque_faces,
             collum_contains_opaque_blocks: {
                 fn check_for_visible_blocks_in_collum(blocks: &Vec<Block>, collum: &Vector2<i32>) -> bool {
                     for y in 0..CHUNK_SIZE as usize {
                         let index = Vector3::new(collum.x, y as i32, collum.y);
 
                         if blocks[ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize]
-                            .is_opaque()
+                            .casts_shadow()
                         {
                             return true;
                         }
@@ -422,6 +791,12 @@ impl BlockBuffer {
         let collum = Vector2::new(in_chunk_pos.x, in_chunk_pos.z);
         self.update_visible_blocks_in_collum(&collum);
 
+        for face in FaceDirection::iter() {
+            if on_face_layer(face, in_chunk_pos) {
+                self.update_opaque_face(face);
+            }
+        }
+
         for x in -1..=1 as i32 {
             for y in -1..=1 as i32 {
                 for z in -1..=1 as i32 {
@@ -485,6 +860,29 @@ impl BlockBuffer {
         }
     }
 
+    // Adds how many of each kind of block the buffer contains, one run at a time instead of block by block
+    // Returns whether any block was replaced, the light source caches are cleared if so
+    pub fn replace_blocks(&mut self, replacements: &[(Block, Block)]) -> bool {
+        if !self.buffer.runs().any(|run| run.value.replaced(replacements).is_some()) {
+            return false;
+        }
+
+        let blocks = self
+            .buffer
+            .iter()
+            .map(|block| block.replaced(replacements).unwrap_or_else(|| block.to_owned()))
+            .collect();
+        *self = BlockBuffer::new(blocks);
+
+        true
+    }
+
+    pub fn count_blocks(&self, counts: &mut FxHashMap<Block, u64>) {
+        for run in self.buffer.runs() {
+            *counts.entry(run.value.without_sunlight()).or_default() += run.len as u64;
+        }
+    }
+
     pub fn contains_rendered_blocks(&self) -> bool {
         let runs = self.buffer.runs();
         if runs.len() == 1 {
@@ -500,6 +898,27 @@ impl BlockBuffer {
         false
     }
 
+    // Whether nothing inside the chunk can be seen through the given face of it
+    pub fn face_fully_opaque(&self, face: FaceDirection) -> bool {
+        self.opaque_faces & 1 << face.as_index() != 0
+    }
+
+    // Buffers loaded from saves come without opaque faces, so they have to be found again
+    #[cfg_attr(not(feature = "save_system"), allow(dead_code))]
+    pub fn update_opaque_faces(&mut self) {
+        for face in FaceDirection::iter() {
+            self.update_opaque_face(face);
+        }
+    }
+
+    fn update_opaque_face(&mut self, face: FaceDirection) {
+        if face_layer(face).all(|pos| self[&pos].is_opaque()) {
+            self.opaque_faces |= 1 << face.as_index();
+        } else {
+            self.opaque_faces &= !(1 << face.as_index());
+        }
+    }
+
     pub fn contains_collum_opaque_blocks(&self, collum: &Vector2<i32>) -> bool {
         self.collum_contains_opaque_blocks[index_from_pos_2d(&collum) as usize]
     }
@@ -507,7 +926,7 @@ impl BlockBuffer {
     fn update_visible_blocks_in_collum(&mut self, collum: &Vector2<i32>) {
         self.collum_contains_opaque_blocks[index_from_pos_2d(&collum) as usize] = (0..CHUNK_SIZE as i32)
             .into_iter()
-            .any(|y| self[&Vector3::new(collum.x, y, collum.y)].is_opaque())
+            .any(|y| self[&Vector3::new(collum.x, y, collum.y)].casts_shadow())
     }
 
     pub fn update_sunlight_in_collum(&mut self, collum: &Vector2<i32>, highest_block_in_chunk_sees_sky: bool) {
@@ -517,7 +936,7 @@ impl BlockBuffer {
             let block_in_chunk_pos = Vector3::new(collum.x, y, collum.y);
             let block = &self[&block_in_chunk_pos];
 
-            if !found_visible && block.is_opaque() {
+            if !found_visible && block.casts_shadow() {
                 found_visible = true
             }
 
@@ -569,6 +988,12 @@ impl BlockBuffer {
         self.light_source_cache = Some(light_source_cache);
         self.sunlight_source_cache = Some(sunlight_source_cache);
     }
+
+    pub fn clear_light_source_caches(&mut self) {
+        self.light_source_cache = None;
+        self.sunlight_source_cache = None;
+        self.to_update_cache_later.clear();
+    }
 }
 
 impl Index<&Vector3<i32>> for BlockBuffer {
@@ -579,6 +1004,36 @@ impl Index<&Vector3<i32>> for BlockBuffer {
     }
 }
 
+// Index along the axis of the face of the layer of blocks on that face of a chunk
+const fn face_layer_index(face: FaceDirection) -> (usize, i32) {
+    match face {
+        FaceDirection::Top => (1, CHUNK_SIZE as i32 - 1),
+        FaceDirection::Bottom => (1, 0),
+        FaceDirection::West => (0, 0),
+        FaceDirection::East => (0, CHUNK_SIZE as i32 - 1),
+        FaceDirection::North => (2, 0),
+        FaceDirection::South => (2, CHUNK_SIZE as i32 - 1),
+    }
+}
+
+fn on_face_layer(face: FaceDirection, in_chunk_pos: &Vector3<i32>) -> bool {
+    let (axis, index) = face_layer_index(face);
+    in_chunk_pos[axis] == index
+}
+
+fn face_layer(face: FaceDirection) -> impl Iterator<Item = Vector3<i32>> {
+    let (axis, index) = face_layer_index(face);
+    (0..CHUNK_SIZE as i32).flat_map(move |a| {
+        (0..CHUNK_SIZE as i32).map(move |b| {
+            let mut pos = Vector3::new(0, 0, 0);
+            pos[axis] = index;
+            pos[(axis + 1) % 3] = a;
+            pos[(axis + 2) % 3] = b;
+            pos
+        })
+    })
+}
+
 // Kind == 0 for LightSource, Kind == 1 for SunlightSource
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LightPosCache<const KIND: u8> {
@@ -587,6 +1042,7 @@ pub struct LightPosCache<const KIND: u8> {
 
 impl<const KIND: u8> LightPosCache<KIND> {
     pub fn new(surrounding_blocks: &[Arc<BlockBuffer>; 27]) -> Self {
+        profile_span!("light_pos_cache");
         let mut self_temp = Self {
             cache: FxHashSet::default(),
         };
@@ -713,3 +1169,105 @@ impl<const KIND: u8> Hash for LightPosCache<KIND> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_from_yaml(light: &str, is_lightsource: bool) -> Result<Block, String> {
+        let yaml = format!(
+            "name: \"Test\"\nis_transparent: false\nis_solid: true\nis_lightsource: {is_lightsource}\ntexture: [\"pure_white\"]\n{light}"
+        );
+        serde_yaml::from_str::<BlockDescriptor>(&yaml).unwrap().try_into()
+    }
+
+    #[test]
+    fn descriptors_set_their_light() {
+        let per_channel =
+            block_from_yaml("light: {strength_red: 12, strength_green: 0, strength_blue: 3}", true).unwrap();
+        assert_eq!(per_channel.light_source(), Some(&LightSource::new(12, 0, 3)));
+        let shared = block_from_yaml("light: {red: true, green: false, blue: true, strength: 9}", true).unwrap();
+        assert_eq!(shared.light_source(), Some(&LightSource::new(9, 0, 9)));
+
+        // Light sources without a light are full strength white, other blocks give off no light even with one
+        assert_eq!(
+            block_from_yaml("", true).unwrap().light_source(),
+            Some(&LightSource::default())
+        );
+        assert_eq!(
+            block_from_yaml("light: {strength_red: 12, strength_green: 0, strength_blue: 3}", false)
+                .unwrap()
+                .light_source(),
+            None
+        );
+        assert_eq!(block_from_yaml("", false).unwrap().light_source(), None);
+
+        let block_manager = BlockManager::new();
+        assert_eq!(
+            Block::new_with_default("Light", &block_manager).light_source(),
+            Some(&LightSource::default())
+        );
+    }
+
+    #[test]
+    fn invalid_descriptors_are_refused() {
+        let too_strong = block_from_yaml(
+            &format!(
+                "light: {{strength_red: {}, strength_green: 0, strength_blue: 0}}",
+                MAX_LIGHT_VAL + 1
+            ),
+            true,
+        );
+        assert!(too_strong.unwrap_err().contains("invalid light"));
+        let dark = block_from_yaml("light: {strength_red: 0, strength_green: 0, strength_blue: 0}", true);
+        assert!(dark.unwrap_err().contains("invalid light"));
+
+        let two_textures = serde_yaml::from_str::<BlockDescriptor>(
+            "name: \"Test\"\nis_transparent: false\nis_solid: true\nis_lightsource: false\ntexture: [\"pure_white\", \"pure_white\"]",
+        )
+        .unwrap();
+        assert!(Block::try_from(two_textures)
+            .unwrap_err()
+            .contains("invalid number of textures"));
+    }
+
+    #[test]
+    fn reloading_keeps_blocks_without_a_valid_file() {
+        let mut block_manager = BlockManager::new();
+        let stone = block_manager.blocks["Stone"].clone();
+        block_manager.blocks.insert("Removed".to_string(), stone.clone());
+
+        assert!(block_manager.reload().is_empty());
+        assert_eq!(block_manager.blocks["Removed"], stone);
+        assert!(block_manager.all_block_names().contains(&"Removed".to_string()));
+    }
+
+    #[test]
+    fn opaque_faces_follow_edits_and_saves() {
+        let block_manager = BlockManager::new();
+        let (stone, air) = (
+            Block::new_with_default("Stone", &block_manager),
+            Block::new_with_default("Air", &block_manager),
+        );
+        let mut blocks = BlockBuffer::new(vec![stone.clone(); (CHUNK_SIZE as usize).pow(3)]);
+        assert!(FaceDirection::iter().all(|face| blocks.face_fully_opaque(face)));
+
+        // A hole in the top layer opens only the top face, a block inside the chunk opens none
+        let top = Vector3::new(5, CHUNK_SIZE as i32 - 1, 7);
+        blocks.set(&top, air.clone());
+        blocks.set(&Vector3::new(5, 5, 5), air.clone());
+        for face in FaceDirection::iter() {
+            assert_eq!(blocks.face_fully_opaque(face), face != FaceDirection::Top, "{:?}", face);
+        }
+        blocks.set(&top, stone);
+        assert!(blocks.face_fully_opaque(FaceDirection::Top));
+
+        // Opaque faces aren't saved and are found again after loading
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&blocks, &mut bytes).unwrap();
+        let mut loaded: BlockBuffer = ciborium::from_reader(bytes.as_slice()).unwrap();
+        assert!(!loaded.face_fully_opaque(FaceDirection::Top));
+        loaded.update_opaque_faces();
+        assert!(FaceDirection::iter().all(|face| loaded.face_fully_opaque(face)));
+    }
+}
@@ -5,10 +5,12 @@ use std::{
 
 use block_mesh::ndshape::ConstShape3u32;
 use cgmath::{Vector2, Vector3};
-use either::Either;
+use instant::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 
-use crate::game::world::{Block, BlockBuffer, ChunkData, ChunkMesh, ChunkMeshRaw, LightBuffer, LightPosCache};
+use crate::game::world::{
+    Block, BlockBuffer, ChunkBufferPool, ChunkData, ChunkMesh, ChunkMeshRaw, LightBuffer, LightPosCache, PreviousLights,
+};
 
 pub const CHUNK_SIZE: u32 = 32;
 pub const CHUNK_SIZE_VEC: Vector3<i32> = Vector3::new(CHUNK_SIZE as i32, CHUNK_SIZE as i32, CHUNK_SIZE as i32);
@@ -23,12 +25,22 @@ pub struct Chunk {
     light_pos_cache_requested_for_state: Option<u64>,
     #[serde(skip)]
     lights_requested_for_state: Option<u64>,
+    // The blocks the current lights were computed from and the sunlight spread used, so a relight after an edit only
+    // has to update the area around it
+    #[serde(skip)]
+    lit_from: Option<([Arc<BlockBuffer>; 27], u32)>,
     lights_up_to_date: bool,
     #[serde(skip)]
-    mesh: Option<Either<(ChunkMesh, ChunkMesh), (ChunkMeshRaw, ChunkMeshRaw)>>,
+    mesh: Option<(ChunkMesh, ChunkMesh, ChunkMesh)>,
+    // Newest mesh from the mesh generator, uploaded into the buffers of the current one the next time it is drawn
+    #[serde(skip)]
+    mesh_raw: Option<(ChunkMeshRaw, ChunkMeshRaw, ChunkMeshRaw)>,
     #[serde(skip)]
     mesh_requested_for_state: Option<u64>,
     mesh_up_to_date: bool,
+    // When the chunk was last outdated by an edit since its last mesh request, so rapid edits can share one remesh
+    #[serde(skip)]
+    outdated_at: Option<Instant>,
 }
 
 impl Chunk {
@@ -37,10 +49,13 @@ impl Chunk {
             data: ChunkData::new(blocks),
             light_pos_cache_requested_for_state: None,
             lights_requested_for_state: None,
+            lit_from: None,
             lights_up_to_date: false,
             mesh: None,
+            mesh_raw: None,
             mesh_requested_for_state: None,
             mesh_up_to_date: false,
+            outdated_at: None,
         }
     }
 
@@ -54,26 +69,60 @@ impl Chunk {
         self.data.set_block(in_chunk_pos, block)
     }
 
+    pub fn set_blocks(&mut self, blocks: Vec<(Vector3<i32>, Block)>) -> Vec<(Vector2<i32>, bool)> {
+        self.data.set_blocks(blocks)
+    }
+
+    // Swaps old block definitions for new ones, see `BlockBuffer::replace_blocks`
+    pub fn replace_blocks(&mut self, replacements: &[(Block, Block)]) -> bool {
+        let replaced = self.data.replace_blocks(replacements);
+        if replaced {
+            self.set_light_pos_cache_requested(false);
+        }
+
+        replaced
+    }
+
     // --------------------------------
 
-    pub fn mesh(&mut self, device: &wgpu::Device) -> Option<&(ChunkMesh, ChunkMesh)> {
-        if self.mesh.is_some() {
-            if self.mesh.as_ref().unwrap().is_right() {
-                let mesh_raw = self.mesh.take().unwrap().right().unwrap();
-                self.mesh = Some(Either::Left((
-                    ChunkMesh::new(mesh_raw.0, device),
-                    ChunkMesh::new(mesh_raw.1, device),
-                )))
+    pub fn mesh(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &mut ChunkBufferPool,
+    ) -> Option<&(ChunkMesh, ChunkMesh, ChunkMesh)> {
+        if let Some(mesh_raw) = self.mesh_raw.take() {
+            if let Some(mesh) = self.mesh.as_mut() {
+                mesh.0.replace(mesh_raw.0, device, queue, pool);
+                mesh.1.replace(mesh_raw.1, device, queue, pool);
+                mesh.2.replace(mesh_raw.2, device, queue, pool);
+            } else {
+                self.mesh = Some((
+                    ChunkMesh::new(mesh_raw.0, device, queue, pool),
+                    ChunkMesh::new(mesh_raw.1, device, queue, pool),
+                    ChunkMesh::new(mesh_raw.2, device, queue, pool),
+                ))
             }
-            Some(self.mesh.as_ref().unwrap().as_ref().left().unwrap())
-        } else {
-            None
         }
+        self.mesh.as_ref()
     }
 
-    pub fn set_mesh(&mut self, mesh_raw: (ChunkMeshRaw, ChunkMeshRaw)) {
+    pub fn set_mesh(&mut self, mesh_raw: (ChunkMeshRaw, ChunkMeshRaw, ChunkMeshRaw)) {
         self.mesh_up_to_date = true;
-        self.mesh = Some(Either::Right(mesh_raw))
+        self.mesh_raw = Some(mesh_raw)
+    }
+
+    pub fn has_mesh(&self) -> bool {
+        self.mesh.is_some() || self.mesh_raw.is_some()
+    }
+
+    // Hands the buffers of the uploaded mesh back to the pool, for a chunk that is about to be unloaded
+    pub fn recycle_mesh(&mut self, pool: &mut ChunkBufferPool) {
+        if let Some(mesh) = self.mesh.take() {
+            mesh.0.recycle(pool);
+            mesh.1.recycle(pool);
+            mesh.2.recycle(pool);
+        }
     }
 
     pub fn mesh_requested(&self) -> bool {
@@ -86,6 +135,7 @@ impl Chunk {
 
     pub fn set_mesh_requested(&mut self, val: bool) {
         if val {
+            self.outdated_at = None;
             self.mesh_requested_for_state = Some(self.state_hash())
         } else {
             self.mesh_requested_for_state = None
@@ -97,7 +147,13 @@ impl Chunk {
     }
 
     pub fn set_mesh_outdated(&mut self) {
-        self.mesh_up_to_date = false
+        self.mesh_up_to_date = false;
+        self.outdated_at = Some(Instant::now());
+    }
+
+    // How long ago the chunk was last outdated, None if it wasn't since its last mesh request
+    pub fn outdated_for(&self) -> Option<Duration> {
+        self.outdated_at.map(|outdated_at| outdated_at.elapsed())
     }
 
     // --------------------------------
@@ -111,6 +167,11 @@ impl Chunk {
             .set_light_source_caches(light_source_cache, sunlight_source_cache)
     }
 
+    pub fn clear_light_source_caches(&mut self) {
+        self.data.clear_light_source_caches();
+        self.set_light_pos_cache_requested(false);
+    }
+
     pub fn light_pos_cache_requested(&self) -> bool {
         if let Some(hash) = self.light_pos_cache_requested_for_state {
             hash == self.state_hash()
@@ -133,11 +194,22 @@ impl Chunk {
         self.data.lights()
     }
 
-    pub fn set_lights(&mut self, lights: LightBuffer) {
+    pub fn set_lights(&mut self, lights: LightBuffer, lit_from: Option<([Arc<BlockBuffer>; 27], u32)>) {
         self.lights_up_to_date = true;
+        self.lit_from = lit_from;
         self.data.set_lights(lights)
     }
 
+    pub fn previous_lights(&self) -> Option<PreviousLights> {
+        let (surrounding_blocks, sunlight_spread) = self.lit_from.clone()?;
+
+        Some(PreviousLights {
+            lights: self.lights()?,
+            surrounding_blocks,
+            sunlight_spread,
+        })
+    }
+
     pub fn lights_requested(&self) -> bool {
         if let Some(hash) = self.lights_requested_for_state {
             hash == self.state_hash()
@@ -159,7 +231,8 @@ impl Chunk {
     }
 
     pub fn set_lights_outdated(&mut self) {
-        self.lights_up_to_date = false
+        self.lights_up_to_date = false;
+        self.outdated_at = Some(Instant::now());
     }
 
     // --------------------------------
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use cgmath::{Vector2, Vector3};
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 
 use crate::game::world::{Block, BlockBuffer, LightBuffer, LightPosCache, CHUNK_SIZE};
@@ -39,6 +40,42 @@ impl ChunkData {
         (contains_collum_opaque_block_old, contains_collum_opaque_block_new)
     }
 
+    // Like `set_block` for many blocks, copying the buffer only once. Returns every collum a block was set in and whether
+    // it gained or lost all of its opaque blocks
+    pub fn set_blocks(&mut self, blocks: Vec<(Vector3<i32>, Block)>) -> Vec<(Vector2<i32>, bool)> {
+        let mut buffer = (*self.blocks).clone();
+
+        let mut collums = FxHashMap::default();
+        for (in_chunk_pos, block) in blocks {
+            let collum = Vector2::new(in_chunk_pos.x, in_chunk_pos.z);
+            collums
+                .entry(collum)
+                .or_insert_with(|| buffer.contains_collum_opaque_blocks(&collum));
+            buffer.set(&in_chunk_pos, block);
+        }
+        self.blocks = Arc::new(buffer);
+
+        collums
+            .into_iter()
+            .map(|(collum, contains_collum_opaque_block_old)| {
+                (
+                    collum,
+                    contains_collum_opaque_block_old != self.blocks.contains_collum_opaque_blocks(&collum),
+                )
+            })
+            .collect()
+    }
+
+    pub fn replace_blocks(&mut self, replacements: &[(Block, Block)]) -> bool {
+        let mut blocks = (*self.blocks).clone();
+        let replaced = blocks.replace_blocks(replacements);
+        if replaced {
+            self.blocks = Arc::new(blocks);
+        }
+
+        replaced
+    }
+
     pub fn blocks(&self) -> Arc<BlockBuffer> {
         self.blocks.clone()
     }
@@ -63,6 +100,12 @@ impl ChunkData {
         self.blocks = Arc::new(blocks);
     }
 
+    pub fn clear_light_source_caches(&mut self) {
+        let mut blocks = (*self.blocks).clone();
+        blocks.clear_light_source_caches();
+        self.blocks = Arc::new(blocks);
+    }
+
     pub fn update_sunlight_in_collum(&mut self, collum: &Vector2<u32>, highest_block_in_chunk_sees_sky: bool) {
         let collum = Vector2::new(collum.x as i32, collum.y as i32);
 
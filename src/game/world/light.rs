@@ -1,4 +1,4 @@
-use std::{cmp::max, iter, ops::Index, sync::Arc};
+use std::{cmp::max, mem, ops::Index, sync::Arc};
 
 use block_mesh::ndshape::ConstShape;
 use cgmath::Vector3;
@@ -10,51 +10,105 @@ use strum::IntoEnumIterator;
 use crate::{
     engine::face::FaceDirection,
     game::world::{coordinate_in_surrounding_buffers_cube, BlockBuffer, ChunkShape, CHUNK_SIZE},
-    misc::index::{index_from_relative_pos_surrounding_cubes, relative_pos_surrounding_cubes_from_index},
+    misc::{
+        index::{index_from_relative_pos_surrounding_cubes, relative_pos_surrounding_cubes_from_index},
+        profiling::profile_span,
+    },
 };
 
 pub const MAX_LIGHT_VAL: u8 = 15;
+pub const DEFAULT_MAX_LIGHT_SOURCES: u32 = 256;
+// How many blocks sunlight spreads from sunlit blocks, e.g. under overhangs. The default loses one light level per block
+pub const DEFAULT_SUNLIGHT_SPREAD: u32 = MAX_LIGHT_VAL as u32 - 1;
+// Past this sunlight could have to spread from outside of the surrounding chunks
+pub const MAX_SUNLIGHT_SPREAD: u32 = CHUNK_SIZE - 1;
+// Past this many changed blocks relighting around each of them is about as slow as recomputing the whole chunk
+const MAX_RELIGHT_CHANGES: usize = 128;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "LightSourceRepr")]
 pub struct LightSource {
-    pub red: bool,
-    pub green: bool,
-    pub blue: bool,
-    pub strength: u8,
+    pub strength_red: u8,
+    pub strength_green: u8,
+    pub strength_blue: u8,
 }
 
 impl LightSource {
-    pub fn new(red: bool, green: bool, blue: bool, strength: u8) -> Self {
-        debug_assert!(strength <= MAX_LIGHT_VAL);
-        debug_assert!(red || green || blue);
+    pub fn new(strength_red: u8, strength_green: u8, strength_blue: u8) -> Self {
+        debug_assert!(
+            strength_red <= MAX_LIGHT_VAL && strength_green <= MAX_LIGHT_VAL && strength_blue <= MAX_LIGHT_VAL
+        );
+        debug_assert!(strength_red > 0 || strength_green > 0 || strength_blue > 0);
 
         Self {
-            red,
-            green,
-            blue,
-            strength,
+            strength_red,
+            strength_green,
+            strength_blue,
         }
     }
 
     pub fn is_valid(&self) -> bool {
-        debug_assert!(self.strength <= MAX_LIGHT_VAL);
+        debug_assert!(self.strength() <= MAX_LIGHT_VAL);
 
-        self.red || self.green || self.blue
+        self.strength() > 0
+    }
+
+    // Strength of the brightest channel, which decides how far the light spreads
+    pub fn strength(&self) -> u8 {
+        max(self.strength_red, max(self.strength_green, self.strength_blue))
     }
 
     pub fn light_raw(&self) -> [u8; 4] {
-        [
-            if self.red { self.strength } else { 0 },
-            if self.green { self.strength } else { 0 },
-            if self.blue { self.strength } else { 0 },
-            0,
-        ]
+        [self.strength_red, self.strength_green, self.strength_blue, 0]
     }
 }
 
 impl Default for LightSource {
     fn default() -> Self {
-        Self::new(true, true, true, MAX_LIGHT_VAL)
+        Self::new(MAX_LIGHT_VAL, MAX_LIGHT_VAL, MAX_LIGHT_VAL)
+    }
+}
+
+// Saves from before each channel had its own strength store which channels are on and one shared strength
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LightSourceRepr {
+    PerChannel {
+        strength_red: u8,
+        strength_green: u8,
+        strength_blue: u8,
+    },
+    Shared {
+        red: bool,
+        green: bool,
+        blue: bool,
+        strength: u8,
+    },
+}
+
+impl From<LightSourceRepr> for LightSource {
+    fn from(value: LightSourceRepr) -> Self {
+        match value {
+            LightSourceRepr::PerChannel {
+                strength_red,
+                strength_green,
+                strength_blue,
+            } => Self {
+                strength_red,
+                strength_green,
+                strength_blue,
+            },
+            LightSourceRepr::Shared {
+                red,
+                green,
+                blue,
+                strength,
+            } => Self {
+                strength_red: if red { strength } else { 0 },
+                strength_green: if green { strength } else { 0 },
+                strength_blue: if blue { strength } else { 0 },
+            },
+        }
     }
 }
 
@@ -78,12 +132,7 @@ impl LightVal {
 
 impl From<LightSource> for LightVal {
     fn from(value: LightSource) -> Self {
-        Self::new(
-            if value.red { value.strength } else { 0 },
-            if value.green { value.strength } else { 0 },
-            if value.blue { value.strength } else { 0 },
-            0,
-        )
+        Self::new(value.strength_red, value.strength_green, value.strength_blue, 0)
     }
 }
 
@@ -92,9 +141,193 @@ pub struct LightBuffer {
     buffer: RleVec<LightVal>,
 }
 
+// Lights of a chunk together with the blocks and settings they were computed from
+#[derive(Clone)]
+pub struct PreviousLights {
+    pub lights: Arc<LightBuffer>,
+    pub surrounding_blocks: [Arc<BlockBuffer>; 27],
+    pub sunlight_spread: u32,
+}
+
 impl LightBuffer {
-    pub fn new<'a>(surrounding_blocks: [Arc<BlockBuffer>; 27]) -> Option<Self> {
-        let (light_sources, sunlight_sources) = {
+    // Returns the lights and how many light sources were skipped because of max_light_sources
+    pub fn new<'a>(
+        surrounding_blocks: [Arc<BlockBuffer>; 27],
+        max_light_sources: u32,
+        sunlight_spread: u32,
+        scratch: &mut LightScratch,
+    ) -> Option<(Self, u32)> {
+        profile_span!("light_buffer");
+        let (mut light_sources, sunlight_sources) = Self::light_sources_in(&surrounding_blocks)?;
+
+        // Each source is a separate BFS, so a chunk packed with lights could stall the light thread. Sources closest
+        // to the chunk are kept, as they contribute the most to it
+        let mut skipped_light_sources = 0;
+        if light_sources.len() > max_light_sources as usize {
+            let center = Vector3::new(CHUNK_SIZE as i32 / 2, CHUNK_SIZE as i32 / 2, CHUNK_SIZE as i32 / 2);
+
+            light_sources.sort_unstable_by_key(|(in_chunk_pos, _)| {
+                let offset = in_chunk_pos - center;
+                offset.x.abs() + offset.y.abs() + offset.z.abs()
+            });
+            skipped_light_sources = (light_sources.len() - max_light_sources as usize) as u32;
+            light_sources.truncate(max_light_sources as usize);
+        }
+
+        scratch.reset();
+
+        scratch.handle_sunlight(&surrounding_blocks);
+        for in_chunk_pos in sunlight_sources {
+            scratch.spread_sunlight_from_source(
+                in_chunk_pos,
+                sunlight_spread.clamp(1, MAX_SUNLIGHT_SPREAD) as i32,
+                &surrounding_blocks,
+            )
+        }
+
+        for (in_chunk_pos, source) in light_sources {
+            scratch.spread_light_from_source(in_chunk_pos, source, &surrounding_blocks)
+        }
+
+        let lights = Self {
+            buffer: scratch.lights.iter().cloned().collect(),
+        };
+
+        Some((lights, skipped_light_sources))
+    }
+
+    // Relights only around the blocks that changed since `previous` was computed, which gives the same lights as `new`.
+    // None if that isn't possible and the chunk has to be lit with `new` instead
+    pub fn relight(
+        previous: &PreviousLights,
+        surrounding_blocks: [Arc<BlockBuffer>; 27],
+        max_light_sources: u32,
+        sunlight_spread: u32,
+        scratch: &mut LightScratch,
+    ) -> Option<Self> {
+        profile_span!("light_buffer_relight");
+        if previous.sunlight_spread != sunlight_spread {
+            return None;
+        }
+
+        let changed = changed_blocks(&previous.surrounding_blocks, &surrounding_blocks)?;
+        let (light_sources, sunlight_sources) = Self::light_sources_in(&surrounding_blocks)?;
+        // Which sources get skipped depends on all of them, not just the ones near the changes
+        if light_sources.len() > max_light_sources as usize {
+            return None;
+        }
+
+        let spread = sunlight_spread.clamp(1, MAX_SUNLIGHT_SPREAD) as i32;
+        previous
+            .lights
+            .remove_light_from(&changed, spread, &surrounding_blocks, scratch);
+
+        Some(Self::add_light_from(
+            &changed,
+            spread,
+            light_sources,
+            sunlight_sources,
+            &surrounding_blocks,
+            scratch,
+        ))
+    }
+
+    // Loads the lights into scratch and clears all light the changed blocks could have affected. Light can only reach
+    // MAX_LIGHT_VAL - 1 blocks from a light source and sunlight `spread` blocks from a sunlit block, so anything
+    // further from the changes stays the same
+    fn remove_light_from(
+        &self,
+        changed: &[Vector3<i32>],
+        spread: i32,
+        surrounding_blocks: &[Arc<BlockBuffer>; 27],
+        scratch: &mut LightScratch,
+    ) {
+        scratch.lights.clear();
+        scratch.lights.extend(self.buffer.iter().cloned());
+
+        let (light_reach, sunlight_reach) = (MAX_LIGHT_VAL as i32 - 1, spread);
+        let reach = max(light_reach, sunlight_reach);
+        let center_blocks =
+            &surrounding_blocks[index_from_relative_pos_surrounding_cubes(&Vector3::new(0, 0, 0)) as usize];
+
+        let (lowest, highest) = changed.iter().fold(
+            (
+                Vector3::new(i32::MAX, i32::MAX, i32::MAX),
+                Vector3::new(i32::MIN, i32::MIN, i32::MIN),
+            ),
+            |(lowest, highest), pos| {
+                (
+                    Vector3::new(lowest.x.min(pos.x), lowest.y.min(pos.y), lowest.z.min(pos.z)),
+                    Vector3::new(highest.x.max(pos.x), highest.y.max(pos.y), highest.z.max(pos.z)),
+                )
+            },
+        );
+        let range = |lowest: i32, highest: i32| (lowest - reach).max(0)..(highest + reach + 1).min(CHUNK_SIZE as i32);
+
+        for x in range(lowest.x, highest.x) {
+            for y in range(lowest.y, highest.y) {
+                for z in range(lowest.z, highest.z) {
+                    let pos = Vector3::new(x, y, z);
+
+                    if let Some(distance) = distance_to_closest(&pos, changed) {
+                        if distance <= reach {
+                            let mut light_val = scratch[&pos].clone();
+
+                            if distance <= light_reach {
+                                (light_val.red, light_val.green, light_val.blue) = (0, 0, 0);
+                            }
+                            if distance <= sunlight_reach {
+                                light_val.sun = if center_blocks[&pos].is_sunlit() {
+                                    MAX_LIGHT_VAL
+                                } else {
+                                    0
+                                };
+                            }
+
+                            scratch.set(&pos, light_val)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Spreads light again from every source that could reach the area cleared by `remove_light_from`
+    fn add_light_from(
+        changed: &[Vector3<i32>],
+        spread: i32,
+        light_sources: Vec<(Vector3<i32>, LightSource)>,
+        sunlight_sources: Vec<Vector3<i32>>,
+        surrounding_blocks: &[Arc<BlockBuffer>; 27],
+        scratch: &mut LightScratch,
+    ) -> Self {
+        let near_changed = |pos: &Vector3<i32>, reach: i32| {
+            distance_to_closest(pos, changed).is_some_and(|distance| distance <= reach)
+        };
+
+        for in_chunk_pos in sunlight_sources {
+            if near_changed(&in_chunk_pos, 2 * spread) {
+                scratch.spread_sunlight_from_source(in_chunk_pos, spread, surrounding_blocks)
+            }
+        }
+
+        for (in_chunk_pos, source) in light_sources {
+            if near_changed(&in_chunk_pos, 2 * (MAX_LIGHT_VAL as i32 - 1)) {
+                scratch.spread_light_from_source(in_chunk_pos, source, surrounding_blocks)
+            }
+        }
+
+        Self {
+            buffer: scratch.lights.iter().cloned().collect(),
+        }
+    }
+
+    // Light and sunlight sources in and around the center chunk, relative to it
+    #[allow(clippy::type_complexity)]
+    fn light_sources_in(
+        surrounding_blocks: &[Arc<BlockBuffer>; 27],
+    ) -> Option<(Vec<(Vector3<i32>, LightSource)>, Vec<Vector3<i32>>)> {
+        let (mut light_sources, sunlight_sources) = {
             let (mut light_sources_temp, mut sunlight_sources_temp) = (Vec::new(), Vec::new());
 
             for (index, blocks) in surrounding_blocks.iter().enumerate() {
@@ -103,7 +336,7 @@ impl LightBuffer {
                 light_sources_temp.extend(blocks.light_sources()?.cache().iter().map(|in_chunk_pos| {
                     (
                         in_chunk_pos + chunk_offset * CHUNK_SIZE as i32,
-                        blocks[&in_chunk_pos].light_source().unwrap(),
+                        *blocks[&in_chunk_pos].light_source().unwrap(),
                     )
                 }));
                 sunlight_sources_temp.extend(
@@ -118,41 +351,120 @@ impl LightBuffer {
             (light_sources_temp, sunlight_sources_temp)
         };
 
-        let mut lights = Self::new_unlit();
+        light_sources.retain(|(in_chunk_pos, _)| {
+            (-(MAX_LIGHT_VAL as i32) + 1..CHUNK_SIZE as i32 + MAX_LIGHT_VAL as i32 - 1).contains(&in_chunk_pos.x)
+                && (-(MAX_LIGHT_VAL as i32) + 1..CHUNK_SIZE as i32 + MAX_LIGHT_VAL as i32 - 1).contains(&in_chunk_pos.y)
+                && (-(MAX_LIGHT_VAL as i32) + 1..CHUNK_SIZE as i32 + MAX_LIGHT_VAL as i32 - 1).contains(&in_chunk_pos.z)
+        });
 
-        lights.handle_sunlight(&surrounding_blocks);
-        for in_chunk_pos in sunlight_sources {
-            lights.spread_sunlight_from_source(in_chunk_pos, &surrounding_blocks)
-        }
+        Some((light_sources, sunlight_sources))
+    }
+}
 
-        for (in_chunk_pos, source) in light_sources {
-            lights.spread_light_from_source(
-                in_chunk_pos,
-                source.red,
-                source.green,
-                source.blue,
-                source.strength,
-                &surrounding_blocks,
-            )
-        }
+// Positions relative to the center chunk of every block whose change could affect the lights, None if there are too
+// many of them
+fn changed_blocks(
+    old_surrounding_blocks: &[Arc<BlockBuffer>; 27],
+    surrounding_blocks: &[Arc<BlockBuffer>; 27],
+) -> Option<Vec<Vector3<i32>>> {
+    let mut changed = Vec::new();
 
-        Some(lights)
-    }
+    for (index, (old_blocks, blocks)) in old_surrounding_blocks.iter().zip(surrounding_blocks.iter()).enumerate() {
+        if !Arc::ptr_eq(old_blocks, blocks) {
+            let chunk_offset = relative_pos_surrounding_cubes_from_index(index as u8) * CHUNK_SIZE as i32;
 
-    pub fn set(&mut self, index: &Vector3<i32>, val: LightVal) {
-        self.buffer.set(
-            ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize,
-            val,
-        )
+            for x in 0..CHUNK_SIZE as i32 {
+                for y in 0..CHUNK_SIZE as i32 {
+                    for z in 0..CHUNK_SIZE as i32 {
+                        let in_chunk_pos = Vector3::new(x, y, z);
+                        let (old_block, block) = (&old_blocks[&in_chunk_pos], &blocks[&in_chunk_pos]);
+
+                        if old_block.is_opaque() != block.is_opaque()
+                            || old_block.is_sunlit() != block.is_sunlit()
+                            || old_block.light_source() != block.light_source()
+                        {
+                            changed.push(in_chunk_pos + chunk_offset)
+                        }
+                    }
+                }
+            }
+
+            // Whether a block is a source also depends on its neighbours
+            changed.extend(
+                old_blocks
+                    .light_sources()?
+                    .cache()
+                    .symmetric_difference(blocks.light_sources()?.cache())
+                    .map(|in_chunk_pos| in_chunk_pos + chunk_offset),
+            );
+            changed.extend(
+                old_blocks
+                    .sunlight_sources()?
+                    .cache()
+                    .symmetric_difference(blocks.sunlight_sources()?.cache())
+                    .map(|in_chunk_pos| in_chunk_pos + chunk_offset),
+            );
+
+            if changed.len() > MAX_RELIGHT_CHANGES {
+                return None;
+            }
+        }
     }
 
-    fn new_unlit() -> Self {
+    Some(changed)
+}
+
+fn distance_to_closest(pos: &Vector3<i32>, others: &[Vector3<i32>]) -> Option<i32> {
+    others
+        .iter()
+        .map(|other| {
+            let offset = pos - other;
+            offset.x.abs() + offset.y.abs() + offset.z.abs()
+        })
+        .min()
+}
+
+#[cfg(test)]
+impl LightBuffer {
+    pub fn from_fn(light: impl Fn(Vector3<i32>) -> LightVal) -> Self {
         Self {
-            buffer: iter::repeat(LightVal::default())
-                .take((CHUNK_SIZE as usize).pow(3))
+            buffer: (0..ChunkShape::USIZE)
+                .map(|index| {
+                    let [x, y, z] = ChunkShape::delinearize(index as u32);
+                    light(Vector3::new(x as i32, y as i32, z as i32))
+                })
                 .collect(),
         }
     }
+}
+
+impl Index<&Vector3<i32>> for LightBuffer {
+    type Output = LightVal;
+
+    fn index(&self, index: &Vector3<i32>) -> &Self::Output {
+        &self.buffer[ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize]
+    }
+}
+
+// Working memory reused between relights on the same thread, so a relight doesn't have to allocate the BFS queues
+// and a dense light array for every chunk
+#[derive(Default)]
+pub struct LightScratch {
+    lights: Vec<LightVal>,
+    to_process: Vec<(Vector3<i32>, u8)>,
+    to_process_next: Vec<(Vector3<i32>, u8)>,
+    processed: FxHashSet<Vector3<i32>>,
+}
+
+impl LightScratch {
+    fn reset(&mut self) {
+        self.lights.clear();
+        self.lights.resize((CHUNK_SIZE as usize).pow(3), LightVal::default());
+    }
+
+    fn set(&mut self, index: &Vector3<i32>, val: LightVal) {
+        self.lights[ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize] = val
+    }
 
     fn handle_sunlight(&mut self, surrounding_blocks: &[Arc<BlockBuffer>; 27]) {
         for x in 0..CHUNK_SIZE as i32 {
@@ -172,12 +484,14 @@ impl LightBuffer {
     fn spread_light_from_source(
         &mut self,
         source_in_chunk_pos: Vector3<i32>,
-        source_red: bool,
-        source_green: bool,
-        source_blue: bool,
-        source_strength: u8,
+        source: LightSource,
         surrounding_blocks: &[Arc<BlockBuffer>; 27],
     ) {
+        // The queue holds the remaining strength of the brightest channel, the others are dimmer by the same amount
+        let source_strength = source.strength();
+        let channel_at =
+            |channel_strength: u8, strength: u8| channel_strength.saturating_sub(source_strength - strength);
+
         if source_in_chunk_pos.x > -(MAX_LIGHT_VAL as i32)
             && source_in_chunk_pos.x < CHUNK_SIZE as i32 + (MAX_LIGHT_VAL as i32 - 1)
             && source_in_chunk_pos.y > -(MAX_LIGHT_VAL as i32)
@@ -185,9 +499,14 @@ impl LightBuffer {
             && source_in_chunk_pos.z > -(MAX_LIGHT_VAL as i32)
             && source_in_chunk_pos.z < CHUNK_SIZE as i32 + (MAX_LIGHT_VAL as i32 - 1)
         {
-            if source_strength > 1 && (source_red || source_blue || source_green) {
-                let mut to_process = Vec::new();
-                let mut processed = FxHashSet::default();
+            if source_strength > 1 {
+                let (mut to_process, mut to_process_next, mut processed) = (
+                    mem::take(&mut self.to_process),
+                    mem::take(&mut self.to_process_next),
+                    mem::take(&mut self.processed),
+                );
+                to_process.clear();
+                processed.clear();
 
                 {
                     let (chunk_pos, in_chunk_pos) = coordinate_in_surrounding_buffers_cube(source_in_chunk_pos);
@@ -196,15 +515,9 @@ impl LightBuffer {
                         self.set(&in_chunk_pos, {
                             let mut light_val = self[&in_chunk_pos].clone();
 
-                            if source_red {
-                                light_val.red = source_strength;
-                            }
-                            if source_green {
-                                light_val.green = source_strength;
-                            }
-                            if source_blue {
-                                light_val.blue = source_strength;
-                            }
+                            light_val.red = max(light_val.red, source.strength_red);
+                            light_val.green = max(light_val.green, source.strength_green);
+                            light_val.blue = max(light_val.blue, source.strength_blue);
 
                             light_val
                         });
@@ -217,9 +530,7 @@ impl LightBuffer {
                 }
 
                 while !to_process.is_empty() {
-                    let mut to_process_next = Vec::new();
-
-                    for (pos, strength) in to_process.into_iter() {
+                    for (pos, strength) in to_process.drain(..) {
                         if !processed.contains(&pos) {
                             let (chunk_pos, in_chunk_pos) = coordinate_in_surrounding_buffers_cube(pos);
                             let block = &surrounding_blocks
@@ -230,15 +541,11 @@ impl LightBuffer {
                                     self.set(&in_chunk_pos, {
                                         let mut light_val = self[&in_chunk_pos].clone();
 
-                                        if source_red {
-                                            light_val.red = max(light_val.red, strength);
-                                        }
-                                        if source_green {
-                                            light_val.green = max(light_val.green, strength);
-                                        }
-                                        if source_blue {
-                                            light_val.blue = max(light_val.blue, strength);
-                                        }
+                                        light_val.red = max(light_val.red, channel_at(source.strength_red, strength));
+                                        light_val.green =
+                                            max(light_val.green, channel_at(source.strength_green, strength));
+                                        light_val.blue =
+                                            max(light_val.blue, channel_at(source.strength_blue, strength));
 
                                         light_val
                                     });
@@ -256,26 +563,42 @@ impl LightBuffer {
                         }
                     }
 
-                    to_process = to_process_next;
+                    mem::swap(&mut to_process, &mut to_process_next);
                 }
+
+                (self.to_process, self.to_process_next, self.processed) = (to_process, to_process_next, processed);
             }
         }
     }
 
+    // Unlike block light the queue holds the number of steps from the source, the light level falls off linearly over
+    // `spread` steps so the spread can be longer than `MAX_LIGHT_VAL`
     fn spread_sunlight_from_source(
         &mut self,
         source_in_chunk_pos: Vector3<i32>,
+        spread: i32,
         surrounding_blocks: &[Arc<BlockBuffer>; 27],
     ) {
-        if source_in_chunk_pos.x > -(MAX_LIGHT_VAL as i32)
-            && source_in_chunk_pos.x < CHUNK_SIZE as i32 + (MAX_LIGHT_VAL as i32 - 1)
-            && source_in_chunk_pos.y > -(MAX_LIGHT_VAL as i32)
-            && source_in_chunk_pos.y < CHUNK_SIZE as i32 + (MAX_LIGHT_VAL as i32 - 1)
-            && source_in_chunk_pos.z > -(MAX_LIGHT_VAL as i32)
-            && source_in_chunk_pos.z < CHUNK_SIZE as i32 + (MAX_LIGHT_VAL as i32 - 1)
+        let strength_at = |step: u8| (MAX_LIGHT_VAL as i32 * (spread + 1 - step as i32) / (spread + 1)) as u8;
+        let in_surrounding_chunks = |pos: &Vector3<i32>| {
+            let range = -(CHUNK_SIZE as i32)..2 * CHUNK_SIZE as i32;
+            range.contains(&pos.x) && range.contains(&pos.y) && range.contains(&pos.z)
+        };
+
+        if source_in_chunk_pos.x > -(spread + 1)
+            && source_in_chunk_pos.x < CHUNK_SIZE as i32 + spread
+            && source_in_chunk_pos.y > -(spread + 1)
+            && source_in_chunk_pos.y < CHUNK_SIZE as i32 + spread
+            && source_in_chunk_pos.z > -(spread + 1)
+            && source_in_chunk_pos.z < CHUNK_SIZE as i32 + spread
         {
-            let mut to_process = Vec::new();
-            let mut processed = FxHashSet::default();
+            let (mut to_process, mut to_process_next, mut processed) = (
+                mem::take(&mut self.to_process),
+                mem::take(&mut self.to_process_next),
+                mem::take(&mut self.processed),
+            );
+            to_process.clear();
+            processed.clear();
 
             {
                 let (chunk_pos, in_chunk_pos) = coordinate_in_surrounding_buffers_cube(source_in_chunk_pos);
@@ -290,15 +613,13 @@ impl LightBuffer {
 
                 for face in FaceDirection::iter() {
                     let dir = face.as_dir();
-                    to_process.push((source_in_chunk_pos + dir, MAX_LIGHT_VAL - 1))
+                    to_process.push((source_in_chunk_pos + dir, 1))
                 }
             }
 
             while !to_process.is_empty() {
-                let mut to_process_next = Vec::new();
-
-                for (pos, strength) in to_process.into_iter() {
-                    if !processed.contains(&pos) {
+                for (pos, step) in to_process.drain(..) {
+                    if !processed.contains(&pos) && in_surrounding_chunks(&pos) {
                         let (chunk_pos, in_chunk_pos) = coordinate_in_surrounding_buffers_cube(pos);
                         let block = &surrounding_blocks[index_from_relative_pos_surrounding_cubes(&chunk_pos) as usize]
                             [&in_chunk_pos];
@@ -308,16 +629,16 @@ impl LightBuffer {
                                 self.set(&in_chunk_pos, {
                                     let mut light_val = self[&in_chunk_pos].clone();
 
-                                    light_val.sun = max(light_val.sun, strength);
+                                    light_val.sun = max(light_val.sun, strength_at(step));
 
                                     light_val
                                 });
                             }
 
-                            if strength > 1 {
+                            if (step as i32) < spread {
                                 for face in FaceDirection::iter() {
                                     let dir = face.as_dir();
-                                    to_process_next.push((pos + dir, strength - 1))
+                                    to_process_next.push((pos + dir, step + 1))
                                 }
                             }
                         }
@@ -326,16 +647,277 @@ impl LightBuffer {
                     }
                 }
 
-                to_process = to_process_next;
+                mem::swap(&mut to_process, &mut to_process_next);
             }
+
+            (self.to_process, self.to_process_next, self.processed) = (to_process, to_process_next, processed);
         }
     }
 }
 
-impl Index<&Vector3<i32>> for LightBuffer {
+impl Index<&Vector3<i32>> for LightScratch {
     type Output = LightVal;
 
     fn index(&self, index: &Vector3<i32>) -> &Self::Output {
-        &self.buffer[ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize]
+        &self.lights[ChunkShape::linearize([index.x as u32, index.y as u32, index.z as u32]) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector2;
+    use rustc_hash::FxHashMap;
+
+    use super::*;
+    use crate::game::world::{Block, BlockManager, LightPosCache};
+
+    // The 5x5x5 chunks around the lit chunk, so all chunks around it can get light source caches. Positions are
+    // relative to the lit chunk and there is open sky above all chunks
+    struct TestWorld {
+        block_manager: BlockManager,
+        chunks: FxHashMap<Vector3<i32>, BlockBuffer>,
+    }
+
+    impl TestWorld {
+        fn new() -> Self {
+            let block_manager = BlockManager::new();
+            let air = BlockBuffer::new(vec![
+                Block::new_with_default("Air", &block_manager);
+                (CHUNK_SIZE as usize).pow(3)
+            ]);
+
+            let mut chunks = FxHashMap::default();
+            for x in -2..=2 {
+                for y in -2..=2 {
+                    for z in -2..=2 {
+                        chunks.insert(Vector3::new(x, y, z), air.clone());
+                    }
+                }
+            }
+
+            Self { block_manager, chunks }
+        }
+
+        fn set(&mut self, pos: Vector3<i32>, block_name: &str) {
+            let block = Block::new_with_default(block_name, &self.block_manager);
+            let split = |coord: i32| (coord.div_euclid(CHUNK_SIZE as i32), coord.rem_euclid(CHUNK_SIZE as i32));
+            let ((chunk_x, x), (chunk_y, y), (chunk_z, z)) = (split(pos.x), split(pos.y), split(pos.z));
+
+            self.chunks
+                .get_mut(&Vector3::new(chunk_x, chunk_y, chunk_z))
+                .unwrap()
+                .set(&Vector3::new(x, y, z), block);
+        }
+
+        fn fill(&mut self, min: Vector3<i32>, max: Vector3<i32>, block_name: &str) {
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        self.set(Vector3::new(x, y, z), block_name);
+                    }
+                }
+            }
+        }
+
+        // Blocks around the lit chunk with sunlight and light source caches, the way the terrain sets them up. Chunks
+        // that are the same as in `previous` are shared with it, like the terrain only replaces changed chunks
+        fn surrounding_blocks(&self, previous: Option<&[Arc<BlockBuffer>; 27]>) -> [Arc<BlockBuffer>; 27] {
+            let mut chunks = self.chunks.clone();
+            for chunk_x in -2..=2 {
+                for chunk_z in -2..=2 {
+                    for x in 0..CHUNK_SIZE as i32 {
+                        for z in 0..CHUNK_SIZE as i32 {
+                            let collum = Vector2::new(x, z);
+                            let mut sees_sky = true;
+
+                            for chunk_y in (-2..=2).rev() {
+                                let chunk = chunks.get_mut(&Vector3::new(chunk_x, chunk_y, chunk_z)).unwrap();
+                                chunk.update_sunlight_in_collum(&collum, sees_sky);
+                                sees_sky &= !chunk.contains_collum_opaque_blocks(&collum);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let around = |chunks: &FxHashMap<Vector3<i32>, BlockBuffer>, center: Vector3<i32>| {
+                std::array::from_fn(|index| {
+                    Arc::new(chunks[&(center + relative_pos_surrounding_cubes_from_index(index as u8))].clone())
+                })
+            };
+            std::array::from_fn(|index| {
+                let chunk_pos = relative_pos_surrounding_cubes_from_index(index as u8);
+                let surrounding_blocks: [Arc<BlockBuffer>; 27] = around(&chunks, chunk_pos);
+
+                let mut blocks = chunks[&chunk_pos].clone();
+                blocks.set_light_source_caches(
+                    LightPosCache::new(&surrounding_blocks),
+                    LightPosCache::new(&surrounding_blocks),
+                );
+
+                match previous {
+                    Some(previous) if same_blocks(&previous[index], &blocks) => previous[index].clone(),
+                    _ => Arc::new(blocks),
+                }
+            })
+        }
+
+        fn lights(&self, sunlight_spread: u32) -> LightBuffer {
+            LightBuffer::new(
+                self.surrounding_blocks(None),
+                DEFAULT_MAX_LIGHT_SOURCES,
+                sunlight_spread,
+                &mut LightScratch::default(),
+            )
+            .unwrap()
+            .0
+        }
+    }
+
+    fn same_blocks(first: &BlockBuffer, second: &BlockBuffer) -> bool {
+        let caches = |blocks: &BlockBuffer| {
+            (
+                blocks.light_sources().map(LightPosCache::cache).cloned(),
+                blocks.sunlight_sources().map(LightPosCache::cache).cloned(),
+            )
+        };
+
+        caches(first) == caches(second)
+            && (0..CHUNK_SIZE as i32).all(|x| {
+                (0..CHUNK_SIZE as i32).all(|y| {
+                    (0..CHUNK_SIZE as i32).all(|z| first[&Vector3::new(x, y, z)] == second[&Vector3::new(x, y, z)])
+                })
+            })
+    }
+
+    // A roof over the whole lit chunk along z, which sunlight can only get under from the open sides along x
+    fn overhang() -> TestWorld {
+        let mut world = TestWorld::new();
+        let (floor, roof) = (0, 4);
+        world.fill(
+            Vector3::new(0, floor, -(CHUNK_SIZE as i32)),
+            Vector3::new(CHUNK_SIZE as i32 - 1, floor, 2 * CHUNK_SIZE as i32 - 1),
+            "Stone",
+        );
+        world.fill(
+            Vector3::new(0, roof, -(CHUNK_SIZE as i32)),
+            Vector3::new(CHUNK_SIZE as i32 - 1, roof, 2 * CHUNK_SIZE as i32 - 1),
+            "Stone",
+        );
+        world
+    }
+
+    fn assert_overhang_lit(sunlight_spread: u32) {
+        let lights = overhang().lights(sunlight_spread);
+        let spread = sunlight_spread as i32;
+
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 1..4 {
+                // Sunlight sources are the sunlit blocks just outside of the overhang next to the floor and the roof
+                let steps = (x + 1).min(CHUNK_SIZE as i32 - x) + (y - 1).min(3 - y);
+                let expected = if steps <= spread {
+                    (MAX_LIGHT_VAL as i32 * (spread + 1 - steps) / (spread + 1)) as u8
+                } else {
+                    0
+                };
+
+                for z in 0..CHUNK_SIZE as i32 {
+                    assert_eq!(
+                        lights[&Vector3::new(x, y, z)].sun,
+                        expected,
+                        "sunlight at {:?} with spread {}",
+                        (x, y, z),
+                        sunlight_spread
+                    );
+                }
+            }
+            assert_eq!(lights[&Vector3::new(x, 5, 8)].sun, MAX_LIGHT_VAL);
+        }
+    }
+
+    #[test]
+    fn default_spread_under_overhang() {
+        assert_eq!(DEFAULT_SUNLIGHT_SPREAD, 14);
+        assert_overhang_lit(DEFAULT_SUNLIGHT_SPREAD);
+
+        // One level less per block, like before the spread was configurable
+        let lights = overhang().lights(DEFAULT_SUNLIGHT_SPREAD);
+        let row = (0..8).map(|x| lights[&Vector3::new(x, 3, 8)].sun).collect::<Vec<_>>();
+        assert_eq!(row, [14, 13, 12, 11, 10, 9, 8, 7]);
+    }
+
+    #[test]
+    fn larger_spread_under_overhang() {
+        assert_overhang_lit(24);
+        assert_overhang_lit(4);
+    }
+
+    // Lights the world, changes it and checks relighting only around the changes gives the same lights as lighting it
+    // again from scratch
+    fn assert_relight_matches_full_light(mut world: TestWorld, change: impl FnOnce(&mut TestWorld)) {
+        let mut scratch = LightScratch::default();
+        let blocks = world.surrounding_blocks(None);
+        let previous = PreviousLights {
+            lights: Arc::new(
+                LightBuffer::new(
+                    blocks.clone(),
+                    DEFAULT_MAX_LIGHT_SOURCES,
+                    DEFAULT_SUNLIGHT_SPREAD,
+                    &mut scratch,
+                )
+                .unwrap()
+                .0,
+            ),
+            surrounding_blocks: blocks.clone(),
+            sunlight_spread: DEFAULT_SUNLIGHT_SPREAD,
+        };
+
+        change(&mut world);
+        let changed_blocks = world.surrounding_blocks(Some(&blocks));
+        assert!(changed_blocks
+            .iter()
+            .zip(&blocks)
+            .any(|(new, old)| Arc::ptr_eq(new, old)));
+
+        let relit = LightBuffer::relight(
+            &previous,
+            changed_blocks.clone(),
+            DEFAULT_MAX_LIGHT_SOURCES,
+            DEFAULT_SUNLIGHT_SPREAD,
+            &mut scratch,
+        )
+        .expect("relight of a few changed blocks");
+        let lit = LightBuffer::new(
+            changed_blocks,
+            DEFAULT_MAX_LIGHT_SOURCES,
+            DEFAULT_SUNLIGHT_SPREAD,
+            &mut scratch,
+        )
+        .unwrap()
+        .0;
+
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                for z in 0..CHUNK_SIZE as i32 {
+                    let pos = Vector3::new(x, y, z);
+                    assert_eq!(relit[&pos], lit[&pos], "light at {pos:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relight_matches_full_light() {
+        // Sunlight falling through a hole in the roof
+        assert_relight_matches_full_light(overhang(), |world| world.set(Vector3::new(8, 4, 8), "Air"));
+        // A light placed and one removed under the roof
+        assert_relight_matches_full_light(overhang(), |world| world.set(Vector3::new(3, 2, 8), "Light"));
+        let mut lit_overhang = overhang();
+        lit_overhang.set(Vector3::new(12, 1, 4), "Light");
+        assert_relight_matches_full_light(lit_overhang, |world| world.set(Vector3::new(12, 1, 4), "Air"));
+        // A wall in the neighbouring chunk shading the open side
+        assert_relight_matches_full_light(overhang(), |world| {
+            world.fill(Vector3::new(-1, 1, 7), Vector3::new(-1, 3, 9), "Stone")
+        });
     }
 }
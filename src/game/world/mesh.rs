@@ -1,35 +1,50 @@
 use std::{
+    borrow::Cow,
+    fmt::Write,
     mem::{self, MaybeUninit},
     num::NonZeroI32,
     sync::Arc,
 };
 
 use block_mesh::{
-    greedy_quads, ndshape::ConstShape, ndshape::ConstShape3u32, GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG,
+    greedy_quads, ndshape::ConstShape, ndshape::ConstShape3u32, visible_block_faces, GreedyQuadsBuffer, UnitQuadBuffer,
+    UnorientedQuad, RIGHT_HANDED_Y_UP_CONFIG,
 };
-use cgmath::Vector3;
-use either::Either;
+use cgmath::{InnerSpace, Vector2, Vector3};
 use strum::IntoEnumIterator;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
-    BindingType, Buffer, BufferAddress, BufferBindingType, BufferUsages, Device, IndexFormat, RenderPass, ShaderStages,
-    VertexBufferLayout, VertexStepMode,
+    BindingType, Buffer, BufferAddress, BufferBindingType, BufferDescriptor, BufferUsages, Device, IndexFormat, Queue,
+    RenderPass, ShaderStages, VertexBufferLayout, VertexStepMode, COPY_BUFFER_ALIGNMENT,
 };
 
 use super::coordinate_in_surrounding_buffers;
 use crate::{
     engine::{
-        face::{FaceDirection, SideDirection},
+        face::FaceDirection,
         resource::{Draw, Material, Vertex},
         TextureAtlas,
     },
-    game::world::{Block, BlockBuffer, LightBuffer, LightVal, Voxel, CHUNK_SIZE_MESHING},
-    misc::index::index_from_relative_pos_surrounding,
+    game::world::{
+        Block, BlockBuffer, BlockShape, InternalFaces, LightBuffer, LightVal, Voxel, CHUNK_SIZE, CHUNK_SIZE_MESHING,
+    },
+    misc::{index::index_from_relative_pos_surrounding, profiling::profile_span},
 };
 
 pub type ChunkShapeMeshing = ConstShape3u32<CHUNK_SIZE_MESHING, CHUNK_SIZE_MESHING, CHUNK_SIZE_MESHING>;
 
+// Ambient occlusion is packed above the two bits of the face brightness
+const AO_SHIFT: u8 = 2;
+// Set in the brightness of the top corners of fluid surfaces, which the shader lowers and moves with time
+const FLUID_SURFACE_FLAG: u8 = 16;
+// Set in the transparency of faces with a cutout texture, so only they pay for the alpha test
+const CUTOUT_FLAG: u8 = 2;
+// Faces with an animated texture store the index of its animation plus one above the flags, zero means static
+const ANIMATION_SHIFT: u8 = 2;
+// Texture coordinates closer than this to a block edge count as on it when splitting faces for export
+const OBJ_EDGE_TOLERANCE: f32 = 0.001;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Zeroable)]
 pub struct BlockVertex {
@@ -100,37 +115,271 @@ impl ChunkMeshRaw {
             },
         }
     }
+
+    // Appends the mesh to a Wavefront OBJ in world space, first_vertex counts the vertices already written before it.
+    // The shader repeats the texture of a face once per block, which coordinates into the atlas can't, so merged faces
+    // are split back into one face per block
+    pub fn write_obj(&self, obj: &mut String, first_vertex: &mut usize, tile_size: (f32, f32)) {
+        let origin = self.chunk_pos.map(|coord| (coord * CHUNK_SIZE as i32) as f32);
+        let world_pos = |vertex: &BlockVertex| {
+            Vector3::new(
+                vertex.pos[0] as f32 - (vertex.pos[3] & 1) as f32 * 0.5,
+                vertex.pos[1] as f32 - ((vertex.pos[3] >> 1) & 1) as f32 * 0.5,
+                vertex.pos[2] as f32 - ((vertex.pos[3] >> 2) & 1) as f32 * 0.5,
+            ) + origin
+        };
+
+        for quad_indices in self.indices.chunks_exact(6) {
+            let start = *quad_indices.iter().min().unwrap() as usize;
+            let Some(corners) = self.vertices.get(start..start + 4) else {
+                continue;
+            };
+
+            // Same texture axes as the shader
+            let normal = Vector3::new(
+                corners[0].normal[0] as f32,
+                corners[0].normal[1] as f32,
+                corners[0].normal[2] as f32,
+            );
+            let mut axis1 = normal.cross(Vector3::new(0.0, 1.0, 0.0));
+            if axis1 == Vector3::new(0.0, 0.0, 0.0) {
+                axis1 = normal.cross(Vector3::new(0.0, 0.0, 1.0));
+            }
+            if normal.x != 0.0 && normal.z != 0.0 {
+                axis1 = Vector3::new(axis1.x, 0.0, 0.0);
+            }
+            let axis2 = normal.cross(axis1);
+
+            let poses = corners.iter().map(world_pos).collect::<Vec<_>>();
+            let uvs = poses
+                .iter()
+                .map(|pos| Vector2::new(axis1.dot(*pos), axis2.dot(*pos)))
+                .collect::<Vec<_>>();
+            let (min, max) = uvs.iter().fold(
+                (Vector2::new(f32::MAX, f32::MAX), Vector2::new(f32::MIN, f32::MIN)),
+                |(min, max), uv| {
+                    (
+                        Vector2::new(min.x.min(uv.x), min.y.min(uv.y)),
+                        Vector2::new(max.x.max(uv.x), max.y.max(uv.y)),
+                    )
+                },
+            );
+            if max.x - min.x < OBJ_EDGE_TOLERANCE || max.y - min.y < OBJ_EDGE_TOLERANCE {
+                continue;
+            }
+
+            // Which side of the face every corner is on, and the position at a point of the face by its sides
+            let sides = uvs
+                .iter()
+                .map(|uv| {
+                    [
+                        uv.x - min.x > (max.x - min.x) / 2.0,
+                        uv.y - min.y > (max.y - min.y) / 2.0,
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let corner_pos = |side: [bool; 2]| poses[sides.iter().position(|other| *other == side).unwrap_or(0)];
+            let pos_at = |uv: Vector2<f32>| {
+                let (s, t) = ((uv.x - min.x) / (max.x - min.x), (uv.y - min.y) / (max.y - min.y));
+                corner_pos([false, false]) * ((1.0 - s) * (1.0 - t))
+                    + corner_pos([true, false]) * (s * (1.0 - t))
+                    + corner_pos([false, true]) * ((1.0 - s) * t)
+                    + corner_pos([true, true]) * (s * t)
+            };
+            let normal = normal.normalize();
+            let texture_atlas_pos = corners[0].texture_atlas_pos;
+
+            for u in ((min.x + OBJ_EDGE_TOLERANCE).floor() as i32)..((max.x - OBJ_EDGE_TOLERANCE).ceil() as i32) {
+                for v in ((min.y + OBJ_EDGE_TOLERANCE).floor() as i32)..((max.y - OBJ_EDGE_TOLERANCE).ceil() as i32) {
+                    for side in &sides {
+                        let uv = Vector2::new(
+                            if side[0] {
+                                max.x.min(u as f32 + 1.0)
+                            } else {
+                                min.x.max(u as f32)
+                            },
+                            if side[1] {
+                                max.y.min(v as f32 + 1.0)
+                            } else {
+                                min.y.max(v as f32)
+                            },
+                        );
+                        let pos = pos_at(uv);
+                        // Texture coordinates of OBJ start at the bottom of the image, those of wgpu at the top
+                        let texture_pos = [
+                            texture_atlas_pos[0] + (uv.x - u as f32) * tile_size.0,
+                            1.0 - (texture_atlas_pos[1] + (uv.y - v as f32) * tile_size.1),
+                        ];
+
+                        writeln!(obj, "v {} {} {}", pos.x, pos.y, pos.z).ok();
+                        writeln!(obj, "vt {} {}", texture_pos[0], texture_pos[1]).ok();
+                        writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z).ok();
+                    }
+
+                    for triangle in quad_indices.chunks_exact(3) {
+                        write!(obj, "f").ok();
+                        for index in triangle {
+                            let index = *first_vertex + *index as usize - start + 1;
+                            write!(obj, " {index}/{index}/{index}").ok();
+                        }
+                        writeln!(obj).ok();
+                    }
+                    *first_vertex += 4;
+                }
+            }
+        }
+    }
+}
+
+// Largest number of free buffers of each kind the pool keeps, more are dropped
+const MAX_POOLED_BUFFERS: usize = 256;
+// New buffers get a quarter more room than needed, rounded up to this many bytes, so a mesh that grows a little after an
+// edit still fits
+const POOLED_BUFFER_SIZE_STEP: u64 = 256;
+// A buffer more than this many times larger than a new buffer would be isn't used for it, so small meshes don't hold on
+// to large buffers
+const MAX_POOLED_BUFFER_OVERSIZE: u64 = 4;
+
+// Vertex and index buffers of replaced and unloaded chunk meshes, reused for new meshes so remeshing and moving around
+// don't keep allocating on the GPU
+#[derive(Default)]
+pub struct ChunkBufferPool {
+    vertex_buffers: Vec<Buffer>,
+    index_buffers: Vec<Buffer>,
+    allocations: u64,
+}
+
+impl ChunkBufferPool {
+    // Buffers created so far because none in the pool fit
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+
+    fn buffers_mut(&mut self, usage: BufferUsages) -> &mut Vec<Buffer> {
+        if usage.contains(BufferUsages::VERTEX) {
+            &mut self.vertex_buffers
+        } else {
+            &mut self.index_buffers
+        }
+    }
+
+    // A buffer with room for at least size bytes that can be written to, the smallest fitting one of the pool if any
+    fn take(&mut self, device: &Device, usage: BufferUsages, size: u64) -> Buffer {
+        if size > 0 {
+            let buffers = self.buffers_mut(usage);
+            if let Some(index) = best_fit(buffers.iter().map(|buffer| buffer.size()), size) {
+                return buffers.swap_remove(index);
+            }
+        }
+
+        self.allocations += 1;
+        device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: if size > 0 { pooled_buffer_size(size) } else { 0 },
+            usage: usage | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn recycle(&mut self, buffer: Buffer, usage: BufferUsages) {
+        let buffers = self.buffers_mut(usage);
+        if buffer.size() > 0 && buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
+
+fn pooled_buffer_size(size: u64) -> u64 {
+    (size + size / 4).next_multiple_of(POOLED_BUFFER_SIZE_STEP)
+}
+
+fn buffer_fits(capacity: u64, size: u64) -> bool {
+    capacity >= size && (size == 0 || capacity <= pooled_buffer_size(size) * MAX_POOLED_BUFFER_OVERSIZE)
+}
+
+fn best_fit(capacities: impl Iterator<Item = u64>, size: u64) -> Option<usize> {
+    capacities
+        .enumerate()
+        .filter(|(_, capacity)| buffer_fits(*capacity, size))
+        .min_by_key(|(_, capacity)| *capacity)
+        .map(|(index, _)| index)
+}
+
+// Writes data to the start of buffer, swapping it for one from the pool first if it doesn't fit. Returns how many bytes
+// of it are used
+fn write_pooled(
+    buffer: &mut Buffer,
+    data: &[u8],
+    usage: BufferUsages,
+    device: &Device,
+    queue: &Queue,
+    pool: &mut ChunkBufferPool,
+) -> u64 {
+    // Writes have to be a multiple of COPY_BUFFER_ALIGNMENT long, which u16 indices may not be
+    let padded = if (data.len() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT) {
+        Cow::Borrowed(data)
+    } else {
+        let mut padded = data.to_vec();
+        padded.resize((data.len() as u64).next_multiple_of(COPY_BUFFER_ALIGNMENT) as usize, 0);
+        Cow::Owned(padded)
+    };
+
+    if !buffer_fits(buffer.size(), padded.len() as u64) {
+        let old = mem::replace(buffer, pool.take(device, usage, padded.len() as u64));
+        pool.recycle(old, usage);
+    }
+    if !padded.is_empty() {
+        queue.write_buffer(buffer, 0, &padded);
+    }
+
+    data.len() as u64
 }
 
 pub struct ChunkMesh {
     pub name: String,
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
+    // Bytes of the buffers used by the mesh, the buffers themselves can be larger
+    pub vertex_buffer_used: u64,
+    pub index_buffer_used: u64,
+    pub index_format: IndexFormat,
     pub num_elements: u32,
     pub chunk_pos_buffer: Buffer,
     pub chunk_pos: BindGroup,
 }
 
 impl ChunkMesh {
-    pub fn new(mesh_raw: ChunkMeshRaw, device: &Device) -> Self {
+    pub fn new(mesh_raw: ChunkMeshRaw, device: &Device, queue: &Queue, pool: &mut ChunkBufferPool) -> Self {
         let chunk_pos_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(&[mesh_raw.chunk_pos.x, mesh_raw.chunk_pos.y, mesh_raw.chunk_pos.z, 0]),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
-        Self {
-            name: mesh_raw.name,
-            num_elements: mesh_raw.indices.len() as u32,
-            vertex_buffer: device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&mesh_raw.vertices),
-                usage: BufferUsages::VERTEX,
-            }),
-            index_buffer: device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&mesh_raw.indices),
-                usage: BufferUsages::INDEX,
-            }),
+
+        let index_size = match Self::index_format(mesh_raw.vertices.len()) {
+            IndexFormat::Uint16 => mem::size_of::<u16>(),
+            IndexFormat::Uint32 => mem::size_of::<u32>(),
+        };
+        let (vertex_bytes, index_bytes) = (
+            mem::size_of_val(mesh_raw.vertices.as_slice()) as u64,
+            (mesh_raw.indices.len() * index_size) as u64,
+        );
+        let mut out = Self {
+            name: String::new(),
+            vertex_buffer: pool.take(
+                device,
+                BufferUsages::VERTEX,
+                vertex_bytes.next_multiple_of(COPY_BUFFER_ALIGNMENT),
+            ),
+            index_buffer: pool.take(
+                device,
+                BufferUsages::INDEX,
+                index_bytes.next_multiple_of(COPY_BUFFER_ALIGNMENT),
+            ),
+            vertex_buffer_used: 0,
+            index_buffer_used: 0,
+            index_format: IndexFormat::Uint16,
+            num_elements: 0,
             chunk_pos: device.create_bind_group(&BindGroupDescriptor {
                 label: None,
                 layout: {
@@ -154,6 +403,60 @@ impl ChunkMesh {
                 }],
             }),
             chunk_pos_buffer,
+        };
+        out.replace(mesh_raw, device, queue, pool);
+
+        out
+    }
+
+    // Takes in a new mesh of the same chunk, writing it to the current buffers where it fits
+    pub fn replace(&mut self, mesh_raw: ChunkMeshRaw, device: &Device, queue: &Queue, pool: &mut ChunkBufferPool) {
+        let (index_format, indices) = Self::index_data(&mesh_raw.indices, mesh_raw.vertices.len());
+
+        self.vertex_buffer_used = write_pooled(
+            &mut self.vertex_buffer,
+            bytemuck::cast_slice(&mesh_raw.vertices),
+            BufferUsages::VERTEX,
+            device,
+            queue,
+            pool,
+        );
+        self.index_buffer_used = write_pooled(
+            &mut self.index_buffer,
+            &indices,
+            BufferUsages::INDEX,
+            device,
+            queue,
+            pool,
+        );
+        self.index_format = index_format;
+        self.num_elements = mesh_raw.indices.len() as u32;
+        self.name = mesh_raw.name;
+    }
+
+    // Hands the buffers back for other meshes to use
+    pub fn recycle(self, pool: &mut ChunkBufferPool) {
+        pool.recycle(self.vertex_buffer, BufferUsages::VERTEX);
+        pool.recycle(self.index_buffer, BufferUsages::INDEX);
+    }
+
+    // Meshes with few enough vertices for every index to fit in 16 bits use them, halving the size of the index buffer
+    fn index_format(vertex_count: usize) -> IndexFormat {
+        if vertex_count <= u16::MAX as usize {
+            IndexFormat::Uint16
+        } else {
+            IndexFormat::Uint32
+        }
+    }
+
+    // The indices as they are uploaded, in the format picked for the vertex count
+    fn index_data(indices: &[u32], vertex_count: usize) -> (IndexFormat, Cow<'_, [u8]>) {
+        match Self::index_format(vertex_count) {
+            IndexFormat::Uint16 => (
+                IndexFormat::Uint16,
+                Cow::Owned(indices.iter().flat_map(|index| (*index as u16).to_ne_bytes()).collect()),
+            ),
+            IndexFormat::Uint32 => (IndexFormat::Uint32, Cow::Borrowed(bytemuck::cast_slice(indices))),
         }
     }
 }
@@ -166,20 +469,25 @@ impl Draw for ChunkMesh {
         settings_bind_group: &'a BindGroup,
         render_pass: &mut RenderPass<'a>,
     ) {
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..self.vertex_buffer_used));
+        render_pass.set_index_buffer(self.index_buffer.slice(..self.index_buffer_used), self.index_format);
         render_pass.set_bind_group(0, &material.bind_group, &[]);
         render_pass.set_bind_group(1, camera_bind_group, &[]);
         render_pass.set_bind_group(2, settings_bind_group, &[]);
         render_pass.set_bind_group(3, &self.chunk_pos, &[]);
         render_pass.draw_indexed(0..self.num_elements, 0, 0..1);
     }
+
+    fn element_count(&self) -> u32 {
+        self.num_elements
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct MeshBuffer {
     pub solid_mesh: ChunkMeshRaw,
     pub transparent_mesh: ChunkMeshRaw,
+    pub double_sided_mesh: ChunkMeshRaw,
 }
 
 impl MeshBuffer {
@@ -189,20 +497,24 @@ impl MeshBuffer {
         surrounding_lights: [Arc<LightBuffer>; 7],
         texture_atlas: &TextureAtlas,
         transparency: bool,
+        smooth_lighting: bool,
         reused_buffers: &mut (GreedyQuadsBuffer, Vec<Voxel>),
     ) -> Self {
+        profile_span!("mesh_buffer");
         let mesh = Self::generate_mesh(
             chunk_pos,
             surrounding_blocks,
             surrounding_lights,
             texture_atlas,
             transparency,
+            smooth_lighting,
             reused_buffers,
         );
 
         Self {
             solid_mesh: mesh.0,
             transparent_mesh: mesh.1,
+            double_sided_mesh: mesh.2,
         }
     }
 
@@ -213,8 +525,9 @@ impl MeshBuffer {
         surrounding_lights: [Arc<LightBuffer>; 7],
         texture_atlas: &TextureAtlas,
         transparency: bool,
+        smooth_lighting: bool,
         reused_buffers: &mut (GreedyQuadsBuffer, Vec<Voxel>),
-    ) -> (ChunkMeshRaw, ChunkMeshRaw) {
+    ) -> (ChunkMeshRaw, ChunkMeshRaw, ChunkMeshRaw) {
         let faces = RIGHT_HANDED_Y_UP_CONFIG.faces;
 
         if !surrounding_blocks[index_from_relative_pos_surrounding(&Vector3::new(0, 0, 0)) as usize]
@@ -233,6 +546,12 @@ impl MeshBuffer {
                     Vec::new(),
                     *chunk_pos,
                 ),
+                ChunkMeshRaw::new(
+                    format!("ChunkMesh - Double sided {chunk_pos:?}"),
+                    Vec::new(),
+                    Vec::new(),
+                    *chunk_pos,
+                ),
             );
         }
 
@@ -293,93 +612,273 @@ impl MeshBuffer {
         }
 
         reused_buffers.0.reset(MeshBuffer::BUFFER_SIZE);
-        greedy_quads(
-            &reused_buffers.1,
-            &ChunkShapeMeshing {},
-            [0; 3],
-            [CHUNK_SIZE_MESHING - 1; 3],
-            &faces,
-            &mut reused_buffers.0,
-        );
+        if smooth_lighting {
+            // Smooth lighting differs at every corner, so faces are kept apart instead of being merged
+            let mut unit_quads = UnitQuadBuffer::new();
+            visible_block_faces(
+                &reused_buffers.1,
+                &ChunkShapeMeshing {},
+                [0; 3],
+                [CHUNK_SIZE_MESHING - 1; 3],
+                &faces,
+                &mut unit_quads,
+            );
+            for (group, unit_group) in reused_buffers.0.quads.groups.iter_mut().zip(unit_quads.groups) {
+                group.extend(unit_group.into_iter().map(UnorientedQuad::from));
+            }
+        } else {
+            greedy_quads(
+                &reused_buffers.1,
+                &ChunkShapeMeshing {},
+                [0; 3],
+                [CHUNK_SIZE_MESHING - 1; 3],
+                &faces,
+                &mut reused_buffers.0,
+            );
+        }
+
+        for x in 1..=CHUNK_SIZE {
+            for y in 1..=CHUNK_SIZE {
+                for z in 1..=CHUNK_SIZE {
+                    let voxel = &reused_buffers.1[ChunkShapeMeshing::linearize([x, y, z]) as usize];
+                    if voxel.internal_faces() == InternalFaces::Hidden || !voxel.is_translucent() {
+                        continue;
+                    }
+
+                    for (group, face) in reused_buffers.0.quads.groups.iter_mut().zip(faces.iter()) {
+                        let normal = face.signed_normal();
+                        let neighbour = &reused_buffers.1[ChunkShapeMeshing::linearize([
+                            (x as i32 + normal.x) as u32,
+                            (y as i32 + normal.y) as u32,
+                            (z as i32 + normal.z) as u32,
+                        ]) as usize];
+
+                        if voxel.shows_internal_face(neighbour) {
+                            group.push(UnorientedQuad {
+                                minimum: [x, y, z],
+                                width: 1,
+                                height: 1,
+                            })
+                        }
+                    }
+                }
+            }
+        }
+
+        // Blocks that aren't full cubes get the faces of their own box, those on the edge of their voxel are hidden by
+        // opaque neighbours like the faces of full cubes
+        let (mut partial_quads, mut cross_blocks) = (Vec::new(), Vec::new());
+        for x in 1..=CHUNK_SIZE {
+            for y in 1..=CHUNK_SIZE {
+                for z in 1..=CHUNK_SIZE {
+                    let voxel = &reused_buffers.1[ChunkShapeMeshing::linearize([x, y, z]) as usize];
+                    if !voxel.is_renderer() || voxel.shape().is_full() {
+                        continue;
+                    }
+                    if voxel.shape() == BlockShape::Cross {
+                        cross_blocks.push([x, y, z]);
+                        continue;
+                    }
+
+                    let (min, max) = voxel.shape().half_block_bounds();
+                    for (face_index, face) in faces.iter().enumerate() {
+                        let normal = face.signed_normal();
+                        let axis = (0..3).find(|axis| normal[*axis] != 0).unwrap();
+                        let on_edge = if normal[axis] > 0 {
+                            max[axis] == 2
+                        } else {
+                            min[axis] == 0
+                        };
+                        let neighbour = &reused_buffers.1[ChunkShapeMeshing::linearize([
+                            (x as i32 + normal.x) as u32,
+                            (y as i32 + normal.y) as u32,
+                            (z as i32 + normal.z) as u32,
+                        ]) as usize];
+
+                        if !on_edge || !neighbour.is_opaque() {
+                            partial_quads.push((
+                                face_index,
+                                UnorientedQuad {
+                                    minimum: [x, y, z],
+                                    width: 1,
+                                    height: 1,
+                                },
+                                voxel.shape(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
 
         let (num_indices, num_vertices) = (
-            reused_buffers.0.quads.num_quads() * 6,
-            reused_buffers.0.quads.num_quads() * 4,
+            (reused_buffers.0.quads.num_quads() + partial_quads.len()) * 6,
+            (reused_buffers.0.quads.num_quads() + partial_quads.len()) * 4,
         );
         let (mut solid_indices, mut solid_vertices) =
             (Vec::with_capacity(num_indices), Vec::with_capacity(num_vertices));
         let (mut transparent_indices, mut transparent_vertices) = (Vec::new(), Vec::new());
+        let (mut double_sided_indices, mut double_sided_vertices) = (Vec::new(), Vec::new());
 
-        for (group, face) in reused_buffers.0.quads.groups.iter().zip(faces.into_iter()) {
-            for quad in group.iter() {
-                solid_indices.extend_from_slice(&face.quad_mesh_indices(solid_vertices.len() as u32));
-                transparent_indices.extend_from_slice(&face.quad_mesh_indices(transparent_vertices.len() as u32));
+        let texture = |voxel: &Voxel, face_direction: FaceDirection| {
+            if let Some(textures) = voxel.texture() {
+                let texture = textures.face(face_direction);
+                let atlas_pos = texture_atlas.texture_coordinates(texture);
+                let animation = texture_atlas
+                    .animation_index(texture)
+                    .map_or(0, |index| (index + 1) << ANIMATION_SHIFT);
+                (
+                    [atlas_pos.0, atlas_pos.1],
+                    animation
+                        | if texture_atlas.is_cutout(texture) {
+                            CUTOUT_FLAG
+                        } else {
+                            0
+                        },
+                )
+            } else {
+                log::warn!("Creating vertex without texture");
+                ([0.0, 0.0], 0)
+            }
+        };
 
-                let quad_mesh_poses = face.quad_mesh_positions(quad, 1.0);
-                let normals = face.quad_mesh_normals();
+        let quads = reused_buffers
+            .0
+            .quads
+            .groups
+            .iter()
+            .enumerate()
+            .flat_map(|(face_index, group)| group.iter().map(move |quad| (face_index, *quad, BlockShape::Full)))
+            .chain(partial_quads);
+        for (face_index, quad, shape) in quads {
+            let face = &faces[face_index];
+            let quad_mesh_poses = face.quad_mesh_positions(&quad, 1.0);
+            let normals = face.quad_mesh_normals();
 
-                let pos = Vector3::new(quad.minimum[0] as i32, quad.minimum[1] as i32, quad.minimum[2] as i32);
-                let voxel = reused_buffers.1
-                    [ChunkShapeMeshing::linearize([(pos.x) as u32, (pos.y) as u32, (pos.z) as u32]) as usize]
-                    .clone();
+            let pos = Vector3::new(quad.minimum[0] as i32, quad.minimum[1] as i32, quad.minimum[2] as i32);
+            let voxel = reused_buffers.1
+                [ChunkShapeMeshing::linearize([(pos.x) as u32, (pos.y) as u32, (pos.z) as u32]) as usize]
+                .clone();
 
-                for i in 0..4 {
-                    let mesh_pos = quad_mesh_poses[i];
-                    let normal = {
-                        let normal = normals[i];
-                        [normal[0] as i8, normal[1] as i8, normal[2] as i8, 0]
-                    };
+            // Double sided blocks keep their own mesh whether they are transparent or not, the renderer draws it with
+            // culling off
+            let is_transparent = transparency && voxel.is_transparent();
+            let (indices, vertices) = if voxel.is_double_sided() {
+                (&mut double_sided_indices, &mut double_sided_vertices)
+            } else if is_transparent {
+                (&mut transparent_indices, &mut transparent_vertices)
+            } else {
+                (&mut solid_indices, &mut solid_vertices)
+            };
+            indices.extend_from_slice(&face.quad_mesh_indices(vertices.len() as u32));
 
-                    let face_direction =
-                        FaceDirection::from_dir(&Vector3::new(normal[0] as i32, normal[1] as i32, normal[2] as i32))
-                            .unwrap();
-
-                    let light_color = voxel.face_lighting().unwrap()[face_direction.as_index()];
-
-                    let texture_atlas_pos = {
-                        if let Some(textures) = voxel.texture() {
-                            let atlas_pos = texture_atlas.texture_coordinates(match textures {
-                                Either::Left(texture) => texture,
-                                Either::Right([texture_top, texture_side, texture_bottom]) => {
-                                    match face_direction.into() {
-                                        SideDirection::Top => texture_top,
-                                        SideDirection::Side => texture_side,
-                                        SideDirection::Bottom => texture_bottom,
-                                    }
-                                }
-                            });
-                            [atlas_pos.0, atlas_pos.1]
+            for i in 0..4 {
+                let mesh_pos = quad_mesh_poses[i];
+                let normal = {
+                    let normal = normals[i];
+                    [normal[0] as i8, normal[1] as i8, normal[2] as i8, 0]
+                };
+
+                let face_direction =
+                    FaceDirection::from_dir(&Vector3::new(normal[0] as i32, normal[1] as i32, normal[2] as i32))
+                        .unwrap();
+
+                let (light_color, ao) = {
+                    let face_light = voxel.face_lighting().unwrap()[face_direction.as_index()];
+
+                    if smooth_lighting && !voxel.is_light_source() {
+                        let corner = Vector3::new(
+                            mesh_pos[0] as i32 - quad.minimum[0] as i32,
+                            mesh_pos[1] as i32 - quad.minimum[1] as i32,
+                            mesh_pos[2] as i32 - quad.minimum[2] as i32,
+                        );
+                        Self::smooth_vertex_light(
+                            pos - Vector3::new(1, 1, 1),
+                            face_direction.as_dir(),
+                            corner,
+                            face_light,
+                            &surrounding_blocks,
+                            &surrounding_lights,
+                        )
+                    } else {
+                        (face_light, 0)
+                    }
+                };
+                // Corners on top of a fluid are part of its surface unless more of the fluid is above them. Merged quads
+                // only contain the same voxel, so the block above is taken from the column of the quad at the corner
+                let fluid_surface = voxel.is_fluid() && shape.is_full() && mesh_pos[1] as u32 > quad.minimum[1] && {
+                    let column = |axis: usize| {
+                        if mesh_pos[axis] as u32 > quad.minimum[axis] {
+                            mesh_pos[axis] as u32 - 1
                         } else {
-                            log::warn!("Creating vertex without texture");
-                            [0.0, 0.0]
+                            mesh_pos[axis] as u32
                         }
                     };
+                    !reused_buffers.1[ChunkShapeMeshing::linearize([column(0), mesh_pos[1] as u32, column(2)]) as usize]
+                        .is_fluid()
+                };
+                let brightness =
+                    face_direction.brightness() | (ao << AO_SHIFT) | if fluid_surface { FLUID_SURFACE_FLAG } else { 0 };
 
-                    let pos = {
-                        let temp_pos = [mesh_pos[0] as i32 - 1, mesh_pos[1] as i32 - 1, mesh_pos[2] as i32 - 1];
-                        [temp_pos[0] as u8, temp_pos[1] as u8, temp_pos[2] as u8, 0]
-                    };
+                let (texture_atlas_pos, cutout) = texture(&voxel, face_direction);
 
-                    if transparency && voxel.is_transparent() {
-                        transparent_indices.extend_from_slice(&face.quad_mesh_indices(solid_vertices.len() as u32));
-                        transparent_vertices.push(BlockVertex {
-                            pos,
-                            normal,
-                            color: light_color,
-                            texture_atlas_pos,
-                            brightness: face_direction.brightness(),
-                            transparency: 1,
-                        })
-                    } else {
-                        solid_vertices.push(BlockVertex {
-                            pos,
-                            normal,
-                            color: light_color,
-                            texture_atlas_pos,
-                            brightness: face_direction.brightness(),
-                            transparency: 0,
-                        })
+                // Partial shapes pull the corners of the face in to their box. Corners halfway through a block are
+                // rounded up and flagged in the last component, so the shader moves them back down half a block
+                let pos = {
+                    let (min, max) = shape.half_block_bounds();
+                    let mut pos = [0; 4];
+                    for axis in 0..3 {
+                        let half_blocks = if shape.is_full() {
+                            mesh_pos[axis] as u32 * 2
+                        } else {
+                            quad.minimum[axis] * 2
+                                + ((mesh_pos[axis] as u32 - quad.minimum[axis]) * 2).clamp(min[axis], max[axis])
+                        };
+                        pos[axis] = (half_blocks.div_ceil(2) - 1) as u8;
+                        pos[3] |= ((half_blocks % 2) as u8) << axis;
                     }
+                    pos
+                };
+
+                vertices.push(BlockVertex {
+                    pos,
+                    normal,
+                    color: light_color,
+                    texture_atlas_pos,
+                    brightness,
+                    transparency: is_transparent as u8 | cutout,
+                })
+            }
+        }
+
+        // Cross blocks get two quads along the diagonals of their voxel, lit by the light inside it. They are cut out
+        // rather than blended and go into the double sided mesh, so they can be seen from both sides
+        for [x, y, z] in cross_blocks {
+            let voxel = &reused_buffers.1[ChunkShapeMeshing::linearize([x, y, z]) as usize];
+            let light_color = if voxel.is_light_source() {
+                voxel.face_lighting().unwrap()[0]
+            } else {
+                surrounding_lights[index_from_relative_pos_surrounding(&Vector3::new(0, 0, 0)) as usize]
+                    [&Vector3::new(x as i32 - 1, y as i32 - 1, z as i32 - 1)]
+                    .light_raw()
+            };
+            let (texture_atlas_pos, cutout) = texture(voxel, FaceDirection::West);
+            let (x, y, z) = ((x - 1) as u8, (y - 1) as u8, (z - 1) as u8);
+
+            for (corners, normal) in [([[0, 0], [1, 1]], [1, 0, -1, 0]), ([[1, 0], [0, 1]], [1, 0, 1, 0])] {
+                let start = double_sided_vertices.len() as u32;
+                double_sided_indices.extend_from_slice(&[start, start + 1, start + 2, start, start + 2, start + 3]);
+
+                for (corner, height) in [(0, 0), (1, 0), (1, 1), (0, 1)] {
+                    let [corner_x, corner_z] = corners[corner];
+                    double_sided_vertices.push(BlockVertex {
+                        pos: [x + corner_x, y + height, z + corner_z, 0],
+                        normal,
+                        color: light_color,
+                        texture_atlas_pos,
+                        brightness: FaceDirection::West.brightness(),
+                        transparency: cutout,
+                    })
                 }
             }
         }
@@ -397,6 +896,193 @@ impl MeshBuffer {
                 transparent_indices,
                 *chunk_pos,
             ),
+            ChunkMeshRaw::new(
+                format!("ChunkMesh - Double sided {chunk_pos:?}"),
+                double_sided_vertices,
+                double_sided_indices,
+                *chunk_pos,
+            ),
         )
     }
+
+    // Averages the light in front of the face with the three blocks touching the corner, and counts how many of them
+    // are opaque for ambient occlusion. Blocks past the edges of the neighbouring chunks are not available and skipped
+    fn smooth_vertex_light(
+        voxel_pos: Vector3<i32>,
+        normal: Vector3<i32>,
+        corner: Vector3<i32>,
+        face_light: [u8; 4],
+        surrounding_blocks: &[Arc<BlockBuffer>; 7],
+        surrounding_lights: &[Arc<LightBuffer>; 7],
+    ) -> ([u8; 4], u8) {
+        let sample = |pos: Vector3<i32>| {
+            coordinate_in_surrounding_buffers(pos).map(|(chunk_pos, in_chunk_pos)| {
+                let index = index_from_relative_pos_surrounding(&chunk_pos) as usize;
+                (
+                    surrounding_blocks[index][&in_chunk_pos].is_opaque(),
+                    surrounding_lights[index][&in_chunk_pos].light_raw(),
+                )
+            })
+        };
+        let is_opaque = |sample: &Option<(bool, [u8; 4])>| sample.is_some_and(|(is_opaque, _)| is_opaque);
+
+        let mut sides = (0..3).filter(|axis| normal[*axis] == 0).map(|axis| {
+            let mut dir = Vector3::new(0, 0, 0);
+            dir[axis] = if corner[axis] > 0 { 1 } else { -1 };
+            dir
+        });
+        let (side_1_dir, side_2_dir) = (sides.next().unwrap(), sides.next().unwrap());
+
+        let front = voxel_pos + normal;
+        let (side_1, side_2) = (sample(front + side_1_dir), sample(front + side_2_dir));
+        // Light can't reach the corner block through two opaque sides, so it neither lights nor occludes the vertex
+        let (corner, ao) = if is_opaque(&side_1) && is_opaque(&side_2) {
+            (None, 3)
+        } else {
+            let corner = sample(front + side_1_dir + side_2_dir);
+            let ao = is_opaque(&side_1) as u8 + is_opaque(&side_2) as u8 + is_opaque(&corner) as u8;
+            (corner, ao)
+        };
+
+        let (mut sum, mut count) = (face_light.map(u32::from), 1);
+        for (_, light) in [side_1, side_2, corner]
+            .into_iter()
+            .flatten()
+            .filter(|(is_opaque, _)| !is_opaque)
+        {
+            for (sum, light) in sum.iter_mut().zip(light) {
+                *sum += light as u32;
+            }
+            count += 1;
+        }
+
+        (sum.map(|sum| (sum as f32 / count as f32).round() as u8), ao)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::world::BlockManager;
+
+    const VOXEL: Vector3<i32> = Vector3::new(5, 5, 5);
+    const UP: Vector3<i32> = Vector3::new(0, 1, 0);
+    // The corner of the top face towards +x and +z, its sides are the blocks above it towards +x and +z
+    const CORNER: Vector3<i32> = Vector3::new(1, 1, 1);
+    const SIDE_1: Vector3<i32> = Vector3::new(6, 6, 5);
+    const SIDE_2: Vector3<i32> = Vector3::new(5, 6, 6);
+    const DIAGONAL: Vector3<i32> = Vector3::new(6, 6, 6);
+
+    fn corner_light(opaque: &[Vector3<i32>], light: impl Fn(Vector3<i32>) -> u8) -> ([u8; 4], u8) {
+        let block_manager = BlockManager::new();
+        let mut blocks = BlockBuffer::new(vec![
+            Block::new_with_default("Air", &block_manager);
+            (CHUNK_SIZE as usize).pow(3)
+        ]);
+        for pos in opaque {
+            blocks.set(pos, Block::new_with_default("Stone", &block_manager));
+        }
+        let lights = LightBuffer::from_fn(|pos| LightVal::new(0, 0, 0, light(pos)));
+
+        MeshBuffer::smooth_vertex_light(
+            VOXEL,
+            UP,
+            CORNER,
+            [0, 0, 0, 12],
+            &std::array::from_fn(|_| Arc::new(blocks.clone())),
+            &std::array::from_fn(|_| Arc::new(lights.clone())),
+        )
+    }
+
+    #[test]
+    fn pooled_buffers_fit_without_being_much_larger() {
+        assert_eq!(pooled_buffer_size(1000), 1280);
+        assert!(buffer_fits(1000, 1000) && !buffer_fits(999, 1000));
+        assert!(buffer_fits(1280 * MAX_POOLED_BUFFER_OVERSIZE, 1000));
+        assert!(!buffer_fits(1280 * MAX_POOLED_BUFFER_OVERSIZE + 1, 1000));
+        // Empty meshes fit anywhere
+        assert!(buffer_fits(0, 0) && buffer_fits(1 << 20, 0));
+
+        // The smallest buffer that fits is picked
+        assert_eq!(best_fit([4096, 1280, 999, 100_000].into_iter(), 1000), Some(1));
+        assert_eq!(best_fit([999, 100_000].into_iter(), 1000), None);
+        assert_eq!(best_fit(std::iter::empty(), 1000), None);
+    }
+
+    #[test]
+    fn remeshing_reuses_pooled_buffers() {
+        // Same swapping as `write_pooled`, with only the capacities of the buffers
+        let (mut capacity, mut pool, mut allocations) = (0, Vec::new(), 0);
+        let mut state = 12345_u64;
+        for _ in 0..10000 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let size = 9000 + (state >> 33) % 2000;
+            if !buffer_fits(capacity, size) {
+                if capacity > 0 {
+                    pool.push(capacity);
+                }
+                capacity = match best_fit(pool.iter().copied(), size) {
+                    Some(index) => pool.swap_remove(index),
+                    None => {
+                        allocations += 1;
+                        pooled_buffer_size(size)
+                    }
+                };
+            }
+        }
+        assert_eq!(allocations, 1);
+    }
+
+    #[test]
+    fn indices_are_16_bit_when_they_fit() {
+        assert_eq!(ChunkMesh::index_format(0), IndexFormat::Uint16);
+        assert_eq!(ChunkMesh::index_format(u16::MAX as usize), IndexFormat::Uint16);
+        assert_eq!(ChunkMesh::index_format(u16::MAX as usize + 1), IndexFormat::Uint32);
+
+        let indices = [0, 1, 2, 2, 1, u16::MAX as u32 - 1];
+        let (index_format, data) = ChunkMesh::index_data(&indices, u16::MAX as usize);
+        assert_eq!(index_format, IndexFormat::Uint16);
+        assert_eq!(data.len(), indices.len() * mem::size_of::<u16>());
+        let read_back = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]) as u32)
+            .collect::<Vec<_>>();
+        assert_eq!(read_back, indices);
+
+        // Indices past u16::MAX are kept as they are
+        let indices = [0, u16::MAX as u32, u16::MAX as u32 + 1];
+        let (index_format, data) = ChunkMesh::index_data(&indices, u16::MAX as usize + 2);
+        assert_eq!(index_format, IndexFormat::Uint32);
+        assert_eq!(*data, *bytemuck::cast_slice::<u32, u8>(&indices));
+    }
+
+    #[test]
+    fn open_corner_keeps_face_light() {
+        assert_eq!(corner_light(&[], |_| 12), ([0, 0, 0, 12], 0));
+    }
+
+    #[test]
+    fn corner_averages_light_around_it() {
+        let light = |pos| match pos {
+            SIDE_1 | SIDE_2 => 6,
+            _ => 0,
+        };
+        // (12 + 6 + 6 + 0) / 4
+        assert_eq!(corner_light(&[], light), ([0, 0, 0, 6], 0));
+    }
+
+    #[test]
+    fn opaque_blocks_occlude_corner() {
+        let light = |pos| if pos == DIAGONAL { 3 } else { 12 };
+
+        // Opaque blocks add occlusion and are left out of the average
+        assert_eq!(corner_light(&[SIDE_1], light), ([0, 0, 0, 9], 1));
+        assert_eq!(corner_light(&[DIAGONAL], light), ([0, 0, 0, 12], 1));
+        assert_eq!(corner_light(&[SIDE_1, DIAGONAL], light), ([0, 0, 0, 12], 2));
+        // Between two opaque sides the corner is fully occluded and the block on the diagonal can't light it
+        assert_eq!(corner_light(&[SIDE_1, SIDE_2], light), ([0, 0, 0, 12], 3));
+        assert_eq!(corner_light(&[SIDE_1, SIDE_2, DIAGONAL], light), ([0, 0, 0, 12], 3));
+    }
 }
@@ -3,18 +3,28 @@ mod chunk;
 mod chunk_data;
 mod light;
 mod mesh;
+#[cfg(feature = "request_log")]
+mod request_log;
 mod terrain;
 mod terrain_generator;
 mod voxel;
 
-pub use block::{Block, BlockBuffer, BlockManager, LightPosCache, TextureID};
+pub use block::{
+    Block, BlockBuffer, BlockManager, BlockShape, BlockTextures, InternalFaces, LightPosCache, TextureAnimation,
+    TextureID,
+};
 pub use chunk::{
     coordinate_in_surrounding_buffers, coordinate_in_surrounding_buffers_cube, Chunk, ChunkShape, CHUNK_SIZE,
     CHUNK_SIZE_MESHING,
 };
 pub use chunk_data::{CacheUpdateActionKind, ChunkData};
-pub use light::{LightBuffer, LightSource, LightVal, MAX_LIGHT_VAL};
-pub use mesh::{BlockVertex, ChunkMesh, ChunkMeshRaw, MeshBuffer};
-pub use terrain::Terrain;
-pub use terrain_generator::TerrainGenerator;
+pub use light::{
+    LightBuffer, LightScratch, LightSource, LightVal, PreviousLights, DEFAULT_MAX_LIGHT_SOURCES,
+    DEFAULT_SUNLIGHT_SPREAD, MAX_LIGHT_VAL, MAX_SUNLIGHT_SPREAD,
+};
+pub use mesh::{BlockVertex, ChunkBufferPool, ChunkMesh, ChunkMeshRaw, MeshBuffer};
+#[cfg(feature = "request_log")]
+pub use request_log::{load_request_log, save_request_log, ChunkRequest, ChunkRequestKind, RequestLog};
+pub use terrain::{Terrain, DEFAULT_MAX_QUEUED_SAVES, DEFAULT_SAVE_THREADS};
+pub use terrain_generator::{DeferredBlocks, TerrainConfig, TerrainGenerator, TerrainGeneratorKind};
 pub use voxel::Voxel;
@@ -0,0 +1,65 @@
+use std::{num::NonZeroI32, path::Path};
+
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::misc::loader::load_string;
+
+// Which of the chunk generation steps was requested, in the order a chunk usually goes through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkRequestKind {
+    Blocks,
+    LightPosCache,
+    Light,
+    Mesh,
+}
+
+// A request that was actually sent to a generation thread, recorded by `Terrain` while its request log is on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRequest {
+    pub kind: ChunkRequestKind,
+    pub chunk_pos: Vector3<NonZeroI32>,
+}
+
+impl ChunkRequest {
+    pub fn new(kind: ChunkRequestKind, chunk_pos: Vector3<NonZeroI32>) -> Self {
+        Self { kind, chunk_pos }
+    }
+}
+
+// Requests only replay the same when the world is generated from the same seed, so it is kept with them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RequestLog {
+    pub seed: u32,
+    pub requests: Vec<ChunkRequest>,
+}
+
+pub fn save_request_log(path: impl AsRef<Path>, request_log: &RequestLog) -> Result<(), ()> {
+    let path = path.as_ref();
+
+    match serde_yaml::to_string(request_log) {
+        Ok(text) => std::fs::write(path, text).map_err(|e| {
+            log::warn!("Failed writing request log to {} - {}", path.display(), e);
+        }),
+        Err(e) => {
+            log::warn!("Failed serializing request log - {}", e);
+            Err(())
+        }
+    }
+}
+
+pub fn load_request_log(path: impl AsRef<Path>) -> Option<RequestLog> {
+    let path = path.as_ref();
+
+    let Ok(text) = load_string(path) else {
+        log::warn!("Failed reading request log from {}", path.display());
+        return None;
+    };
+    match serde_yaml::from_str(&text) {
+        Ok(request_log) => Some(request_log),
+        Err(e) => {
+            log::warn!("Failed deserializing request log from {} - {}", path.display(), e);
+            None
+        }
+    }
+}
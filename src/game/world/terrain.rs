@@ -1,33 +1,38 @@
 use std::{
     cell::RefCell,
+    cmp::Reverse,
     mem::{self, MaybeUninit},
     num::NonZeroI32,
     pin::Pin,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
         Arc,
     },
 };
 
 use block_mesh::GreedyQuadsBuffer;
 use cfg_if::cfg_if;
-use cgmath::{MetricSpace, Vector2, Vector3};
-use either::Either;
+use cgmath::{InnerSpace, MetricSpace, Vector2, Vector3};
 use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
-use instant::Duration;
+use instant::{Duration, Instant};
 use ref_thread_local::{ref_thread_local, RefThreadLocal};
 use rustc_hash::{FxHashMap, FxHashSet};
 use strum::IntoEnumIterator;
 
+#[cfg(feature = "request_log")]
+use crate::game::world::{ChunkRequest, ChunkRequestKind};
 #[cfg(feature = "save_system")]
-use crate::misc::save_helper::save_many;
+use crate::misc::save_helper::{save_many, SaveThreadPool};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::misc::vox::Structure;
 use crate::{
     engine::{face::FaceDirection, TextureAtlas},
     game::{
         world::{
-            coordinate_in_surrounding_buffers_cube, Block, BlockBuffer, BlockManager, Chunk, ChunkMesh, LightBuffer,
-            LightPosCache, LightVal, MeshBuffer, TerrainGenerator, Voxel, CHUNK_SIZE, CHUNK_SIZE_MESHING,
-            MAX_LIGHT_VAL,
+            coordinate_in_surrounding_buffers_cube, Block, BlockBuffer, BlockManager, Chunk, ChunkBufferPool,
+            ChunkMesh, DeferredBlocks, LightBuffer, LightPosCache, LightScratch, LightVal, MeshBuffer, PreviousLights,
+            TerrainConfig, TerrainGenerator, TerrainGeneratorKind, Voxel, CHUNK_SIZE, CHUNK_SIZE_MESHING,
+            DEFAULT_MAX_LIGHT_SOURCES, DEFAULT_SUNLIGHT_SPREAD, MAX_LIGHT_VAL,
         },
         Camera,
     },
@@ -37,6 +42,7 @@ use crate::{
             relative_pos_surrounding_cubes_from_index,
         },
         pos::{add_non_zero_i32_vector3, add_to_non_zero_i32, Pos},
+        profiling::profile_span,
     },
 };
 
@@ -52,54 +58,174 @@ cfg_if! {
 use rayon::prelude::*;
 
 const THREAD_SLEEP_TIME: u64 = 10;
+pub const DEFAULT_SAVE_THREADS: u32 = 2;
+pub const DEFAULT_MAX_QUEUED_SAVES: u32 = 512;
+// Longest a save waits for the terrain generator to finish the chunks it is working on
+#[cfg(feature = "save_system")]
+const MAX_GENERATION_PAUSE: Duration = Duration::from_millis(500);
+// Chunks past the render distance that requests are still worked on for, as the chunks around the rendered ones are
+// needed to light and mesh them
+const REQUEST_DROP_MARGIN: u32 = 4;
+
+// Tells the generators of different terrains apart on threads they share
+static NEXT_TERRAIN_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Requests are queued on their generation thread and the ones with the lowest priority are worked on first
+trait ThreadRequest {
+    fn pos(&self) -> Vector3<NonZeroI32>;
+    fn priority(&self) -> u32;
+    fn for_state(&self) -> u64;
+}
+
+// What a generation thread sends back for a request. Requests for chunks that got too far from the camera before
+// their turn came are dropped with the state they were made for, so the chunk can be requested again once it is back
+enum ThreadReturn<T> {
+    Done(T),
+    Dropped(Vector3<NonZeroI32>, u64),
+}
+
+// Chunk the camera is in and how far from it requests are still worth working on, shared with the generation threads.
+// Nothing is dropped until it is first set
+struct RequestFocus {
+    chunk_pos: [AtomicI32; 3],
+    horizontal: AtomicU32,
+    up: AtomicU32,
+    down: AtomicU32,
+}
+
+impl RequestFocus {
+    fn new() -> Self {
+        Self {
+            chunk_pos: [AtomicI32::new(1), AtomicI32::new(1), AtomicI32::new(1)],
+            horizontal: AtomicU32::new(u32::MAX),
+            up: AtomicU32::new(u32::MAX),
+            down: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    fn set(&self, chunk_pos: &Vector3<NonZeroI32>, horizontal: u32, up: u32, down: u32) {
+        for (axis, val) in self.chunk_pos.iter().zip([chunk_pos.x, chunk_pos.y, chunk_pos.z]) {
+            axis.store(val.into(), Ordering::Relaxed);
+        }
+        self.horizontal.store(horizontal, Ordering::Relaxed);
+        self.up.store(up, Ordering::Relaxed);
+        self.down.store(down, Ordering::Relaxed);
+    }
+
+    fn out_of_range(&self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
+        let [x, y, z] = self
+            .chunk_pos
+            .each_ref()
+            .map(|axis| NonZeroI32::new(axis.load(Ordering::Relaxed)).unwrap_or(NonZeroI32::new(1).unwrap()));
+        let offset = chunk_offset(&Vector3::new(x, y, z), chunk_pos);
+        let horizontal = self.horizontal.load(Ordering::Relaxed) as f32;
+
+        offset.x.abs() > horizontal
+            || offset.z.abs() > horizontal
+            || offset.y > self.up.load(Ordering::Relaxed) as f32
+            || -offset.y > self.down.load(Ordering::Relaxed) as f32
+    }
+}
 
 struct BlocksThreadRequest {
     pos: Vector3<NonZeroI32>,
+    #[cfg(feature = "save_system")]
     current_save_name: String,
+    // Format version of the save the chunk is loaded from, see `load_block_buffer`
+    #[cfg(feature = "save_system")]
+    save_format_version: u32,
+    priority: u32,
 }
 
-impl BlocksThreadRequest {
-    fn new(pos: Vector3<NonZeroI32>, current_save_name: String) -> Self {
-        Self { pos, current_save_name }
+impl ThreadRequest for BlocksThreadRequest {
+    fn pos(&self) -> Vector3<NonZeroI32> {
+        self.pos
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn for_state(&self) -> u64 {
+        0
     }
 }
 
 struct BlocksThreadReturn {
     pos: Vector3<NonZeroI32>,
     blocks: BlockBuffer,
+    deferred: DeferredBlocks,
 }
 
 impl BlocksThreadReturn {
-    fn new(pos: Vector3<NonZeroI32>, blocks: BlockBuffer) -> Self {
-        Self { pos, blocks }
+    fn new(pos: Vector3<NonZeroI32>, blocks: BlockBuffer, deferred: DeferredBlocks) -> Self {
+        Self { pos, blocks, deferred }
     }
 }
 
 struct LightThreadRequest {
     pos: Vector3<NonZeroI32>,
     surrounding_blocks: [Arc<BlockBuffer>; 27],
+    previous: Option<PreviousLights>,
     for_state: u64,
+    priority: u32,
 }
 
 impl LightThreadRequest {
-    fn new(pos: Vector3<NonZeroI32>, surrounding_blocks: [Arc<BlockBuffer>; 27], for_state: u64) -> Self {
+    fn new(
+        pos: Vector3<NonZeroI32>,
+        surrounding_blocks: [Arc<BlockBuffer>; 27],
+        previous: Option<PreviousLights>,
+        for_state: u64,
+        priority: u32,
+    ) -> Self {
         Self {
             pos,
             surrounding_blocks,
+            previous,
             for_state,
+            priority,
         }
     }
 }
 
+impl ThreadRequest for LightThreadRequest {
+    fn pos(&self) -> Vector3<NonZeroI32> {
+        self.pos
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn for_state(&self) -> u64 {
+        self.for_state
+    }
+}
+
 struct LightThreadReturn {
     pos: Vector3<NonZeroI32>,
     lights: Option<LightBuffer>,
+    skipped_light_sources: u32,
+    lit_from: Box<([Arc<BlockBuffer>; 27], u32)>,
     for_state: u64,
 }
 
 impl LightThreadReturn {
-    fn new(pos: Vector3<NonZeroI32>, lights: Option<LightBuffer>, for_state: u64) -> Self {
-        Self { pos, lights, for_state }
+    fn new(
+        pos: Vector3<NonZeroI32>,
+        lights: Option<LightBuffer>,
+        skipped_light_sources: u32,
+        lit_from: Box<([Arc<BlockBuffer>; 27], u32)>,
+        for_state: u64,
+    ) -> Self {
+        Self {
+            pos,
+            lights,
+            skipped_light_sources,
+            lit_from,
+            for_state,
+        }
     }
 }
 
@@ -107,18 +233,39 @@ struct LightPosCacheThreadRequest {
     pos: Vector3<NonZeroI32>,
     surrounding_blocks: [Arc<BlockBuffer>; 27],
     for_state: u64,
+    priority: u32,
 }
 
 impl LightPosCacheThreadRequest {
-    fn new(pos: Vector3<NonZeroI32>, surrounding_blocks: [Arc<BlockBuffer>; 27], for_state: u64) -> Self {
+    fn new(
+        pos: Vector3<NonZeroI32>,
+        surrounding_blocks: [Arc<BlockBuffer>; 27],
+        for_state: u64,
+        priority: u32,
+    ) -> Self {
         Self {
             pos,
             surrounding_blocks,
             for_state,
+            priority,
         }
     }
 }
 
+impl ThreadRequest for LightPosCacheThreadRequest {
+    fn pos(&self) -> Vector3<NonZeroI32> {
+        self.pos
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn for_state(&self) -> u64 {
+        self.for_state
+    }
+}
+
 struct LightPosCacheThreadReturn {
     pos: Vector3<NonZeroI32>,
     light_source_cache: LightPosCache<0>,
@@ -147,6 +294,7 @@ struct MeshThreadRequest {
     surrounding_blocks: [Arc<BlockBuffer>; 7],
     surrounding_lights: [Arc<LightBuffer>; 7],
     for_state: u64,
+    priority: u32,
 }
 
 impl MeshThreadRequest {
@@ -155,16 +303,32 @@ impl MeshThreadRequest {
         surrounding_blocks: [Arc<BlockBuffer>; 7],
         surrounding_lights: [Arc<LightBuffer>; 7],
         for_state: u64,
+        priority: u32,
     ) -> Self {
         Self {
             pos,
             surrounding_blocks,
             surrounding_lights,
             for_state,
+            priority,
         }
     }
 }
 
+impl ThreadRequest for MeshThreadRequest {
+    fn pos(&self) -> Vector3<NonZeroI32> {
+        self.pos
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn for_state(&self) -> u64 {
+        self.for_state
+    }
+}
+
 struct MeshThreadReturn {
     pos: Vector3<NonZeroI32>,
     mesh: MeshBuffer,
@@ -180,12 +344,12 @@ impl MeshThreadReturn {
 #[cfg(feature = "save_system")]
 struct SaveChunkRequest {
     current_save_name: String,
-    chunks: Vec<(String, BlockBuffer)>,
+    chunks: Vec<(String, Arc<BlockBuffer>)>,
 }
 
 #[cfg(feature = "save_system")]
 impl SaveChunkRequest {
-    fn new(current_save_name: String, chunks: Vec<(String, BlockBuffer)>) -> Self {
+    fn new(current_save_name: String, chunks: Vec<(String, Arc<BlockBuffer>)>) -> Self {
         Self {
             current_save_name,
             chunks,
@@ -196,31 +360,88 @@ impl SaveChunkRequest {
 pub struct Terrain {
     chunks: FxHashMap<Vector3<NonZeroI32>, Pin<Box<Chunk>>>,
     requested_chunks_list: FxHashSet<Vector3<NonZeroI32>>,
-    mesh_reciever: UnboundedReceiver<MeshThreadReturn>,
+    mesh_reciever: UnboundedReceiver<ThreadReturn<MeshThreadReturn>>,
     mesh_sender: UnboundedSender<MeshThreadRequest>,
-    blocks_reciever: UnboundedReceiver<BlocksThreadReturn>,
+    blocks_reciever: UnboundedReceiver<ThreadReturn<BlocksThreadReturn>>,
     blocks_sender: UnboundedSender<BlocksThreadRequest>,
-    light_pos_cache_reciever: UnboundedReceiver<LightPosCacheThreadReturn>,
+    light_pos_cache_reciever: UnboundedReceiver<ThreadReturn<LightPosCacheThreadReturn>>,
     light_pos_cache_sender: UnboundedSender<LightPosCacheThreadRequest>,
-    light_reciever: UnboundedReceiver<LightThreadReturn>,
+    light_reciever: UnboundedReceiver<ThreadReturn<LightThreadReturn>>,
     light_sender: UnboundedSender<LightThreadRequest>,
     #[cfg(feature = "save_system")]
     chunk_save_sender: UnboundedSender<SaveChunkRequest>,
     #[cfg(feature = "save_system")]
     current_save_name: String,
+    // Format version of the chunks in the save, older ones are migrated when loaded
+    #[cfg(feature = "save_system")]
+    save_format_version: u32,
+    // Chunks waiting to be sent to the saver thread, keyed by save name and position so repeated saves coalesce
+    #[cfg(feature = "save_system")]
+    pending_saves: FxHashMap<(String, Vector3<NonZeroI32>), Arc<BlockBuffer>>,
+    #[cfg(feature = "save_system")]
+    max_queued_saves: u32,
+    #[cfg(feature = "save_system")]
+    pause_generation_on_save: bool,
+    #[cfg(feature = "save_system")]
+    generation_paused: Arc<AtomicBool>,
+    // Number of terrain generator batches in progress
+    #[cfg(feature = "save_system")]
+    generating: Arc<AtomicU32>,
     transparency: bool,
     texture_atlas: TextureAtlas,
     loading_chunks: u32,
     saving_chunks: Arc<AtomicU32>,
+    save_threads: Arc<AtomicU32>,
+    max_light_sources: Arc<AtomicU32>,
+    sunlight_spread: Arc<AtomicU32>,
+    smooth_lighting: Arc<AtomicBool>,
+    light_capped_chunks: FxHashSet<Vector3<NonZeroI32>>,
+    buffer_pool: ChunkBufferPool,
+    loading_frozen: bool,
+    // Structure blocks generated by neighbours, waiting for their chunk to load or for its neighbours to load
+    deferred_blocks: DeferredBlocks,
+    mesh_requests: Vec<Vector3<NonZeroI32>>,
+    prioritize_look_direction: bool,
+    occlusion_culling: bool,
+    // Where the camera was the last time meshes were gathered, requests are prioritized by their distance from it
+    camera_chunk_pos: Vector3<NonZeroI32>,
+    camera_forward: Vector3<f32>,
+    request_focus: Arc<RequestFocus>,
+    chunk_request_delay: Duration,
+    remesh_coalescing_window: Duration,
+    remeshes: u32,
+    chunks_first_seen: FxHashMap<Vector3<NonZeroI32>, (Instant, u64)>,
+    meshes_to_render_frame: u64,
+    skipped_chunk_requests: u32,
     block_manager: BlockManager,
+    // Old and new definitions of blocks changed since the generation threads were started, applied to every chunk they
+    // send back
+    block_replacements: Vec<(Block, Block)>,
+    terrain_config: TerrainConfig,
+    generator_kind: TerrainGeneratorKind,
+    // Only recorded while on, it grows with every request
+    #[cfg(feature = "request_log")]
+    request_log: Option<Vec<ChunkRequest>>,
 }
 
 impl Terrain {
-    pub fn new(transparency: bool, texture_atlas: &TextureAtlas, seed: u32, block_manager: BlockManager) -> Self {
+    pub fn new(
+        transparency: bool,
+        texture_atlas: &TextureAtlas,
+        seed: u32,
+        block_manager: BlockManager,
+        terrain_config: TerrainConfig,
+        generator_kind: TerrainGeneratorKind,
+    ) -> Self {
+        let request_focus = Arc::new(RequestFocus::new());
+
         let (main_mesh_sender, mut thread_mesh_reciever) = unbounded::<MeshThreadRequest>();
-        let (thread_mesh_sender, main_mesh_reciever) = unbounded::<MeshThreadReturn>();
+        let (thread_mesh_sender, main_mesh_reciever) = unbounded::<ThreadReturn<MeshThreadReturn>>();
 
         let atlas_clone = texture_atlas.clone_without_image();
+        let smooth_lighting = Arc::new(AtomicBool::new(true));
+        let smooth_lighting_2 = smooth_lighting.clone();
+        let request_focus_2 = request_focus.clone();
         thread::Builder::new()
             .name("Mesh generator".to_string())
             .spawn(move || {
@@ -229,27 +450,32 @@ impl Terrain {
                     static REUSED_BUFFER: RefCell<(GreedyQuadsBuffer, Vec<Voxel>)> = RefCell::new((GreedyQuadsBuffer::new(BUFFER_SIZE), Vec::from_iter(std::iter::repeat(Voxel::default()).take(BUFFER_SIZE))));
                 }
 
+                let mut queue = Vec::new();
                 loop {
+                    let Ok(requests) = next_requests(&mut thread_mesh_reciever, &mut queue, &request_focus_2, &thread_mesh_sender) else {
+                        break;
+                    };
+
                     #[allow(unused_mut)]
                     let mut recieved_messages = {
                         #[cfg(feature = "rayon")] 
                         {
-                            collect_messages(&mut thread_mesh_reciever).into_par_iter()
+                            requests.into_par_iter()
                         }
                         #[cfg(not(feature = "rayon"))]
                         {
-                            collect_messages(&mut thread_mesh_reciever).into_iter()
+                            requests.into_iter()
                         }
                     };
 
                     if recieved_messages.len() == 0 {
                         thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
                     } else if recieved_messages.try_for_each(|recieved| {
-                        let mesh = REUSED_BUFFER.with(|buffer| MeshBuffer::new(&recieved.pos, recieved.surrounding_blocks, recieved.surrounding_lights, &atlas_clone, transparency, &mut buffer.borrow_mut()));
+                        let mesh = REUSED_BUFFER.with(|buffer| MeshBuffer::new(&recieved.pos, recieved.surrounding_blocks, recieved.surrounding_lights, &atlas_clone, transparency, smooth_lighting_2.load(Ordering::Relaxed), &mut buffer.borrow_mut()));
 
                         thread_mesh_sender
                             .clone()
-                            .unbounded_send(MeshThreadReturn::new(recieved.pos, mesh, recieved.for_state))
+                            .unbounded_send(ThreadReturn::Done(MeshThreadReturn::new(recieved.pos, mesh, recieved.for_state)))
                     }).is_err() {
                         break;
                     }
@@ -259,128 +485,222 @@ impl Terrain {
 
         let (main_lightpos_cache_sender, mut thread_lightpos_cache_reciever) =
             unbounded::<LightPosCacheThreadRequest>();
-        let (thread_lightpos_cache_sender, main_lightpos_cache_reciever) = unbounded::<LightPosCacheThreadReturn>();
+        let (thread_lightpos_cache_sender, main_lightpos_cache_reciever) =
+            unbounded::<ThreadReturn<LightPosCacheThreadReturn>>();
 
+        let request_focus_2 = request_focus.clone();
         thread::Builder::new()
             .name("Lightpos cache generator".to_string())
-            .spawn(move || loop {
-                #[allow(unused_mut)]
-                let mut recieved_messages = {
-                    #[cfg(feature = "rayon")]
-                    {
-                        collect_messages(&mut thread_lightpos_cache_reciever).into_par_iter()
-                    }
-                    #[cfg(not(feature = "rayon"))]
-                    {
-                        collect_messages(&mut thread_lightpos_cache_reciever).into_iter()
-                    }
-                };
+            .spawn(move || {
+                let mut queue = Vec::new();
+                loop {
+                    let Ok(requests) = next_requests(
+                        &mut thread_lightpos_cache_reciever,
+                        &mut queue,
+                        &request_focus_2,
+                        &thread_lightpos_cache_sender,
+                    ) else {
+                        break;
+                    };
 
-                if recieved_messages.len() == 0 {
-                    thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
-                } else if recieved_messages
-                    .try_for_each(|recieved| {
-                        let (light_pos_cache, sunlight_pos_cache) = (
-                            LightPosCache::<0>::new(&recieved.surrounding_blocks),
-                            LightPosCache::<1>::new(&recieved.surrounding_blocks),
-                        );
+                    #[allow(unused_mut)]
+                    let mut recieved_messages = {
+                        #[cfg(feature = "rayon")]
+                        {
+                            requests.into_par_iter()
+                        }
+                        #[cfg(not(feature = "rayon"))]
+                        {
+                            requests.into_iter()
+                        }
+                    };
 
-                        thread_lightpos_cache_sender
-                            .clone()
-                            .unbounded_send(LightPosCacheThreadReturn::new(
-                                recieved.pos,
-                                light_pos_cache,
-                                sunlight_pos_cache,
-                                recieved.for_state,
+                    if recieved_messages.len() == 0 {
+                        thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+                    } else if recieved_messages
+                        .try_for_each(|recieved| {
+                            let (light_pos_cache, sunlight_pos_cache) = (
+                                LightPosCache::<0>::new(&recieved.surrounding_blocks),
+                                LightPosCache::<1>::new(&recieved.surrounding_blocks),
+                            );
+
+                            thread_lightpos_cache_sender.clone().unbounded_send(ThreadReturn::Done(
+                                LightPosCacheThreadReturn::new(
+                                    recieved.pos,
+                                    light_pos_cache,
+                                    sunlight_pos_cache,
+                                    recieved.for_state,
+                                ),
                             ))
-                    })
-                    .is_err()
-                {
-                    break;
+                        })
+                        .is_err()
+                    {
+                        break;
+                    }
                 }
             })
             .unwrap();
 
         let (main_light_sender, mut thread_light_reciever) = unbounded::<LightThreadRequest>();
-        let (thread_light_sender, main_light_reciever) = unbounded::<LightThreadReturn>();
+        let (thread_light_sender, main_light_reciever) = unbounded::<ThreadReturn<LightThreadReturn>>();
+
+        let max_light_sources = Arc::new(AtomicU32::new(DEFAULT_MAX_LIGHT_SOURCES));
+        let max_light_sources_2 = max_light_sources.clone();
+        let sunlight_spread = Arc::new(AtomicU32::new(DEFAULT_SUNLIGHT_SPREAD));
+        let sunlight_spread_2 = sunlight_spread.clone();
+        let request_focus_2 = request_focus.clone();
 
         thread::Builder::new()
             .name("Light generator".to_string())
-            .spawn(move || loop {
-                #[allow(unused_mut)]
-                let mut recieved_messages = {
-                    #[cfg(feature = "rayon")]
-                    {
-                        collect_messages(&mut thread_light_reciever).into_par_iter()
-                    }
-                    #[cfg(not(feature = "rayon"))]
-                    {
-                        collect_messages(&mut thread_light_reciever).into_iter()
-                    }
-                };
+            .spawn(move || {
+                thread_local! {
+                    static REUSED_SCRATCH: RefCell<LightScratch> = RefCell::new(LightScratch::default());
+                }
 
-                if recieved_messages.len() == 0 {
-                    thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
-                } else if recieved_messages
-                    .try_for_each(|recieved| {
-                        let lights = LightBuffer::new(recieved.surrounding_blocks);
+                let mut queue = Vec::new();
+                loop {
+                    let Ok(requests) = next_requests(
+                        &mut thread_light_reciever,
+                        &mut queue,
+                        &request_focus_2,
+                        &thread_light_sender,
+                    ) else {
+                        break;
+                    };
 
-                        thread_light_sender.clone().unbounded_send(LightThreadReturn::new(
-                            recieved.pos,
-                            lights,
-                            recieved.for_state,
-                        ))
-                    })
-                    .is_err()
-                {
-                    break;
+                    #[allow(unused_mut)]
+                    let mut recieved_messages = {
+                        #[cfg(feature = "rayon")]
+                        {
+                            requests.into_par_iter()
+                        }
+                        #[cfg(not(feature = "rayon"))]
+                        {
+                            requests.into_iter()
+                        }
+                    };
+
+                    if recieved_messages.len() == 0 {
+                        thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+                    } else if recieved_messages
+                        .try_for_each(|recieved| {
+                            let (max_light_sources, sunlight_spread) = (
+                                max_light_sources_2.load(Ordering::Relaxed),
+                                sunlight_spread_2.load(Ordering::Relaxed),
+                            );
+
+                            let (lights, skipped_light_sources) = REUSED_SCRATCH.with(|scratch| {
+                                let scratch = &mut scratch.borrow_mut();
+
+                                if let Some(lights) = recieved.previous.as_ref().and_then(|previous| {
+                                    LightBuffer::relight(
+                                        previous,
+                                        recieved.surrounding_blocks.clone(),
+                                        max_light_sources,
+                                        sunlight_spread,
+                                        scratch,
+                                    )
+                                }) {
+                                    (Some(lights), 0)
+                                } else {
+                                    match LightBuffer::new(
+                                        recieved.surrounding_blocks.clone(),
+                                        max_light_sources,
+                                        sunlight_spread,
+                                        scratch,
+                                    ) {
+                                        Some((lights, skipped_light_sources)) => (Some(lights), skipped_light_sources),
+                                        None => (None, 0),
+                                    }
+                                }
+                            });
+
+                            thread_light_sender
+                                .clone()
+                                .unbounded_send(ThreadReturn::Done(LightThreadReturn::new(
+                                    recieved.pos,
+                                    lights,
+                                    skipped_light_sources,
+                                    Box::new((recieved.surrounding_blocks, sunlight_spread)),
+                                    recieved.for_state,
+                                )))
+                        })
+                        .is_err()
+                    {
+                        break;
+                    }
                 }
             })
             .unwrap();
 
         let (main_blocks_sender, mut thread_blocks_reciever) = unbounded::<BlocksThreadRequest>();
-        let (thread_blocks_sender, main_blocks_reciever) = unbounded::<BlocksThreadReturn>();
-
-        let block_manager_2 = block_manager.clone();
+        let (thread_blocks_sender, main_blocks_reciever) = unbounded::<ThreadReturn<BlocksThreadReturn>>();
+
+        let (block_manager_2, terrain_config_2, generator_kind_2) =
+            (block_manager.clone(), terrain_config.clone(), generator_kind.clone());
+        let (generation_paused, generating) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicU32::new(0)));
+        let (generation_paused_2, generating_2) = (generation_paused.clone(), generating.clone());
+        let request_focus_2 = request_focus.clone();
+        let terrain_id = NEXT_TERRAIN_ID.fetch_add(1, Ordering::Relaxed);
         thread::Builder::new()
             .name("Terrain generator".to_string())
             .spawn(move || {
+                // With rayon the generators run on the global thread pool, which is shared with every other terrain
                 ref_thread_local! {
-                    static managed TERRAIN_GENERATOR: Option<TerrainGenerator> = None;
+                    static managed TERRAIN_GENERATOR: Option<(usize, TerrainGenerator)> = None;
                 }
 
+                let mut queue = Vec::new();
                 loop {
+                    // Counted as generating before checking for a pause, so a save that paused and then sees nothing
+                    // generating can't miss a batch that is just starting
+                    generating_2.fetch_add(1, Ordering::SeqCst);
+                    if generation_paused_2.load(Ordering::SeqCst) {
+                        generating_2.fetch_sub(1, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+                        continue;
+                    }
+
+                    let Ok(requests) =
+                        next_requests(&mut thread_blocks_reciever, &mut queue, &request_focus_2, &thread_blocks_sender)
+                    else {
+                        break;
+                    };
+
                     #[allow(unused_mut)]
                     let mut recieved_messages = {
                         #[cfg(feature = "rayon")]
                         {
-                            collect_messages(&mut thread_blocks_reciever).into_par_iter()
+                            requests.into_par_iter()
                         }
                         #[cfg(not(feature = "rayon"))]
                         {
-                            collect_messages(&mut thread_blocks_reciever).into_iter()
+                            requests.into_iter()
                         }
                     };
 
                     if recieved_messages.len() == 0 {
+                        generating_2.fetch_sub(1, Ordering::SeqCst);
                         thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME))
                     } else if recieved_messages
                         .try_for_each(|recieved| {
-                            if TERRAIN_GENERATOR.borrow().is_none() {
+                            if TERRAIN_GENERATOR.borrow().as_ref().is_none_or(|(id, _)| *id != terrain_id) {
                                 *TERRAIN_GENERATOR.borrow_mut() =
-                                    Some(TerrainGenerator::new(seed, block_manager_2.clone()));
+                                    Some((terrain_id, TerrainGenerator::new(seed, block_manager_2.clone(), terrain_config_2.clone(), generator_kind_2.clone())));
                             }
 
-                            let blocks = {
+                            // Saved chunks already contain the parts of structures from their neighbours
+                            let (blocks, deferred) = {
                                 cfg_if! {
                                     if #[cfg(feature = "save_system")] {
-                                        if let Some(block_buffer) = crate::misc::save_helper::load_block_buffer(recieved.current_save_name, "chunks/".to_string() + &chunk_file_name(&recieved.pos)) {
-                                            block_buffer
+                                        if let Some(block_buffer) = crate::misc::save_helper::load_block_buffer(recieved.current_save_name, "chunks/".to_string() + &chunk_file_name(&recieved.pos), recieved.save_format_version) {
+                                            (block_buffer, DeferredBlocks::default())
                                         } else {
                                             TERRAIN_GENERATOR
                                                 .borrow_mut()
                                                 .as_mut()
                                                 .unwrap()
+                                                .1
                                                 .generate_blocks(&recieved.pos)
                                         }
                                     } else {
@@ -388,6 +708,7 @@ impl Terrain {
                                             .borrow_mut()
                                             .as_mut()
                                             .unwrap()
+                                            .1
                                             .generate_blocks(&recieved.pos)
                                     }
                                 }
@@ -395,11 +716,13 @@ impl Terrain {
 
                             thread_blocks_sender
                                 .clone()
-                                .unbounded_send(BlocksThreadReturn::new(recieved.pos, blocks))
+                                .unbounded_send(ThreadReturn::Done(BlocksThreadReturn::new(recieved.pos, blocks, deferred)))
                         })
                         .is_err()
                     {
                         break;
+                    } else {
+                        generating_2.fetch_sub(1, Ordering::SeqCst);
                     }
                 }
             })
@@ -410,23 +733,30 @@ impl Terrain {
         #[cfg(feature = "save_system")]
         let (main_chunk_save_sender, mut thread_chunk_save_reciever) = unbounded::<SaveChunkRequest>();
 
+        let save_threads = Arc::new(AtomicU32::new(DEFAULT_SAVE_THREADS));
+
         #[cfg(feature = "save_system")]
         {
-            let saving_chunks = saving_chunks.clone();
+            let (saving_chunks, save_threads) = (saving_chunks.clone(), save_threads.clone());
+            let mut thread_pool = SaveThreadPool::default();
             thread::Builder::new()
                 .name("Chunk saver".to_string())
                 .spawn(move || loop {
-                    collect_messages(&mut thread_chunk_save_reciever)
-                        .into_iter()
-                        .for_each(|recieved| {
-                            saving_chunks.fetch_add(recieved.chunks.len() as u32, Ordering::Relaxed);
-                            save_many(
-                                recieved.current_save_name,
-                                "chunks",
-                                recieved.chunks,
-                                Some(saving_chunks.clone()),
-                            );
-                        });
+                    let recieved_messages = collect_messages(&mut thread_chunk_save_reciever);
+                    if recieved_messages.is_empty() {
+                        thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+                    }
+
+                    recieved_messages.into_iter().for_each(|recieved| {
+                        save_many(
+                            recieved.current_save_name,
+                            "chunks",
+                            recieved.chunks,
+                            Some(saving_chunks.clone()),
+                            &mut thread_pool,
+                            save_threads.load(Ordering::Relaxed) as usize,
+                        );
+                    });
                 })
                 .unwrap();
         }
@@ -446,11 +776,52 @@ impl Terrain {
             chunk_save_sender: main_chunk_save_sender,
             #[cfg(feature = "save_system")]
             current_save_name: String::default(),
+            #[cfg(feature = "save_system")]
+            save_format_version: crate::misc::save_helper::WORLD_FORMAT_VERSION,
+            #[cfg(feature = "save_system")]
+            pending_saves: FxHashMap::default(),
+            #[cfg(feature = "save_system")]
+            max_queued_saves: DEFAULT_MAX_QUEUED_SAVES,
+            #[cfg(feature = "save_system")]
+            pause_generation_on_save: true,
+            #[cfg(feature = "save_system")]
+            generation_paused,
+            #[cfg(feature = "save_system")]
+            generating,
             transparency,
             texture_atlas: texture_atlas.clone_without_image(),
             loading_chunks: 0,
             saving_chunks,
+            save_threads,
+            max_light_sources,
+            sunlight_spread,
+            smooth_lighting,
+            light_capped_chunks: FxHashSet::default(),
+            buffer_pool: ChunkBufferPool::default(),
+            loading_frozen: false,
+            deferred_blocks: DeferredBlocks::default(),
+            mesh_requests: Vec::new(),
+            prioritize_look_direction: true,
+            occlusion_culling: true,
+            camera_chunk_pos: Vector3::new(
+                NonZeroI32::new(1).unwrap(),
+                NonZeroI32::new(1).unwrap(),
+                NonZeroI32::new(1).unwrap(),
+            ),
+            camera_forward: Vector3::new(0.0, 0.0, 0.0),
+            request_focus,
+            chunk_request_delay: Duration::ZERO,
+            remesh_coalescing_window: Duration::ZERO,
+            remeshes: 0,
+            chunks_first_seen: FxHashMap::default(),
+            meshes_to_render_frame: 0,
+            skipped_chunk_requests: 0,
             block_manager,
+            block_replacements: Vec::new(),
+            terrain_config,
+            generator_kind,
+            #[cfg(feature = "request_log")]
+            request_log: None,
         }
     }
 
@@ -459,13 +830,23 @@ impl Terrain {
         self.current_save_name = name;
     }
 
+    #[cfg(feature = "save_system")]
+    pub fn save_format_version(&self) -> u32 {
+        self.save_format_version
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn set_save_format_version(&mut self, format_version: u32) {
+        self.save_format_version = format_version;
+    }
+
     #[allow(dead_code)]
     pub fn get_chunk(&mut self, chunk_pos: &Vector3<NonZeroI32>, load: bool) -> Option<Pin<&Chunk>> {
         if self.chunks.get(chunk_pos).is_some() {
             Some(self.chunks.get(chunk_pos).unwrap().as_ref())
         } else {
             if load {
-                self.request_chunk_blocks(chunk_pos)
+                self.request_chunk_blocks(chunk_pos);
             }
             None
         }
@@ -477,7 +858,7 @@ impl Terrain {
             Some(self.chunks.get_mut(chunk_pos).unwrap().as_mut())
         } else {
             if load {
-                self.request_chunk_blocks(chunk_pos)
+                self.request_chunk_blocks(chunk_pos);
             }
             None
         }
@@ -502,7 +883,7 @@ impl Terrain {
 
             if !chunk.lights_up_to_date() {
                 if load {
-                    self.request_chunk_light(chunk_pos)
+                    self.request_chunk_light(chunk_pos);
                 }
                 if !return_if_outdated {
                     return None;
@@ -628,36 +1009,36 @@ impl Terrain {
         Some(unsafe { mem::transmute(out) })
     }
 
-    pub fn set_block(&mut self, pos: &Pos, block: Block) {
-        const fn can_affect_chunk_light(x: i32, y: i32, z: i32, in_chunk_pos: Vector3<u32>) -> bool {
-            !((x != 0 && y != 0 && z != 0)
-                && ((x == -1 && in_chunk_pos.x >= MAX_LIGHT_VAL as u32)
-                    || (x == 1 && in_chunk_pos.x <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)
-                    || (y == -1 && in_chunk_pos.x >= MAX_LIGHT_VAL as u32)
-                    || (y == 1 && in_chunk_pos.y <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)
-                    || (z == -1 && in_chunk_pos.z >= MAX_LIGHT_VAL as u32)
-                    || (z == 1 && in_chunk_pos.z <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)))
-        }
-
-        fn highest_block_in_chunk_sees_sky(terrain: &mut Terrain, pos: &Pos) -> bool {
-            let collum = {
-                let in_chunk_pos = pos.in_chunk_pos_i32();
-                Vector2::new(in_chunk_pos.x, in_chunk_pos.z)
-            };
-            let mut current_chunk_pos = *pos.chunk_pos();
+    // Whether no loaded chunk above has an opaque block in the collum
+    fn highest_block_in_chunk_sees_sky(&mut self, chunk_pos: &Vector3<NonZeroI32>, collum: &Vector2<i32>) -> bool {
+        let mut current_chunk_pos = add_non_zero_i32_vector3(*chunk_pos, Vector3::new(0, 1, 0));
+        while let Some(blocks) = self.get_blocks(&current_chunk_pos, false) {
+            if blocks.contains_collum_opaque_blocks(collum) {
+                return false;
+            }
 
             current_chunk_pos = add_non_zero_i32_vector3(current_chunk_pos, Vector3::new(0, 1, 0));
-            while let Some(blocks) = terrain.get_blocks(&current_chunk_pos, false) {
-                if blocks.contains_collum_opaque_blocks(&collum) {
-                    return false;
-                }
+        }
 
-                current_chunk_pos = add_non_zero_i32_vector3(current_chunk_pos, Vector3::new(0, 1, 0));
-            }
+        true
+    }
 
-            true
+    // Chunks at the edge of the loaded area can't update their caches now, so they get rebuilt once all their
+    // neighbours are loaded
+    fn do_cache_updates(&mut self, chunk_positions: FxHashSet<Vector3<NonZeroI32>>) {
+        for chunk_pos in chunk_positions {
+            if let Some(surrounding_blocks) = self.get_surrounding_blocks_cube(&chunk_pos, false) {
+                if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                    chunk.do_cache_updates(&surrounding_blocks)
+                }
+            } else if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                log::warn!("Trying to set block in a chunk with atleast one nonexistent neighbouring chunk");
+                chunk.clear_light_source_caches();
+            }
         }
+    }
 
+    pub fn set_block(&mut self, pos: &Pos, block: Block) {
         fn chunks_to_update(pos: &Pos) -> FxHashSet<Vector3<NonZeroI32>> {
             let mut out = FxHashSet::default();
             let in_chunk_pos = {
@@ -686,7 +1067,7 @@ impl Terrain {
             terrain: &mut Terrain,
             pos: &Pos,
             contains_collum_opaque_blocks_changed: bool,
-        ) -> FxHashSet<(Vector3<NonZeroI32>, Option<Either<(), Vector2<u32>>>)> {
+        ) -> FxHashSet<(Vector3<NonZeroI32>, bool)> {
             let mut out = FxHashSet::default();
             let in_chunk_pos = pos.in_chunk_pos_i32();
             let collum = { Vector2::new(in_chunk_pos.x, in_chunk_pos.z) };
@@ -694,7 +1075,7 @@ impl Terrain {
             let mut current_chunk_pos = *pos.chunk_pos();
             out.insert((
                 Vector3::new(pos.chunk_pos().x, pos.chunk_pos().y, pos.chunk_pos().z),
-                Some(Either::Left(())),
+                true,
             ));
 
             if contains_collum_opaque_blocks_changed {
@@ -719,29 +1100,10 @@ impl Terrain {
                                         add_to_non_zero_i32(pos.chunk_pos.z, z),
                                     );
 
-                                    {
-                                        if can_affect_chunk_light(x, y, z, in_chunk_pos) {
-                                            out.insert((
-                                                current_chunk_pos,
-                                                if x == 0 && y == 0 && z == 0 {
-                                                    Some(Either::Left(()))
-                                                } else {
-                                                    None
-                                                },
-                                            ));
-
-                                            {
-                                                let collum = Vector2::new(in_chunk_pos.x, in_chunk_pos.z);
-
-                                                if (x == -1 && collum.x == 0)
-                                                    || (x == 1 && collum.x == CHUNK_SIZE - 1)
-                                                    || (z == -1 && collum.y == 0)
-                                                    || (z == 1 && collum.y == CHUNK_SIZE - 1)
-                                                {
-                                                    out.insert((current_chunk_pos, Some(Either::Right(collum))));
-                                                }
-                                            }
-                                        }
+                                    // Collums next to the updated one in neighbouring chunks get refreshed when the
+                                    // chunk itself is updated, the rest only need their light recalculated
+                                    if can_affect_chunk_light(x, y, z, in_chunk_pos) {
+                                        out.insert((current_chunk_pos, x == 0 && y == 0 && z == 0));
                                     }
                                 }
                             }
@@ -759,7 +1121,10 @@ impl Terrain {
             out
         }
 
-        let highest_block_in_chunk_sees_sky = highest_block_in_chunk_sees_sky(self, pos);
+        let highest_block_in_chunk_sees_sky = {
+            let in_chunk_pos = pos.in_chunk_pos_i32();
+            self.highest_block_in_chunk_sees_sky(pos.chunk_pos(), &Vector2::new(in_chunk_pos.x, in_chunk_pos.z))
+        };
         if let Some(mut chunk) = self.get_chunk_mut(&pos.chunk_pos, false) {
             let (contains_collum_opaque_block_old, contains_collum_opaque_block_new) =
                 chunk.set_block(&pos.in_chunk_pos_i32(), block);
@@ -777,11 +1142,7 @@ impl Terrain {
                         pos,
                         contains_collum_opaque_block_old != contains_collum_opaque_block_new,
                     );
-                    chunks_to_update_tmp.extend(
-                        chunks_to_update(pos)
-                            .into_iter()
-                            .map(|x| (x, Option::<Either<(), Vector2<u32>>>::None)),
-                    );
+                    chunks_to_update_tmp.extend(chunks_to_update(pos).into_iter().map(|x| (x, false)));
                     chunks_to_update_tmp
                 };
 
@@ -791,50 +1152,40 @@ impl Terrain {
 
                     for (current_chunk_pos, update_sunlight) in chunks_to_update {
                         if let Some(mut current_chunk) = self.get_chunk_mut(&current_chunk_pos, false) {
-                            if let Some(update_sunlight) = update_sunlight {
-                                match update_sunlight {
-                                    Either::Left(_) => {
-                                        current_chunk.update_sunlight_in_collum(
-                                            &main_chunk_collum,
-                                            if current_chunk_pos.y < pos.chunk_pos.y {
-                                                !contains_collum_opaque_block_new && highest_block_in_chunk_sees_sky
-                                            } else {
-                                                highest_block_in_chunk_sees_sky
-                                            },
-                                        );
-
-                                        for i in 0..=3 {
-                                            let collum =
-                                                Vector2::new(main_chunk_collum.x as i32, main_chunk_collum.y as i32)
-                                                    + (match i {
-                                                        0 => Vector2::new(1, 0),
-                                                        1 => Vector2::new(-1, 0),
-                                                        2 => Vector2::new(0, 1),
-                                                        3 => Vector2::new(0, -1),
-                                                        _ => unreachable!(),
-                                                    });
-
-                                            if (collum.x >= 0 && collum.x < CHUNK_SIZE as i32)
-                                                && (collum.y >= 0 && collum.y < CHUNK_SIZE as i32)
-                                            {
-                                                current_chunk.refresh_sunlight_in_collum(&Vector2::new(
-                                                    collum.x as u32,
-                                                    collum.y as u32,
-                                                ))
-                                            } else {
-                                                let (to_update_chunk_pos, to_update_in_chunk_pos) =
-                                                    coordinate_in_surrounding_buffers_cube(Vector3::new(
-                                                        collum.x, 0, collum.y,
-                                                    ));
-
-                                                to_update_collums_in_other_chunks.push((
-                                                    add_non_zero_i32_vector3(current_chunk_pos, to_update_chunk_pos),
-                                                    Vector2::new(to_update_in_chunk_pos.x, to_update_in_chunk_pos.z),
-                                                ));
-                                            }
-                                        }
+                            if update_sunlight {
+                                current_chunk.update_sunlight_in_collum(
+                                    &main_chunk_collum,
+                                    if current_chunk_pos.y < pos.chunk_pos.y {
+                                        !contains_collum_opaque_block_new && highest_block_in_chunk_sees_sky
+                                    } else {
+                                        highest_block_in_chunk_sees_sky
+                                    },
+                                );
+
+                                for i in 0..=3 {
+                                    let collum = Vector2::new(main_chunk_collum.x as i32, main_chunk_collum.y as i32)
+                                        + (match i {
+                                            0 => Vector2::new(1, 0),
+                                            1 => Vector2::new(-1, 0),
+                                            2 => Vector2::new(0, 1),
+                                            3 => Vector2::new(0, -1),
+                                            _ => unreachable!(),
+                                        });
+
+                                    if (collum.x >= 0 && collum.x < CHUNK_SIZE as i32)
+                                        && (collum.y >= 0 && collum.y < CHUNK_SIZE as i32)
+                                    {
+                                        current_chunk
+                                            .refresh_sunlight_in_collum(&Vector2::new(collum.x as u32, collum.y as u32))
+                                    } else {
+                                        let (to_update_chunk_pos, to_update_in_chunk_pos) =
+                                            coordinate_in_surrounding_buffers_cube(Vector3::new(collum.x, 0, collum.y));
+
+                                        to_update_collums_in_other_chunks.push((
+                                            add_non_zero_i32_vector3(current_chunk_pos, to_update_chunk_pos),
+                                            Vector2::new(to_update_in_chunk_pos.x, to_update_in_chunk_pos.z),
+                                        ));
                                     }
-                                    Either::Right(collum) => current_chunk.refresh_sunlight_in_collum(&collum),
                                 }
 
                                 chunks_to_update_cache.insert(current_chunk_pos);
@@ -855,20 +1206,7 @@ impl Terrain {
                         }
                     }
 
-                    for to_update_cache_chunk_pos in chunks_to_update_cache {
-                        if let Some(surrounding_blocks) =
-                            self.get_surrounding_blocks_cube(&to_update_cache_chunk_pos, false)
-                        {
-                            if let Some(mut current_chunk) = self.get_chunk_mut(&to_update_cache_chunk_pos, false) {
-                                current_chunk.do_cache_updates(&surrounding_blocks)
-                            }
-                        } else {
-                            log::error!(
-                                "Trying to set block in a chunk with atleast one nonexistent neighbouring chunk"
-                            );
-                            return;
-                        }
-                    }
+                    self.do_cache_updates(chunks_to_update_cache);
                 }
             }
         } else {
@@ -882,180 +1220,433 @@ impl Terrain {
             .map(|blocks| blocks[&pos.in_chunk_pos_i32()].clone())
     }
 
-    #[allow(dead_code)]
-    pub fn get_light(&mut self, pos: &Pos) -> Option<LightVal> {
-        self.get_lights(&pos.chunk_pos, false, false)
-            .map(|lights| lights[&pos.in_chunk_pos_i32()].clone())
+    // Like `get_block` for the block at an absolute block coordinate, see `Pos::from_abs_block`
+    pub fn get_block_abs(&mut self, abs_block_pos: Vector3<i64>) -> Option<Block> {
+        Pos::from_abs_block(abs_block_pos).and_then(|pos| self.get_block(&pos))
     }
 
-    pub fn meshes_to_render(
-        &mut self,
-        camera: &Camera,
-        render_distance_horizontal: u32,
-        render_distance_vertical: u32,
-        device: &wgpu::Device,
-    ) -> Vec<&ChunkMesh> {
-        #[inline]
-        fn append_all_chunk_combinations(
-            terrain: &mut Terrain,
-            camera: &Camera,
-            camera_offset: Vector3<i32>,
-            device: &wgpu::Device,
-            out: &mut Vec<&ChunkMesh>,
-            out_transparents: &mut Vec<&ChunkMesh>,
-        ) {
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(camera_offset.x, camera_offset.y, camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(-camera_offset.x, camera_offset.y, camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(camera_offset.x, -camera_offset.y, camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(camera_offset.x, camera_offset.y, -camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(-camera_offset.x, -camera_offset.y, camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(camera_offset.x, -camera_offset.y, -camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(-camera_offset.x, camera_offset.y, -camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
-            chunk_to_out(
-                terrain,
-                add_non_zero_i32_vector3(
-                    camera.pos.chunk_pos,
-                    Vector3::new(-camera_offset.x, -camera_offset.y, -camera_offset.z),
-                ),
-                device,
-                out,
-                out_transparents,
-            );
+    pub fn set_block_abs(&mut self, abs_block_pos: Vector3<i64>, block: Block) {
+        match Pos::from_abs_block(abs_block_pos) {
+            Some(pos) => self.set_block(&pos, block),
+            None => log::warn!("Trying to set block at {abs_block_pos:?}, past the last chunk"),
         }
+    }
 
-        #[inline]
-        fn chunk_to_out(
-            terrain: &mut Terrain,
-            chunk_pos: Vector3<NonZeroI32>,
-            device: &wgpu::Device,
-            out: &mut Vec<&ChunkMesh>,
-            out_transparents: &mut Vec<&ChunkMesh>,
-        ) {
-            mesh_to_out(terrain, chunk_pos, device, out, out_transparents);
+    // Sets every block in between and including both corners to block, see `set_blocks`
+    pub fn fill_region(&mut self, min: &Pos, max: &Pos, block: Block) -> usize {
+        profile_span!("fill_region");
+        let (first, second) = (min.abs_block_pos(), max.abs_block_pos());
+        let (min, max) = (first.zip(second, i64::min), first.zip(second, i64::max));
+
+        self.set_blocks(
+            (min.x..=max.x)
+                .flat_map(|x| (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| Vector3::new(x, y, z))))
+                .filter_map(Pos::from_abs_block)
+                .map(|pos| (pos, block.clone())),
+        )
+    }
+
+    // Ends up the same as calling `set_block` for every block, but the sunlight of each collum that changed is worked out
+    // once, and so are the caches of each chunk. Blocks in chunks that aren't loaded are skipped, returns how many blocks
+    // were set
+    pub fn set_blocks(&mut self, blocks: impl IntoIterator<Item = (Pos, Block)>) -> usize {
+        profile_span!("set_blocks");
+        let mut chunk_blocks = FxHashMap::<Vector3<NonZeroI32>, Vec<_>>::default();
+        for (pos, block) in blocks {
+            chunk_blocks
+                .entry(pos.chunk_pos)
+                .or_default()
+                .push((pos.in_chunk_pos_i32(), block));
         }
 
-        #[inline]
-        fn mesh_to_out(
-            terrain: &mut Terrain,
-            chunk_pos: Vector3<NonZeroI32>,
-            device: &wgpu::Device,
-            out: &mut Vec<&ChunkMesh>,
-            out_transparents: &mut Vec<&ChunkMesh>,
-        ) {
-            let mut do_request = false;
+        let (mut set, mut skipped) = (0, 0);
+        // Collums that need their sunlight worked out again, and every chunk whose light and mesh are outdated
+        let (mut sunlight_collums, mut outdated_chunks) = (FxHashSet::default(), FxHashSet::default());
+        for (chunk_pos, blocks) in chunk_blocks {
+            let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) else {
+                skipped += blocks.len();
+                continue;
+            };
 
-            if let Some(mut chunk) = terrain.get_chunk_mut(&chunk_pos, true) {
-                if !chunk.mesh_up_to_date() || !chunk.lights_up_to_date() {
-                    do_request = true
+            let (min, max) = blocks.iter().fold(
+                (
+                    Vector3::new(i32::MAX, i32::MAX, i32::MAX),
+                    Vector3::new(i32::MIN, i32::MIN, i32::MIN),
+                ),
+                |(min, max), (in_chunk_pos, _)| (min.zip(*in_chunk_pos, i32::min), max.zip(*in_chunk_pos, i32::max)),
+            );
+            set += blocks.len();
+            let collums = chunk.set_blocks(blocks);
+            outdated_chunks.extend(chunks_in_light_reach(chunk_pos, min, max));
+
+            for (collum, contains_collum_opaque_blocks_changed) in collums {
+                sunlight_collums.insert((chunk_pos, collum));
+                if !contains_collum_opaque_blocks_changed {
+                    continue;
                 }
 
-                if let Some(mesh) = chunk.mesh(device) {
-                    unsafe {
-                        if mesh.0.num_elements > 0 {
-                            out.push(mem::transmute::<&ChunkMesh, &'static ChunkMesh>(&mesh.0));
-                        }
-                        if mesh.1.num_elements > 0 {
-                            out_transparents.push(mem::transmute::<&ChunkMesh, &'static ChunkMesh>(&mesh.1));
-                        }
-                    }
-                } else {
+                // Sunlight below changes down to the first chunk with an opaque block in the collum
+                let collum_box = |y: i32| Vector3::new(collum.x, y, collum.y);
+                let mut current_chunk_pos = add_non_zero_i32_vector3(chunk_pos, Vector3::new(0, -1, 0));
+                while let Some(blocks) = self.get_blocks(&current_chunk_pos, false) {
+                    sunlight_collums.insert((current_chunk_pos, collum));
+                    outdated_chunks.extend(chunks_in_light_reach(
+                        current_chunk_pos,
+                        collum_box(0),
+                        collum_box(CHUNK_SIZE as i32 - 1),
+                    ));
+                    if blocks.contains_collum_opaque_blocks(&collum) {
+                        break;
+                    }
+
+                    current_chunk_pos = add_non_zero_i32_vector3(current_chunk_pos, Vector3::new(0, -1, 0));
+                }
+            }
+        }
+
+        // Collums next to changed ones only have their sunlight sources refreshed
+        let mut refreshed_collums = FxHashSet::default();
+        let mut chunks_to_update_cache = FxHashSet::default();
+        for (chunk_pos, collum) in &sunlight_collums {
+            let highest_block_in_chunk_sees_sky = self.highest_block_in_chunk_sees_sky(chunk_pos, collum);
+            if let Some(mut chunk) = self.get_chunk_mut(chunk_pos, false) {
+                chunk.update_sunlight_in_collum(
+                    &Vector2::new(collum.x as u32, collum.y as u32),
+                    highest_block_in_chunk_sees_sky,
+                );
+                chunks_to_update_cache.insert(*chunk_pos);
+            }
+
+            for offset in [
+                Vector2::new(1, 0),
+                Vector2::new(-1, 0),
+                Vector2::new(0, 1),
+                Vector2::new(0, -1),
+            ] {
+                let neighbour = collum + offset;
+                let (neighbour_chunk_pos, neighbour_in_chunk_pos) =
+                    coordinate_in_surrounding_buffers_cube(Vector3::new(neighbour.x, 0, neighbour.y));
+                let neighbour = (
+                    add_non_zero_i32_vector3(*chunk_pos, neighbour_chunk_pos),
+                    Vector2::new(neighbour_in_chunk_pos.x, neighbour_in_chunk_pos.z),
+                );
+                if !sunlight_collums.contains(&neighbour) {
+                    refreshed_collums.insert(neighbour);
+                }
+            }
+        }
+        for (chunk_pos, collum) in refreshed_collums {
+            if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                chunk.refresh_sunlight_in_collum(&Vector2::new(collum.x as u32, collum.y as u32));
+                chunks_to_update_cache.insert(chunk_pos);
+                outdated_chunks.insert(chunk_pos);
+            }
+        }
+
+        for chunk_pos in outdated_chunks.iter().chain(&chunks_to_update_cache) {
+            if let Some(mut chunk) = self.get_chunk_mut(chunk_pos, false) {
+                chunk.set_lights_outdated();
+                chunk.set_mesh_outdated();
+            }
+        }
+        self.do_cache_updates(chunks_to_update_cache);
+
+        if skipped > 0 {
+            log::warn!("Skipped setting {skipped} blocks in chunks that aren't loaded");
+        }
+        set
+    }
+
+    // Sets the blocks of the structure with its most negative corner at origin. Blocks in chunks that aren't loaded are
+    // skipped, returns how many blocks were set
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn paste_structure(&mut self, origin: &Pos, structure: &Structure) -> usize {
+        profile_span!("paste_structure");
+        let origin = origin.abs_pos().map(|coord| coord.floor());
+
+        let (mut blocks, mut unknown) = (Vec::new(), 0);
+        for (offset, block_name) in structure.blocks() {
+            if self.block_manager.get(block_name).is_none() {
+                unknown += 1;
+                continue;
+            }
+
+            blocks.push((
+                Pos::from_abs(origin + offset.map(|coord| coord as f64 + 0.5)),
+                Block::new_with_default(block_name, &self.block_manager),
+            ));
+        }
+
+        if unknown > 0 {
+            log::warn!("Skipped {unknown} blocks of the structure without a block file");
+        }
+        self.set_blocks(blocks)
+    }
+
+    // Wavefront OBJ of every loaded chunk in between and including the chunks of both corners, its faces use the
+    // material atlas of material_library. Chunks are meshed again, as only the buffers of their meshes are kept
+    pub fn export_region_obj(&mut self, corners: (Pos, Pos), material_library: &str) -> String {
+        profile_span!("export_region_obj");
+        const BUFFER_SIZE: usize = CHUNK_SIZE_MESHING.pow(3) as usize;
+
+        let chunk_range = |first: NonZeroI32, second: NonZeroI32| {
+            let (first, second) = (i32::from(first), i32::from(second));
+            (first.min(second)..=first.max(second)).filter_map(NonZeroI32::new)
+        };
+        let (first, second) = (corners.0.chunk_pos, corners.1.chunk_pos);
+
+        let mut obj = format!("mtllib {material_library}\nusemtl atlas\n");
+        let mut reused_buffers = (GreedyQuadsBuffer::new(BUFFER_SIZE), vec![Voxel::default(); BUFFER_SIZE]);
+        let (mut first_vertex, mut skipped) = (0, 0);
+        for x in chunk_range(first.x, second.x) {
+            for y in chunk_range(first.y, second.y) {
+                for z in chunk_range(first.z, second.z) {
+                    let chunk_pos = Vector3::new(x, y, z);
+                    let (Some(blocks), Some(lights)) = (
+                        self.get_surrounding_blocks(&chunk_pos, false),
+                        self.get_surrounding_lights(&chunk_pos, false, true),
+                    ) else {
+                        skipped += 1;
+                        continue;
+                    };
+
+                    let mesh = MeshBuffer::new(
+                        &chunk_pos,
+                        blocks,
+                        lights,
+                        &self.texture_atlas,
+                        self.transparency,
+                        self.smooth_lighting.load(Ordering::Relaxed),
+                        &mut reused_buffers,
+                    );
+                    obj.push_str(&format!("o chunk_{x}_{y}_{z}\n"));
+                    for mesh in [&mesh.solid_mesh, &mesh.transparent_mesh, &mesh.double_sided_mesh] {
+                        mesh.write_obj(&mut obj, &mut first_vertex, self.texture_atlas.tile_size());
+                    }
+                }
+            }
+        }
+
+        if skipped > 0 {
+            log::warn!("Left {skipped} chunks without them and their neighbours loaded out of the export");
+        }
+        obj
+    }
+
+    #[allow(dead_code)]
+    pub fn get_light(&mut self, pos: &Pos) -> Option<LightVal> {
+        self.get_lights(&pos.chunk_pos, false, false)
+            .map(|lights| lights[&pos.in_chunk_pos_i32()].clone())
+    }
+
+    // Top face color of the highest opaque block in every column up to radius blocks away from the camera, row after
+    // row of increasing x from the most negative corner. Only loaded chunks from up chunks above to down chunks below
+    // the camera are searched, columns without an opaque block in them are None
+    pub fn surface_colors(&self, camera_pos: &Pos, radius: u32, up: u32, down: u32) -> Vec<Option<[u8; 3]>> {
+        profile_span!("surface_colors");
+        let radius = radius as i32;
+        let center = camera_pos.abs_pos();
+        let chunk_y = camera_pos.chunk_pos().y;
+
+        let mut out = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+        for z in -radius..=radius {
+            for x in -radius..=radius {
+                let column = Pos::from_abs(Vector3::new(center.x + x as f64, 0.0, center.z + z as f64));
+                let (chunk_pos, in_chunk_pos) = (column.chunk_pos(), column.in_chunk_pos_i32());
+
+                out.push((-(down as i32)..=up as i32).rev().find_map(|offset| {
+                    let chunk = self.chunks.get(&Vector3::new(
+                        chunk_pos.x,
+                        add_to_non_zero_i32(chunk_y, offset),
+                        chunk_pos.z,
+                    ))?;
+                    let blocks = chunk.blocks();
+                    (0..CHUNK_SIZE as i32).rev().find_map(|y| {
+                        let block = &blocks[&Vector3::new(in_chunk_pos.x, y, in_chunk_pos.z)];
+                        match block.texture_id() {
+                            Some(textures) if !block.is_transparent() => {
+                                Some(self.texture_atlas.average_color(textures.face(FaceDirection::Top)))
+                            }
+                            _ => None,
+                        }
+                    })
+                }));
+            }
+        }
+        out
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn meshes_to_render(
+        &mut self,
+        camera: &Camera,
+        render_distance_horizontal: u32,
+        render_distance_up: u32,
+        render_distance_down: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        solid_only: bool,
+    ) -> (Vec<&ChunkMesh>, Vec<&ChunkMesh>, Vec<&ChunkMesh>) {
+        profile_span!("meshes_to_render");
+        self.chunks_in_range(
+            camera,
+            render_distance_horizontal,
+            render_distance_up,
+            render_distance_down,
+            Some((device, queue)),
+            solid_only,
+        )
+    }
+
+    // Requests chunks around the camera like `meshes_to_render` without uploading their meshes, for when nothing is
+    // drawn
+    pub fn load_chunks(
+        &mut self,
+        camera: &Camera,
+        render_distance_horizontal: u32,
+        render_distance_up: u32,
+        render_distance_down: u32,
+    ) {
+        profile_span!("load_chunks");
+        self.chunks_in_range(
+            camera,
+            render_distance_horizontal,
+            render_distance_up,
+            render_distance_down,
+            None,
+            false,
+        );
+    }
+
+    // Without a device the chunks are only requested, none of them are returned
+    fn chunks_in_range(
+        &mut self,
+        camera: &Camera,
+        render_distance_horizontal: u32,
+        render_distance_up: u32,
+        render_distance_down: u32,
+        gpu: Option<(&wgpu::Device, &wgpu::Queue)>,
+        solid_only: bool,
+    ) -> (Vec<&ChunkMesh>, Vec<&ChunkMesh>, Vec<&ChunkMesh>) {
+        self.meshes_to_render_frame += 1;
+
+        self.camera_chunk_pos = camera.pos.chunk_pos;
+        self.camera_forward = camera.forward_vec_xyz();
+        self.request_focus.set(
+            &camera.pos.chunk_pos,
+            render_distance_horizontal + REQUEST_DROP_MARGIN,
+            render_distance_up + REQUEST_DROP_MARGIN,
+            render_distance_down + REQUEST_DROP_MARGIN,
+        );
+
+        #[cfg(feature = "save_system")]
+        self.send_pending_saves(false);
+
+        #[inline]
+        fn mesh_to_out(
+            terrain: &mut Terrain,
+            chunk_pos: Vector3<NonZeroI32>,
+            gpu: Option<(&wgpu::Device, &wgpu::Queue)>,
+            out: &mut Vec<&ChunkMesh>,
+            out_transparents: &mut Vec<&ChunkMesh>,
+            out_double_sided: &mut Vec<&ChunkMesh>,
+        ) {
+            let mut do_request = false;
+
+            let load = !terrain.loading_frozen && terrain.chunk_request_due(&chunk_pos);
+            let remesh_coalescing_window = terrain.remesh_coalescing_window;
+            let occluded = terrain.occlusion_culling && terrain.occluded(&chunk_pos);
+            let mut buffer_pool = mem::take(&mut terrain.buffer_pool);
+            if let Some(mut chunk) = terrain.get_chunk_mut(&chunk_pos, load) {
+                // Edited chunks keep their old mesh until no edit came in for the coalescing window
+                let coalescing = chunk
+                    .outdated_for()
+                    .is_some_and(|outdated_for| outdated_for < remesh_coalescing_window);
+                if (!chunk.mesh_up_to_date() || !chunk.lights_up_to_date()) && !coalescing {
+                    do_request = true
+                }
+
+                // Without a device meshes are left waiting to be uploaded
+                let mesh = match gpu {
+                    Some((device, queue)) => chunk.mesh(device, queue, &mut buffer_pool),
+                    None => {
+                        if !chunk.has_mesh() {
+                            do_request = true
+                        }
+                        None
+                    }
+                };
+                if let Some(mesh) = mesh {
+                    // Occluded chunks are still kept meshed, so they show up as soon as a neighbour opens up
+                    if !occluded {
+                        unsafe {
+                            if mesh.0.num_elements > 0 {
+                                out.push(mem::transmute::<&ChunkMesh, &'static ChunkMesh>(&mesh.0));
+                            }
+                            if mesh.1.num_elements > 0 {
+                                out_transparents.push(mem::transmute::<&ChunkMesh, &'static ChunkMesh>(&mesh.1));
+                            }
+                            if mesh.2.num_elements > 0 {
+                                out_double_sided.push(mem::transmute::<&ChunkMesh, &'static ChunkMesh>(&mesh.2));
+                            }
+                        }
+                    }
+                } else if gpu.is_some() {
                     do_request = true
                 }
             }
 
-            if do_request {
-                terrain.request_chunk_mesh(&chunk_pos)
+            terrain.buffer_pool = buffer_pool;
+
+            if do_request && !terrain.loading_frozen {
+                terrain.mesh_requests.push(chunk_pos)
             }
         }
 
-        let mut out =
-            Vec::with_capacity(((render_distance_horizontal * 2 + 1).pow(2) * render_distance_vertical) as usize);
+        let chunk_count = (render_distance_horizontal * 2 + 1).pow(2) * (render_distance_up + render_distance_down + 1);
+        let mut out = Vec::with_capacity(chunk_count as usize);
         let mut out_transparents =
-            Vec::with_capacity(((render_distance_horizontal * 2 + 1) * render_distance_vertical) as usize);
-
-        for x in 0..=render_distance_horizontal {
-            for y in 0..=render_distance_vertical {
-                for z in 0..=render_distance_horizontal {
-                    append_all_chunk_combinations(
+            Vec::with_capacity(chunk_count as usize / (render_distance_horizontal * 2 + 1) as usize);
+        let mut out_double_sided = Vec::new();
+
+        let render_distance_horizontal = render_distance_horizontal as i32;
+        for x in -render_distance_horizontal..=render_distance_horizontal {
+            for y in -(render_distance_down as i32)..=render_distance_up as i32 {
+                for z in -render_distance_horizontal..=render_distance_horizontal {
+                    mesh_to_out(
                         self,
-                        camera,
-                        Vector3::new(x as i32, y as i32, z as i32),
-                        device,
+                        add_non_zero_i32_vector3(camera.pos.chunk_pos, Vector3::new(x, y, z)),
+                        gpu,
                         &mut out,
                         &mut out_transparents,
+                        &mut out_double_sided,
                     );
                 }
             }
         }
 
-        out.extend(out_transparents);
-        out
+        let mut mesh_requests = mem::take(&mut self.mesh_requests);
+        mesh_requests.sort_by_cached_key(|chunk_pos| self.request_priority(chunk_pos));
+        for chunk_pos in mesh_requests.drain(..) {
+            self.request_chunk_mesh(&chunk_pos);
+        }
+        self.mesh_requests = mesh_requests;
+
+        // Chunks that went out of range before their request was due never had to be generated
+        let frame = self.meshes_to_render_frame;
+        let len_before = self.chunks_first_seen.len();
+        self.chunks_first_seen
+            .retain(|_, (_, last_seen_frame)| *last_seen_frame == frame);
+        self.skipped_chunk_requests += (len_before - self.chunks_first_seen.len()) as u32;
+
+        if solid_only {
+            out_transparents.clear();
+        }
+        (out, out_transparents, out_double_sided)
     }
 
     pub fn update(&mut self) {
+        profile_span!("terrain_update");
         self.handle_recieved_chunk_blocks();
         self.handle_recieved_chunk_light_pos_caches();
         self.handle_recieved_chunk_lights();
@@ -1067,8 +1658,8 @@ impl Terrain {
         camera_chunk_pos: &Vector3<NonZeroI32>,
         render_distance_horizontal: u32,
         render_distance_vertical: u32,
+        kept_surrounding_chunks: u32,
     ) {
-        const KEPT_SURROUNDING_CHUNKS: u32 = 4;
         log::info!("Purging chunks");
 
         let camera_pos_f32 = Vector3::new(
@@ -1080,52 +1671,137 @@ impl Terrain {
         #[cfg(feature = "save_system")]
         let mut to_save = Vec::new();
 
-        self.chunks.retain(|chunk_pos, chunk| {
-            if camera_pos_f32.distance(Vector3::new(
+        let kept = |chunk_pos: &Vector3<NonZeroI32>| {
+            camera_pos_f32.distance(Vector3::new(
                 Into::<i32>::into(chunk_pos.x) as f32,
                 Into::<i32>::into(chunk_pos.y) as f32,
                 Into::<i32>::into(chunk_pos.z) as f32,
-            )) <= (render_distance_horizontal.max(render_distance_vertical) + KEPT_SURROUNDING_CHUNKS) as f32
-            {
+            )) <= (render_distance_horizontal.max(render_distance_vertical) + kept_surrounding_chunks) as f32
+        };
+
+        self.chunks.retain(|chunk_pos, chunk| {
+            if kept(chunk_pos) {
                 true
             } else {
                 #[cfg(feature = "save_system")]
-                to_save.push((chunk_file_name(chunk_pos), chunk.blocks()));
+                to_save.push((*chunk_pos, chunk.blocks()));
+                chunk.recycle_mesh(&mut self.buffer_pool);
 
                 false
             }
         });
+        self.light_capped_chunks
+            .retain(|chunk_pos| self.chunks.contains_key(chunk_pos));
+        // Structure blocks for chunks that were never loaded would otherwise pile up with the explored area
+        self.deferred_blocks.retain(|chunk_pos, _| kept(chunk_pos));
 
         #[cfg(feature = "save_system")]
-        self.chunk_save_sender
-            .unbounded_send(SaveChunkRequest::new(
-                self.current_save_name.clone(),
-                to_save
-                    .into_iter()
-                    .map(|(save_name, blocks)| (save_name, (*blocks).clone()))
-                    .collect(),
-            ))
-            .unwrap();
+        self.queue_saves(to_save);
     }
 
     #[cfg(feature = "save_system")]
     pub fn save(&mut self) {
-        self.chunk_save_sender
-            .unbounded_send(SaveChunkRequest::new(
-                self.current_save_name.clone(),
-                self.chunks
-                    .iter()
-                    .map(|(chunk_pos, chunk)| (chunk_file_name(chunk_pos), (*chunk.blocks()).clone()))
-                    .collect::<Vec<_>>(),
-            ))
-            .unwrap();
+        if self.pause_generation_on_save {
+            self.pause_generation();
+        }
+
+        let to_save = self
+            .chunks
+            .iter()
+            .map(|(chunk_pos, chunk)| (*chunk_pos, chunk.blocks()))
+            .collect::<Vec<_>>();
+        self.queue_saves(to_save);
+
+        self.generation_paused.store(false, Ordering::SeqCst);
+    }
+
+    // Lets the terrain generator finish the chunks it is working on and takes them in, so the saved chunks contain the
+    // structure blocks they spread to each other
+    #[cfg(feature = "save_system")]
+    fn pause_generation(&mut self) {
+        let start = Instant::now();
+        self.generation_paused.store(true, Ordering::SeqCst);
+
+        while self.generating.load(Ordering::SeqCst) > 0 {
+            if start.elapsed() >= MAX_GENERATION_PAUSE {
+                log::warn!(
+                    "Terrain generator still busy after {:?}, saving anyway",
+                    start.elapsed()
+                );
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        self.handle_recieved_chunk_blocks();
+
+        log::info!("Paused terrain generation for {:?} to save", start.elapsed());
+    }
+
+    #[cfg(feature = "save_system")]
+    fn queue_saves(&mut self, chunks: Vec<(Vector3<NonZeroI32>, Arc<BlockBuffer>)>) {
+        for (chunk_pos, blocks) in chunks {
+            self.pending_saves
+                .insert((self.current_save_name.clone(), chunk_pos), blocks);
+        }
+        self.send_pending_saves(false);
+    }
+
+    // Hands pending chunks to the saver thread, without `force` only until `max_queued_saves` chunks are in flight
+    #[cfg(feature = "save_system")]
+    fn send_pending_saves(&mut self, force: bool) {
+        if self.pending_saves.is_empty() {
+            return;
+        }
+
+        let free = if force || self.max_queued_saves == 0 {
+            usize::MAX
+        } else {
+            self.max_queued_saves
+                .saturating_sub(self.saving_chunks.load(Ordering::Relaxed)) as usize
+        };
+        let to_send = self.pending_saves.keys().take(free).cloned().collect::<Vec<_>>();
+
+        let mut requests: FxHashMap<String, Vec<(String, Arc<BlockBuffer>)>> = FxHashMap::default();
+        for key in to_send {
+            if let Some(blocks) = self.pending_saves.remove(&key) {
+                let (save_name, chunk_pos) = key;
+                requests
+                    .entry(save_name)
+                    .or_default()
+                    .push((chunk_file_name(&chunk_pos), blocks));
+            }
+        }
+
+        for (save_name, chunks) in requests {
+            self.saving_chunks.fetch_add(chunks.len() as u32, Ordering::Relaxed);
+            self.chunk_save_sender
+                .unbounded_send(SaveChunkRequest::new(save_name, chunks))
+                .unwrap();
+        }
     }
 
     fn handle_recieved_chunk_meshes(&mut self) {
         for recieved in collect_messages(&mut self.mesh_reciever) {
+            let recieved = match recieved {
+                ThreadReturn::Done(recieved) => recieved,
+                ThreadReturn::Dropped(chunk_pos, for_state) => {
+                    if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                        if chunk.mesh_requested() && for_state == chunk.state_hash() {
+                            chunk.set_mesh_requested(false);
+                            self.loading_chunks -= 1;
+                        }
+                    }
+                    continue;
+                }
+            };
+
             if let Some(mut chunk) = self.get_chunk_mut(&recieved.pos, false) {
                 if chunk.mesh_requested() && recieved.for_state == chunk.state_hash() {
-                    chunk.set_mesh((recieved.mesh.solid_mesh, recieved.mesh.transparent_mesh));
+                    chunk.set_mesh((
+                        recieved.mesh.solid_mesh,
+                        recieved.mesh.transparent_mesh,
+                        recieved.mesh.double_sided_mesh,
+                    ));
 
                     chunk.set_mesh_requested(false);
 
@@ -1139,13 +1815,41 @@ impl Terrain {
 
     fn handle_recieved_chunk_lights(&mut self) {
         for recieved in collect_messages(&mut self.light_reciever) {
+            let recieved = match recieved {
+                ThreadReturn::Done(recieved) => recieved,
+                ThreadReturn::Dropped(chunk_pos, for_state) => {
+                    if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                        if chunk.lights_requested() && for_state == chunk.state_hash() {
+                            chunk.set_lights_requested(false);
+                            self.loading_chunks -= 1;
+                        }
+                    }
+                    continue;
+                }
+            };
+
             if let Some(mut chunk) = self.get_chunk_mut(&recieved.pos, false) {
                 if chunk.lights_requested() && recieved.for_state == chunk.state_hash() {
                     if let Some(lights) = recieved.lights {
-                        chunk.set_lights(lights);
+                        // Lights with skipped sources can't be relit incrementally, the skipped sources could change
+                        chunk.set_lights(
+                            lights,
+                            (recieved.skipped_light_sources == 0).then_some(*recieved.lit_from),
+                        );
 
                         chunk.set_lights_requested(false);
                         self.loading_chunks -= 1;
+
+                        if recieved.skipped_light_sources > 0 {
+                            log::warn!(
+                                "Chunk {:?} has too many light sources, skipped {}",
+                                recieved.pos,
+                                recieved.skipped_light_sources
+                            );
+                            self.light_capped_chunks.insert(recieved.pos);
+                        } else {
+                            self.light_capped_chunks.remove(&recieved.pos);
+                        }
                     }
                 }
             } else {
@@ -1156,6 +1860,19 @@ impl Terrain {
 
     fn handle_recieved_chunk_light_pos_caches(&mut self) {
         for recieved in collect_messages(&mut self.light_pos_cache_reciever) {
+            let recieved = match recieved {
+                ThreadReturn::Done(recieved) => recieved,
+                ThreadReturn::Dropped(chunk_pos, for_state) => {
+                    if let Some(mut chunk) = self.get_chunk_mut(&chunk_pos, false) {
+                        if chunk.light_pos_cache_requested() && for_state == chunk.state_hash() {
+                            chunk.set_light_pos_cache_requested(false);
+                            self.loading_chunks -= 1;
+                        }
+                    }
+                    continue;
+                }
+            };
+
             if let Some(mut chunk) = self.get_chunk_mut(&recieved.pos, false) {
                 if chunk.light_pos_cache_requested() && recieved.for_state == chunk.state_hash() {
                     chunk.set_light_source_caches(recieved.light_source_cache, recieved.sunlight_source_cache);
@@ -1170,26 +1887,120 @@ impl Terrain {
     }
 
     fn handle_recieved_chunk_blocks(&mut self) {
+        let mut recieved_any = false;
+
         for recieved in collect_messages(&mut self.blocks_reciever) {
+            let recieved = match recieved {
+                ThreadReturn::Done(recieved) => recieved,
+                ThreadReturn::Dropped(chunk_pos, _) => {
+                    if self.requested_chunks_list.remove(&chunk_pos) {
+                        self.loading_chunks -= 1;
+                        self.skipped_chunk_requests += 1;
+                    }
+                    continue;
+                }
+            };
+
+            self.store_deferred_blocks(recieved.deferred);
+
             if self.get_chunk(&recieved.pos, false).is_some() {
                 log::warn!("Recieved blocks for already loaded chunk");
             } else {
-                self.chunks.insert(recieved.pos, Box::pin(Chunk::new(recieved.blocks)));
+                self.insert_loaded_chunk(recieved.pos, recieved.blocks);
 
                 self.requested_chunks_list.remove(&recieved.pos);
 
                 self.loading_chunks -= 1;
             }
+
+            recieved_any = true;
+        }
+
+        if recieved_any {
+            self.apply_deferred_blocks();
         }
     }
 
-    fn request_chunk_mesh(&mut self, chunk_pos: &Vector3<NonZeroI32>) {
+    fn store_deferred_blocks(&mut self, deferred: DeferredBlocks) {
+        for (chunk_pos, blocks) in deferred {
+            let blocks = blocks.into_iter().map(|(in_chunk_pos, block)| {
+                let block = block.replaced(&self.block_replacements).unwrap_or(block);
+                (in_chunk_pos, block)
+            });
+            self.deferred_blocks.entry(chunk_pos).or_default().extend(blocks);
+        }
+    }
+
+    fn insert_loaded_chunk(&mut self, chunk_pos: Vector3<NonZeroI32>, mut blocks: BlockBuffer) {
+        for (in_chunk_pos, block) in self.deferred_blocks.remove(&chunk_pos).unwrap_or_default() {
+            if !blocks[&in_chunk_pos].is_rendered() {
+                blocks.set(&in_chunk_pos, block);
+            }
+        }
+        blocks.replace_blocks(&self.block_replacements);
+
+        self.chunks.insert(chunk_pos, Box::pin(Chunk::new(blocks)));
+    }
+
+    // Places deferred blocks into chunks that were already loaded, once all their neighbours are there for relighting
+    fn apply_deferred_blocks(&mut self) {
+        let ready = self
+            .deferred_blocks
+            .keys()
+            .filter(|chunk_pos| self.chunks.contains_key(chunk_pos))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for chunk_pos in ready {
+            if self.get_surrounding_blocks_cube(&chunk_pos, false).is_none() {
+                continue;
+            }
+
+            for (in_chunk_pos, block) in self.deferred_blocks.remove(&chunk_pos).unwrap_or_default() {
+                let pos = Pos::new(
+                    chunk_pos,
+                    Vector3::new(in_chunk_pos.x as f32, in_chunk_pos.y as f32, in_chunk_pos.z as f32),
+                );
+                if self.get_block(&pos).is_some_and(|current| !current.is_rendered()) {
+                    self.set_block(&pos, block);
+                }
+            }
+        }
+    }
+
+    // A chunk other than the one the camera is in can't be seen if the faces all six of its neighbours share with it are
+    // fully opaque. Unloaded neighbours don't hide anything
+    fn occluded(&self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
+        *chunk_pos != self.camera_chunk_pos
+            && FaceDirection::iter().all(|face| {
+                self.chunks
+                    .get(&add_non_zero_i32_vector3(*chunk_pos, face.as_dir()))
+                    .is_some_and(|neighbour| neighbour.blocks().face_fully_opaque(face.opposite()))
+            })
+    }
+
+    // Requests with a lower priority are worked on first, they go by distance from the camera. With
+    // prioritize_look_direction a chunk straight ahead is worth three times as much as one the same distance behind
+    fn request_priority(&self, chunk_pos: &Vector3<NonZeroI32>) -> u32 {
+        let offset = chunk_offset(&self.camera_chunk_pos, chunk_pos);
+        let distance = offset.magnitude();
+        let facing = if self.prioritize_look_direction && distance > 0.0 {
+            self.camera_forward.dot(offset / distance)
+        } else {
+            1.0
+        };
+
+        (distance * (2.0 - facing) * 1000.0) as u32
+    }
+
+    // Each request_chunk_ function returns whether its request was sent off
+    fn request_chunk_mesh(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
         let mut set_mesh_requested = false;
 
         let chunk = if let Some(chunk) = self.get_chunk(chunk_pos, true) {
             chunk
         } else {
-            return;
+            return false;
         };
         let for_state = chunk.state_hash();
 
@@ -1197,13 +2008,13 @@ impl Terrain {
             let surrounding_blocks = if let Some(surrounding_blocks) = self.get_surrounding_blocks(chunk_pos, true) {
                 surrounding_blocks
             } else {
-                return;
+                return false;
             };
             let surrounding_lights =
                 if let Some(surrounding_lights) = self.get_surrounding_lights(chunk_pos, true, false) {
                     surrounding_lights
                 } else {
-                    return;
+                    return false;
                 };
 
             set_mesh_requested = true;
@@ -1214,6 +2025,7 @@ impl Terrain {
                     surrounding_blocks,
                     surrounding_lights,
                     for_state,
+                    self.request_priority(chunk_pos),
                 ))
                 .unwrap();
 
@@ -1221,26 +2033,32 @@ impl Terrain {
         }
 
         if set_mesh_requested {
+            if self.chunks.get(chunk_pos).is_some_and(|chunk| chunk.has_mesh()) {
+                self.remeshes += 1;
+            }
             self.get_chunk_mut(chunk_pos, true).unwrap().set_mesh_requested(true);
+            #[cfg(feature = "request_log")]
+            self.log_request(ChunkRequestKind::Mesh, chunk_pos);
         }
+        set_mesh_requested
     }
 
-    fn request_chunk_light(&mut self, chunk_pos: &Vector3<NonZeroI32>) {
+    fn request_chunk_light(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
         let mut set_lights_requested = false;
 
         let chunk = if let Some(chunk) = self.get_chunk(chunk_pos, true) {
             chunk
         } else {
-            return;
+            return false;
         };
-        let for_state = chunk.state_hash();
+        let (for_state, previous) = (chunk.state_hash(), chunk.previous_lights());
 
         if !chunk.lights_requested() {
             let surrounding_blocks = if let Some(surrounding_blocks) = self.get_surrounding_blocks_cube(chunk_pos, true)
             {
                 surrounding_blocks
             } else {
-                return;
+                return false;
             };
 
             let mut all_fine = true;
@@ -1250,7 +2068,7 @@ impl Terrain {
                     self.request_chunk_light_pos_cache(&add_non_zero_i32_vector3(
                         *chunk_pos,
                         relative_pos_surrounding_cubes_from_index(index as u8),
-                    ))
+                    ));
                 }
             }
 
@@ -1258,7 +2076,13 @@ impl Terrain {
                 set_lights_requested = true;
 
                 self.light_sender
-                    .unbounded_send(LightThreadRequest::new(*chunk_pos, surrounding_blocks, for_state))
+                    .unbounded_send(LightThreadRequest::new(
+                        *chunk_pos,
+                        surrounding_blocks,
+                        previous,
+                        for_state,
+                        self.request_priority(chunk_pos),
+                    ))
                     .unwrap();
 
                 self.loading_chunks += 1;
@@ -1267,16 +2091,19 @@ impl Terrain {
 
         if set_lights_requested {
             self.get_chunk_mut(chunk_pos, true).unwrap().set_lights_requested(true);
+            #[cfg(feature = "request_log")]
+            self.log_request(ChunkRequestKind::Light, chunk_pos);
         }
+        set_lights_requested
     }
 
-    fn request_chunk_light_pos_cache(&mut self, chunk_pos: &Vector3<NonZeroI32>) {
+    fn request_chunk_light_pos_cache(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
         let mut set_light_pos_cache_requested = false;
 
         let chunk = if let Some(chunk) = self.get_chunk(chunk_pos, true) {
             chunk
         } else {
-            return;
+            return false;
         };
         let for_state = chunk.state_hash();
 
@@ -1285,7 +2112,7 @@ impl Terrain {
             {
                 surrounding_blocks
             } else {
-                return;
+                return false;
             };
 
             set_light_pos_cache_requested = true;
@@ -1295,6 +2122,7 @@ impl Terrain {
                     *chunk_pos,
                     surrounding_blocks,
                     for_state,
+                    self.request_priority(chunk_pos),
                 ))
                 .unwrap();
 
@@ -1305,39 +2133,90 @@ impl Terrain {
             self.get_chunk_mut(chunk_pos, true)
                 .unwrap()
                 .set_light_pos_cache_requested(true);
+            #[cfg(feature = "request_log")]
+            self.log_request(ChunkRequestKind::LightPosCache, chunk_pos);
+        }
+        set_light_pos_cache_requested
+    }
+
+    // A chunk is only requested once it has stayed in range for chunk_request_delay, so chunks that are just flown
+    // past don't get generated
+    fn chunk_request_due(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
+        if self.chunk_request_delay.is_zero()
+            || self.chunks.contains_key(chunk_pos)
+            || self.requested_chunks_list.contains(chunk_pos)
+        {
+            return true;
+        }
+
+        let frame = self.meshes_to_render_frame;
+        let (first_seen, last_seen_frame) = self
+            .chunks_first_seen
+            .entry(*chunk_pos)
+            .or_insert((Instant::now(), frame));
+        *last_seen_frame = frame;
+
+        if first_seen.elapsed() >= self.chunk_request_delay {
+            self.chunks_first_seen.remove(chunk_pos);
+            true
+        } else {
+            false
         }
     }
 
-    fn request_chunk_blocks(&mut self, chunk_pos: &Vector3<NonZeroI32>) {
+    fn request_chunk_blocks(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
         if self.get_chunk(chunk_pos, false).is_some() {
             log::warn!("Requsting blocks for existing chunk");
+            false
         } else {
+            // A chunk still waiting to be saved would be stale on disk, so it is taken from the queue instead
+            #[cfg(feature = "save_system")]
+            if let Some(blocks) = self.pending_saves.get(&(self.current_save_name.clone(), *chunk_pos)) {
+                let blocks = (**blocks).clone();
+                self.insert_loaded_chunk(*chunk_pos, blocks);
+                #[cfg(feature = "request_log")]
+                self.log_request(ChunkRequestKind::Blocks, chunk_pos);
+                return true;
+            }
+
             if !self.requested_chunks_list.contains(chunk_pos) {
                 self.requested_chunks_list.insert(*chunk_pos);
 
                 self.blocks_sender
-                    .unbounded_send(BlocksThreadRequest::new(*chunk_pos, {
+                    .unbounded_send(BlocksThreadRequest {
+                        pos: *chunk_pos,
                         #[cfg(feature = "save_system")]
-                        {
-                            self.current_save_name.clone()
-                        }
-                        #[cfg(not(feature = "save_system"))]
-                        {
-                            String::default()
-                        }
-                    }))
+                        current_save_name: self.current_save_name.clone(),
+                        #[cfg(feature = "save_system")]
+                        save_format_version: self.save_format_version,
+                        priority: self.request_priority(chunk_pos),
+                    })
                     .unwrap();
 
                 self.loading_chunks += 1;
+                #[cfg(feature = "request_log")]
+                self.log_request(ChunkRequestKind::Blocks, chunk_pos);
+                true
+            } else {
+                false
             }
         }
     }
 
     pub fn reset_chunks(&mut self, seed: u32) {
-        let mut new_terrain = Terrain::new(self.transparency, &self.texture_atlas, seed, self.block_manager.clone());
+        let mut new_terrain = Terrain::new(
+            self.transparency,
+            &self.texture_atlas,
+            seed,
+            self.block_manager.clone(),
+            self.terrain_config.clone(),
+            self.generator_kind.clone(),
+        );
 
         mem::swap(self, &mut new_terrain);
-        self.chunks = new_terrain.chunks;
+        self.chunks = mem::take(&mut new_terrain.chunks);
+        mem::swap(&mut self.buffer_pool, &mut new_terrain.buffer_pool);
+        self.block_replacements = mem::take(&mut new_terrain.block_replacements);
 
         self.chunks.iter_mut().for_each(|(_, chunk)| {
             chunk.set_mesh_requested(false);
@@ -1346,6 +2225,24 @@ impl Terrain {
         });
     }
 
+    // Takes in block definitions reloaded from their files together with the ones that changed. A changed block can
+    // change light and sunlight around it, so every loaded chunk is relit if any contained one
+    pub fn set_block_manager(&mut self, block_manager: BlockManager, replacements: &[(Block, Block)]) {
+        self.block_manager = block_manager;
+        self.block_replacements.extend_from_slice(replacements);
+
+        let mut replaced_any = false;
+        for (_, chunk) in self.chunks.iter_mut() {
+            replaced_any |= chunk.replace_blocks(replacements);
+        }
+        if replaced_any {
+            self.chunks.iter_mut().for_each(|(_, chunk)| {
+                chunk.set_lights_outdated();
+                chunk.set_mesh_outdated();
+            });
+        }
+    }
+
     pub fn transparency(&self) -> bool {
         self.transparency
     }
@@ -1354,12 +2251,356 @@ impl Terrain {
         &self.texture_atlas
     }
 
+    pub fn terrain_config(&self) -> &TerrainConfig {
+        &self.terrain_config
+    }
+
+    pub fn generator_kind(&self) -> &TerrainGeneratorKind {
+        &self.generator_kind
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // Only covers the chunks that are loaded
+    pub fn count_blocks(&self) -> FxHashMap<Block, u64> {
+        let mut counts = FxHashMap::default();
+        for chunk in self.chunks.values() {
+            chunk.blocks().count_blocks(&mut counts);
+        }
+        counts
+    }
+
     pub fn loading_chunks(&self) -> u32 {
         self.loading_chunks / 4
     }
 
     pub fn saving_chunks(&self) -> u32 {
-        self.saving_chunks.load(Ordering::Relaxed)
+        let saving_chunks = self.saving_chunks.load(Ordering::Relaxed);
+        #[cfg(feature = "save_system")]
+        let saving_chunks = saving_chunks + self.pending_saves.len() as u32;
+        saving_chunks
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn set_max_queued_saves(&mut self, max_queued_saves: u32) {
+        self.max_queued_saves = max_queued_saves;
+    }
+
+    #[cfg(feature = "save_system")]
+    pub fn set_pause_generation_on_save(&mut self, pause_generation_on_save: bool) {
+        self.pause_generation_on_save = pause_generation_on_save;
+    }
+
+    pub fn set_save_threads(&self, save_threads: u32) {
+        self.save_threads.store(save_threads, Ordering::Relaxed)
+    }
+
+    pub fn set_occlusion_culling(&mut self, occlusion_culling: bool) {
+        self.occlusion_culling = occlusion_culling
+    }
+
+    pub fn set_prioritize_look_direction(&mut self, prioritize_look_direction: bool) {
+        self.prioritize_look_direction = prioritize_look_direction
+    }
+
+    pub fn set_chunk_request_delay(&mut self, chunk_request_delay: Duration) {
+        self.chunk_request_delay = chunk_request_delay
+    }
+
+    // Edited chunks are only relit and remeshed once they weren't edited for this long, so edits in quick succession
+    // only cause one remesh
+    pub fn set_remesh_coalescing_window(&mut self, remesh_coalescing_window: Duration) {
+        self.remesh_coalescing_window = remesh_coalescing_window
+    }
+
+    // Mesh requests for chunks that already had a mesh, e.g. after an edit
+    pub fn remeshes(&self) -> u32 {
+        self.remeshes
+    }
+
+    pub fn skipped_chunk_requests(&self) -> u32 {
+        self.skipped_chunk_requests
+    }
+
+    // Mesh buffers created because none of the pool fit
+    pub fn buffer_allocations(&self) -> u64 {
+        self.buffer_pool.allocations()
+    }
+
+    pub fn set_max_light_sources(&self, max_light_sources: u32) {
+        self.max_light_sources.store(max_light_sources, Ordering::Relaxed)
+    }
+
+    // Changing the spread relights every loaded chunk
+    pub fn set_sunlight_spread(&mut self, sunlight_spread: u32) {
+        if self.sunlight_spread.swap(sunlight_spread, Ordering::Relaxed) != sunlight_spread {
+            self.chunks.iter_mut().for_each(|(_, chunk)| {
+                chunk.set_lights_outdated();
+                chunk.set_mesh_outdated();
+            });
+        }
+    }
+
+    pub fn set_smooth_lighting(&mut self, smooth_lighting: bool) {
+        if self.smooth_lighting.swap(smooth_lighting, Ordering::Relaxed) != smooth_lighting {
+            self.chunks.iter_mut().for_each(|(_, chunk)| chunk.set_mesh_outdated());
+        }
+    }
+
+    pub fn light_capped_chunks(&self) -> u32 {
+        self.light_capped_chunks.len() as u32
+    }
+
+    // Stops meshes_to_render from requesting chunks, already loaded chunks are still rendered
+    pub fn set_loading_frozen(&mut self, loading_frozen: bool) {
+        self.loading_frozen = loading_frozen
+    }
+
+    pub fn loading_frozen(&self) -> bool {
+        self.loading_frozen
+    }
+
+    // Requests blocks, lights and meshes for all given chunks that are not ready yet, meant for tools and tests since
+    // the render loop already requests what it needs in `meshes_to_render`
+    pub fn ensure_loaded(&mut self, positions: &[Vector3<NonZeroI32>]) {
+        for chunk_pos in positions {
+            if !self.chunk_ready(chunk_pos) {
+                self.request_chunk_mesh(chunk_pos);
+            }
+        }
+    }
+
+    // Whether the chunk has its blocks, up to date lights and an up to date mesh
+    pub fn chunk_ready(&self, chunk_pos: &Vector3<NonZeroI32>) -> bool {
+        self.chunks
+            .get(chunk_pos)
+            .is_some_and(|chunk| chunk.lights_up_to_date() && chunk.mesh_up_to_date() && chunk.has_mesh())
+    }
+
+    pub fn chunks_ready(&self, positions: &[Vector3<NonZeroI32>]) -> bool {
+        positions.iter().all(|chunk_pos| self.chunk_ready(chunk_pos))
+    }
+
+    // Blocks until all given chunks are ready or `timeout` passes, returns whether they got ready. Pumps `update` itself,
+    // so it must not be used from the render loop
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_blocking(&mut self, positions: &[Vector3<NonZeroI32>], timeout: Duration) -> bool {
+        let start = Instant::now();
+
+        loop {
+            self.update();
+            if self.chunks_ready(positions) {
+                return true;
+            }
+            if start.elapsed() >= timeout {
+                return false;
+            }
+
+            self.ensure_loaded(positions);
+            std::thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+        }
+    }
+
+    // Starts recording every chunk request sent from now on, dropping any earlier recording
+    #[cfg(feature = "request_log")]
+    pub fn start_request_log(&mut self) {
+        self.request_log = Some(Vec::new())
+    }
+
+    // Stops recording and returns what was recorded
+    #[cfg(feature = "request_log")]
+    pub fn take_request_log(&mut self) -> Option<Vec<ChunkRequest>> {
+        self.request_log.take()
+    }
+
+    #[cfg(feature = "request_log")]
+    fn log_request(&mut self, kind: ChunkRequestKind, chunk_pos: &Vector3<NonZeroI32>) {
+        if let Some(request_log) = &mut self.request_log {
+            request_log.push(ChunkRequest::new(kind, *chunk_pos))
+        }
+    }
+
+    // Sends the recorded requests in their recorded order, meant for a fresh terrain with the same seed. Each request
+    // waits until the results it depends on arrived, up to `timeout`. Requests the terrain makes on its own while doing
+    // so, like blocks for the surroundings of a mesh, are sent as well. Pumps `update` itself, so it must not be used
+    // from the render loop
+    #[cfg(all(feature = "request_log", not(target_arch = "wasm32")))]
+    pub fn replay_requests(&mut self, requests: &[ChunkRequest], timeout: Duration) -> Result<(), ()> {
+        for (index, request) in requests.iter().enumerate() {
+            let start = Instant::now();
+
+            loop {
+                self.update();
+                let sent = match request.kind {
+                    ChunkRequestKind::Blocks => {
+                        self.chunks.contains_key(&request.chunk_pos) || self.request_chunk_blocks(&request.chunk_pos)
+                    }
+                    ChunkRequestKind::LightPosCache => self.request_chunk_light_pos_cache(&request.chunk_pos),
+                    ChunkRequestKind::Light => self.request_chunk_light(&request.chunk_pos),
+                    ChunkRequestKind::Mesh => self.request_chunk_mesh(&request.chunk_pos),
+                };
+                if sent {
+                    break;
+                }
+                if start.elapsed() >= timeout {
+                    log::warn!(
+                        "Replaying request {} of {} timed out - {:?}",
+                        index + 1,
+                        requests.len(),
+                        request
+                    );
+                    return Err(());
+                }
+
+                std::thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Runs one step of generation for every chunk in chunk_positions and waits until all of them are done, the results
+    // the step depends on must already be there for the time to only cover this step. Pumps `update` itself like
+    // `replay_requests`
+    #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+    pub fn generate_step(
+        &mut self,
+        kind: ChunkRequestKind,
+        chunk_positions: &[Vector3<NonZeroI32>],
+        timeout: Duration,
+    ) -> Result<Duration, ()> {
+        let start = Instant::now();
+        let mut pending = chunk_positions.to_vec();
+
+        loop {
+            self.update();
+            pending.retain(|chunk_pos| {
+                let Some(chunk) = self.chunks.get(chunk_pos) else {
+                    return true;
+                };
+                match kind {
+                    ChunkRequestKind::Blocks => false,
+                    ChunkRequestKind::LightPosCache => {
+                        let blocks = chunk.blocks();
+                        blocks.light_sources().is_none() || blocks.sunlight_sources().is_none()
+                    }
+                    ChunkRequestKind::Light => chunk.lights().is_none() || !chunk.lights_up_to_date(),
+                    ChunkRequestKind::Mesh => !chunk.has_mesh() || !chunk.mesh_up_to_date(),
+                }
+            });
+            if pending.is_empty() {
+                return Ok(start.elapsed());
+            }
+
+            for chunk_pos in &pending {
+                match kind {
+                    ChunkRequestKind::Blocks => {
+                        self.request_chunk_blocks(chunk_pos);
+                    }
+                    ChunkRequestKind::LightPosCache => {
+                        self.request_chunk_light_pos_cache(chunk_pos);
+                    }
+                    ChunkRequestKind::Light => {
+                        self.request_chunk_light(chunk_pos);
+                    }
+                    ChunkRequestKind::Mesh => {
+                        self.request_chunk_mesh(chunk_pos);
+                    }
+                }
+            }
+
+            if start.elapsed() >= timeout {
+                log::warn!("Generating {:?} timed out with {} chunks left", kind, pending.len());
+                return Err(());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn loaded_chunk_positions(&self) -> impl Iterator<Item = &Vector3<NonZeroI32>> {
+        self.chunks.keys()
+    }
+
+    // Closest unloaded chunk on the same vertical level as `chunk_pos`, that borders a loaded chunk
+    pub fn nearest_unloaded_frontier(&self, chunk_pos: &Vector3<NonZeroI32>) -> Option<Vector3<NonZeroI32>> {
+        let to_f32 = |pos: &Vector3<NonZeroI32>| {
+            Vector3::new(
+                Into::<i32>::into(pos.x) as f32,
+                Into::<i32>::into(pos.y) as f32,
+                Into::<i32>::into(pos.z) as f32,
+            )
+        };
+        let center = to_f32(chunk_pos);
+
+        self.loaded_chunk_positions()
+            .filter(|loaded_pos| loaded_pos.y == chunk_pos.y)
+            .flat_map(|loaded_pos| {
+                [
+                    Vector3::new(1, 0, 0),
+                    Vector3::new(-1, 0, 0),
+                    Vector3::new(0, 0, 1),
+                    Vector3::new(0, 0, -1),
+                ]
+                .map(|dir| add_non_zero_i32_vector3(*loaded_pos, dir))
+            })
+            .filter(|neighbour_pos| !self.chunks.contains_key(neighbour_pos))
+            .min_by(|a, b| center.distance(to_f32(a)).total_cmp(&center.distance(to_f32(b))))
+    }
+}
+
+// Whether light from a block at in_chunk_pos can reach the neighbouring chunk at offset x, y, z. Only chunks touching
+// at a corner can be too far
+const fn can_affect_chunk_light(x: i32, y: i32, z: i32, in_chunk_pos: Vector3<u32>) -> bool {
+    !((x != 0 && y != 0 && z != 0)
+        && ((x == -1 && in_chunk_pos.x >= MAX_LIGHT_VAL as u32)
+            || (x == 1 && in_chunk_pos.x <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)
+            || (y == -1 && in_chunk_pos.y >= MAX_LIGHT_VAL as u32)
+            || (y == 1 && in_chunk_pos.y <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)
+            || (z == -1 && in_chunk_pos.z >= MAX_LIGHT_VAL as u32)
+            || (z == 1 && in_chunk_pos.z <= CHUNK_SIZE - MAX_LIGHT_VAL as u32)))
+}
+
+// The chunk and its neighbours light from any block in between min and max in it can reach
+fn chunks_in_light_reach(
+    chunk_pos: Vector3<NonZeroI32>,
+    min: Vector3<i32>,
+    max: Vector3<i32>,
+) -> impl Iterator<Item = Vector3<NonZeroI32>> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| Vector3::new(x, y, z))))
+        .filter(move |offset| {
+            // The block of the box closest to the neighbour
+            let nearest = Vector3::new(
+                if offset.x < 0 { min.x } else { max.x },
+                if offset.y < 0 { min.y } else { max.y },
+                if offset.z < 0 { min.z } else { max.z },
+            );
+            can_affect_chunk_light(offset.x, offset.y, offset.z, nearest.map(|coord| coord as u32))
+        })
+        .map(move |offset| add_non_zero_i32_vector3(chunk_pos, offset))
+}
+
+// Offset in chunks between two chunk positions, accounting for there being no chunk 0
+fn chunk_offset(from: &Vector3<NonZeroI32>, to: &Vector3<NonZeroI32>) -> Vector3<f32> {
+    fn continuous(val: NonZeroI32) -> f32 {
+        let val: i32 = val.into();
+        (if val < 0 { val + 1 } else { val }) as f32
+    }
+
+    Vector3::new(
+        continuous(to.x) - continuous(from.x),
+        continuous(to.y) - continuous(from.y),
+        continuous(to.z) - continuous(from.z),
+    )
+}
+
+// Nothing queued is lost when the terrain is replaced, e.g. when loading another save
+#[cfg(feature = "save_system")]
+impl Drop for Terrain {
+    fn drop(&mut self) {
+        self.send_pending_saves(true);
     }
 }
 
@@ -1373,6 +2614,49 @@ fn chunk_file_name(chunk_pos: &Vector3<impl Into<i32> + Copy>) -> String {
     )
 }
 
+// Requests a generation thread takes off its queue at once, requests sent in the meantime can only get ahead of the ones
+// after them
+fn request_batch_size() -> usize {
+    #[cfg(feature = "rayon")]
+    {
+        rayon::current_num_threads() * 2
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        1
+    }
+}
+
+// Queues newly sent requests and returns the next ones to work on, lowest priority first. Queued requests for chunks
+// that are out of range by now are sent back as dropped, fails once nothing listens for what the thread sends back
+fn next_requests<T: ThreadRequest, R>(
+    reciever: &mut UnboundedReceiver<T>,
+    queue: &mut Vec<T>,
+    focus: &RequestFocus,
+    sender: &UnboundedSender<ThreadReturn<R>>,
+) -> Result<Vec<T>, ()> {
+    let recieved = collect_messages(reciever);
+    if !recieved.is_empty() {
+        queue.extend(recieved);
+        // Sorted back to front, so the next requests come off the end
+        queue.sort_by_key(|request| Reverse(request.priority()));
+    }
+
+    let mut dropped = Vec::new();
+    queue.retain(|request| {
+        let out_of_range = focus.out_of_range(&request.pos());
+        if out_of_range {
+            dropped.push(ThreadReturn::Dropped(request.pos(), request.for_state()));
+        }
+        !out_of_range
+    });
+    for dropped in dropped {
+        sender.unbounded_send(dropped).map_err(|_| ())?;
+    }
+
+    Ok(queue.split_off(queue.len().saturating_sub(request_batch_size())))
+}
+
 fn collect_messages<T>(reciever: &mut UnboundedReceiver<T>) -> Vec<T> {
     {
         let mut out = Vec::new();
@@ -1384,3 +2668,415 @@ fn collect_messages<T>(reciever: &mut UnboundedReceiver<T>) -> Vec<T> {
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u32 = 1234;
+
+    fn terrain(generator_kind: TerrainGeneratorKind) -> Terrain {
+        let block_manager = BlockManager::new();
+        let texture_atlas = pollster::block_on(TextureAtlas::new(
+            block_manager.all_texture_names(),
+            block_manager.texture_animations(),
+            &"texture",
+        ));
+
+        Terrain::new(
+            true,
+            &texture_atlas,
+            SEED,
+            block_manager,
+            TerrainConfig::default(),
+            generator_kind,
+        )
+    }
+
+    fn chunk_pos(x: i32, y: i32, z: i32) -> Vector3<NonZeroI32> {
+        Vector3::new(
+            NonZeroI32::new(x).unwrap(),
+            NonZeroI32::new(y).unwrap(),
+            NonZeroI32::new(z).unwrap(),
+        )
+    }
+
+    // The chunk and its 26 neighbours
+    fn surrounding(chunk_pos: Vector3<NonZeroI32>) -> Vec<Vector3<NonZeroI32>> {
+        (0..27)
+            .map(|index| add_non_zero_i32_vector3(chunk_pos, relative_pos_surrounding_cubes_from_index(index)))
+            .collect()
+    }
+
+    // Names of all blocks of a loaded chunk, ignoring whether they are sunlit
+    fn block_names(terrain: &Terrain, chunk_pos: Vector3<NonZeroI32>) -> Vec<Option<String>> {
+        let blocks = terrain.chunks[&chunk_pos].blocks();
+        (0..CHUNK_SIZE as i32)
+            .flat_map(|x| (0..CHUNK_SIZE as i32).flat_map(move |y| (0..CHUNK_SIZE as i32).map(move |z| (x, y, z))))
+            .map(|(x, y, z)| {
+                terrain
+                    .block_manager
+                    .block_name(&blocks[&Vector3::new(x, y, z)])
+                    .map(str::to_string)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trees_across_chunk_borders_dont_depend_on_load_order() {
+        let target = chunk_pos(9, 1, 6);
+        let mut generator = TerrainGenerator::new(
+            SEED,
+            BlockManager::new(),
+            TerrainConfig::default(),
+            TerrainGeneratorKind::Normal,
+        );
+        let generated = surrounding(target)
+            .into_iter()
+            .filter(|chunk_pos| chunk_pos.y == target.y)
+            .map(|chunk_pos| (chunk_pos, generator.generate_blocks(&chunk_pos)))
+            .collect::<Vec<_>>();
+        assert!(
+            generated
+                .iter()
+                .filter(|(chunk_pos, (_, deferred))| *chunk_pos != target && deferred.contains_key(&target))
+                .count()
+                >= 3
+        );
+
+        // The target chunk either comes first, so the trees of its neighbours are placed into the loaded chunk, or last,
+        // so they are placed before it is inserted. The neighbours come in both orders each time
+        let target_index = generated
+            .iter()
+            .position(|(chunk_pos, _)| *chunk_pos == target)
+            .unwrap();
+        let forward = (0..generated.len())
+            .filter(|index| *index != target_index)
+            .collect::<Vec<_>>();
+        let backward = forward.iter().rev().cloned().collect::<Vec<_>>();
+        let orders = [
+            [vec![target_index], forward.clone()].concat(),
+            [vec![target_index], backward.clone()].concat(),
+            [forward, vec![target_index]].concat(),
+            [backward, vec![target_index]].concat(),
+        ];
+        let results = orders.map(|order| {
+            let mut terrain = terrain(TerrainGeneratorKind::Normal);
+            let air = BlockBuffer::new(vec![
+                Block::new_with_default("Air", &terrain.block_manager);
+                (CHUNK_SIZE as usize).pow(3)
+            ]);
+            for chunk_pos in surrounding(target) {
+                if chunk_pos.y != target.y {
+                    terrain.insert_loaded_chunk(chunk_pos, air.clone());
+                }
+            }
+
+            for index in order {
+                let (chunk_pos, (blocks, deferred)) = generated[index].clone();
+                terrain.store_deferred_blocks(deferred);
+                terrain.insert_loaded_chunk(chunk_pos, blocks);
+                terrain.apply_deferred_blocks();
+            }
+            assert!(!terrain.deferred_blocks.contains_key(&target));
+
+            block_names(&terrain, target)
+        });
+
+        assert!(results.iter().all(|result| *result == results[0]));
+    }
+
+    #[test]
+    fn load_blocking_waits_for_chunks() {
+        let mut terrain = terrain(TerrainGeneratorKind::flat());
+        let positions = [chunk_pos(1, 1, 1)];
+
+        assert!(!terrain.chunks_ready(&positions));
+        assert!(!terrain.load_blocking(&positions, Duration::ZERO));
+
+        assert!(terrain.load_blocking(&positions, Duration::from_secs(60)));
+        assert!(terrain.chunks_ready(&positions));
+        assert!(!terrain.chunk_ready(&chunk_pos(1, 1, 5)));
+    }
+
+    #[test]
+    fn roof_over_shaft_shades_and_lights_chunks_below() {
+        // An open pit from y 0 down through the three chunks below the one at y 0
+        let mut terrain = terrain(TerrainGeneratorKind::Flat {
+            layers: vec![("Stone".to_string(), 1), ("Air".to_string(), 41)],
+        });
+        let shaft = [
+            chunk_pos(1, 1, 1),
+            chunk_pos(1, -1, 1),
+            chunk_pos(1, -2, 1),
+            chunk_pos(1, -3, 1),
+        ];
+        let pos = |y: i64| Pos::from_abs_block(Vector3::new(8, y, 8)).unwrap();
+        let sunlight = |terrain: &mut Terrain, y: i64| {
+            (
+                terrain.get_block(&pos(y)).unwrap().is_sunlit(),
+                terrain.get_light(&pos(y)).unwrap().sun,
+            )
+        };
+        assert!(terrain.load_blocking(&shaft, Duration::from_secs(300)));
+
+        // Sunlight spreads in from the sunlit collums next to the roofed one where they touch the roof or the floor
+        terrain.set_block(&pos(0), Block::new_with_default("Stone", &terrain.block_manager));
+        assert!(terrain.load_blocking(&shaft, Duration::from_secs(300)));
+        for y in -40..0 {
+            let steps = 1 + (y + 40).min(-1 - y) as u8;
+            let sun = MAX_LIGHT_VAL.saturating_sub(steps);
+            assert_eq!(sunlight(&mut terrain, y), (false, sun), "roofed at y {y}");
+        }
+
+        terrain.set_block(&pos(0), Block::new_with_default("Air", &terrain.block_manager));
+        assert!(terrain.load_blocking(&shaft, Duration::from_secs(300)));
+        for y in -40..=0 {
+            assert_eq!(sunlight(&mut terrain, y), (true, MAX_LIGHT_VAL), "opened at y {y}");
+        }
+    }
+
+    // Waits for the blocks of the chunks only, without lighting or meshing them
+    fn load_blocks(terrain: &mut Terrain, positions: &[Vector3<NonZeroI32>]) {
+        let start = Instant::now();
+        while !positions.iter().all(|chunk_pos| terrain.chunks.contains_key(chunk_pos)) {
+            assert!(start.elapsed() < Duration::from_secs(60), "blocks didn't load");
+            for chunk_pos in positions {
+                terrain.get_chunk(chunk_pos, true);
+            }
+            terrain.update();
+            std::thread::sleep(Duration::from_millis(THREAD_SLEEP_TIME));
+        }
+    }
+
+    #[test]
+    fn abs_block_access_round_trip() {
+        let mut terrain = terrain(TerrainGeneratorKind::flat());
+        let mut around_origin = Vec::new();
+        for x in [-1, 1] {
+            for y in [-1, 1] {
+                for z in [-1, 1] {
+                    around_origin.push(chunk_pos(x, y, z));
+                }
+            }
+        }
+        load_blocks(&mut terrain, &around_origin);
+        let name = |terrain: &mut Terrain, abs_block_pos: [i64; 3]| {
+            let block = terrain.get_block_abs(abs_block_pos.into())?;
+            terrain.block_manager.block_name(&block).map(str::to_string)
+        };
+
+        // The flat world has its grass at y 0 and the layers below it in the chunks at y -1
+        for (y, block_name) in [
+            (1, "Air"),
+            (0, "Grass"),
+            (-1, "Dirt"),
+            (-3, "Dirt"),
+            (-4, "Stone"),
+            (-7, "Air"),
+        ] {
+            for (x, z) in [(0, 0), (-1, -1), (15, -16), (-16, 15)] {
+                assert_eq!(
+                    name(&mut terrain, [x, y, z]).as_deref(),
+                    Some(block_name),
+                    "block at {:?}",
+                    (x, y, z)
+                );
+            }
+        }
+
+        let brick = Block::new_with_default("Brick", &terrain.block_manager);
+        let changed = [[0, 0, 0], [-1, -1, -1], [15, 3, -16], [-16, -5, 15]];
+        for abs_block_pos in changed {
+            terrain.set_block_abs(abs_block_pos.into(), brick.clone());
+        }
+        for abs_block_pos in changed {
+            assert_eq!(name(&mut terrain, abs_block_pos).as_deref(), Some("Brick"));
+            let pos = Pos::from_abs_block(abs_block_pos.into()).unwrap();
+            assert_eq!(terrain.get_block(&pos), terrain.get_block_abs(abs_block_pos.into()));
+        }
+        // The blocks next to them across the chunk borders stay as they were
+        assert_eq!(name(&mut terrain, [-1, 0, 0]).as_deref(), Some("Grass"));
+        assert_eq!(name(&mut terrain, [0, -1, -1]).as_deref(), Some("Dirt"));
+        assert_eq!(name(&mut terrain, [14, 3, -16]).as_deref(), Some("Air"));
+
+        // Blocks outside of the loaded chunks and past the last chunk can't be read or set
+        assert_eq!(name(&mut terrain, [40, 0, 0]), None);
+        terrain.set_block_abs(Vector3::new(i64::MAX, 0, 0), brick.clone());
+        assert_eq!(terrain.get_block_abs(Vector3::new(i64::MIN, 0, 0)), None);
+    }
+
+    #[test]
+    fn flat_chunk_exports_one_face_per_block() {
+        let mut terrain = terrain(TerrainGeneratorKind::flat());
+        assert!(terrain.load_blocking(&[chunk_pos(1, 1, 1)], Duration::from_secs(60)));
+        let pos = Pos::from_abs_block(Vector3::new(0, 0, 0)).unwrap();
+        let obj = terrain.export_region_obj((pos, pos), "export.mtl");
+        assert!(obj.starts_with("mtllib export.mtl\nusemtl atlas\no chunk_1_1_1\n"));
+
+        let values = |prefix: &str| {
+            obj.lines()
+                .filter_map(|line| line.strip_prefix(prefix))
+                .map(|line| {
+                    line.split(' ')
+                        .map(|value| value.parse::<f32>().unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let (poses, texture_poses, normals) = (values("v "), values("vt "), values("vn "));
+        let faces = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .collect::<Vec<_>>();
+
+        // Only the top of the grass is visible, the greedy mesh of it is split back into two triangles per block
+        let blocks = (CHUNK_SIZE as usize).pow(2);
+        assert_eq!(faces.len(), blocks * 2);
+        assert_eq!(poses.len(), blocks * 4);
+        assert_eq!((texture_poses.len(), normals.len()), (poses.len(), poses.len()));
+        assert!(normals.iter().all(|normal| *normal == [0.0, 1.0, 0.0]));
+        let (min, max) = (CHUNK_SIZE as f32, 2.0 * CHUNK_SIZE as f32);
+        assert!(poses
+            .iter()
+            .all(|pos| pos[1] == min + 1.0 && [pos[0], pos[2]].iter().all(|coord| (min..=max).contains(coord))));
+        for face in faces {
+            for vertex in face.split(' ') {
+                let index = vertex.split('/').next().unwrap().parse::<usize>().unwrap();
+                assert!((1..=poses.len()).contains(&index), "{} is out of range", index);
+                assert_eq!(vertex, format!("{index}/{index}/{index}"));
+            }
+        }
+
+        // Every block gets the whole texture tile and nothing of the tiles around it
+        let tile_size = terrain.texture_atlas.tile_size();
+        for (block_poses, block_texture_poses) in poses.chunks_exact(4).zip(texture_poses.chunks_exact(4)) {
+            let span = |values: &[Vec<f32>], axis: usize| {
+                let values = values.iter().map(|value| value[axis]);
+                values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+            };
+            assert_eq!((span(block_poses, 0), span(block_poses, 2)), (1.0, 1.0));
+            assert!((span(block_texture_poses, 0) - tile_size.0).abs() < 1e-5);
+            assert!((span(block_texture_poses, 1) - tile_size.1).abs() < 1e-5);
+            assert!(block_texture_poses
+                .iter()
+                .flatten()
+                .all(|coord| (0.0..=1.0).contains(coord)));
+        }
+    }
+
+    #[test]
+    fn chunk_enclosed_by_opaque_faces_is_occluded() {
+        let mut terrain = terrain(TerrainGeneratorKind::flat());
+        let (stone, air) = (
+            Block::new_with_default("Stone", &terrain.block_manager),
+            Block::new_with_default("Air", &terrain.block_manager),
+        );
+        let target = chunk_pos(1, 1, 1);
+        terrain.camera_chunk_pos = chunk_pos(5, 5, 5);
+        terrain.insert_loaded_chunk(
+            target,
+            BlockBuffer::new(vec![air.clone(); (CHUNK_SIZE as usize).pow(3)]),
+        );
+        let neighbours = FaceDirection::iter()
+            .map(|face| (face, add_non_zero_i32_vector3(target, face.as_dir())))
+            .collect::<Vec<_>>();
+        let insert_stone = |terrain: &mut Terrain, chunk_pos: Vector3<NonZeroI32>, hole: Option<Vector3<i32>>| {
+            let mut blocks = BlockBuffer::new(vec![stone.clone(); (CHUNK_SIZE as usize).pow(3)]);
+            if let Some(hole) = hole {
+                blocks.set(&hole, air.clone());
+            }
+            terrain.insert_loaded_chunk(chunk_pos, blocks);
+        };
+
+        // Every neighbour but one, a missing neighbour doesn't hide anything
+        for (_, chunk_pos) in &neighbours[1..] {
+            insert_stone(&mut terrain, *chunk_pos, None);
+        }
+        assert!(!terrain.occluded(&target));
+        insert_stone(&mut terrain, neighbours[0].1, None);
+        assert!(terrain.occluded(&target));
+
+        // Chunks with the camera in them are always drawn
+        terrain.camera_chunk_pos = target;
+        assert!(!terrain.occluded(&target));
+        terrain.camera_chunk_pos = chunk_pos(5, 5, 5);
+
+        // A hole on the far side of a neighbour doesn't open up the chunk, one on the face it shares with it does
+        let above = add_non_zero_i32_vector3(target, FaceDirection::Top.as_dir());
+        insert_stone(&mut terrain, above, Some(Vector3::new(3, CHUNK_SIZE as i32 - 1, 3)));
+        assert!(terrain.occluded(&target));
+        insert_stone(&mut terrain, above, Some(Vector3::new(3, 0, 3)));
+        assert!(!terrain.occluded(&target));
+    }
+
+    #[test]
+    fn fill_region_matches_setting_each_block() {
+        let around_origin = [-1, 1]
+            .into_iter()
+            .flat_map(|x| {
+                [-1, 1]
+                    .into_iter()
+                    .flat_map(move |y| [-1, 1].map(|z| chunk_pos(x, y, z)))
+            })
+            .collect::<Vec<_>>();
+        let (mut filled, mut set_one_by_one) = (
+            terrain(TerrainGeneratorKind::flat()),
+            terrain(TerrainGeneratorKind::flat()),
+        );
+        for terrain in [&mut filled, &mut set_one_by_one] {
+            assert!(terrain.load_blocking(&around_origin, Duration::from_secs(300)));
+        }
+        let pos = |x: i64, y: i64, z: i64| Pos::from_abs_block(Vector3::new(x, y, z)).unwrap();
+
+        // A stone block over the chunk borders around the origin casting a shadow on the ground, then a pit dug through
+        // it into the ground
+        for (min, max, block_name, count) in [
+            ([-3, -2, -3], [3, 2, 3], "Stone", 245),
+            ([1, 1, 1], [-1, -4, -1], "Air", 54),
+        ] {
+            let block = Block::new_with_default(block_name, &filled.block_manager);
+            assert_eq!(
+                filled.fill_region(
+                    &pos(min[0], min[1], min[2]),
+                    &pos(max[0], max[1], max[2]),
+                    block.clone()
+                ),
+                count
+            );
+            let (first, second) = (Vector3::from(min), Vector3::from(max));
+            let (min, max) = (first.zip(second, i64::min), first.zip(second, i64::max));
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        set_one_by_one.set_block(&pos(x, y, z), block.clone());
+                    }
+                }
+            }
+
+            for terrain in [&mut filled, &mut set_one_by_one] {
+                assert!(terrain.load_blocking(&around_origin, Duration::from_secs(300)));
+            }
+            for x in -12..12 {
+                for y in -8..8 {
+                    for z in -12..12 {
+                        let pos = pos(x, y, z);
+                        assert_eq!(
+                            filled.get_block(&pos),
+                            set_one_by_one.get_block(&pos),
+                            "block at {:?}",
+                            (x, y, z)
+                        );
+                        let light = filled.get_light(&pos);
+                        assert!(light.is_some());
+                        assert_eq!(light, set_one_by_one.get_light(&pos), "light at {:?}", (x, y, z));
+                    }
+                }
+            }
+        }
+
+        // The pit is closed off by the stone above it, so sunlight doesn't reach into it
+        assert!(!filled.get_block(&pos(0, -3, 0)).unwrap().is_sunlit());
+        assert!(filled.get_light(&pos(0, -3, 0)).unwrap().sun < MAX_LIGHT_VAL);
+    }
+}
@@ -1,38 +1,266 @@
-use std::{iter, num::NonZeroI32};
+use std::{iter, num::NonZeroI32, path::PathBuf};
 
 use block_mesh::ndshape::ConstShape;
 use cgmath::Vector3;
+use image::{Rgb, RgbImage};
 use noise::{Cache, NoiseFn, Perlin};
-use rand::prelude::*;
+use rand::{prelude::*, rngs::StdRng};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     game::world::{Block, BlockBuffer, BlockManager, ChunkShape, CHUNK_SIZE},
-    misc::pos::Pos,
+    misc::{loader::load_resource_string, pos::Pos, profiling::profile_span},
 };
 
-const BASE_GROUND_LEVEL: f64 = -10.0;
-const SEA_LEVEL: i32 = 0;
-const HILLINESS: f64 = 20.0;
-const LEVELS_OF_DIRT: u32 = 5;
+// Blocks below ground are carved out where the magnitude of the cave noise exceeds this value
+const CAVE_THRESHOLD: f64 = 0.5;
+const CAVE_SCALE: f64 = 24.0;
+// Over this many blocks below the ground the threshold rises until nothing is carved at the surface
+const CAVE_SURFACE_DEPTH: i32 = 8;
+const TREE_MIN_HEIGHT: i32 = 4;
+const TREE_MAX_HEIGHT: i32 = 6;
+// Leaves reach this many blocks away from the trunk
+const TREE_RADIUS: i32 = 2;
+// Biomes are picked from noise sampled at this scale, so they span a few hundred blocks
+const BIOME_SCALE: f64 = 300.0;
+// Biome noise values between which neighbouring biomes are blended, ordered like `Biome::ALL`
+const BIOME_BOUNDARIES: [f64; 3] = [-0.25, 0.0, 0.25];
+// Half width of the blending band in noise space, roughly a few blocks in the world
+const BIOME_BLEND: f64 = 0.02;
+// Ground height is summed from this many octaves of noise, each one sampled at `LACUNARITY` times the frequency and
+// `PERSISTENCE` times the amplitude of the one before
+const OCTAVES: u32 = 4;
+const LACUNARITY: f64 = 2.0;
+const PERSISTENCE: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Desert,
+    Plains,
+    Forest,
+    Mountains,
+}
+
+impl Biome {
+    // Ordered so that only similar biomes border each other
+    const ALL: [Biome; 4] = [Biome::Desert, Biome::Plains, Biome::Forest, Biome::Mountains];
+
+    fn surface_block(&self) -> &'static str {
+        match self {
+            Biome::Desert => "Sand",
+            Biome::Plains | Biome::Forest => "Grass",
+            Biome::Mountains => "Stone",
+        }
+    }
+
+    fn filler_block(&self) -> &'static str {
+        match self {
+            Biome::Desert => "Sand",
+            Biome::Plains | Biome::Forest => "Dirt",
+            Biome::Mountains => "Stone",
+        }
+    }
+
+    fn base_ground_level(&self) -> f64 {
+        match self {
+            Biome::Desert => -4.0,
+            Biome::Plains => -6.0,
+            Biome::Forest => -10.0,
+            Biome::Mountains => -5.0,
+        }
+    }
+
+    // Chance of a tree growing on a grass column
+    fn tree_chance(&self) -> f64 {
+        match self {
+            Biome::Forest => 0.02,
+            Biome::Plains => 0.002,
+            Biome::Desert | Biome::Mountains => 0.0,
+        }
+    }
+
+    fn hilliness(&self) -> f64 {
+        match self {
+            Biome::Desert => 8.0,
+            Biome::Plains => 10.0,
+            Biome::Forest => 20.0,
+            Biome::Mountains => 50.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OreConfig {
+    pub block: String,
+    // Ore is placed where the 3D noise exceeds this value, so higher values give rarer and smaller veins
+    pub threshold: f64,
+    pub scale: f64,
+    pub max_height: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    pub sea_level: i32,
+    pub levels_of_dirt: u32,
+    // Added to the ground level of every biome
+    pub base_ground_level: f64,
+    // Multiplies the hilliness of every biome
+    pub hilliness: f64,
+    // Everything above this height is left as air
+    pub max_height: Option<i32>,
+    // Places a layer of bedrock at `max_height`
+    pub ceiling: bool,
+    pub ores: Vec<OreConfig>,
+    // Surface blocks at or above this height are covered in snow
+    pub snow_height: i32,
+    // Surface blocks at or above this height are left as bare stone when the slope is at least `steep_slope`
+    pub bare_stone_height: i32,
+    pub steep_slope: i32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            sea_level: 0,
+            levels_of_dirt: 5,
+            base_ground_level: 0.0,
+            hilliness: 1.0,
+            max_height: None,
+            ceiling: false,
+            ores: vec![
+                OreConfig {
+                    block: "Coal Ore".to_string(),
+                    threshold: 0.55,
+                    scale: 6.0,
+                    max_height: -16,
+                },
+                OreConfig {
+                    block: "Iron Ore".to_string(),
+                    threshold: 0.65,
+                    scale: 4.0,
+                    max_height: -32,
+                },
+                OreConfig {
+                    block: "Gold Ore".to_string(),
+                    threshold: 0.75,
+                    scale: 3.0,
+                    max_height: -64,
+                },
+            ],
+            snow_height: 14,
+            bare_stone_height: 8,
+            steep_slope: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerrainGeneratorKind {
+    #[default]
+    Normal,
+    // Block names with the number of blocks of each, from the bottom up. The top layer ends at sea level
+    Flat {
+        layers: Vec<(String, u32)>,
+    },
+}
+
+impl TerrainGeneratorKind {
+    #[allow(dead_code)]
+    pub fn flat() -> Self {
+        Self::Flat {
+            layers: vec![
+                ("Stone".to_string(), 3),
+                ("Dirt".to_string(), 3),
+                ("Grass".to_string(), 1),
+            ],
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        matches!(self, Self::Flat { .. })
+    }
+}
+
+impl TerrainConfig {
+    // Falls back to the defaults when `terrain.yaml` is missing or malformed, fields left out of it use their defaults
+    pub fn load() -> Self {
+        match load_resource_string(PathBuf::new().join("terrain.yaml")) {
+            Ok(config_string) => match serde_yaml::from_str(config_string.as_str()) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::warn!("Failed parsing `terrain.yaml`, using default terrain config - {e:?}");
+                    Self::default()
+                }
+            },
+            Err(_) => {
+                log::warn!("Missing `terrain.yaml`, using default terrain config");
+                Self::default()
+            }
+        }
+    }
+}
+
+// Blocks of structures that reach outside of the chunk generating them, keyed by the chunk they belong to
+pub type DeferredBlocks = FxHashMap<Vector3<NonZeroI32>, Vec<(Vector3<i32>, Block)>>;
 
 #[derive(Clone, Debug)]
 pub struct TerrainGenerator {
-    #[allow(dead_code)]
     seed: u32,
     noise: Cache<Perlin>,
+    biome_noise: Perlin,
+    cave_noise: Cache<Perlin>,
+    ore_noise: Vec<Perlin>,
+    octaves: u32,
+    lacunarity: f64,
+    persistence: f64,
+    config: TerrainConfig,
+    kind: TerrainGeneratorKind,
+    // Blocks of a flat world column from its lowest block up
+    flat_column: Vec<Block>,
     block_manager: BlockManager,
 }
 
 impl TerrainGenerator {
-    pub fn new(seed: u32, block_manager: BlockManager) -> Self {
+    pub fn new(seed: u32, block_manager: BlockManager, config: TerrainConfig, kind: TerrainGeneratorKind) -> Self {
+        let flat_column = match &kind {
+            TerrainGeneratorKind::Normal => Vec::new(),
+            TerrainGeneratorKind::Flat { layers } => layers
+                .iter()
+                .filter(|(block_name, _)| {
+                    let exists = block_manager.get(block_name).is_some();
+                    if !exists {
+                        log::error!("Skipping flat world layer of unknown block `{block_name:}`");
+                    }
+                    exists
+                })
+                .flat_map(|(block_name, count)| {
+                    iter::repeat_n(Block::new_with_default(block_name, &block_manager), *count as usize)
+                })
+                .collect(),
+        };
+
         Self {
             seed,
+            kind,
+            flat_column,
             block_manager,
             noise: Cache::new(Perlin::new(seed)),
+            biome_noise: Perlin::new(seed ^ 0xB10E),
+            cave_noise: Cache::new(Perlin::new(seed ^ 0x1234)),
+            ore_noise: (0..config.ores.len() as u32)
+                .map(|index| Perlin::new(seed.wrapping_add(index + 1).wrapping_mul(0x9E37_79B9)))
+                .collect(),
+            octaves: OCTAVES,
+            lacunarity: LACUNARITY,
+            persistence: PERSISTENCE,
+            config,
         }
     }
 
-    pub fn generate_blocks(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> BlockBuffer {
+    pub fn generate_blocks(&mut self, chunk_pos: &Vector3<NonZeroI32>) -> (BlockBuffer, DeferredBlocks) {
+        profile_span!("generate_blocks");
         let mut blocks = Vec::from_iter(iter::repeat(Block::default()).take((CHUNK_SIZE as usize).pow(3)));
 
         for x in 0..CHUNK_SIZE as usize {
@@ -50,42 +278,646 @@ impl TerrainGenerator {
             }
         }
 
-        BlockBuffer::new(blocks)
+        let mut deferred = DeferredBlocks::default();
+        if self.kind.is_flat() {
+            return (BlockBuffer::new(blocks), deferred);
+        }
+
+        for (abs_pos, block_name) in self.trees(chunk_pos) {
+            if self.is_above_max_height(abs_pos.y) {
+                continue;
+            }
+
+            let (block_chunk_pos, in_chunk_pos) = split_abs_pos(&abs_pos);
+            let block = Block::new_with_default(block_name, &self.block_manager);
+
+            if block_chunk_pos == *chunk_pos {
+                let index = ChunkShape::linearize([in_chunk_pos.x as u32, in_chunk_pos.y as u32, in_chunk_pos.z as u32])
+                    as usize;
+                if !blocks[index].is_rendered() {
+                    blocks[index] = block;
+                }
+            } else {
+                deferred.entry(block_chunk_pos).or_default().push((in_chunk_pos, block));
+            }
+        }
+
+        (BlockBuffer::new(blocks), deferred)
+    }
+
+    // Blocks of all trees growing from columns of the chunk, which can reach into neighbouring chunks
+    fn trees(&self, chunk_pos: &Vector3<NonZeroI32>) -> Vec<(Vector3<i32>, &'static str)> {
+        let origin = {
+            let abs_pos = Pos::new(*chunk_pos, Vector3::new(0.0, 0.0, 0.0)).abs_pos();
+            Vector3::new(abs_pos.x as i32, abs_pos.y as i32, abs_pos.z as i32)
+        };
+
+        // Trunks of the trees of the chunk and of all trees close enough for their leaves to reach into it. Leaves are
+        // never placed into another trunk, so where trees of two chunks overlap it doesn't matter which is placed first
+        let trunks = (origin.x - TREE_RADIUS..origin.x + CHUNK_SIZE as i32 + TREE_RADIUS)
+            .flat_map(|x| (origin.z - TREE_RADIUS..origin.z + CHUNK_SIZE as i32 + TREE_RADIUS).map(move |z| (x, z)))
+            .filter_map(|(x, z)| self.tree_at(x, z).map(|trunk| ((x, z), trunk)))
+            .collect::<FxHashMap<_, _>>();
+        let is_trunk = |x: i32, y: i32, z: i32| {
+            trunks
+                .get(&(x, z))
+                .is_some_and(|(ground_y, top)| (ground_y + 1..=*top).contains(&y))
+        };
+
+        let mut out = Vec::new();
+        for x in origin.x..origin.x + CHUNK_SIZE as i32 {
+            for z in origin.z..origin.z + CHUNK_SIZE as i32 {
+                let Some(&(ground_y, top)) = trunks.get(&(x, z)) else {
+                    continue;
+                };
+                if ground_y + 1 < origin.y || ground_y + 1 >= origin.y + CHUNK_SIZE as i32 {
+                    continue;
+                }
+
+                for y in ground_y + 1..=top {
+                    out.push((Vector3::new(x, y, z), "Wood"));
+                }
+                for y in top - 1..=top + 2 {
+                    let radius = if y < top + 1 { TREE_RADIUS } else { TREE_RADIUS - 1 };
+                    for dx in -radius..=radius {
+                        for dz in -radius..=radius {
+                            let corner = dx.abs() == radius && dz.abs() == radius;
+                            if !corner && !is_trunk(x + dx, y, z + dz) {
+                                out.push((Vector3::new(x + dx, y, z + dz), "Leaves"));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    // Ground height and top of the trunk of the tree growing from the column, if one does
+    fn tree_at(&self, x: i32, z: i32) -> Option<(i32, i32)> {
+        let ground_y = self.ground_height(x, z);
+        let biome = self.biome_at(x, z);
+        if biome.tree_chance() <= 0.0 || self.surface_block(x, z, ground_y, biome) != "Grass" {
+            return None;
+        }
+
+        // Seeded per column, so a tree is the same no matter which chunk is generated first
+        let mut rng = StdRng::seed_from_u64(
+            (self.seed as u64)
+                ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+        );
+        rng.gen_bool(biome.tree_chance())
+            .then(|| (ground_y, ground_y + rng.gen_range(TREE_MIN_HEIGHT..=TREE_MAX_HEIGHT)))
     }
 
     fn generate_block(&mut self, abs_pos: &Vector3<i32>) -> Block {
-        let xy = [abs_pos.x as f64 / 100.0, abs_pos.z as f64 / 100.0];
-        let ground_y = (BASE_GROUND_LEVEL - ((self.noise.get(xy) - 0.5) * HILLINESS)) as i32;
+        if self.is_above_max_height(abs_pos.y) {
+            return Block::new_with_default("Air", &self.block_manager);
+        } else if self.config.ceiling && Some(abs_pos.y) == self.config.max_height {
+            return Block::new_with_default("Bedrock", &self.block_manager);
+        }
+
+        match self.kind {
+            TerrainGeneratorKind::Normal => self.generate_normal_block(abs_pos),
+            TerrainGeneratorKind::Flat { .. } => {
+                let layer = abs_pos.y - (self.config.sea_level - self.flat_column.len() as i32 + 1);
+                usize::try_from(layer)
+                    .ok()
+                    .and_then(|layer| self.flat_column.get(layer))
+                    .cloned()
+                    .unwrap_or_else(|| Block::new_with_default("Air", &self.block_manager))
+            }
+        }
+    }
+
+    fn generate_normal_block(&mut self, abs_pos: &Vector3<i32>) -> Block {
+        let ground_y = self.ground_height(abs_pos.x, abs_pos.z);
+        let biome = self.biome_at(abs_pos.x, abs_pos.z);
 
         let block_name = if abs_pos.y > ground_y {
-            if abs_pos.y <= SEA_LEVEL {
+            if abs_pos.y <= self.config.sea_level {
                 "Water"
             } else {
                 "Air"
             }
         } else {
             if abs_pos.y == ground_y {
-                if ground_y < SEA_LEVEL {
-                    "Sand"
-                } else {
-                    "Grass"
-                }
-            } else if abs_pos.y > ground_y - LEVELS_OF_DIRT as i32 {
-                if ground_y < SEA_LEVEL {
+                self.surface_block(abs_pos.x, abs_pos.z, ground_y, biome)
+            } else if abs_pos.y > ground_y - self.config.levels_of_dirt as i32 {
+                if ground_y < self.config.sea_level {
                     "Sand"
                 } else {
-                    "Dirt"
+                    biome.filler_block()
                 }
             } else {
-                "Stone"
+                self.ore_at(abs_pos).unwrap_or("Stone")
             }
         };
+        let block_name = if abs_pos.y <= ground_y && self.is_cave(abs_pos, ground_y) {
+            "Air"
+        } else {
+            block_name
+        };
 
         Block::new_with_default(block_name, &self.block_manager)
     }
 
+    fn is_above_max_height(&self, y: i32) -> bool {
+        self.config.max_height.is_some_and(|max_height| y > max_height)
+    }
+
+    fn surface_block(&self, x: i32, z: i32, ground_y: i32, biome: Biome) -> &'static str {
+        if ground_y < self.config.sea_level {
+            "Sand"
+        } else if ground_y >= self.config.bare_stone_height && self.slope(x, z, ground_y) >= self.config.steep_slope {
+            "Stone"
+        } else if ground_y >= self.config.snow_height {
+            "Snow"
+        } else {
+            biome.surface_block()
+        }
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let value = self.biome_value(x, z);
+        Biome::ALL[BIOME_BOUNDARIES.iter().filter(|boundary| value >= **boundary).count()]
+    }
+
+    fn biome_value(&self, x: i32, z: i32) -> f64 {
+        self.biome_noise.get([x as f64 / BIOME_SCALE, z as f64 / BIOME_SCALE])
+    }
+
+    fn ground_height(&self, x: i32, z: i32) -> i32 {
+        let value = self.biome_value(x, z);
+        // How far past a boundary the column is, 0.0 before the blending band and 1.0 after it
+        let past = |boundary: Option<&f64>, outside: f64| {
+            boundary.map_or(outside, |boundary| {
+                ((value - boundary) / (2.0 * BIOME_BLEND) + 0.5).clamp(0.0, 1.0)
+            })
+        };
+
+        // Weights of all biomes add up to 1.0, so the height blends smoothly across biome edges
+        let (base_ground_level, hilliness) =
+            Biome::ALL
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(base_ground_level, hilliness), (index, biome)| {
+                    let lower = index.checked_sub(1).and_then(|index| BIOME_BOUNDARIES.get(index));
+                    let weight = past(lower, 1.0) - past(BIOME_BOUNDARIES.get(index), 0.0);
+                    (
+                        base_ground_level + biome.base_ground_level() * weight,
+                        hilliness + biome.hilliness() * weight,
+                    )
+                });
+
+        let height = self.fbm(
+            x as f64 / 100.0,
+            z as f64 / 100.0,
+            self.octaves,
+            self.lacunarity,
+            self.persistence,
+        );
+        (base_ground_level + self.config.base_ground_level - ((height - 0.5) * hilliness * self.config.hilliness))
+            as i32
+    }
+
+    // Fractal brownian motion, divided by the sum of the amplitudes so it stays in the range of a single sample
+    pub fn fbm(&self, x: f64, z: f64, octaves: u32, lacunarity: f64, persistence: f64) -> f64 {
+        let (sum, amplitudes, _, _) =
+            (0..octaves.max(1)).fold((0.0, 0.0, 1.0, 1.0), |(sum, amplitudes, frequency, amplitude), _| {
+                (
+                    sum + self.noise.get([x * frequency, z * frequency]) * amplitude,
+                    amplitudes + amplitude,
+                    frequency * lacunarity,
+                    amplitude * persistence,
+                )
+            });
+        sum / amplitudes
+    }
+
+    // Largest height difference between the column and its four direct neighbours
+    fn slope(&self, x: i32, z: i32, ground_y: i32) -> i32 {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(dx, dz)| (self.ground_height(x + dx, z + dz) - ground_y).abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn ore_at(&self, abs_pos: &Vector3<i32>) -> Option<&str> {
+        self.config
+            .ores
+            .iter()
+            .zip(self.ore_noise.iter())
+            .find(|(ore, noise)| {
+                abs_pos.y <= ore.max_height
+                    && noise.get([
+                        abs_pos.x as f64 / ore.scale,
+                        abs_pos.y as f64 / ore.scale,
+                        abs_pos.z as f64 / ore.scale,
+                    ]) > ore.threshold
+            })
+            .map(|(ore, _)| ore.block.as_str())
+    }
+
+    fn is_cave(&self, abs_pos: &Vector3<i32>, ground_y: i32) -> bool {
+        let depth = ground_y - abs_pos.y;
+        let threshold = if depth < CAVE_SURFACE_DEPTH {
+            let taper = (CAVE_SURFACE_DEPTH - depth) as f64 / CAVE_SURFACE_DEPTH as f64;
+            CAVE_THRESHOLD + (1.0 - CAVE_THRESHOLD) * taper
+        } else {
+            CAVE_THRESHOLD
+        };
+
+        self.cave_noise
+            .get([
+                abs_pos.x as f64 / CAVE_SCALE,
+                abs_pos.y as f64 / CAVE_SCALE,
+                abs_pos.z as f64 / CAVE_SCALE,
+            ])
+            .abs()
+            > threshold
+    }
+
+    // Top-down view of the surface with one pixel every `step` blocks around `center`. Only column heights are sampled,
+    // so no chunks have to be generated for it
+    #[allow(dead_code)]
+    pub fn preview(&self, center: (i32, i32), size: u32, step: u32) -> RgbImage {
+        profile_span!("world_preview");
+        let offset = |pixel: u32| (pixel as i32 - size as i32 / 2) * step as i32;
+
+        RgbImage::from_fn(size, size, |x, y| {
+            let (abs_x, abs_z) = (center.0 + offset(x), center.1 + offset(y));
+            let (block_name, height) = match &self.kind {
+                TerrainGeneratorKind::Normal => {
+                    let ground_y = self.ground_height(abs_x, abs_z);
+                    if ground_y < self.config.sea_level {
+                        ("Water", ground_y)
+                    } else {
+                        (
+                            self.surface_block(abs_x, abs_z, ground_y, self.biome_at(abs_x, abs_z)),
+                            ground_y,
+                        )
+                    }
+                }
+                TerrainGeneratorKind::Flat { layers } => (
+                    layers.last().map_or("Air", |(block_name, _)| block_name.as_str()),
+                    self.config.sea_level,
+                ),
+            };
+
+            // Higher ground is drawn lighter, deeper water darker
+            let shade = (1.0 + (height - self.config.sea_level) as f32 / 64.0).clamp(0.4, 1.4);
+            Rgb(preview_color(block_name).map(|channel| (channel as f32 * shade).min(u8::MAX as f32) as u8))
+        })
+    }
+
     pub fn generate_seed() -> u32 {
         let mut rng = rand::thread_rng();
         rng.gen()
     }
+
+    // Numbers are used as they are, so a copied seed gives the same world. Other text is hashed with FNV-1a, which
+    // unlike the std hasher is guaranteed to stay the same between Rust versions
+    #[cfg_attr(not(feature = "save_system"), allow(dead_code))]
+    pub fn seed_from_str(s: &str) -> u32 {
+        let s = s.trim();
+        if s.is_empty() {
+            Self::generate_seed()
+        } else if let Ok(seed) = s.parse() {
+            seed
+        } else {
+            s.bytes().fold(0x811C_9DC5, |hash: u32, byte| {
+                (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+            })
+        }
+    }
+}
+
+fn preview_color(block_name: &str) -> [u8; 3] {
+    match block_name {
+        "Water" => [40, 80, 180],
+        "Sand" => [210, 195, 130],
+        "Grass" => [80, 150, 60],
+        "Dirt" => [120, 85, 55],
+        "Snow" => [235, 240, 245],
+        "Stone" | "Bedrock" => [125, 125, 125],
+        _ => [150, 150, 150],
+    }
+}
+
+// Chunk and in chunk position of a block, the inverse of `Pos::abs_pos`
+fn split_abs_pos(abs_pos: &Vector3<i32>) -> (Vector3<NonZeroI32>, Vector3<i32>) {
+    let split = |coord: i32| {
+        let chunk = coord.div_euclid(CHUNK_SIZE as i32);
+        (
+            NonZeroI32::new(if chunk >= 0 { chunk + 1 } else { chunk }).unwrap(),
+            coord.rem_euclid(CHUNK_SIZE as i32),
+        )
+    };
+
+    let ((chunk_x, x), (chunk_y, y), (chunk_z, z)) = (split(abs_pos.x), split(abs_pos.y), split(abs_pos.z));
+    (Vector3::new(chunk_x, chunk_y, chunk_z), Vector3::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(octaves: u32) -> TerrainGenerator {
+        TerrainGenerator {
+            octaves,
+            ..TerrainGenerator::new(
+                1234,
+                BlockManager::new(),
+                TerrainConfig::default(),
+                TerrainGeneratorKind::Normal,
+            )
+        }
+    }
+
+    #[test]
+    fn terrain_config_yaml_round_trip() {
+        let bundled: TerrainConfig = serde_yaml::from_str(include_str!("../../../res/terrain.yaml")).unwrap();
+        assert_eq!(bundled, TerrainConfig::default());
+
+        let config = TerrainConfig {
+            sea_level: -12,
+            hilliness: 2.5,
+            max_height: Some(96),
+            ceiling: true,
+            ores: vec![OreConfig {
+                block: "Diamond Ore".to_string(),
+                threshold: 0.9,
+                scale: 2.0,
+                max_height: -100,
+            }],
+            ..TerrainConfig::default()
+        };
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert_eq!(serde_yaml::from_str::<TerrainConfig>(&yaml).unwrap(), config);
+
+        // Left out fields keep their defaults
+        let partial: TerrainConfig = serde_yaml::from_str("sea_level: 20\nceiling: true").unwrap();
+        assert_eq!(
+            partial,
+            TerrainConfig {
+                sea_level: 20,
+                ceiling: true,
+                ..TerrainConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn more_octaves_stay_in_range() {
+        let generator = generator(OCTAVES);
+        for x in -50..50 {
+            let value = generator.fbm(x as f64 / 7.0, x as f64 / 13.0, OCTAVES, LACUNARITY, PERSISTENCE);
+            assert!((-1.0..=1.0).contains(&value));
+        }
+    }
+
+    #[test]
+    fn single_octave_fbm_matches_single_sample() {
+        let generator = generator(1);
+        for (x, z) in [(0, 0), (17, -3), (-250, 999), (4096, -4096)] {
+            let xy = [x as f64 / 100.0, z as f64 / 100.0];
+            // Lacunarity and persistence only apply from the second octave on
+            for (lacunarity, persistence) in [(LACUNARITY, PERSISTENCE), (3.0, 0.25)] {
+                assert_eq!(
+                    generator.fbm(xy[0], xy[1], 1, lacunarity, persistence),
+                    generator.noise.get(xy)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_text_gives_same_seed() {
+        for text in ["My World", "rezcraft", "  padded  ", "Ünïcödé"] {
+            assert_eq!(
+                TerrainGenerator::seed_from_str(text),
+                TerrainGenerator::seed_from_str(text)
+            );
+        }
+        assert_eq!(
+            TerrainGenerator::seed_from_str("padded"),
+            TerrainGenerator::seed_from_str("  padded  ")
+        );
+        assert_ne!(
+            TerrainGenerator::seed_from_str("My World"),
+            TerrainGenerator::seed_from_str("My world")
+        );
+
+        // Numbers are the seed itself, so the seed of a world can be typed in again
+        assert_eq!(TerrainGenerator::seed_from_str("1234"), 1234);
+        assert_eq!(TerrainGenerator::seed_from_str(" 4294967295 "), u32::MAX);
+        // FNV-1a of "a", fixed so seeds stay the same between versions
+        assert_eq!(TerrainGenerator::seed_from_str("a"), 0xE40C_292C);
+    }
+
+    #[test]
+    fn biomes_are_deterministic_for_a_seed() {
+        let (first, second) = (generator(OCTAVES), generator(OCTAVES));
+        let other_seed = TerrainGenerator::new(
+            4321,
+            BlockManager::new(),
+            TerrainConfig::default(),
+            TerrainGeneratorKind::Normal,
+        );
+
+        let mut seen = FxHashMap::default();
+        let mut differs_for_other_seed = false;
+        for x in (-3000..3000).step_by(50) {
+            for z in (-3000..3000).step_by(50) {
+                let biome = first.biome_at(x, z);
+                assert_eq!(biome, second.biome_at(x, z));
+                *seen.entry(biome).or_insert(0) += 1;
+                differs_for_other_seed |= biome != other_seed.biome_at(x, z);
+            }
+        }
+
+        assert_eq!(seen.len(), Biome::ALL.len(), "{seen:?}");
+        assert!(differs_for_other_seed);
+    }
+
+    #[test]
+    fn biome_edges_blend_height() {
+        let generator = generator(1);
+        let step = |x: i32, z: i32| (generator.ground_height(x, z) - generator.ground_height(x + 1, z)).abs();
+
+        // Edges are no steeper than the hills inside of biomes, without blending they would be cliffs of up to the
+        // difference in base ground level and hilliness
+        let (mut steepest_edge, mut steepest_inside) = (0, 0);
+        for z in (-3000..3000).step_by(100) {
+            for x in -3000..3000 {
+                if generator.biome_at(x, z) != generator.biome_at(x + 1, z) {
+                    steepest_edge = steepest_edge.max(step(x, z));
+                } else {
+                    steepest_inside = steepest_inside.max(step(x, z));
+                }
+            }
+        }
+        assert!(steepest_edge > 0);
+        assert!(steepest_edge <= steepest_inside, "{steepest_edge} {steepest_inside}");
+    }
+
+    // Every block of the chunk with its absolute position
+    fn chunk_blocks(blocks: &BlockBuffer, chunk_pos: Vector3<NonZeroI32>) -> Vec<(Vector3<i32>, Block)> {
+        let mut out = Vec::new();
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                for z in 0..CHUNK_SIZE as i32 {
+                    let abs_pos = Pos::new(chunk_pos, Vector3::new(x as f32, y as f32, z as f32)).abs_pos();
+                    out.push((
+                        Vector3::new(abs_pos.x as i32, abs_pos.y as i32, abs_pos.z as i32),
+                        blocks[&Vector3::new(x, y, z)].clone(),
+                    ));
+                }
+            }
+        }
+        out
+    }
+
+    fn chunk_pos(x: i32, y: i32, z: i32) -> Vector3<NonZeroI32> {
+        Vector3::new(
+            NonZeroI32::new(x).unwrap(),
+            NonZeroI32::new(y).unwrap(),
+            NonZeroI32::new(z).unwrap(),
+        )
+    }
+
+    #[test]
+    fn flat_world_only_has_its_layers() {
+        let layers = vec![
+            ("Stone".to_string(), 2),
+            ("Missing Block".to_string(), 5),
+            ("Dirt".to_string(), 3),
+            ("Grass".to_string(), 1),
+        ];
+        let mut generator = TerrainGenerator::new(
+            1234,
+            BlockManager::new(),
+            TerrainConfig::default(),
+            TerrainGeneratorKind::Flat { layers },
+        );
+        let block = |block_name| Block::new_with_default(block_name, &generator.block_manager);
+        // Unknown blocks are left out, the top layer ends at sea level
+        let sea_level = generator.config.sea_level;
+        let expected_column = [
+            (sea_level - 5, block("Stone")),
+            (sea_level - 4, block("Stone")),
+            (sea_level - 3, block("Dirt")),
+            (sea_level - 2, block("Dirt")),
+            (sea_level - 1, block("Dirt")),
+            (sea_level, block("Grass")),
+        ]
+        .into_iter()
+        .collect::<FxHashMap<_, _>>();
+        let air = block("Air");
+
+        for chunk_pos in [chunk_pos(3, -1, -2), chunk_pos(3, 1, -2), chunk_pos(-40, -1, 25)] {
+            let (blocks, deferred) = generator.generate_blocks(&chunk_pos);
+            assert!(deferred.is_empty());
+
+            for (abs_pos, block) in chunk_blocks(&blocks, chunk_pos) {
+                assert_eq!(
+                    block,
+                    expected_column.get(&abs_pos.y).unwrap_or(&air).clone(),
+                    "block at {abs_pos:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn caves_are_reproducible_and_stay_underground() {
+        let (mut first, mut second) = (generator(OCTAVES), generator(OCTAVES));
+        let (air, water) = (
+            Block::new_with_default("Air", &first.block_manager),
+            Block::new_with_default("Water", &first.block_manager),
+        );
+
+        let mut carved = 0;
+        for chunk_pos in [
+            chunk_pos(1, -3, 1),
+            chunk_pos(-2, -1, 3),
+            chunk_pos(2, 1, -1),
+            chunk_pos(-1, -2, -2),
+        ] {
+            let first_blocks = chunk_blocks(&first.generate_blocks(&chunk_pos).0, chunk_pos);
+            let second_blocks = chunk_blocks(&second.generate_blocks(&chunk_pos).0, chunk_pos);
+            assert!(first_blocks == second_blocks);
+
+            for (abs_pos, block) in first_blocks {
+                let ground_y = first.ground_height(abs_pos.x, abs_pos.z);
+                if abs_pos.y > ground_y {
+                    let expected = if abs_pos.y <= first.config.sea_level {
+                        &water
+                    } else {
+                        &air
+                    };
+                    if block != *expected {
+                        // Only trees grow above the ground
+                        assert!(block.is_solid(), "{abs_pos:?} is {block:?}");
+                    }
+                } else if abs_pos.y == ground_y {
+                    assert_ne!(block, air, "the surface at {abs_pos:?} is carved");
+                } else if block == air {
+                    assert!(first.is_cave(&abs_pos, ground_y));
+                    carved += 1;
+                }
+            }
+        }
+
+        assert!(carved > 0);
+    }
+
+    #[test]
+    fn leaves_never_replace_trunks() {
+        let generator = generator(OCTAVES);
+        let (mut wood, mut leaves) = (FxHashMap::default(), Vec::new());
+        for x in -6..6 {
+            for z in -6..6 {
+                for y in [-1, 1] {
+                    if x == 0 || z == 0 {
+                        continue;
+                    }
+                    for (abs_pos, block_name) in generator.trees(&chunk_pos(x, y, z)) {
+                        match block_name {
+                            "Wood" => {
+                                wood.insert(abs_pos, chunk_pos(x, y, z));
+                            }
+                            _ => leaves.push((abs_pos, chunk_pos(x, y, z))),
+                        }
+                    }
+                }
+            }
+        }
+
+        // Trees close enough to each other for their leaves to reach the other trunk
+        let close_trees = wood
+            .keys()
+            .filter(|abs_pos| {
+                (-TREE_RADIUS..=TREE_RADIUS).any(|dx| {
+                    (-TREE_RADIUS..=TREE_RADIUS).any(|dz| {
+                        (dx, dz) != (0, 0)
+                            && wood.contains_key(&Vector3::new(abs_pos.x + dx, abs_pos.y, abs_pos.z + dz))
+                    })
+                })
+            })
+            .count();
+        assert!(close_trees > 0);
+
+        for (abs_pos, chunk_pos) in leaves {
+            assert!(
+                !wood.contains_key(&abs_pos),
+                "leaves of a tree of chunk {chunk_pos:?} replace the trunk at {abs_pos:?} of a tree of chunk {:?}",
+                wood[&abs_pos]
+            );
+        }
+    }
 }
@@ -1,14 +1,17 @@
-use either::Either;
-
-use crate::game::world::{Block, TextureID};
+use crate::game::world::{Block, BlockShape, BlockTextures, InternalFaces, TextureID};
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Voxel {
-    texture: Option<Either<TextureID, [TextureID; 3]>>,
+    texture: Option<BlockTextures<TextureID>>,
     face_lighting: Option<[[u8; 4]; 6]>,
     is_renderer: bool,
     is_opaque: bool,
     is_transparent: bool,
+    is_light_source: bool,
+    internal_faces: InternalFaces,
+    shape: BlockShape,
+    is_double_sided: bool,
+    is_fluid: bool,
 }
 
 impl Voxel {
@@ -18,11 +21,16 @@ impl Voxel {
             is_renderer: block.is_rendered(),
             is_opaque: block.is_opaque(),
             is_transparent: block.is_transparent(),
+            is_light_source: block.light_source().is_some(),
+            internal_faces: block.internal_faces(),
+            shape: block.shape(),
+            is_double_sided: block.is_double_sided(),
+            is_fluid: block.is_fluid(),
             face_lighting,
         }
     }
 
-    pub const fn texture(&self) -> Option<&Either<TextureID, [TextureID; 3]>> {
+    pub const fn texture(&self) -> Option<&BlockTextures<TextureID>> {
         self.texture.as_ref()
     }
 
@@ -41,11 +49,47 @@ impl Voxel {
     pub const fn is_transparent(&self) -> bool {
         self.is_transparent
     }
+
+    pub const fn is_light_source(&self) -> bool {
+        self.is_light_source
+    }
+
+    pub const fn internal_faces(&self) -> InternalFaces {
+        self.internal_faces
+    }
+
+    pub const fn shape(&self) -> BlockShape {
+        self.shape
+    }
+
+    pub const fn is_double_sided(&self) -> bool {
+        self.is_double_sided
+    }
+
+    pub const fn is_fluid(&self) -> bool {
+        self.is_fluid
+    }
+
+    // Only full cubes, partial blocks are left out of greedy meshing
+    pub fn is_translucent(&self) -> bool {
+        self.is_transparent() && self.is_renderer() && self.shape.is_full()
+    }
+
+    // Whether the face towards `neighbour` is meshed, where greedy meshing skips faces between two translucent voxels
+    pub fn shows_internal_face(&self, neighbour: &Voxel) -> bool {
+        self.is_translucent()
+            && neighbour.is_translucent()
+            && match self.internal_faces {
+                InternalFaces::Hidden => false,
+                InternalFaces::DifferentBlocks => self.texture != neighbour.texture,
+                InternalFaces::Always => true,
+            }
+    }
 }
 
 impl block_mesh::Voxel for Voxel {
     fn get_visibility(&self) -> block_mesh::VoxelVisibility {
-        if self.is_transparent() && self.is_renderer() {
+        if self.is_translucent() {
             block_mesh::VoxelVisibility::Translucent
         } else if self.is_opaque() {
             block_mesh::VoxelVisibility::Opaque
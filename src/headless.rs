@@ -0,0 +1,95 @@
+use std::num::NonZeroI32;
+
+use cgmath::Vector3;
+use instant::Duration;
+
+use crate::{
+    engine::TextureAtlas,
+    game::world::{BlockManager, ChunkRequestKind, Terrain, TerrainConfig, TerrainGeneratorKind},
+    misc::{pos::add_non_zero_i32_vector3, Settings},
+};
+
+// Seconds each step may take for all chunks before the benchmark gives up
+const STEP_TIMEOUT: u64 = 300;
+
+// Generates the chunks up to render_distance chunks around the origin on a terrain without a renderer, frames times
+// over on a fresh terrain each, and prints how long each step took. Vertically the default render distances are used.
+// Every step only starts once the one before is done for all chunks, and reaches one chunk further out than the
+// next, as lights need the light caches of all chunks around them and those need all blocks around them
+pub fn run_headless(seed: u32, frames: u32, render_distance: u32) {
+    let block_manager = BlockManager::new();
+    let texture_atlas = pollster::block_on(TextureAtlas::new(
+        block_manager.all_texture_names(),
+        block_manager.texture_animations(),
+        &"texture",
+    ));
+    let settings = Settings::default();
+
+    let steps = [
+        (ChunkRequestKind::Blocks, 3),
+        (ChunkRequestKind::LightPosCache, 2),
+        (ChunkRequestKind::Light, 1),
+        (ChunkRequestKind::Mesh, 0),
+    ];
+    let mut totals = [Duration::ZERO; 4];
+
+    for frame in 0..frames {
+        let mut terrain = Terrain::new(
+            true,
+            &texture_atlas,
+            seed,
+            block_manager.clone(),
+            TerrainConfig::load(),
+            TerrainGeneratorKind::Normal,
+        );
+
+        for ((kind, margin), total) in steps.iter().zip(totals.iter_mut()) {
+            let chunk_positions = chunks_around(
+                render_distance + margin,
+                settings.render_distance_up + margin,
+                settings.render_distance_down + margin,
+            );
+
+            match terrain.generate_step(*kind, &chunk_positions, Duration::from_secs(STEP_TIMEOUT)) {
+                Ok(took) => {
+                    println!(
+                        "Frame {}: {:?} for {} chunks took {:.1} ms",
+                        frame + 1,
+                        kind,
+                        chunk_positions.len(),
+                        took.as_secs_f64() * 1000.0
+                    );
+                    *total += took;
+                }
+                Err(()) => {
+                    println!("Frame {}: {:?} timed out", frame + 1, kind);
+                    return;
+                }
+            }
+        }
+    }
+
+    for ((kind, _), total) in steps.iter().zip(totals) {
+        println!(
+            "Average {:?}: {:.1} ms",
+            kind,
+            total.as_secs_f64() * 1000.0 / frames.max(1) as f64
+        );
+    }
+}
+
+fn chunks_around(horizontal: u32, up: u32, down: u32) -> Vec<Vector3<NonZeroI32>> {
+    let one = NonZeroI32::new(1).unwrap();
+    let origin = Vector3::new(one, one, one);
+    let horizontal = horizontal as i32;
+
+    let mut out = Vec::new();
+    for x in -horizontal..=horizontal {
+        for y in -(down as i32)..=up as i32 {
+            for z in -horizontal..=horizontal {
+                out.push(add_non_zero_i32_vector3(origin, Vector3::new(x, y, z)));
+            }
+        }
+    }
+    out
+}
@@ -1,17 +1,26 @@
+// Failures are logged where they happen, callers only need to know that something failed
+#![allow(clippy::result_unit_err)]
+
+pub mod embed;
 mod engine;
 mod game;
+#[cfg(feature = "headless")]
+mod headless;
 mod misc;
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-#[cfg(any(not(feature = "portable"), feature = "save_system"))]
+#[cfg(any(not(feature = "portable"), feature = "save_system", not(target_arch = "wasm32")))]
 use std::{env, path::PathBuf};
 
 use cfg_if::cfg_if;
 use cgmath::{Deg, Rad};
-#[cfg(any(not(feature = "portable"), feature = "save_system"))]
+#[cfg(any(not(feature = "portable"), feature = "save_system", not(target_arch = "wasm32")))]
 use lazy_static::lazy_static;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -22,22 +31,43 @@ use winit::{
     window::{self, Icon},
 };
 
+#[cfg(all(feature = "request_log", not(target_arch = "wasm32")))]
+use crate::game::world::{load_request_log, save_request_log};
 #[cfg(target_arch = "wasm32")]
 use crate::misc::wasm;
 use crate::{
     engine::{resource::Vertex, Renderer},
     game::{
-        world::{BlockManager, BlockVertex},
+        world::{BlockManager, BlockVertex, TerrainGeneratorKind},
         State,
     },
-    misc::{loader::load_resource_binary, ui::UI, Settings},
+    misc::{
+        loader::load_resource_binary,
+        ui::{block_icon, BlockIcons, FrameInfo, MenuState, MINIMAP_RADIUS, UI},
+        Settings, RENDER_DISTANCE_HORIZONTAL_RANGE,
+    },
 };
 
 #[cfg(all(target_arch = "wasm32", feature = "save_system"))]
 compile_error!("feature \"save_system\" cannot be used on wasm");
+#[cfg(all(target_arch = "wasm32", feature = "headless"))]
+compile_error!("feature \"headless\" cannot be used on wasm");
+
+pub use embed::Game;
+#[cfg(feature = "headless")]
+pub use headless::run_headless;
 
 pub const TITLE: &'static str = "Rezcraft";
 const FPS_UPDATE_INTERVAL: f64 = 0.1;
+// Number of recent frames the 1% low and max frametime are taken from
+const FRAME_TIME_WINDOW: usize = 1000;
+// Seconds a message from a hotkey stays in the hud
+const HUD_MESSAGE_DURATION: f64 = 2.0;
+// Seconds between updates of the minimap, searching every column for its surface is too slow for every frame
+const MINIMAP_UPDATE_INTERVAL: f64 = 0.5;
+// Seconds a replayed chunk request may wait on the results it depends on
+#[cfg(all(feature = "request_log", not(target_arch = "wasm32")))]
+const REPLAY_TIMEOUT: u64 = 10;
 
 #[cfg(feature = "portable")]
 pub static RESOURCE_DIR: include_dir::Dir<'_> = include_dir::include_dir!("$CARGO_MANIFEST_DIR/res");
@@ -60,6 +90,15 @@ lazy_static! {
     };
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+lazy_static! {
+    pub static ref EXPORTS_PATH: PathBuf = if let Ok(var) = env::var("EXPORTS_PATH") {
+        PathBuf::from(var)
+    } else {
+        PathBuf::from("./exports")
+    };
+}
+
 #[cfg(target_arch = "wasm32")]
 mod wasm_main {
     use wasm_bindgen::prelude::*;
@@ -126,28 +165,102 @@ pub async fn do_run() {
     }
 
     let block_manager = BlockManager::new();
-    let mut selected_block_template = block_manager.all_rendered_block_names()[0].to_owned();
+    let mut menu = MenuState {
+        selected_block_template: block_manager.all_rendered_block_names()[0].to_owned(),
+        ..Default::default()
+    };
 
     let mut settings = Settings::load_from_file();
     let mut renderer = Renderer::<crate::game::Projection>::new(
         window,
         BlockVertex::desc(),
         block_manager.all_texture_names(),
+        block_manager.texture_animations(),
         &"texture",
         &settings,
     )
     .await;
-    let mut game_state = State::new(renderer.texture_atlas(), block_manager, false);
+    let mut game_state = State::new(
+        renderer.texture_atlas(),
+        block_manager,
+        settings.continue_last_world,
+        TerrainGeneratorKind::Normal,
+    );
+    // Developer tool, RECORD_REQUESTS records chunk requests into the file it names on exit and REPLAY_REQUESTS replays
+    // the file it names on start
+    #[cfg(all(feature = "request_log", not(target_arch = "wasm32")))]
+    {
+        if let Ok(path) = std::env::var("REPLAY_REQUESTS") {
+            if let Some(request_log) = load_request_log(path) {
+                match game_state.replay_requests(&request_log, instant::Duration::from_secs(REPLAY_TIMEOUT)) {
+                    Ok(()) => log::info!("Replayed all chunk requests"),
+                    Err(()) => log::warn!("Stopped replaying chunk requests"),
+                }
+            }
+        }
+        if std::env::var("RECORD_REQUESTS").is_ok() {
+            game_state.start_request_log();
+        }
+    }
+    let mut hud_message: Option<(String, instant::Instant)> = None;
+    let mut last_minimap_update: Option<instant::Instant> = None;
+    // Kept across frames, so changes from hotkeys or a settings reload are noticed as well as ones from the menu
+    let mut last_render_distance = (
+        settings.render_distance_horizontal,
+        settings.render_distance_up,
+        settings.render_distance_down,
+    );
+
+    #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+    let mut tracing_guard = Some(crate::misc::profiling::init_tracing());
 
     let mut last_render_time = instant::Instant::now();
     let (mut dt_fps_sum, mut dt_fps, mut dt_frames_occured) = (0.0, 0.0, 0);
+    let mut recent_frame_times = VecDeque::with_capacity(FRAME_TIME_WINDOW);
+    let (mut dt_one_percent_low, mut dt_max) = (0.0, 0.0);
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
+        // With an fps cap, sleep until the next frame is due instead of polling
+        let next_frame = settings
+            .max_fps
+            .map(|max_fps| last_render_time + instant::Duration::from_secs_f64(1.0 / max_fps.max(1) as f64));
+        *control_flow = match next_frame {
+            Some(next_frame) => ControlFlow::WaitUntil(next_frame),
+            None => ControlFlow::Poll,
+        };
         renderer.egui_platform_mut().handle_event(&event);
 
+        // The key pressed after clicking an action in the settings is bound to it, escape cancels
+        if let (
+            Some(action),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            },
+        ) = (menu.rebinding, &event)
+        {
+            if *key != VirtualKeyCode::Escape {
+                *settings.keybinds.key_mut(action) = *key;
+            }
+            menu.rebinding = None;
+            return;
+        }
+
         match event {
-            Event::MainEventsCleared => renderer.window().request_redraw(),
+            Event::MainEventsCleared if next_frame.is_none_or(|next_frame| instant::Instant::now() >= next_frame) => {
+                renderer.window().request_redraw()
+            }
+            #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+            Event::LoopDestroyed => drop(tracing_guard.take()),
             Event::DeviceEvent {
                 event: DeviceEvent::MouseMotion { delta },
                 ..
@@ -162,12 +275,12 @@ pub async fn do_run() {
                         cfg_if! {
                             if #[cfg(target_arch = "wasm32")] {
                                 if wasm::is_pointer_locked() {
-                                    game_state.input(event)
+                                    game_state.input(event, &settings.keybinds)
                                 } else {
                                     false
                                 }
                             } else {
-                                game_state.input(event)
+                                game_state.input(event, &settings.keybinds)
                             }
                         }
                     } else {
@@ -187,6 +300,12 @@ pub async fn do_run() {
                         ..
                     } => {
                         settings.save();
+                        #[cfg(feature = "request_log")]
+                        if let (Ok(path), Some(request_log)) =
+                            (std::env::var("RECORD_REQUESTS"), game_state.take_request_log())
+                        {
+                            let _ = save_request_log(path, &request_log);
+                        }
 
                         *control_flow = ControlFlow::Exit
                     }
@@ -207,6 +326,45 @@ pub async fn do_run() {
                             renderer.window().set_cursor_grab(window::CursorGrabMode::None).ok();
                         }
                     }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F4),
+                                ..
+                            },
+                        ..
+                    } => renderer.toggle_depth_view(),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode:
+                                    Some(
+                                        key @ (VirtualKeyCode::Equals
+                                        | VirtualKeyCode::NumpadAdd
+                                        | VirtualKeyCode::Minus
+                                        | VirtualKeyCode::NumpadSubtract),
+                                    ),
+                                ..
+                            },
+                        ..
+                    } if running.load(Ordering::Relaxed) => {
+                        settings.render_distance_horizontal =
+                            if matches!(key, VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) {
+                                settings.render_distance_horizontal.saturating_sub(1)
+                            } else {
+                                settings.render_distance_horizontal + 1
+                            }
+                            .clamp(
+                                *RENDER_DISTANCE_HORIZONTAL_RANGE.start(),
+                                *RENDER_DISTANCE_HORIZONTAL_RANGE.end(),
+                            );
+                        hud_message = Some((
+                            format!("Render distance {}", settings.render_distance_horizontal),
+                            instant::Instant::now(),
+                        ));
+                    }
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
@@ -228,11 +386,11 @@ pub async fn do_run() {
                         input:
                             KeyboardInput {
                                 state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                virtual_keycode: Some(key),
                                 ..
                             },
                         ..
-                    } => {
+                    } if *key == settings.keybinds.pause => {
                         settings.save();
 
                         running.store(running.load(Ordering::Relaxed) ^ true, Ordering::Relaxed);
@@ -255,26 +413,40 @@ pub async fn do_run() {
                             },
                         ..
                     } => settings.reload(),
+                    #[cfg(not(target_arch = "wasm32"))]
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } if *key == settings.keybinds.export => {
+                        if let Ok(path) = game_state.export_obj(renderer.texture_atlas()) {
+                            hud_message = Some((format!("Exported to {}", path.display()), instant::Instant::now()));
+                        }
+                    }
                     #[cfg(feature = "save_system")]
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
                                 state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::F5),
+                                virtual_keycode: Some(key),
                                 ..
                             },
                         ..
-                    } => game_state.save(),
+                    } if *key == settings.keybinds.save => game_state.save(),
                     #[cfg(feature = "save_system")]
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
                                 state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                virtual_keycode: Some(key),
                                 ..
                             },
                         ..
-                    } => game_state.load(),
+                    } if *key == settings.keybinds.load => game_state.load(),
                     _ => {}
                 }
             }
@@ -303,60 +475,123 @@ pub async fn do_run() {
                     if dt_fps_sum >= FPS_UPDATE_INTERVAL {
                         dt_fps = dt_fps_sum / dt_frames_occured as f64;
 
+                        let mut sorted_frame_times = Vec::from_iter(recent_frame_times.iter().copied());
+                        sorted_frame_times.sort_by(f64::total_cmp);
+                        dt_one_percent_low = sorted_frame_times
+                            .get(sorted_frame_times.len() - sorted_frame_times.len().div_ceil(100))
+                            .copied()
+                            .unwrap_or(0.0);
+                        dt_max = sorted_frame_times.last().copied().unwrap_or(0.0);
+
                         dt_fps_sum = 0.0;
                         dt_frames_occured = 0;
                     }
 
                     dt_fps_sum += dt.as_secs_f64();
                     dt_frames_occured += 1;
+
+                    if recent_frame_times.len() == FRAME_TIME_WINDOW {
+                        recent_frame_times.pop_front();
+                    }
+                    recent_frame_times.push_back(dt.as_secs_f64());
                 }
 
                 game_state.update(running.load(Ordering::Relaxed), dt, &settings);
-                renderer.update(game_state.camera(), &settings);
+                if let Some(message) = game_state.take_hud_message() {
+                    hud_message = Some((message, instant::Instant::now()));
+                }
+                let sky_color = game_state.sky_color(&settings);
+                renderer.update(
+                    game_state.camera(),
+                    &settings,
+                    game_state.current_sunlight_intensity(&settings),
+                    game_state.sun_color(&settings),
+                    sky_color,
+                    game_state.sky_zenith_color(&settings),
+                    game_state.cloud_layer(&settings),
+                );
 
                 let settings_clone = settings.clone();
                 let mut selected_block = game_state.selected_block_mut().clone();
+                let mut palette = game_state.palette_mut().clone();
+                let mut game_rules = game_state.game_rules().clone();
+                let mut time_of_day = game_state.time_of_day();
 
-                let mut selected_save = {
-                    #[cfg(feature = "save_system")]
-                    {
-                        game_state.selected_save()
-                    }
-                    #[cfg(not(feature = "save_system"))]
-                    {
-                        "".to_string()
-                    }
-                };
+                #[cfg(feature = "save_system")]
+                {
+                    menu.selected_save = game_state.selected_save();
+                    menu.save_error = game_state.save_error();
+                }
 
-                let (mut do_save, mut do_load) = (false, false);
-                let (last_vertical_fov, last_render_distance) = (
-                    settings.vertical_fov,
-                    (settings.render_distance_horizontal, settings.render_distance_vertical),
-                );
+                let last_vertical_fov = settings.vertical_fov;
+                let minimap_colors = if settings.show_minimap
+                    && last_minimap_update
+                        .is_none_or(|last_update| last_update.elapsed().as_secs_f64() >= MINIMAP_UPDATE_INTERVAL)
+                {
+                    last_minimap_update = Some(instant::Instant::now());
+                    Some(game_state.surface_colors(MINIMAP_RADIUS, &settings))
+                } else {
+                    None
+                };
+                if !settings.show_minimap {
+                    // Shown up to date right away when turned back on
+                    last_minimap_update = None;
+                }
+                let block_icons = BlockIcons {
+                    atlas_texture_id: renderer.egui_atlas_texture_id(),
+                    hotbar: game_state
+                        .player()
+                        .hotbar
+                        .each_ref()
+                        .map(|block| block_icon(block, game_state.texture_atlas())),
+                    template: game_state
+                        .block_manager()
+                        .get(&menu.selected_block_template)
+                        .and_then(|block| block_icon(block, game_state.texture_atlas())),
+                };
 
                 let mut ui = UI::new(
-                    running.clone(),
-                    dt_fps,
-                    game_state.player().clone(),
+                    FrameInfo {
+                        running: running.clone(),
+                        elapsed_secs: dt_fps,
+                        frame_time_spikes: (dt_one_percent_low, dt_max),
+                        player: game_state.player().clone(),
+                        block_manager: game_state.block_manager(),
+                        loading_chunks: game_state.loading_chunks(),
+                        saving_chunks: game_state.saving_chunks(),
+                        light_capped_chunks: game_state.light_capped_chunks(),
+                        skipped_chunk_requests: game_state.skipped_chunk_requests(),
+                        remeshes: game_state.remeshes(),
+                        buffer_allocations: game_state.buffer_allocations(),
+                        render_stats: renderer.render_stats(),
+                        loading_frozen: game_state.loading_frozen(),
+                        seed: game_state.seed(),
+                        hud_message: hud_message
+                            .as_ref()
+                            .filter(|(_, shown_at)| shown_at.elapsed().as_secs_f64() < HUD_MESSAGE_DURATION)
+                            .map(|(message, _)| message.as_str()),
+                        block_icons,
+                        minimap_colors,
+                    },
                     &mut settings,
                     &mut selected_block,
-                    &mut selected_block_template,
-                    game_state.block_manager(),
-                    game_state.loading_chunks(),
-                    game_state.saving_chunks(),
-                    &mut selected_save,
-                    &mut do_save,
-                    &mut do_load,
+                    &mut palette,
+                    &mut game_rules,
+                    &mut time_of_day,
+                    &mut menu,
                 );
 
-                let to_render = game_state.meshes_to_render(renderer.device(), &settings_clone);
+                let overlays = game_state.overlays(&settings_clone);
+                let ground_plane = game_state.ground_plane(&settings_clone);
+                let (to_render, to_render_transparent, to_render_double_sided) =
+                    game_state.meshes_to_render(renderer.device(), renderer.queue(), &settings_clone);
                 match renderer.render(
                     to_render,
-                    Some((
-                        settings_clone.sky_color[0],
-                        settings_clone.sky_color[1],
-                        settings_clone.sky_color[2],
-                    )),
+                    to_render_transparent,
+                    to_render_double_sided,
+                    Some((sky_color[0], sky_color[1], sky_color[2])),
+                    &overlays,
+                    ground_plane,
                     &mut ui,
                 ) {
                     Ok(_) => {}
@@ -368,21 +603,57 @@ pub async fn do_run() {
                 if settings.vertical_fov != last_vertical_fov {
                     renderer.set_vfov(Rad::from(Deg(settings.vertical_fov)))
                 }
-                if (settings.render_distance_horizontal, settings.render_distance_vertical) != last_render_distance {
+                // A larger render distance still needs every chunk that was requested, so requests are only cancelled
+                // when it shrinks and chunks out of range shouldn't keep the generation threads busy
+                let render_distance = (
+                    settings.render_distance_horizontal,
+                    settings.render_distance_up,
+                    settings.render_distance_down,
+                );
+                if render_distance.0 < last_render_distance.0
+                    || render_distance.1 < last_render_distance.1
+                    || render_distance.2 < last_render_distance.2
+                {
                     game_state.cancel_requests()
                 }
+                last_render_distance = render_distance;
 
                 *game_state.selected_block_mut() = selected_block;
+                *game_state.palette_mut() = palette;
+                game_state.set_game_rules(game_rules);
+                game_state.set_time_of_day(time_of_day);
+                #[cfg(not(target_arch = "wasm32"))]
+                if std::mem::take(&mut menu.do_paste_structure) {
+                    game_state.paste_structure(&menu.structure_path);
+                }
 
                 #[cfg(feature = "save_system")]
                 {
-                    game_state.set_selected_save(selected_save);
+                    game_state.set_save_error(menu.save_error.take());
 
-                    if do_save {
+                    if std::mem::take(&mut menu.do_load) {
+                        let _ = game_state.switch_save(menu.selected_save.clone());
+                    } else {
+                        game_state.set_selected_save(menu.selected_save.clone());
+                    }
+                    if std::mem::take(&mut menu.do_save) {
                         game_state.save();
                     }
-                    if do_load {
-                        game_state.load();
+                    if std::mem::take(&mut menu.do_delete_save) {
+                        game_state.delete_save();
+                    }
+                    if std::mem::take(&mut menu.do_export_palette) {
+                        game_state.export_palette(&menu.palette_name);
+                    }
+                    if std::mem::take(&mut menu.do_import_palette) {
+                        game_state.import_palette(&menu.palette_name);
+                    }
+                    if std::mem::take(&mut menu.do_new_world) {
+                        game_state.new_world(if menu.new_world_flat {
+                            TerrainGeneratorKind::flat()
+                        } else {
+                            TerrainGeneratorKind::Normal
+                        });
                     }
                 }
             }
@@ -41,6 +41,42 @@ pub fn load_resource_string(path: impl AsRef<Path>) -> Result<String, ()> {
     }
 }
 
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// Fetches a resource from an absolute URL, so web builds can load resource packs that are not embedded
+pub async fn load_url_binary(url: &str) -> Result<Vec<u8>, ()> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let response = JsFuture::from(crate::misc::wasm::window().fetch_with_str(url))
+            .await
+            .map_err(|e| log::error!("Failed fetching {url:?} - {e:?}"))?
+            .dyn_into::<web_sys::Response>()
+            .map_err(|_| ())?;
+
+        if !response.ok() {
+            log::error!("Failed fetching {url:?} - status {}", response.status());
+            return Err(());
+        }
+
+        let buffer = JsFuture::from(response.array_buffer().map_err(|_| ())?)
+            .await
+            .map_err(|e| log::error!("Failed reading response from {url:?} - {e:?}"))?;
+
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        log::error!("Loading {url:?} failed, loading from URLs is only supported on wasm");
+        Err(())
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn load_string(path: impl AsRef<Path>) -> Result<String, ()> {
     if let Ok(txt) = std::fs::read_to_string(path) {
@@ -1,11 +1,14 @@
 pub mod index;
 pub mod loader;
 pub mod pos;
+pub mod profiling;
 #[cfg(feature = "save_system")]
 pub mod save_helper;
 mod settings;
 pub mod ui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vox;
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
-pub use settings::Settings;
+pub use settings::{Keybinds, PresentMode, Settings, RENDER_DISTANCE_HORIZONTAL_RANGE};
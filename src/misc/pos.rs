@@ -98,6 +98,59 @@ impl Pos {
                 },
         ) - Vector3::new(CHUNK_SIZE as f64, CHUNK_SIZE as f64, CHUNK_SIZE as f64)
     }
+
+    // Absolute coordinate of the block the position is in, the block at 0 is the first one of chunk 1
+    pub fn abs_block_pos(&self) -> Vector3<i64> {
+        let axis = |chunk: NonZeroI32, in_chunk: i32| {
+            let chunk = i32::from(chunk) as i64;
+            (if chunk > 0 { chunk - 1 } else { chunk }) * CHUNK_SIZE as i64 + in_chunk as i64
+        };
+
+        let in_chunk_pos = self.in_chunk_pos_i32();
+        Vector3::new(
+            axis(self.chunk_pos.x, in_chunk_pos.x),
+            axis(self.chunk_pos.y, in_chunk_pos.y),
+            axis(self.chunk_pos.z, in_chunk_pos.z),
+        )
+    }
+
+    // Inverse of `abs_block_pos`, at the lower corner of the block. None for blocks past the last chunk
+    pub fn from_abs_block(abs_block_pos: Vector3<i64>) -> Option<Self> {
+        let split = |coord: i64| {
+            let chunk = coord.div_euclid(CHUNK_SIZE as i64);
+            i32::try_from(if chunk >= 0 { chunk + 1 } else { chunk })
+                .ok()
+                .and_then(NonZeroI32::new)
+                .map(|chunk| (chunk, coord.rem_euclid(CHUNK_SIZE as i64) as f32))
+        };
+
+        let ((chunk_x, x), (chunk_y, y), (chunk_z, z)) = (
+            split(abs_block_pos.x)?,
+            split(abs_block_pos.y)?,
+            split(abs_block_pos.z)?,
+        );
+        Some(Self::new(
+            Vector3::new(chunk_x, chunk_y, chunk_z),
+            Vector3::new(x, y, z),
+        ))
+    }
+
+    // Inverse of `abs_pos`, chunk 1 starts at 0 and chunk -1 ends right before it
+    pub fn from_abs(abs_pos: Vector3<f64>) -> Self {
+        let split = |coord: f64| {
+            let chunk = (coord / CHUNK_SIZE as f64).floor();
+            (
+                NonZeroI32::new(if chunk >= 0.0 { chunk as i32 + 1 } else { chunk as i32 }).unwrap(),
+                (coord - chunk * CHUNK_SIZE as f64) as f32,
+            )
+        };
+
+        let ((chunk_x, x), (chunk_y, y), (chunk_z, z)) = (split(abs_pos.x), split(abs_pos.y), split(abs_pos.z));
+        let mut pos = Self::new(Vector3::new(chunk_x, chunk_y, chunk_z), Vector3::new(x, y, z));
+        // Rounding to f32 can land exactly on the end of the chunk
+        pos.check_in_chunk_overflow();
+        pos
+    }
 }
 
 #[inline]
@@ -120,3 +173,86 @@ pub fn add_non_zero_i32_vector3(vec1: Vector3<NonZeroI32>, vec2: Vector3<i32>) -
         add_to_non_zero_i32(vec1.z, vec2.z),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i64 = CHUNK_SIZE as i64;
+
+    fn chunk(chunk: i32) -> NonZeroI32 {
+        NonZeroI32::new(chunk).unwrap()
+    }
+
+    #[test]
+    fn from_abs_block_at_chunk_borders() {
+        for (coord, chunk_pos, in_chunk_pos) in [
+            (0, 1, 0),
+            (SIZE - 1, 1, SIZE - 1),
+            (SIZE, 2, 0),
+            (-1, -1, SIZE - 1),
+            (-SIZE, -1, 0),
+            (-SIZE - 1, -2, SIZE - 1),
+        ] {
+            let pos = Pos::from_abs_block(Vector3::new(coord, -coord - 1, coord)).unwrap();
+            assert_eq!(pos.chunk_pos.x, chunk(chunk_pos), "chunk of {coord}");
+            assert_eq!(pos.in_chunk_pos.x, in_chunk_pos as f32, "in chunk position of {coord}");
+            // The block mirrored around the border between chunk -1 and 1
+            assert_eq!(pos.chunk_pos.y, chunk(-chunk_pos));
+            assert_eq!(pos.in_chunk_pos.y, (SIZE - 1 - in_chunk_pos) as f32);
+        }
+    }
+
+    #[test]
+    fn abs_block_pos_round_trip() {
+        for coord in
+            (-3 * SIZE - 2..=3 * SIZE + 2).chain([i32::MAX as i64 * SIZE - 1, (i32::MIN as i64 + 1) * SIZE, -1_000_003])
+        {
+            let abs_block_pos = Vector3::new(coord, -coord - 1, coord / 3);
+            let pos = Pos::from_abs_block(abs_block_pos).unwrap();
+
+            assert_eq!(pos.abs_block_pos(), abs_block_pos);
+            assert_eq!(pos.abs_pos(), abs_block_pos.map(|coord| coord as f64));
+            assert_eq!(pos.in_chunk_pos_i32().map(|coord| coord as f32), pos.in_chunk_pos);
+        }
+
+        // Past the last chunk in either direction
+        assert_eq!(Pos::from_abs_block(Vector3::new(i32::MAX as i64 * SIZE, 0, 0)), None);
+        assert_eq!(
+            Pos::from_abs_block(Vector3::new(0, 0, i32::MIN as i64 * SIZE - 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn from_abs_round_trip() {
+        let size = SIZE as f64;
+        for coord in [
+            0.0,
+            0.25,
+            size - 0.5,
+            size,
+            size + 0.125,
+            -0.25,
+            -size,
+            -size - 0.75,
+            -3.0 * size + 0.5,
+            12345.5,
+            -12345.5,
+        ] {
+            let abs_pos = Vector3::new(coord, -coord, coord * 2.0);
+            let pos = Pos::from_abs(abs_pos);
+
+            assert_eq!(pos.abs_pos(), abs_pos, "position {abs_pos:?}");
+            assert_eq!(pos.abs_block_pos(), abs_pos.map(|coord| coord.floor() as i64));
+            for axis in 0..3 {
+                assert!((0.0..CHUNK_SIZE as f32).contains(&pos.in_chunk_pos[axis]));
+            }
+        }
+
+        // Just below a chunk border rounds onto it in f32 and has to move into the next chunk
+        let pos = Pos::from_abs(Vector3::new(-1e-9, 0.0, 0.0));
+        assert!((0.0..CHUNK_SIZE as f32).contains(&pos.in_chunk_pos.x));
+        assert!(pos.abs_pos().x.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,21 @@
+// Enters a tracing span for the rest of the scope with the tracing feature, otherwise expands to nothing
+macro_rules! profile_span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name).entered();
+    };
+}
+
+pub(crate) use profile_span;
+
+// The returned guard has to be dropped before exiting, which writes a trace-<timestamp>.json viewable in
+// chrome://tracing or Perfetto
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+pub fn init_tracing() -> tracing_chrome::FlushGuard {
+    use tracing_subscriber::prelude::*;
+
+    let (chrome_layer, guard) = tracing_chrome::ChromeLayerBuilder::new().build();
+    tracing_subscriber::registry().with(chrome_layer).init();
+
+    guard
+}
@@ -1,22 +1,78 @@
 use std::{
     collections::BTreeSet,
     fs::File,
-    io::Write,
+    io::{Read, Write},
     sync::{
         atomic::{AtomicU32, Ordering},
         Arc,
     },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use cfg_if::cfg_if;
-use serde::Serialize;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use image::RgbImage;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    game::{world::BlockBuffer, Player},
-    misc::loader::{load_binary, load_string},
+    game::{
+        time_of_day,
+        world::{Block, BlockBuffer, BlockManager, TerrainConfig, TerrainGenerator, TerrainGeneratorKind},
+        GameRules, Player,
+    },
+    misc::{
+        loader::{load_binary, load_string},
+        profiling::profile_span,
+    },
     SAVES_PATH,
 };
 
+// Binary saves are gzip compressed, files without it are from before and still load
+const COMPRESSED_EXTENSION: &str = ".cbor.gz";
+const UNCOMPRESSED_EXTENSION: &str = ".cbor";
+
+// Bumped whenever the save layout changes, so older saves can be told apart and migrated. Saves without world.yaml
+// are from before versioning and count as version 0
+pub const WORLD_FORMAT_VERSION: u32 = 1;
+pub const UNVERSIONED_FORMAT_VERSION: u32 = 0;
+
+// Saved as world.yaml, timestamps are seconds since the unix epoch
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldMeta {
+    pub format_version: u32,
+    pub created: u64,
+    pub last_played: u64,
+    pub seed: u32,
+    pub generator_kind: TerrainGeneratorKind,
+}
+
+impl WorldMeta {
+    // Keeps the creation time of the save if it already has metadata. The format version is the one of the chunks
+    // in the save, chunks that were never loaded are not rewritten so an old save stays old
+    pub fn new(save_name: impl ToString, format_version: u32, seed: u32, generator_kind: TerrainGeneratorKind) -> Self {
+        let now = unix_time();
+
+        Self {
+            format_version,
+            created: world_meta(save_name).map_or(now, |world_meta| world_meta.created),
+            last_played: now,
+            seed,
+            generator_kind,
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Pixels of a world preview along each side and blocks between them
+const PREVIEW_SIZE: u32 = 128;
+const PREVIEW_STEP: u32 = 8;
+
 pub fn available_saves() -> BTreeSet<String> {
     match std::fs::read_dir(&*SAVES_PATH) {
         Ok(paths) => paths
@@ -43,15 +99,27 @@ pub fn available_saves() -> BTreeSet<String> {
     }
 }
 
+// Removes the save with everything in it
+pub fn delete_save(save_name: &str) -> std::io::Result<()> {
+    if save_name.is_empty() || save_name.contains(['/', '\\']) || save_name == "." || save_name == ".." {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{:?} is not a save name", save_name),
+        ));
+    }
+
+    std::fs::remove_dir_all(SAVES_PATH.join(save_name))
+}
+
 pub fn save(save_name: impl ToString, file_name: impl ToString, object: &impl Serialize, save_as_bytes: bool) {
     let path = SAVES_PATH
         .join(save_name.to_string())
-        .join(file_name.to_string() + if !save_as_bytes { ".yaml" } else { ".cbor" });
+        .join(file_name.to_string() + if !save_as_bytes { ".yaml" } else { COMPRESSED_EXTENSION });
 
     match File::create(path.clone()) {
         Ok(mut file) => {
             if save_as_bytes {
-                if let Err(e) = ciborium::into_writer(object, file) {
+                if let Err(e) = write_compressed(object, file) {
                     log::warn!("Failed serializing and writing to file {} - {}", path.display(), e)
                 }
             } else {
@@ -72,7 +140,7 @@ pub fn save(save_name: impl ToString, file_name: impl ToString, object: &impl Se
             match File::create(path.clone()) {
                 Ok(mut file) => {
                     if save_as_bytes {
-                        if let Err(e) = ciborium::into_writer(object, file) {
+                        if let Err(e) = write_compressed(object, file) {
                             log::warn!("Failed serializing and writing to file {} - {}", path.display(), e)
                         }
                     } else {
@@ -95,12 +163,68 @@ pub fn save(save_name: impl ToString, file_name: impl ToString, object: &impl Se
     }
 }
 
+fn write_compressed(object: &impl Serialize, file: File) -> Result<(), String> {
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    ciborium::into_writer(object, &mut encoder).map_err(|e| e.to_string())?;
+    encoder.finish().map(|_| ()).map_err(|e| e.to_string())
+}
+
+// Thread pool the chunk saver writes files on, kept between batches and only rebuilt when the thread limit changes
+#[derive(Default)]
+pub struct SaveThreadPool {
+    #[cfg(feature = "rayon")]
+    thread_pool: Option<(usize, rayon::ThreadPool)>,
+}
+
+impl SaveThreadPool {
+    #[cfg(feature = "rayon")]
+    fn get(&mut self, max_threads: usize) -> Option<&rayon::ThreadPool> {
+        if !matches!(&self.thread_pool, Some((num_threads, _)) if *num_threads == max_threads) {
+            self.thread_pool = match rayon::ThreadPoolBuilder::new().num_threads(max_threads).build() {
+                Ok(thread_pool) => Some((max_threads, thread_pool)),
+                Err(e) => {
+                    log::warn!("Failed creating thread pool for saving - {}", e);
+                    None
+                }
+            };
+        }
+
+        self.thread_pool.as_ref().map(|(_, thread_pool)| thread_pool)
+    }
+}
+
+// max_threads limits how many files are written at once so big saves do not saturate the disk, 0 means no limit.
+// Without rayon files are always written one at a time
 pub fn save_many(
     save_name: impl ToString,
     directory_name: impl ToString,
-    objects: Vec<(impl ToString + Sync, impl Serialize + Sync)>,
+    objects: Vec<(impl ToString + Send + Sync, impl Serialize + Send + Sync)>,
+    counter: Option<Arc<AtomicU32>>,
+    thread_pool: &mut SaveThreadPool,
+    max_threads: usize,
+) {
+    let (save_name, directory_name) = (save_name.to_string(), directory_name.to_string());
+
+    cfg_if! {
+        if #[cfg(feature = "rayon")] {
+            match thread_pool.get(max_threads) {
+                Some(thread_pool) => thread_pool.install(move || save_many_limited(save_name, directory_name, objects, counter)),
+                None => save_many_limited(save_name, directory_name, objects, counter),
+            }
+        } else {
+            let _ = (thread_pool, max_threads);
+            save_many_limited(save_name, directory_name, objects, counter)
+        }
+    }
+}
+
+fn save_many_limited(
+    save_name: impl ToString,
+    directory_name: impl ToString,
+    objects: Vec<(impl ToString + Send + Sync, impl Serialize + Send + Sync)>,
     counter: Option<Arc<AtomicU32>>,
 ) {
+    profile_span!("save_many");
     let iterator;
     cfg_if! {
         if #[cfg(feature = "rayon")] {
@@ -137,56 +261,428 @@ pub fn save_many(
     }
 }
 
-pub fn load_player(save_name: impl ToString, file_name: impl ToString) -> Option<Player> {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    Missing,
+    Corrupt(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "file is missing"),
+            LoadError::Corrupt(e) => write!(f, "file is corrupt - {}", e),
+        }
+    }
+}
+
+pub fn save_has_chunks(save_name: impl ToString) -> bool {
+    match std::fs::read_dir(SAVES_PATH.join(save_name.to_string()).join("chunks")) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => false,
+    }
+}
+
+fn load_yaml<T: DeserializeOwned>(save_name: impl ToString, file_name: impl ToString) -> Result<T, LoadError> {
     let path = SAVES_PATH
         .join(save_name.to_string())
         .join(file_name.to_string() + ".yaml");
 
     if let Ok(text) = load_string(&path) {
-        match serde_yaml::from_str(&text) {
-            Ok(player) => Some(player),
-            Err(e) => {
-                log::warn!("Failed deserializing Player from file {} - {}", path.display(), e);
-                None
-            }
+        serde_yaml::from_str(&text).map_err(|e| {
+            log::warn!(
+                "Failed deserializing {} from file {} - {}",
+                std::any::type_name::<T>(),
+                path.display(),
+                e
+            );
+            LoadError::Corrupt(e.to_string())
+        })
+    } else {
+        Err(LoadError::Missing)
+    }
+}
+
+pub fn load_player(save_name: impl ToString, file_name: impl ToString) -> Result<Player, LoadError> {
+    load_yaml(save_name, file_name)
+}
+
+pub fn load_game_rules(save_name: impl ToString, file_name: impl ToString) -> Result<GameRules, LoadError> {
+    load_yaml(save_name, file_name)
+}
+
+pub fn load_time_of_day(save_name: impl ToString, file_name: impl ToString) -> Result<f64, LoadError> {
+    load_yaml(save_name, file_name).map(time_of_day::wrap)
+}
+
+pub fn load_generator_kind(
+    save_name: impl ToString,
+    file_name: impl ToString,
+) -> Result<TerrainGeneratorKind, LoadError> {
+    load_yaml(save_name, file_name)
+}
+
+pub fn load_palette(save_name: impl ToString, file_name: impl ToString) -> Result<Vec<Block>, LoadError> {
+    load_yaml(save_name, file_name)
+}
+
+// format_version is the one of the save the chunk is from, older chunks are migrated before deserializing
+pub fn load_block_buffer(
+    save_name: impl ToString,
+    file_name: impl ToString,
+    format_version: u32,
+) -> Option<BlockBuffer> {
+    profile_span!("load_block_buffer");
+    let path = SAVES_PATH.join(save_name.to_string());
+    let (compressed_path, uncompressed_path) = (
+        path.join(file_name.to_string() + COMPRESSED_EXTENSION),
+        path.join(file_name.to_string() + UNCOMPRESSED_EXTENSION),
+    );
+
+    let (path, bytes) = if let Ok(bytes) = load_binary(&compressed_path) {
+        let mut decompressed = Vec::new();
+        if let Err(e) = GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed) {
+            log::warn!("Failed decompressing file {} - {}", compressed_path.display(), e);
+            return None;
         }
+        (compressed_path, decompressed)
+    } else if let Ok(bytes) = load_binary(&uncompressed_path) {
+        (uncompressed_path, bytes)
     } else {
-        None
+        return None;
+    };
+
+    let block_buffer: Result<BlockBuffer, String> = if format_version < WORLD_FORMAT_VERSION {
+        ciborium::from_reader(bytes.as_slice())
+            .map_err(|e| e.to_string())
+            .and_then(|value| migrate(value, format_version).deserialized().map_err(|e| e.to_string()))
+    } else {
+        ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string())
+    };
+
+    match block_buffer {
+        Ok(mut block_buffer) => {
+            block_buffer.update_opaque_faces();
+            Some(block_buffer)
+        }
+        Err(e) => {
+            log::warn!("Failed deserializing Chunk from file {} - {}", path.display(), e);
+            None
+        }
     }
 }
 
-pub fn load_block_buffer(save_name: impl ToString, file_name: impl ToString) -> Option<BlockBuffer> {
-    let path = SAVES_PATH
-        .join(save_name.to_string())
-        .join(file_name.to_string() + ".cbor");
+// Brings data saved with an older format version up to the current one, one version at a time. Migrations have to
+// leave data that is already in the newer layout untouched, since a save keeps its version while only some of its
+// chunks get rewritten
+fn migrate(mut value: ciborium::Value, from_version: u32) -> ciborium::Value {
+    for version in from_version..WORLD_FORMAT_VERSION {
+        value = match version {
+            0 => migrate_shared_light_strength(value),
+            _ => value,
+        };
+    }
+    value
+}
 
-    if let Ok(bytes) = load_binary(&path) {
-        match ciborium::from_reader(bytes.as_slice()) {
-            Ok(block_buffer) => Some(block_buffer),
-            Err(e) => {
-                log::warn!("Failed deserializing Chunk from file {} - {}", path.display(), e);
-                None
+// Version 0 light sources store which channels are on and one strength shared between them
+fn migrate_shared_light_strength(value: ciborium::Value) -> ciborium::Value {
+    use ciborium::Value;
+
+    match value {
+        Value::Map(entries) => {
+            let field = |name: &str| {
+                entries
+                    .iter()
+                    .find(|(key, _)| key.as_text() == Some(name))
+                    .map(|(_, value)| value)
+            };
+
+            if let (4, Some(red), Some(green), Some(blue), Some(strength)) = (
+                entries.len(),
+                field("red").and_then(Value::as_bool),
+                field("green").and_then(Value::as_bool),
+                field("blue").and_then(Value::as_bool),
+                field("strength").filter(|strength| strength.is_integer()),
+            ) {
+                let channel = |name: &str, on: bool| {
+                    (
+                        Value::Text(name.to_string()),
+                        if on { strength.clone() } else { Value::Integer(0.into()) },
+                    )
+                };
+
+                Value::Map(vec![
+                    channel("strength_red", red),
+                    channel("strength_green", green),
+                    channel("strength_blue", blue),
+                ])
+            } else {
+                Value::Map(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, migrate_shared_light_strength(value)))
+                        .collect(),
+                )
             }
         }
-    } else {
-        None
+        Value::Array(values) => Value::Array(values.into_iter().map(migrate_shared_light_strength).collect()),
+        Value::Tag(tag, value) => Value::Tag(tag, Box::new(migrate_shared_light_strength(*value))),
+        value => value,
     }
 }
 
-pub fn load_u32(save_name: impl ToString, file_name: impl ToString) -> Option<u32> {
-    let path = SAVES_PATH
-        .join(save_name.to_string())
-        .join(file_name.to_string() + ".yaml");
+pub fn load_world_meta(save_name: impl ToString, file_name: impl ToString) -> Result<WorldMeta, LoadError> {
+    load_yaml(save_name, file_name)
+}
 
-    if let Ok(text) = load_string(&path) {
-        match serde_yaml::from_str(&text) {
-            Ok(num) => Some(num),
-            Err(e) => {
-                log::warn!("Failed deserializing u32 from file {} - {}", path.display(), e);
-                None
+// None for saves made before world metadata existed
+pub fn world_meta(save_name: impl ToString) -> Option<WorldMeta> {
+    load_world_meta(save_name, "world").ok()
+}
+
+// The most recently played save, saves without world metadata count as played before all others
+pub fn last_played_save() -> Option<String> {
+    available_saves()
+        .into_iter()
+        .max_by_key(|save_name| world_meta(save_name).map_or(0, |world_meta| world_meta.last_played))
+}
+
+pub fn load_u32(save_name: impl ToString, file_name: impl ToString) -> Result<u32, LoadError> {
+    load_yaml(save_name, file_name)
+}
+
+// Previews are cached in the save under the seed they show, so they never have to be invalidated. Saves without a seed
+// have no preview
+pub fn load_or_create_preview(save_name: impl ToString, block_manager: &BlockManager) -> Option<RgbImage> {
+    let save_name = save_name.to_string();
+    let seed = load_u32(&save_name, "seed").ok()?;
+    let path = SAVES_PATH.join(&save_name).join(format!("preview_{seed}.png"));
+
+    if let Ok(bytes) = load_binary(&path) {
+        match image::load_from_memory(&bytes) {
+            Ok(image) => return Some(image.to_rgb8()),
+            Err(e) => log::warn!("Failed parsing world preview {} - {}", path.display(), e),
+        }
+    }
+
+    let generator_kind = load_generator_kind(&save_name, "generator_kind").unwrap_or_default();
+    let preview = TerrainGenerator::new(seed, block_manager.clone(), TerrainConfig::load(), generator_kind).preview(
+        (0, 0),
+        PREVIEW_SIZE,
+        PREVIEW_STEP,
+    );
+    if let Err(e) = preview.save(&path) {
+        log::warn!("Failed saving world preview {} - {}", path.display(), e)
+    }
+
+    Some(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test writes into its own save, removed again when it is dropped
+    struct TestSave(String);
+
+    impl TestSave {
+        fn new(name: &str) -> Self {
+            let save = Self(format!("test_{}_{}", name, std::process::id()));
+            std::fs::create_dir_all(SAVES_PATH.join(&save.0)).unwrap();
+            save
+        }
+
+        fn write(&self, file_name: &str, contents: &[u8]) {
+            std::fs::write(SAVES_PATH.join(&self.0).join(file_name), contents).unwrap();
+        }
+    }
+
+    impl Drop for TestSave {
+        fn drop(&mut self) {
+            delete_save(&self.0).ok();
+        }
+    }
+
+    fn cbor(object: &impl Serialize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(object, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn truncated_seed_is_corrupt() {
+        let save = TestSave::new("truncated_seed");
+
+        save.write("seed.yaml", b"");
+        assert!(matches!(load_u32(&save.0, "seed"), Err(LoadError::Corrupt(_))));
+
+        save.write("seed.yaml", b"seed: [31415");
+        assert!(matches!(load_u32(&save.0, "seed"), Err(LoadError::Corrupt(_))));
+        assert_eq!(load_or_create_preview(&save.0, &BlockManager::new()), None);
+
+        save.write("seed.yaml", b"31415\n");
+        assert_eq!(load_u32(&save.0, "seed"), Ok(31415));
+    }
+
+    #[test]
+    fn missing_file_is_missing() {
+        let save = TestSave::new("missing_file");
+        assert_eq!(load_u32(&save.0, "seed"), Err(LoadError::Missing));
+    }
+
+    #[test]
+    fn truncated_yaml_is_corrupt() {
+        let save = TestSave::new("truncated_yaml");
+        let world_meta = WorldMeta::new(&save.0, WORLD_FORMAT_VERSION, 7, TerrainGeneratorKind::flat());
+        self::save(&save.0, "world", &world_meta, false);
+        assert_eq!(load_world_meta(&save.0, "world"), Ok(world_meta));
+
+        let text = std::fs::read(SAVES_PATH.join(&save.0).join("world.yaml")).unwrap();
+        save.write("world.yaml", &text[..text.len() / 2]);
+        assert!(matches!(load_world_meta(&save.0, "world"), Err(LoadError::Corrupt(_))));
+    }
+
+    #[test]
+    fn truncated_chunk_is_skipped() {
+        let save = TestSave::new("truncated_chunk");
+        save.write("chunk.cbor.gz", &[0x1f, 0x8b, 0x08]);
+        assert!(load_block_buffer(&save.0, "chunk", WORLD_FORMAT_VERSION).is_none());
+
+        save.write("chunk.cbor", &[0xa1, 0x65]);
+        std::fs::remove_file(SAVES_PATH.join(&save.0).join("chunk.cbor.gz")).unwrap();
+        assert!(load_block_buffer(&save.0, "chunk", WORLD_FORMAT_VERSION).is_none());
+    }
+
+    #[test]
+    fn chunks_are_saved_compressed() {
+        use crate::game::world::CHUNK_SIZE;
+
+        let save = TestSave::new("compressed_chunk");
+        let block_manager = BlockManager::new();
+        let (stone, air) = (
+            Block::new_with_default("Stone", &block_manager),
+            Block::new_with_default("Air", &block_manager),
+        );
+        let blocks = (0..(CHUNK_SIZE as usize).pow(3))
+            .map(|i| {
+                if i % 5 == 0 || i < 1000 {
+                    stone.clone()
+                } else {
+                    air.clone()
+                }
+            })
+            .collect();
+        let block_buffer = BlockBuffer::new(blocks);
+        let serialized = cbor(&block_buffer);
+
+        self::save(&save.0, "chunk", &block_buffer, true);
+        let compressed_path = SAVES_PATH.join(&save.0).join("chunk.cbor.gz");
+        let compressed = std::fs::read(&compressed_path).unwrap();
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert!(compressed.len() < serialized.len());
+        let loaded = load_block_buffer(&save.0, "chunk", WORLD_FORMAT_VERSION).unwrap();
+        assert_eq!(cbor(&loaded), serialized);
+
+        // Saves from before compression only have the uncompressed file
+        std::fs::remove_file(compressed_path).unwrap();
+        save.write("chunk.cbor", &serialized);
+        let loaded = load_block_buffer(&save.0, "chunk", WORLD_FORMAT_VERSION).unwrap();
+        assert_eq!(cbor(&loaded), serialized);
+    }
+
+    // Turns light sources back into the version 0 layout with on/off channels and one shared strength
+    fn shared_light_strength(value: ciborium::Value) -> ciborium::Value {
+        use ciborium::Value;
+
+        match value {
+            Value::Map(entries) if entries.len() == 3 && entries[0].0.as_text() == Some("strength_red") => {
+                let strengths: Vec<u8> = entries
+                    .iter()
+                    .map(|(_, strength)| u8::try_from(strength.as_integer().unwrap()).unwrap())
+                    .collect();
+                let strength = *strengths.iter().max().unwrap();
+                assert!(strengths.iter().all(|channel| [0, strength].contains(channel)));
+
+                let channel = |name: &str, strength: u8| (Value::Text(name.to_string()), Value::Bool(strength > 0));
+                Value::Map(vec![
+                    channel("red", strengths[0]),
+                    channel("green", strengths[1]),
+                    channel("blue", strengths[2]),
+                    (Value::Text("strength".to_string()), Value::Integer(strength.into())),
+                ])
             }
+            Value::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, shared_light_strength(value)))
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.into_iter().map(shared_light_strength).collect()),
+            Value::Tag(tag, value) => Value::Tag(tag, Box::new(shared_light_strength(*value))),
+            value => value,
         }
-    } else {
-        None
+    }
+
+    #[test]
+    fn old_chunks_are_migrated() {
+        use crate::game::world::{LightSource, CHUNK_SIZE};
+
+        let save = TestSave::new("old_chunk");
+        let block_manager = BlockManager::new();
+        let light = |light_source: LightSource| {
+            let mut block = Block::new_with_default("Light", &block_manager);
+            block.set_light_source(Some(light_source));
+            block
+        };
+        let lights = [
+            light(LightSource::new(12, 0, 12)),
+            light(LightSource::new(0, 7, 0)),
+            light(LightSource::default()),
+        ];
+        let air = Block::new_with_default("Air", &block_manager);
+        let blocks = (0..(CHUNK_SIZE as usize).pow(3))
+            .map(|i| match i % 1000 {
+                0..=2 => lights[i % 1000].clone(),
+                _ => air.clone(),
+            })
+            .collect();
+        let serialized = cbor(&BlockBuffer::new(blocks));
+
+        let value: ciborium::Value = ciborium::from_reader(serialized.as_slice()).unwrap();
+        let old_value = shared_light_strength(value.clone());
+        assert_ne!(old_value, value);
+        assert_eq!(migrate(old_value.clone(), UNVERSIONED_FORMAT_VERSION), value);
+        // Chunks that were already rewritten in the newer layout are left alone and current ones are not migrated
+        assert_eq!(migrate(value.clone(), UNVERSIONED_FORMAT_VERSION), value);
+        assert_eq!(migrate(old_value.clone(), WORLD_FORMAT_VERSION), old_value);
+
+        save.write("chunk.cbor", &cbor(&old_value));
+        let loaded = load_block_buffer(&save.0, "chunk", UNVERSIONED_FORMAT_VERSION).unwrap();
+        assert_eq!(cbor(&loaded), serialized);
+        self::save(&save.0, "chunk", &old_value, true);
+        let loaded = load_block_buffer(&save.0, "chunk", UNVERSIONED_FORMAT_VERSION).unwrap();
+        assert_eq!(cbor(&loaded), serialized);
+    }
+
+    #[test]
+    fn last_played_save_is_the_latest() {
+        let (older, newer, unversioned) = (
+            TestSave::new("played_before"),
+            TestSave::new("played_last"),
+            TestSave::new("played_unversioned"),
+        );
+        for (save, last_played) in [(&older, u64::MAX - 1), (&newer, u64::MAX)] {
+            let world_meta = WorldMeta {
+                last_played,
+                ..WorldMeta::new(&save.0, WORLD_FORMAT_VERSION, 7, TerrainGeneratorKind::flat())
+            };
+            self::save(&save.0, "world", &world_meta, false);
+        }
+
+        assert!(available_saves().contains(&unversioned.0));
+        assert_eq!(last_played_save(), Some(newer.0.clone()));
+        delete_save(&newer.0).unwrap();
+        assert_eq!(last_played_save(), Some(older.0.clone()));
     }
 }
@@ -1,24 +1,247 @@
+use std::ops::RangeInclusive;
+
 use cfg_if::cfg_if;
 use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    game::{
+        world::{DEFAULT_MAX_LIGHT_SOURCES, DEFAULT_MAX_QUEUED_SAVES, DEFAULT_SAVE_THREADS, DEFAULT_SUNLIGHT_SPREAD},
+        DEFAULT_MAX_RAY_STEPS, DEFAULT_PLAYER_REACH,
+    },
+    TITLE,
+};
+
+// Bounds of the horizontal render distance slider, the render distance hotkeys keep to them too
+pub const RENDER_DISTANCE_HORIZONTAL_RANGE: RangeInclusive<u32> = 2..=32;
+
+// How frames are presented, modes the surface doesn't support fall back to AutoVsync
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum PresentMode {
+    #[default]
+    AutoVsync,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(value: PresentMode) -> Self {
+        match value {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+// Actions whose key can be changed in the settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    Break,
+    Place,
+    Pick,
+    Save,
+    Load,
+    Pause,
+    ToggleWalk,
+    Sprint,
+    Export,
+    SelectCorner,
+    ClearSelection,
+}
+
+// The arrow keys, K and J and the mouse buttons stay bound to their movement and block actions next to these. The
+// number keys of the hotbar, + and -, M, Escape and the function keys of debug actions can't be changed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybinds {
+    pub forward: VirtualKeyCode,
+    pub back: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    pub break_block: VirtualKeyCode,
+    pub place_block: VirtualKeyCode,
+    pub pick_block: VirtualKeyCode,
+    pub save: VirtualKeyCode,
+    pub load: VirtualKeyCode,
+    pub pause: VirtualKeyCode,
+    pub toggle_walk: VirtualKeyCode,
+    pub sprint: VirtualKeyCode,
+    pub export: VirtualKeyCode,
+    pub select_corner: VirtualKeyCode,
+    pub clear_selection: VirtualKeyCode,
+}
 
-use crate::TITLE;
+impl Keybinds {
+    pub fn key(&self, action: Action) -> VirtualKeyCode {
+        match action {
+            Action::Forward => self.forward,
+            Action::Back => self.back,
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::Up => self.up,
+            Action::Down => self.down,
+            Action::Break => self.break_block,
+            Action::Place => self.place_block,
+            Action::Pick => self.pick_block,
+            Action::Save => self.save,
+            Action::Load => self.load,
+            Action::Pause => self.pause,
+            Action::ToggleWalk => self.toggle_walk,
+            Action::Sprint => self.sprint,
+            Action::Export => self.export,
+            Action::SelectCorner => self.select_corner,
+            Action::ClearSelection => self.clear_selection,
+        }
+    }
+
+    pub fn key_mut(&mut self, action: Action) -> &mut VirtualKeyCode {
+        match action {
+            Action::Forward => &mut self.forward,
+            Action::Back => &mut self.back,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Break => &mut self.break_block,
+            Action::Place => &mut self.place_block,
+            Action::Pick => &mut self.pick_block,
+            Action::Save => &mut self.save,
+            Action::Load => &mut self.load,
+            Action::Pause => &mut self.pause,
+            Action::ToggleWalk => &mut self.toggle_walk,
+            Action::Sprint => &mut self.sprint,
+            Action::Export => &mut self.export,
+            Action::SelectCorner => &mut self.select_corner,
+            Action::ClearSelection => &mut self.clear_selection,
+        }
+    }
+}
+
+impl Default for Keybinds {
+    fn default() -> Self {
+        Self {
+            forward: VirtualKeyCode::W,
+            back: VirtualKeyCode::S,
+            left: VirtualKeyCode::A,
+            right: VirtualKeyCode::D,
+            up: VirtualKeyCode::Space,
+            down: VirtualKeyCode::LShift,
+            break_block: VirtualKeyCode::X,
+            place_block: VirtualKeyCode::C,
+            pick_block: VirtualKeyCode::V,
+            save: VirtualKeyCode::F5,
+            load: VirtualKeyCode::F9,
+            pause: VirtualKeyCode::Tab,
+            toggle_walk: VirtualKeyCode::G,
+            sprint: VirtualKeyCode::LControl,
+            export: VirtualKeyCode::F2,
+            select_corner: VirtualKeyCode::Z,
+            clear_selection: VirtualKeyCode::Back,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub vertical_fov: f32,
     pub render_distance_horizontal: u32,
-    pub render_distance_vertical: u32,
+    // Chunks loaded above and below the camera, the sky usually needs less than caves
+    pub render_distance_up: u32,
+    pub render_distance_down: u32,
+    // Chunks past the render distance kept loaded when purging, so moving back and forth doesn't reload them
+    pub kept_surrounding_chunks: u32,
+    pub chunk_request_delay: f32,
+    pub remesh_coalescing_window: f32,
+    pub prioritize_look_direction: bool,
+    // Skip drawing chunks hidden behind fully opaque faces of all their neighbours
+    pub occlusion_culling: bool,
     pub camera_speed: f32,
+    // Camera speed is multiplied by this while the sprint key is held
+    pub sprint_multiplier: f32,
     pub camera_sensitivity: f32,
+    // Mouse movements up to this many counts are ignored
+    pub look_deadzone: f32,
+    pub look_acceleration: f32,
     pub collision: bool,
+    pub auto_jump: bool,
+    // Half the size of the box around the camera that collides with blocks
+    pub player_half_extent: [f32; 3],
     pub show_crosshair: bool,
     pub show_performance: bool,
+    pub show_held_block: bool,
+    // Size of the held block indicator and its offset from the bottom right corner of the screen, in points
+    pub held_block_scale: f32,
+    pub held_block_offset: [f32; 2],
     pub show_camera: bool,
+    pub show_minimap: bool,
     pub show_working: bool,
     pub sky_color: [f32; 3],
+    pub night_sky_color: [f32; 3],
+    // The sky colors above are used at the horizon, these straight up when the sky is a gradient
+    pub sky_gradient: bool,
+    pub sky_zenith_color: [f32; 3],
+    pub night_sky_zenith_color: [f32; 3],
+    // Color sunlight is tinted with, turning to the sunrise color while the sun is near the horizon in the day night cycle
+    pub sun_color: [f32; 3],
+    pub sunrise_sun_color: [f32; 3],
+    // Hides the void below unloaded chunks, terrain is always drawn over it
+    pub ground_plane: bool,
+    pub ground_plane_height: f32,
+    pub ground_plane_color: [f32; 4],
+    // Noise clouds drifting at a fixed height, dimmed at night like the sky
+    pub clouds: bool,
+    pub cloud_height: f32,
+    pub cloud_speed: f32,
+    pub cloud_coverage: f32,
+    pub cloud_color: [f32; 4],
+    // Distance in blocks where fog starts and where it fully hides terrain in the sky color, off if it ends first
+    pub fog_start: f32,
+    pub fog_end: f32,
+    pub selection_color: [f32; 4],
+    pub outline_color: [f32; 4],
+    // In blocks, 0 draws single pixel lines
+    pub outline_thickness: f32,
+    // Edges of the block that would be broken are drawn in this color
+    pub target_outline: bool,
+    pub target_outline_color: [f32; 4],
     pub sunlight_intensity: u8,
+    // Scales the sunlight intensity with the time of day and blends the sky to its night colors
+    pub day_night_cycle: bool,
     pub base_light_value: f32,
     pub light_power_factor: f32,
+    pub min_brightness: f32,
+    pub max_light_sources: u32,
+    pub sunlight_spread: u32,
+    pub smooth_lighting: bool,
+    pub transparent_backfaces: bool,
+    pub wireframe: bool,
+    pub present_mode: PresentMode,
+    pub max_fps: Option<u32>,
+    pub mipmaps: bool,
+    pub save_threads: u32,
+    pub max_queued_saves: u32,
+    pub pause_generation_on_save: bool,
+    // Load the most recently played save on startup instead of starting a fresh world
+    pub continue_last_world: bool,
+    pub aim_assist: bool,
+    pub aim_assist_cone: f32,
+    // Block boundaries a targeting ray crosses before it gives up
+    pub max_ray_steps: u32,
+    // Farthest blocks can be broken and placed, in blocks
+    pub reach: f32,
+    pub replace_fluids_on_place: bool,
+    pub keybinds: Keybinds,
 }
 
 impl Settings {
@@ -72,19 +295,75 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             render_distance_horizontal: if cfg!(debug_assertions) { 2 } else { 8 },
-            render_distance_vertical: if cfg!(debug_assertions) { 2 } else { 4 },
+            render_distance_up: if cfg!(debug_assertions) { 2 } else { 4 },
+            render_distance_down: if cfg!(debug_assertions) { 2 } else { 4 },
+            kept_surrounding_chunks: 4,
+            chunk_request_delay: 0.1,
+            remesh_coalescing_window: 0.1,
+            prioritize_look_direction: true,
+            occlusion_culling: true,
             camera_speed: 10.0,
+            sprint_multiplier: 2.0,
             camera_sensitivity: if cfg!(not(target_arch = "wasm32")) { 0.5 } else { 0.2 },
+            look_deadzone: 0.0,
+            look_acceleration: 0.0,
             collision: true,
+            auto_jump: true,
+            player_half_extent: [0.3, 0.8, 0.3],
             vertical_fov: 50.0,
             show_crosshair: true,
             show_performance: true,
+            show_held_block: true,
+            held_block_scale: 1.0,
+            held_block_offset: [-16.0, -16.0],
             show_camera: true,
+            show_minimap: false,
             show_working: true,
             sky_color: [0.1, 0.2, 0.3],
+            night_sky_color: [0.005, 0.005, 0.02],
+            sky_gradient: true,
+            sky_zenith_color: [0.02, 0.07, 0.2],
+            night_sky_zenith_color: [0.0, 0.0, 0.005],
+            sun_color: [1.0, 1.0, 1.0],
+            sunrise_sun_color: [1.0, 0.65, 0.4],
+            ground_plane: false,
+            ground_plane_height: 0.0,
+            ground_plane_color: [0.25, 0.35, 0.2, 1.0],
+            clouds: false,
+            cloud_height: 96.0,
+            cloud_speed: 2.0,
+            cloud_coverage: 0.4,
+            cloud_color: [1.0, 1.0, 1.0, 0.8],
+            fog_start: 160.0,
+            fog_end: 240.0,
+            selection_color: [0.2, 0.5, 1.0, 0.25],
+            outline_color: [1.0, 0.85, 0.1, 1.0],
+            outline_thickness: 0.02,
+            target_outline: true,
+            target_outline_color: [0.0, 0.0, 0.0, 0.8],
             sunlight_intensity: 12,
+            day_night_cycle: false,
             base_light_value: 0.003,
             light_power_factor: 1.6,
+            min_brightness: 0.01,
+            max_light_sources: DEFAULT_MAX_LIGHT_SOURCES,
+            sunlight_spread: DEFAULT_SUNLIGHT_SPREAD,
+            smooth_lighting: true,
+            transparent_backfaces: false,
+            wireframe: false,
+            present_mode: PresentMode::default(),
+            max_fps: None,
+            mipmaps: true,
+            save_threads: DEFAULT_SAVE_THREADS,
+            max_queued_saves: DEFAULT_MAX_QUEUED_SAVES,
+            pause_generation_on_save: true,
+            continue_last_world: false,
+            aim_assist: false,
+            aim_assist_cone: 5.0,
+            max_ray_steps: DEFAULT_MAX_RAY_STEPS,
+            reach: DEFAULT_PLAYER_REACH,
+            replace_fluids_on_place: true,
+            keybinds: Keybinds::default(),
         }
     }
 }
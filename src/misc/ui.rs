@@ -6,60 +6,182 @@ use std::{
     },
 };
 
-use egui::{Align, Align2, Area, ComboBox, Context, CursorIcon, Layout, Order, RichText, Window};
-use either::Either;
+use egui::{
+    Align, Align2, Area, Color32, ComboBox, Context, CursorIcon, Layout, Order, Pos2, Rect, RichText, Stroke,
+    TextureHandle, TextureId, Vec2, Window,
+};
+use strum::IntoEnumIterator;
 
 use crate::{
+    engine::{face::FaceDirection, RenderStats, TextureAtlas},
     game::{
-        world::{Block, BlockManager, LightSource, TextureID, MAX_LIGHT_VAL},
-        Player,
+        time_of_day,
+        world::{
+            Block, BlockManager, BlockShape, BlockTextures, InternalFaces, LightSource, TextureID, MAX_LIGHT_VAL,
+            MAX_SUNLIGHT_SPREAD,
+        },
+        GameRules, Player, HOTBAR_SLOTS,
     },
-    misc::settings::Settings,
+    misc::settings::{Action, Keybinds, PresentMode, Settings, RENDER_DISTANCE_HORIZONTAL_RANGE},
 };
 
 pub struct UI<'a> {
     running: Arc<AtomicBool>,
     elapsed_secs: f64,
+    // Frametime of the slowest 1% of recent frames and of the slowest frame
+    frame_time_spikes: (f64, f64),
     player: Player,
     settings: &'a mut Settings,
     selected_block: &'a mut Block,
-    selected_block_template: &'a mut String,
+    palette: &'a mut Vec<Block>,
     block_manager: Rc<BlockManager>,
     loading_chunks: u32,
     saving_chunks: u32,
-    selected_save: &'a mut String,
-    do_save: &'a mut bool,
-    do_load: &'a mut bool,
+    light_capped_chunks: u32,
+    skipped_chunk_requests: u32,
+    remeshes: u32,
+    buffer_allocations: u64,
+    render_stats: RenderStats,
+    loading_frozen: bool,
+    seed: u32,
+    game_rules: &'a mut GameRules,
+    time_of_day: &'a mut f64,
+    hud_message: Option<&'a str>,
+    block_icons: BlockIcons,
+    // New surface colors for the minimap, only given every so often
+    minimap_colors: Option<Vec<Option<[u8; 3]>>>,
+    menu: &'a mut MenuState,
+}
+
+// What the UI shows about the game this frame without changing it
+pub struct FrameInfo<'a> {
+    pub running: Arc<AtomicBool>,
+    pub elapsed_secs: f64,
+    pub frame_time_spikes: (f64, f64),
+    pub player: Player,
+    pub block_manager: Rc<BlockManager>,
+    pub loading_chunks: u32,
+    pub saving_chunks: u32,
+    pub light_capped_chunks: u32,
+    pub skipped_chunk_requests: u32,
+    pub remeshes: u32,
+    pub buffer_allocations: u64,
+    pub render_stats: RenderStats,
+    pub loading_frozen: bool,
+    pub seed: u32,
+    pub hud_message: Option<&'a str>,
+    pub block_icons: BlockIcons,
+    pub minimap_colors: Option<Vec<Option<[u8; 3]>>>,
+}
+
+// State of the menus, kept between frames. The do_ flags are set in the frame their button is clicked in and acted on
+// after it
+#[derive(Default)]
+pub struct MenuState {
+    pub selected_block_template: String,
+    pub block_search: String,
+    // Action waiting for a key press to be bound to
+    pub rebinding: Option<Action>,
+    pub minimap: Option<TextureHandle>,
+    #[cfg(feature = "save_system")]
+    pub selected_save: String,
+    #[cfg(feature = "save_system")]
+    pub save_error: Option<String>,
+    #[cfg(feature = "save_system")]
+    pub do_save: bool,
+    #[cfg(feature = "save_system")]
+    pub do_load: bool,
+    #[cfg(feature = "save_system")]
+    pub do_new_world: bool,
+    #[cfg(feature = "save_system")]
+    pub new_world_flat: bool,
+    #[cfg(feature = "save_system")]
+    pub do_delete_save: bool,
+    #[cfg(feature = "save_system")]
+    pub confirm_delete_save: bool,
+    // Preview of the save selected in the saves menu, kept so it is only loaded when the selection changes
+    #[cfg(feature = "save_system")]
+    pub world_preview: Option<(String, Option<TextureHandle>)>,
+    #[cfg(feature = "save_system")]
+    pub palette_name: String,
+    #[cfg(feature = "save_system")]
+    pub do_export_palette: bool,
+    #[cfg(feature = "save_system")]
+    pub do_import_palette: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub structure_path: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub do_paste_structure: bool,
+}
+
+// Columns shown on each side of the player in the minimap, and points each of them takes up
+pub const MINIMAP_RADIUS: u32 = 48;
+const MINIMAP_SCALE: f32 = 2.0;
+const MINIMAP_BACKGROUND: Color32 = Color32::from_rgb(20, 20, 20);
+const HOTBAR_ICON_SIZE: f32 = 32.0;
+const TEMPLATE_ICON_SIZE: f32 = 20.0;
+
+// The block texture atlas in egui, and where in it the icons of the blocks the UI shows are
+pub struct BlockIcons {
+    pub atlas_texture_id: TextureId,
+    pub hotbar: [Option<Rect>; HOTBAR_SLOTS],
+    pub template: Option<Rect>,
 }
 
 impl<'a> UI<'a> {
     pub fn new(
-        running: Arc<AtomicBool>,
-        elapsed_secs: f64,
-        player: Player,
+        frame_info: FrameInfo<'a>,
         settings: &'a mut Settings,
         selected_block: &'a mut Block,
-        selected_block_template: &'a mut String,
-        block_manager: Rc<BlockManager>,
-        loading_chunks: u32,
-        saving_chunks: u32,
-        selected_save: &'a mut String,
-        do_save: &'a mut bool,
-        do_load: &'a mut bool,
+        palette: &'a mut Vec<Block>,
+        game_rules: &'a mut GameRules,
+        time_of_day: &'a mut f64,
+        menu: &'a mut MenuState,
     ) -> Self {
+        let FrameInfo {
+            running,
+            elapsed_secs,
+            frame_time_spikes,
+            player,
+            block_manager,
+            loading_chunks,
+            saving_chunks,
+            light_capped_chunks,
+            skipped_chunk_requests,
+            remeshes,
+            buffer_allocations,
+            render_stats,
+            loading_frozen,
+            seed,
+            hud_message,
+            block_icons,
+            minimap_colors,
+        } = frame_info;
+
         Self {
             running,
             elapsed_secs,
+            frame_time_spikes,
             player,
             settings,
             selected_block,
-            selected_block_template,
+            palette,
             block_manager,
             loading_chunks,
             saving_chunks,
-            selected_save,
-            do_save,
-            do_load,
+            light_capped_chunks,
+            skipped_chunk_requests,
+            remeshes,
+            buffer_allocations,
+            render_stats,
+            loading_frozen,
+            seed,
+            game_rules,
+            time_of_day,
+            hud_message,
+            block_icons,
+            minimap_colors,
+            menu,
         }
     }
 
@@ -87,6 +209,7 @@ impl<'a> UI<'a> {
                     "InChunk pos: ({:.2}, {:.2}, {:.2})",
                     in_chunk_pos.x, in_chunk_pos.y, in_chunk_pos.z,
                 ));
+                ui.label(format!("Physics mode: {:?}", self.player.physics_mode));
             });
     }
 
@@ -101,6 +224,64 @@ impl<'a> UI<'a> {
         }
     }
 
+    // Anchored to a corner of the screen, so it stays clear of the crosshair whatever the FOV or aspect ratio
+    fn show_held_block(&mut self, ctx: &Context) {
+        let scale = self.settings.held_block_scale;
+
+        Area::new("Held block")
+            .order(Order::Foreground)
+            .anchor(Align2::RIGHT_BOTTOM, self.settings.held_block_offset)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).inner_margin(6.0 * scale).show(ui, |ui| {
+                    ui.label(RichText::new("Held block").size(10.0 * scale));
+                    ui.label(
+                        RichText::new(block_label(self.selected_block, self.block_manager.as_ref()))
+                            .strong()
+                            .size(16.0 * scale),
+                    );
+                });
+            });
+    }
+
+    fn show_hotbar(&mut self, ctx: &Context) {
+        let (atlas_texture_id, icons) = (self.block_icons.atlas_texture_id, self.block_icons.hotbar);
+
+        Area::new("Hotbar")
+            .order(Order::Foreground)
+            .anchor(Align2::CENTER_BOTTOM, [0.0, -8.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for (slot, icon) in icons.into_iter().enumerate() {
+                        // The held block may have been edited since the player was cloned
+                        let block = if slot == self.player.active_slot {
+                            &*self.selected_block
+                        } else {
+                            &self.player.hotbar[slot]
+                        };
+
+                        let mut frame = egui::Frame::popup(ui.style()).inner_margin(4.0);
+                        if slot == self.player.active_slot {
+                            frame = frame.stroke(Stroke::new(2.0, Color32::WHITE));
+                        }
+                        frame
+                            .show(ui, |ui| {
+                                let size = Vec2::splat(HOTBAR_ICON_SIZE);
+                                match icon {
+                                    Some(uv) => ui.add(egui::Image::new(atlas_texture_id, size).uv(uv)),
+                                    None => ui.allocate_response(size, egui::Sense::hover()),
+                                }
+                            })
+                            .response
+                            .on_hover_text(format!(
+                                "{} - {}",
+                                slot + 1,
+                                block_label(block, self.block_manager.as_ref())
+                            ));
+                    }
+                });
+            });
+    }
+
     #[cfg(feature = "save_system")]
     fn show_saves(&mut self, ctx: &Context) {
         Window::new("Saves")
@@ -110,7 +291,7 @@ impl<'a> UI<'a> {
             .show(ctx, |ui| {
                 ui.group(|ui| {
                     ui.horizontal(|ui| {
-                        let current_selected = self.selected_save.clone();
+                        let current_selected = self.menu.selected_save.clone();
 
                         ui.label("Select save:");
                         egui::ComboBox::from_label("")
@@ -118,39 +299,115 @@ impl<'a> UI<'a> {
                             .show_ui(ui, |ui| {
                                 let available_saves = crate::misc::save_helper::available_saves();
 
-                                if !available_saves.contains(&self.selected_save.clone()) {
+                                if !available_saves.contains(&self.menu.selected_save.clone()) {
                                     ui.selectable_value(
-                                        self.selected_save,
-                                        self.selected_save.clone(),
-                                        RichText::new(format!("{:?}", self.selected_save.clone())).italics(),
+                                        &mut self.menu.selected_save,
+                                        current_selected.clone(),
+                                        RichText::new(format!("{:?}", current_selected)).italics(),
                                     );
                                 }
+                                let now = crate::misc::save_helper::unix_time();
                                 for save_name in available_saves {
-                                    ui.selectable_value(
-                                        self.selected_save,
-                                        save_name.clone(),
-                                        format!("{:?}", save_name),
-                                    );
+                                    let text = match crate::misc::save_helper::world_meta(&save_name) {
+                                        Some(world_meta) => format!(
+                                            "{:?} - played {}",
+                                            save_name,
+                                            format_age(now.saturating_sub(world_meta.last_played))
+                                        ),
+                                        None => format!("{:?}", save_name),
+                                    };
+                                    ui.selectable_value(&mut self.menu.selected_save, save_name.clone(), text);
                                 }
                             });
                     });
 
                     ui.horizontal(|ui| {
                         ui.label("Rename save:");
-                        ui.text_edit_singleline(self.selected_save);
+                        ui.text_edit_singleline(&mut self.menu.selected_save);
                     });
                 });
 
                 ui.group(|ui| {
                     ui.horizontal(|ui| {
-                        *self.do_save = ui.button("Save").clicked();
-                        *self.do_load = ui.button("Load").clicked();
+                        self.menu.do_save = ui.button("Save").clicked();
+                        self.menu.do_load = ui.button("Load").clicked();
+                        if ui
+                            .button("Continue")
+                            .on_hover_text("Load the most recently played save")
+                            .clicked()
+                        {
+                            match crate::misc::save_helper::last_played_save() {
+                                Some(save_name) => {
+                                    self.menu.selected_save = save_name;
+                                    self.menu.do_load = true;
+                                }
+                                None => self.menu.save_error = Some("There is no save to continue".to_string()),
+                            }
+                        }
+                        self.menu.do_new_world = ui
+                            .button("New world")
+                            .on_hover_text("Start a new world in a new save, its name is used as the seed")
+                            .clicked();
+                        ui.checkbox(&mut self.menu.new_world_flat, "Flat");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.menu.confirm_delete_save, "Confirm");
+                        self.menu.do_delete_save = ui
+                            .add_enabled(self.menu.confirm_delete_save, egui::Button::new("Delete"))
+                            .on_hover_text("Delete the selected save from disk, this can't be undone")
+                            .clicked();
+                        if self.menu.do_delete_save {
+                            self.menu.confirm_delete_save = false;
+                        }
                     });
+                    ui.checkbox(&mut self.settings.continue_last_world, "Continue last world on startup");
                 });
+
+                if self.menu.do_save || self.menu.do_load || self.menu.do_new_world || self.menu.do_delete_save {
+                    self.menu.world_preview = None;
+                }
+                if self.menu.world_preview.as_ref().map(|(save_name, _)| save_name) != Some(&self.menu.selected_save) {
+                    let texture =
+                        crate::misc::save_helper::load_or_create_preview(&self.menu.selected_save, &self.block_manager)
+                            .map(|image| {
+                                ctx.load_texture(
+                                    "World preview",
+                                    egui::ColorImage::from_rgb(
+                                        [image.width() as usize, image.height() as usize],
+                                        image.as_raw(),
+                                    ),
+                                    egui::TextureOptions::NEAREST,
+                                )
+                            });
+                    self.menu.world_preview = Some((self.menu.selected_save.clone(), texture));
+                }
+                if let Some((_, Some(texture))) = &self.menu.world_preview {
+                    ui.group(|ui| {
+                        ui.label("Preview of the area around spawn");
+                        ui.image(texture.id(), texture.size_vec2());
+                    });
+                }
             });
     }
 
-    fn show_performance(&mut self, ctx: &Context) {
+    #[cfg(feature = "save_system")]
+    fn show_save_error(&mut self, ctx: &Context) {
+        if let Some(save_error) = self.menu.save_error.clone() {
+            Window::new("Save error")
+                .collapsible(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 4.0])
+                .show(ctx, |ui| {
+                    ui.label(RichText::new(save_error).color(egui::Color32::RED));
+
+                    if ui.button("Dismiss").clicked() {
+                        self.menu.save_error = None;
+                    }
+                });
+        }
+    }
+
+    // Returns the bottom of the window, so whatever is anchored below it can keep clear of it
+    fn show_performance(&mut self, ctx: &Context) -> Option<f32> {
         Window::new("Performance")
             .title_bar(false)
             .anchor(Align2::RIGHT_TOP, [-4.0, 4.0])
@@ -160,7 +417,60 @@ impl<'a> UI<'a> {
 
                 ui.label(format!("FPS: {:.2}", fps));
                 ui.label(format!("Frametime: {:.2} ms", frame_time));
-            });
+
+                let (one_percent_low, max) = self.frame_time_spikes;
+                if one_percent_low > 0.0 {
+                    ui.label(format!(
+                        "1% low: {:.2} FPS ({:.2} ms)",
+                        1.0 / one_percent_low,
+                        one_percent_low * 1000.0
+                    ));
+                    ui.label(format!("Max frametime: {:.2} ms", max * 1000.0));
+                }
+                ui.label(format!("Draw calls: {}", self.render_stats.draw_calls));
+                ui.label(format!("Triangles: {}", self.render_stats.triangles));
+                if self.skipped_chunk_requests > 0 {
+                    ui.label(format!("Skipped chunk requests: {}", self.skipped_chunk_requests));
+                }
+                if self.remeshes > 0 {
+                    ui.label(format!("Remeshes: {}", self.remeshes));
+                }
+                ui.label(format!("Mesh buffer allocations: {}", self.buffer_allocations));
+                if self.light_capped_chunks > 0 {
+                    ui.label(format!("Chunks over light limit: {}", self.light_capped_chunks));
+                }
+            })
+            .map(|response| response.response.rect.bottom())
+    }
+
+    // Top-down view of the loaded surface around the player, who is the red pixel in the middle
+    fn show_minimap(&mut self, ctx: &Context, top: f32) {
+        if let Some(colors) = self.minimap_colors.take() {
+            let side = 2 * MINIMAP_RADIUS as usize + 1;
+            let mut image = egui::ColorImage::new([side, side], MINIMAP_BACKGROUND);
+            for (pixel, color) in image.pixels.iter_mut().zip(colors) {
+                if let Some([r, g, b]) = color {
+                    *pixel = Color32::from_rgb(r, g, b);
+                }
+            }
+            image.pixels[side * side / 2] = Color32::RED;
+
+            match &mut self.menu.minimap {
+                Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+                None => self.menu.minimap = Some(ctx.load_texture("Minimap", image, egui::TextureOptions::NEAREST)),
+            }
+        }
+
+        if let Some(texture) = &self.menu.minimap {
+            Area::new("Minimap")
+                .order(Order::Foreground)
+                .anchor(Align2::RIGHT_TOP, [-4.0, top + 4.0])
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).inner_margin(4.0).show(ui, |ui| {
+                        ui.image(texture.id(), texture.size_vec2() * MINIMAP_SCALE);
+                    });
+                });
+        }
     }
 
     fn show_resume(&mut self, ctx: &Context) {
@@ -181,135 +491,273 @@ impl<'a> UI<'a> {
     }
 
     fn show_edit_block(&mut self, ctx: &Context) {
-        Window::new("Edit block").collapsible(false).default_width(0.01).default_height(0.01).show(ctx, |ui| {
-            ui.group(|ui| {
-                ui.with_layout(Layout::top_down(Align::Center), |ui| {
-                    ui.label("Template");
-                });
-
-                ComboBox::from_label("Select template").selected_text(self.selected_block_template.to_owned()).show_ui(ui, |ui| {
-                    for block_name in self.block_manager.all_rendered_block_names() {
-                        ui.selectable_value(self.selected_block_template, block_name.clone(), block_name);
-                    }
-                });
+        Window::new("Edit block")
+            .collapsible(false)
+            .default_width(0.01)
+            .default_height(0.01)
+            .show(ctx, |ui| {
+                ui.group(|ui| {
+                    ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                        ui.label("Template");
+                    });
 
-                if ui.button("Load template").clicked() {
-                    *self.selected_block = Block::new_with_default(self.selected_block_template, self.block_manager.as_ref())
-                }
-            });
+                    ui.horizontal(|ui| {
+                        ui.label("Search:");
+                        ui.text_edit_singleline(&mut self.menu.block_search);
+                    });
+                    ui.horizontal(|ui| {
+                        let size = Vec2::splat(TEMPLATE_ICON_SIZE);
+                        match self.block_icons.template {
+                            Some(uv) => ui.add(egui::Image::new(self.block_icons.atlas_texture_id, size).uv(uv)),
+                            None => ui.allocate_response(size, egui::Sense::hover()),
+                        };
 
-            if let Some(textures) = self.selected_block.texture_id() {
-                match textures {
-                    Either::Left(texture_id) => {
-                        if let Some(mut texture_name) = self.block_manager.get_texture_name(texture_id) {
-                            ui.group(|ui| {
-                                ui.with_layout(Layout::top_down(Align::Center), |ui| {
-                                    ui.label("Texture");
-                                });
+                        let search = self.menu.block_search.to_lowercase();
+                        ComboBox::from_label("Select template")
+                            .selected_text(self.menu.selected_block_template.to_owned())
+                            .show_ui(ui, |ui| {
+                                for block_name in self
+                                    .block_manager
+                                    .all_rendered_block_names()
+                                    .iter()
+                                    .filter(|block_name| block_name.to_lowercase().contains(&search))
+                                {
+                                    ui.selectable_value(
+                                        &mut self.menu.selected_block_template,
+                                        block_name.clone(),
+                                        block_name,
+                                    );
+                                }
+                            });
+                    });
 
-                                ComboBox::from_label("Select texture").selected_text(texture_name) .show_ui(ui, |ui| {
-                                    for possible_texture_name in self.block_manager.all_texture_names() {
-                                        ui.selectable_value(&mut texture_name,possible_texture_name, possible_texture_name );
-                                    }
-                                });
+                    if ui.button("Load template").clicked() {
+                        *self.selected_block =
+                            Block::new_with_default(&self.menu.selected_block_template, self.block_manager.as_ref())
+                    }
+                });
 
-                                ui.separator();
+                ui.group(|ui| {
+                    ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                        ui.label("Palette");
+                    });
 
-                                if ui.button("Make sides have different textures").clicked() {
-                                    self.selected_block.set_texture_id(Some(Either::Right([TextureID::from(texture_name.as_str()), TextureID::from(texture_name.as_str()),TextureID::from(texture_name.as_str())])))
-                                } else {
-                                    self.selected_block.set_texture_id(Some(Either::Left(TextureID::from(texture_name.as_str()))))
-                                }
-                            });
-                        }
+                    let mut to_remove = None;
+                    for (index, block) in self.palette.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{}. {}",
+                                index + 1,
+                                block_label(block, self.block_manager.as_ref())
+                            ));
+                            if ui.button("Select").clicked() {
+                                *self.selected_block = block.clone()
+                            }
+                            if ui.button("Remove").clicked() {
+                                to_remove = Some(index)
+                            }
+                        });
+                    }
+                    if let Some(index) = to_remove {
+                        self.palette.remove(index);
                     }
-                    Either::Right([texture_id_top, texture_id_side, texture_id_bottom]) => {
-                        if let [Some(mut texture_name_top), Some(mut texture_name_side), Some(mut texture_name_bottom)] = [
-                            self.block_manager.get_texture_name(texture_id_top),
-                            self.block_manager.get_texture_name(texture_id_side),
-                            self.block_manager.get_texture_name(texture_id_bottom),
-                        ] {
-                            ui.group(|ui| {
-                                ui.with_layout(Layout::top_down(Align::Center), |ui| {
-                                    ui.label("Textures");
-                                });
 
-                                ComboBox::from_label("Select top texture") .selected_text(texture_name_top) .show_ui(ui, |ui| {
-                                    for possible_texture_name in self.block_manager.all_texture_names() {
-                                        ui.selectable_value(&mut texture_name_top, possible_texture_name, possible_texture_name);
-                                    }
-                                });
-                                ComboBox::from_label("Select side texture") .selected_text(texture_name_side) .show_ui(ui, |ui| {
-                                    for possible_texture_name in self.block_manager.all_texture_names() {
-                                        ui.selectable_value(&mut texture_name_side, possible_texture_name, possible_texture_name);
-                                    }
-                                });
-                                ComboBox::from_label("Select bottom texture") .selected_text(texture_name_bottom) .show_ui(ui, |ui| {
-                                    for possible_texture_name in self.block_manager.all_texture_names() {
-                                        ui.selectable_value(&mut texture_name_bottom, possible_texture_name, possible_texture_name);
-                                    }
-                                });
+                    if ui.button("Add selected block").clicked() {
+                        self.palette.push(self.selected_block.clone())
+                    }
 
-                                ui.separator();
+                    #[cfg(feature = "save_system")]
+                    {
+                        ui.separator();
 
-                                if ui.button("Make sides share a texture").clicked() {
-                                    self.selected_block.set_texture_id(Some(Either::Left(TextureID::from(texture_name_top.as_str()))))
-                                } else {
-                                    self.selected_block.set_texture_id(Some(Either::Right([TextureID::from(texture_name_top.as_str()), TextureID::from(texture_name_side.as_str()),TextureID::from(texture_name_bottom.as_str())])))
-                                }
+                        ui.horizontal(|ui| {
+                            ui.label("Palette name:");
+                            ui.text_edit_singleline(&mut self.menu.palette_name);
+                        });
+                        ui.horizontal(|ui| {
+                            let has_name = !self.menu.palette_name.is_empty();
 
-                            });
-                        }
+                            self.menu.do_export_palette = ui
+                                .add_enabled(has_name, egui::Button::new("Export"))
+                                .on_hover_text("Saved with the current save")
+                                .clicked();
+                            self.menu.do_import_palette = ui
+                                .add_enabled(has_name, egui::Button::new("Import"))
+                                .on_hover_text("Replaces the palette")
+                                .clicked();
+                        });
                     }
-                }
+                });
 
+                #[cfg(not(target_arch = "wasm32"))]
                 ui.group(|ui| {
                     ui.with_layout(Layout::top_down(Align::Center), |ui| {
-                        ui.label("Light source");
+                        ui.label("Structure");
                     });
 
-                    if let Some(light_source) = self.selected_block.light_source_mut() {
-                        let light_source_old = light_source.clone();
+                    ui.horizontal(|ui| {
+                        ui.label(".vox file:");
+                        ui.text_edit_singleline(&mut self.menu.structure_path);
+                    });
+                    self.menu.do_paste_structure = ui
+                        .add_enabled(!self.menu.structure_path.is_empty(), egui::Button::new("Paste"))
+                        .on_hover_text("At the first selected corner, or at the player without a selection")
+                        .clicked();
+                });
 
-                        ui.horizontal(|ui| {
-                            ui.checkbox(&mut light_source.red, "Red");
-                            ui.checkbox(&mut light_source.green, "Green");
-                            ui.checkbox(&mut light_source.blue, "Blue");
-                        });
-                        ui.add(egui::Slider::new(&mut light_source.strength, 1..=MAX_LIGHT_VAL).text("Light strength"));
+                if let Some(textures) = self.selected_block.texture_id().clone() {
+                    let face_labels: &[&str] = match textures {
+                        BlockTextures::Uniform(_) => &[""],
+                        BlockTextures::TopSideBottom(_) => &["top ", "side ", "bottom "],
+                        BlockTextures::SixFaces(_) => &["top ", "bottom ", "north ", "south ", "east ", "west "],
+                    };
+                    let texture_names = textures
+                        .all()
+                        .iter()
+                        .map(|texture_id| self.block_manager.get_texture_name(texture_id))
+                        .collect::<Option<Vec<_>>>();
 
-                        ui.separator();
+                    if let Some(mut texture_names) = texture_names {
+                        ui.group(|ui| {
+                            ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                                ui.label(if texture_names.len() == 1 {
+                                    "Texture"
+                                } else {
+                                    "Textures"
+                                });
+                            });
 
-                        if !light_source.is_valid() {
-                            if light_source_old.is_valid() {
-                                self.selected_block.set_light_source(Some(light_source_old));
-                            } else {
-                                self.selected_block.set_light_source(Some(LightSource::default()))
+                            for (texture_name, face_label) in texture_names.iter_mut().zip(face_labels) {
+                                ComboBox::from_label(format!("Select {face_label}texture"))
+                                    .selected_text(texture_name.as_str())
+                                    .show_ui(ui, |ui| {
+                                        for possible_texture_name in self.block_manager.all_texture_names() {
+                                            ui.selectable_value(
+                                                texture_name,
+                                                possible_texture_name,
+                                                possible_texture_name,
+                                            );
+                                        }
+                                    });
                             }
-                        }
 
-                        if ui.button("Remove light source").clicked() {
-                            self.selected_block.set_light_source(None)
-                        }
-                    } else {
-                        if ui.button("Add light source").clicked() {
-                            self.selected_block.set_light_source(Some(LightSource::default()))
-                        }
+                            ui.separator();
+
+                            let ids = texture_names
+                                .iter()
+                                .map(|texture_name| TextureID::from(texture_name.as_str()))
+                                .collect::<Vec<_>>();
+                            let new_textures = match textures {
+                                BlockTextures::Uniform(_) => {
+                                    if ui.button("Make sides have different textures").clicked() {
+                                        BlockTextures::TopSideBottom([ids[0].clone(), ids[0].clone(), ids[0].clone()])
+                                    } else {
+                                        BlockTextures::Uniform(ids[0].clone())
+                                    }
+                                }
+                                BlockTextures::TopSideBottom(_) => {
+                                    if ui.button("Make sides share a texture").clicked() {
+                                        BlockTextures::Uniform(ids[0].clone())
+                                    } else if ui.button("Give every face its own texture").clicked() {
+                                        BlockTextures::SixFaces([
+                                            ids[0].clone(),
+                                            ids[2].clone(),
+                                            ids[1].clone(),
+                                            ids[1].clone(),
+                                            ids[1].clone(),
+                                            ids[1].clone(),
+                                        ])
+                                    } else {
+                                        BlockTextures::TopSideBottom([ids[0].clone(), ids[1].clone(), ids[2].clone()])
+                                    }
+                                }
+                                BlockTextures::SixFaces(_) => {
+                                    if ui.button("Make sides share a texture").clicked() {
+                                        BlockTextures::TopSideBottom([ids[0].clone(), ids[2].clone(), ids[1].clone()])
+                                    } else {
+                                        BlockTextures::from_slice(&ids).unwrap_or_else(|| textures.clone())
+                                    }
+                                }
+                            };
+                            self.selected_block.set_texture_id(Some(new_textures));
+                        });
                     }
-                });
 
-                {
                     ui.group(|ui| {
                         ui.with_layout(Layout::top_down(Align::Center), |ui| {
-                            ui.label("Properties");
+                            ui.label("Light source");
                         });
 
-                        ui.checkbox(&mut self.selected_block.is_transparent_mut(), "Transparent");
-                        ui.checkbox(&mut self.selected_block.is_solid_mut(), "Solid");
+                        if let Some(light_source) = self.selected_block.light_source_mut() {
+                            let light_source_old = light_source.clone();
+
+                            ui.add(
+                                egui::Slider::new(&mut light_source.strength_red, 0..=MAX_LIGHT_VAL)
+                                    .text("Red strength"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut light_source.strength_green, 0..=MAX_LIGHT_VAL)
+                                    .text("Green strength"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut light_source.strength_blue, 0..=MAX_LIGHT_VAL)
+                                    .text("Blue strength"),
+                            );
+
+                            ui.separator();
+
+                            if !light_source.is_valid() {
+                                if light_source_old.is_valid() {
+                                    self.selected_block.set_light_source(Some(light_source_old));
+                                } else {
+                                    self.selected_block.set_light_source(Some(LightSource::default()))
+                                }
+                            }
+
+                            if ui.button("Remove light source").clicked() {
+                                self.selected_block.set_light_source(None)
+                            }
+                        } else {
+                            if ui.button("Add light source").clicked() {
+                                self.selected_block.set_light_source(Some(LightSource::default()))
+                            }
+                        }
                     });
+
+                    {
+                        ui.group(|ui| {
+                            ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                                ui.label("Properties");
+                            });
+
+                            ui.checkbox(&mut self.selected_block.is_transparent_mut(), "Transparent");
+                            ui.checkbox(&mut self.selected_block.is_solid_mut(), "Solid");
+                            ui.checkbox(self.selected_block.is_double_sided_mut(), "Double sided");
+                            ui.checkbox(self.selected_block.is_fluid_mut(), "Fluid");
+
+                            if self.selected_block.is_transparent() {
+                                let internal_faces = self.selected_block.internal_faces_mut();
+                                ComboBox::from_label("Internal faces")
+                                    .selected_text(format!("{internal_faces:?}"))
+                                    .show_ui(ui, |ui| {
+                                        for kind in InternalFaces::iter() {
+                                            ui.selectable_value(internal_faces, kind, format!("{kind:?}"));
+                                        }
+                                    });
+                            }
+
+                            let shape = self.selected_block.shape_mut();
+                            ComboBox::from_label("Shape")
+                                .selected_text(format!("{shape:?}"))
+                                .show_ui(ui, |ui| {
+                                    for kind in BlockShape::ALL {
+                                        ui.selectable_value(shape, kind, format!("{kind:?}"));
+                                    }
+                                });
+                        });
+                    }
                 }
-            }
-        });
+            });
     }
 
     fn show_settings(&mut self, ctx: &Context) {
@@ -318,18 +766,48 @@ impl<'a> UI<'a> {
             .default_width(0.01)
             .default_height(0.01)
             .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Seed: {}", self.seed));
+                    if ui.button("Copy").clicked() {
+                        ui.output_mut(|output| output.copied_text = self.seed.to_string());
+                    }
+                });
+
                 ui.group(|ui| {
                     ui.with_layout(Layout::top_down(Align::Center), |ui| {
                         ui.label("Render distance");
                     });
 
                     ui.add(
-                        egui::Slider::new(&mut self.settings.render_distance_horizontal, 2..=32)
-                            .text("Horizontal radius"),
+                        egui::Slider::new(
+                            &mut self.settings.render_distance_horizontal,
+                            RENDER_DISTANCE_HORIZONTAL_RANGE,
+                        )
+                        .text("Horizontal radius"),
                     );
+                    ui.add(egui::Slider::new(&mut self.settings.render_distance_up, 1..=32).text("Up radius"));
+                    ui.add(egui::Slider::new(&mut self.settings.render_distance_down, 1..=32).text("Down radius"));
                     ui.add(
-                        egui::Slider::new(&mut self.settings.render_distance_vertical, 2..=32).text("Vertical radius"),
+                        egui::Slider::new(&mut self.settings.kept_surrounding_chunks, 0..=16)
+                            .text("Kept chunks past radius"),
                     );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.chunk_request_delay, 0.0..=1.0)
+                            .text("Chunk request delay (s)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.remesh_coalescing_window, 0.0..=0.5)
+                            .text("Remesh coalescing window (s)"),
+                    )
+                    .on_hover_text(
+                        "Edited chunks wait this long before being remeshed, so quick edits share one remesh",
+                    );
+                    ui.checkbox(
+                        &mut self.settings.prioritize_look_direction,
+                        "Load chunks in view first",
+                    );
+                    ui.checkbox(&mut self.settings.occlusion_culling, "Occlusion culling")
+                        .on_hover_text("Chunks enclosed by opaque faces of all their neighbours aren't drawn");
                 });
 
                 ui.group(|ui| {
@@ -338,10 +816,34 @@ impl<'a> UI<'a> {
                     });
 
                     ui.add(egui::Slider::new(&mut self.settings.camera_speed, 1.0..=100.0).text("Movement speed"));
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.sprint_multiplier, 1.0..=5.0).text("Sprint multiplier"),
+                    );
                     ui.add(
                         egui::Slider::new(&mut self.settings.camera_sensitivity, 0.01..=5.0).text("Mouse sensitivity"),
                     );
+                    ui.add(egui::Slider::new(&mut self.settings.look_deadzone, 0.0..=10.0).text("Look deadzone"))
+                        .on_hover_text("Mouse movements smaller than this are ignored");
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.look_acceleration, 0.0..=2.0).text("Look acceleration"),
+                    )
+                    .on_hover_text("Fast mouse movements turn further, 0 turns it off");
                     ui.add(egui::Slider::new(&mut self.settings.vertical_fov, 1.0..=179.0).text("Vertical FOV"));
+                    ui.checkbox(&mut self.settings.aim_assist, "Aim assist");
+                    ui.add_enabled(
+                        self.settings.aim_assist,
+                        egui::Slider::new(&mut self.settings.aim_assist_cone, 1.0..=15.0).text("Aim assist cone"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.max_ray_steps, 16..=16384)
+                            .logarithmic(true)
+                            .text("Max ray steps"),
+                    )
+                    .on_hover_text("Block boundaries the ray aiming at blocks crosses before it gives up");
+                    ui.add(egui::Slider::new(&mut self.settings.reach, 1.0..=100.0).text("Reach"))
+                        .on_hover_text("Farthest away blocks can be broken and placed");
+                    ui.checkbox(&mut self.settings.replace_fluids_on_place, "Replace fluids on place")
+                        .on_hover_text("Placing a block into water replaces it, otherwise only air can be placed into");
                 });
 
                 ui.group(|ui| {
@@ -350,6 +852,43 @@ impl<'a> UI<'a> {
                     });
 
                     ui.checkbox(&mut self.settings.collision, "Collision detection");
+                    ui.add_enabled(
+                        self.settings.collision,
+                        egui::Checkbox::new(&mut self.settings.auto_jump, "Auto-jump"),
+                    )
+                    .on_hover_text("Step onto blocks one high when walking into them with gravity on");
+                    ui.horizontal(|ui| {
+                        ui.label("Player size:");
+                        for half_extent in self.settings.player_half_extent.iter_mut() {
+                            ui.add(egui::DragValue::new(half_extent).speed(0.01).clamp_range(0.05..=0.95));
+                        }
+                    })
+                    .response
+                    .on_hover_text("Half the width, height and depth of the box that collides with blocks");
+                });
+
+                ui.group(|ui| {
+                    ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                        ui.label("Controls");
+                    });
+
+                    egui::Grid::new("Keybinds").show(ui, |ui| {
+                        for action in Action::iter() {
+                            ui.label(format!("{action:?}"));
+                            let text = if self.menu.rebinding == Some(action) {
+                                "Press a key...".to_string()
+                            } else {
+                                format!("{:?}", self.settings.keybinds.key(action))
+                            };
+                            if ui.button(text).on_hover_text("Click, then press the new key").clicked() {
+                                self.menu.rebinding = Some(action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Reset controls").clicked() {
+                        self.settings.keybinds = Keybinds::default();
+                    }
                 });
 
                 ui.group(|ui| {
@@ -359,8 +898,40 @@ impl<'a> UI<'a> {
 
                     ui.checkbox(&mut self.settings.show_crosshair, "Show Crosshair");
                     ui.checkbox(&mut self.settings.show_performance, "Show Performance info");
+                    ui.checkbox(&mut self.settings.show_held_block, "Show Held block");
+                    if self.settings.show_held_block {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.held_block_scale, 0.5..=3.0).text("Held block scale"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.held_block_offset[0], -1000.0..=0.0)
+                                .text("Held block horizontal offset"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.held_block_offset[1], -1000.0..=0.0)
+                                .text("Held block vertical offset"),
+                        );
+                    }
                     ui.checkbox(&mut self.settings.show_camera, "Show Camera info");
+                    ui.checkbox(&mut self.settings.show_minimap, "Show Minimap");
                     ui.checkbox(&mut self.settings.show_working, "Show Progress when loading / saving");
+                    ui.horizontal(|ui| {
+                        ui.label("Selection color");
+                        ui.color_edit_button_rgba_unmultiplied(&mut self.settings.selection_color);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Outline color");
+                        ui.color_edit_button_rgba_unmultiplied(&mut self.settings.outline_color);
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.outline_thickness, 0.0..=0.2)
+                            .text("Outline thickness")
+                            .step_by(0.005),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.target_outline, "Targeted block outline");
+                        ui.color_edit_button_rgba_unmultiplied(&mut self.settings.target_outline_color);
+                    });
                 });
 
                 ui.group(|ui| {
@@ -372,15 +943,188 @@ impl<'a> UI<'a> {
                         ui.label("Sky color");
                         egui::widgets::color_picker::color_edit_button_rgb(ui, &mut self.settings.sky_color);
                     });
+                    if self.settings.day_night_cycle {
+                        ui.horizontal(|ui| {
+                            ui.label("Night sky color");
+                            egui::widgets::color_picker::color_edit_button_rgb(ui, &mut self.settings.night_sky_color);
+                        });
+                    }
+                    ui.checkbox(&mut self.settings.sky_gradient, "Sky gradient")
+                        .on_hover_text("Fade the sky color at the horizon into a zenith color above");
+                    if self.settings.sky_gradient {
+                        ui.horizontal(|ui| {
+                            ui.label("Sky zenith color");
+                            egui::widgets::color_picker::color_edit_button_rgb(ui, &mut self.settings.sky_zenith_color);
+                        });
+                        if self.settings.day_night_cycle {
+                            ui.horizontal(|ui| {
+                                ui.label("Night sky zenith color");
+                                egui::widgets::color_picker::color_edit_button_rgb(
+                                    ui,
+                                    &mut self.settings.night_sky_zenith_color,
+                                );
+                            });
+                        }
+                    }
+                    ui.checkbox(&mut self.settings.ground_plane, "Ground plane")
+                        .on_hover_text("Fill the void below chunks that haven't loaded yet with a flat plane");
+                    if self.settings.ground_plane {
+                        ui.horizontal(|ui| {
+                            ui.label("Ground plane color");
+                            ui.color_edit_button_rgba_unmultiplied(&mut self.settings.ground_plane_color);
+                        });
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.ground_plane_height, -256.0..=256.0)
+                                .text("Ground plane height"),
+                        );
+                    }
+                    ui.checkbox(&mut self.settings.clouds, "Clouds")
+                        .on_hover_text("Draw a layer of drifting clouds in the sky");
+                    if self.settings.clouds {
+                        ui.horizontal(|ui| {
+                            ui.label("Cloud color");
+                            ui.color_edit_button_rgba_unmultiplied(&mut self.settings.cloud_color);
+                        });
+                        ui.add(egui::Slider::new(&mut self.settings.cloud_height, -256.0..=512.0).text("Cloud height"));
+                        ui.add(egui::Slider::new(&mut self.settings.cloud_speed, 0.0..=32.0).text("Cloud speed"));
+                        ui.add(egui::Slider::new(&mut self.settings.cloud_coverage, 0.0..=1.0).text("Cloud coverage"));
+                    }
+                    ui.add(egui::Slider::new(&mut self.settings.fog_start, 0.0..=1024.0).text("Fog start"));
+                    ui.add(egui::Slider::new(&mut self.settings.fog_end, 0.0..=1024.0).text("Fog end"))
+                        .on_hover_text("Fog is off when it doesn't end after it starts");
                     ui.add(egui::Slider::new(&mut self.settings.sunlight_intensity, 0..=15).text("Sunlight intensity"));
+                    ui.horizontal(|ui| {
+                        ui.label("Sun color");
+                        egui::widgets::color_picker::color_edit_button_rgb(ui, &mut self.settings.sun_color);
+                    });
+                    if self.settings.day_night_cycle {
+                        ui.horizontal(|ui| {
+                            ui.label("Sunrise sun color");
+                            egui::widgets::color_picker::color_edit_button_rgb(
+                                ui,
+                                &mut self.settings.sunrise_sun_color,
+                            );
+                        });
+                    }
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.sunlight_spread, 1..=MAX_SUNLIGHT_SPREAD)
+                            .text("Sunlight spread"),
+                    )
+                    .on_hover_text("How many blocks sunlight reaches under overhangs, relights all chunks");
+                    ui.checkbox(&mut self.settings.smooth_lighting, "Smooth lighting")
+                        .on_hover_text("Blend light between blocks and darken corners, remeshes all chunks");
+                    ui.checkbox(&mut self.settings.transparent_backfaces, "Transparent backfaces")
+                        .on_hover_text("Show the inside faces of transparent blocks, costs some performance");
+                    ui.checkbox(&mut self.settings.mipmaps, "Mipmaps")
+                        .on_hover_text("Use smaller versions of textures in the distance, so they don't shimmer");
+                    ComboBox::from_label("Present mode")
+                        .selected_text(format!("{:?}", self.settings.present_mode))
+                        .show_ui(ui, |ui| {
+                            for present_mode in PresentMode::iter() {
+                                ui.selectable_value(
+                                    &mut self.settings.present_mode,
+                                    present_mode,
+                                    format!("{present_mode:?}"),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("AutoVsync waits for the display, Immediate doesn't and may tear");
+                    ui.horizontal(|ui| {
+                        let mut limit_fps = self.settings.max_fps.is_some();
+                        let mut max_fps = self.settings.max_fps.unwrap_or(60);
+
+                        ui.checkbox(&mut limit_fps, "Limit FPS");
+                        ui.add_enabled(limit_fps, egui::Slider::new(&mut max_fps, 10..=240));
+
+                        self.settings.max_fps = limit_fps.then_some(max_fps);
+                    });
+                    ui.checkbox(&mut self.settings.wireframe, "Wireframe")
+                        .on_hover_text("Draw only the edges of chunk meshes, if the graphics backend supports it");
                     ui.add(egui::Slider::new(&mut self.settings.base_light_value, 0.0..=0.1).text("Base light value"));
                     ui.add(
                         egui::Slider::new(&mut self.settings.light_power_factor, 1.0..=2.0).text("Light power factor"),
                     );
+                    ui.add(egui::Slider::new(&mut self.settings.min_brightness, 0.0..=0.2).text("Minimum brightness"));
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.max_light_sources, 16..=4096)
+                            .logarithmic(true)
+                            .text("Max light sources per chunk"),
+                    );
+                });
+
+                #[cfg(feature = "save_system")]
+                ui.group(|ui| {
+                    ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                        ui.label("Advanced");
+                    });
+
+                    ui.add(egui::Slider::new(&mut self.settings.save_threads, 0..=16).text("Chunk save threads"))
+                        .on_hover_text("How many chunks are written to disk at once, 0 uses all cores");
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.max_queued_saves, 0..=8192)
+                            .logarithmic(true)
+                            .text("Max queued chunk saves"),
+                    )
+                    .on_hover_text("Chunks past this are held back until earlier saves finish, 0 is unlimited");
+                    ui.checkbox(
+                        &mut self.settings.pause_generation_on_save,
+                        "Pause generation when saving",
+                    )
+                    .on_hover_text("Finish generating chunks before saving, so trees crossing chunks are saved whole");
                 });
             });
     }
 
+    fn show_time(&mut self, ctx: &Context) {
+        Window::new("Time")
+            .collapsible(false)
+            .default_width(0.01)
+            .default_height(0.01)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.settings.day_night_cycle, "Day / night cycle");
+
+                if self.settings.day_night_cycle {
+                    let (hours, minutes) = time_of_day::hours_minutes(*self.time_of_day);
+                    ui.label(format!("Time: {:02}:{:02}", hours, minutes));
+
+                    ui.horizontal(|ui| {
+                        for (name, time) in [
+                            ("Dawn", time_of_day::DAWN),
+                            ("Noon", time_of_day::NOON),
+                            ("Dusk", time_of_day::DUSK),
+                            ("Midnight", time_of_day::MIDNIGHT),
+                        ] {
+                            if ui.button(name).clicked() {
+                                *self.time_of_day = time;
+                            }
+                        }
+                    });
+
+                    ui.add(egui::Slider::new(self.time_of_day, 0.0..=1.0).text("Time of day"));
+                    ui.checkbox(&mut self.game_rules.time_paused, "Pause time");
+                }
+            });
+    }
+
+    fn show_loading_frozen(&mut self, ctx: &Context) {
+        Area::new("Loading frozen")
+            .order(Order::TOP)
+            .anchor(Align2::RIGHT_BOTTOM, [-4.0, -4.0])
+            .show(ctx, |ui| {
+                ui.label(RichText::new("Chunk loading frozen").color(egui::Color32::YELLOW));
+            });
+    }
+
+    fn show_hud_message(&mut self, ctx: &Context, message: &str) {
+        Area::new("Hud message")
+            .order(Order::TOP)
+            .anchor(Align2::CENTER_TOP, [0.0, 16.0])
+            .show(ctx, |ui| {
+                ui.label(RichText::new(message).heading());
+            });
+    }
+
     fn show_working(&mut self, ctx: &Context) {
         Window::new("Working...")
             .collapsible(false)
@@ -408,21 +1152,42 @@ impl<'a> crate::engine::GUI for UI<'a> {
             self.show_working(ctx);
         }
 
-        if self.settings.show_performance {
-            self.show_performance(ctx);
+        if self.loading_frozen {
+            self.show_loading_frozen(ctx);
+        }
+
+        if let Some(message) = self.hud_message {
+            self.show_hud_message(ctx, message);
+        }
+
+        let performance_bottom = if self.settings.show_performance {
+            self.show_performance(ctx)
+        } else {
+            None
+        };
+        if self.settings.show_minimap {
+            self.show_minimap(ctx, performance_bottom.unwrap_or(0.0));
         }
         if self.settings.show_camera {
             self.show_camera(ctx);
         }
 
+        if self.settings.show_held_block {
+            self.show_held_block(ctx);
+        }
+        self.show_hotbar(ctx);
+
         if self.running.load(Ordering::Relaxed) {
             self.show_crosshair(ctx);
         } else {
             self.show_resume(ctx);
             self.show_settings(ctx);
+            self.show_time(ctx);
 
             #[cfg(feature = "save_system")]
             self.show_saves(ctx);
+            #[cfg(feature = "save_system")]
+            self.show_save_error(ctx);
 
             self.show_edit_block(ctx);
         }
@@ -432,3 +1197,114 @@ impl<'a> crate::engine::GUI for UI<'a> {
         self.elapsed_secs
     }
 }
+
+// Part of the texture atlas with the side texture of the block, None for blocks without textures
+pub fn block_icon(block: &Block, texture_atlas: &TextureAtlas) -> Option<Rect> {
+    block.texture_id().as_ref().map(|textures| {
+        let min = texture_atlas.texture_coordinates(textures.face(FaceDirection::North));
+        let tile_size = texture_atlas.tile_size();
+        Rect::from_min_max(
+            Pos2::new(min.0, min.1),
+            Pos2::new(min.0 + tile_size.0, min.1 + tile_size.1),
+        )
+    })
+}
+
+fn block_label(block: &Block, block_manager: &BlockManager) -> String {
+    let texture_name = match block.texture_id() {
+        Some(textures) => block_manager
+            .get_texture_name(&textures.all()[0])
+            .map(|texture_name| texture_name.to_owned())
+            .unwrap_or_else(|| "Unknown".to_string()),
+        None => "No texture".to_string(),
+    };
+
+    if block.light_source().is_some() {
+        texture_name + " (light)"
+    } else {
+        texture_name
+    }
+}
+
+// Rough time since something happened, in the biggest unit that fits
+#[cfg(feature = "save_system")]
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::GUI;
+
+    #[test]
+    fn menus_show_without_acting() {
+        let block_manager = Rc::new(BlockManager::new());
+        let stone = Block::new_with_default("Stone", &block_manager);
+        let (mut settings, mut game_rules, mut time_of_day) =
+            (Settings::default(), GameRules::default(), time_of_day::NOON);
+        let (mut selected_block, mut palette) = (stone.clone(), vec![stone.clone()]);
+        let mut menu = MenuState {
+            selected_block_template: "Stone".to_string(),
+            ..Default::default()
+        };
+
+        // Paused, so every menu is shown, twice so the minimap and preview textures are reused
+        let ctx = Context::default();
+        for _ in 0..2 {
+            let mut ui = UI::new(
+                FrameInfo {
+                    running: Arc::new(AtomicBool::new(false)),
+                    elapsed_secs: 0.016,
+                    frame_time_spikes: (0.02, 0.03),
+                    player: Player::new(&block_manager),
+                    block_manager: block_manager.clone(),
+                    loading_chunks: 0,
+                    saving_chunks: 0,
+                    light_capped_chunks: 0,
+                    skipped_chunk_requests: 0,
+                    remeshes: 0,
+                    buffer_allocations: 0,
+                    render_stats: RenderStats::default(),
+                    loading_frozen: false,
+                    seed: 7,
+                    hud_message: Some("Message"),
+                    block_icons: BlockIcons {
+                        atlas_texture_id: TextureId::default(),
+                        hotbar: [None; HOTBAR_SLOTS],
+                        template: None,
+                    },
+                    minimap_colors: Some(vec![None; (2 * MINIMAP_RADIUS as usize + 1).pow(2)]),
+                },
+                &mut settings,
+                &mut selected_block,
+                &mut palette,
+                &mut game_rules,
+                &mut time_of_day,
+                &mut menu,
+            );
+            let _ = ctx.run(egui::RawInput::default(), |ctx| ui.show_ui(ctx));
+        }
+
+        assert_eq!(selected_block, stone);
+        assert_eq!(palette, vec![stone]);
+        assert_eq!(menu.selected_block_template, "Stone");
+        assert_eq!(menu.rebinding, None);
+        #[cfg(feature = "save_system")]
+        assert!(
+            !(menu.do_save
+                || menu.do_load
+                || menu.do_new_world
+                || menu.do_delete_save
+                || menu.do_export_palette
+                || menu.do_import_palette)
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        assert!(!menu.do_paste_structure);
+    }
+}
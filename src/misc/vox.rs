@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::misc::loader::{load_binary, load_resource_string};
+
+const VOX_MAGIC: &[u8; 4] = b"VOX ";
+// Bytes of the id, content size and children size in front of every chunk
+const CHUNK_HEADER_SIZE: usize = 12;
+// MagicaVoxel models are at most this many voxels long on every axis
+const MAX_MODEL_SIZE: u32 = 256;
+
+// First model of a MagicaVoxel file, from its SIZE, XYZI and RGBA chunks. Turned to be y up like the world, models
+// are z up
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: Vector3<u32>,
+    // Position and palette index of every voxel, palette index 0 is never used
+    pub voxels: Vec<(Vector3<u32>, u8)>,
+    pub palette: [[u8; 4]; 256],
+}
+
+impl VoxModel {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let read_u32 = |offset: usize| le_u32(bytes, offset);
+
+        if bytes.get(0..4) != Some(VOX_MAGIC) {
+            return Err("not a .vox file".to_string());
+        }
+        if bytes.get(8..12) != Some(b"MAIN") {
+            return Err("missing MAIN chunk".to_string());
+        }
+
+        let (mut size, mut xyzi, mut rgba, mut models) = (None, None, None, 0);
+        let end = (20 + read_u32(12)? as usize + read_u32(16)? as usize).min(bytes.len());
+        let mut offset = 20 + read_u32(12)? as usize;
+        while offset + CHUNK_HEADER_SIZE <= end {
+            let id = &bytes[offset..offset + 4];
+            let (content_size, children_size) = (read_u32(offset + 4)? as usize, read_u32(offset + 8)? as usize);
+            let content = bytes
+                .get(offset + CHUNK_HEADER_SIZE..offset + CHUNK_HEADER_SIZE + content_size)
+                .ok_or_else(|| "file ends early".to_string())?;
+
+            match id {
+                b"SIZE" => {
+                    models += 1;
+                    if size.is_none() {
+                        let content_u32 = |index: usize| le_u32(content, index * 4);
+                        let model_size = Vector3::new(content_u32(0)?, content_u32(1)?, content_u32(2)?);
+                        if !(0..3).all(|axis| (1..=MAX_MODEL_SIZE).contains(&model_size[axis])) {
+                            return Err(format!(
+                                "model size {}x{}x{} is invalid, every side must be 1 to {MAX_MODEL_SIZE} long",
+                                model_size.x, model_size.y, model_size.z
+                            ));
+                        }
+                        size = Some(model_size)
+                    }
+                }
+                b"XYZI" if xyzi.is_none() => xyzi = Some(content),
+                b"RGBA" => rgba = Some(content),
+                _ => {}
+            }
+            offset += CHUNK_HEADER_SIZE + content_size + children_size;
+        }
+
+        let size = size.ok_or_else(|| "missing SIZE chunk".to_string())?;
+        let xyzi = xyzi.ok_or_else(|| "missing XYZI chunk".to_string())?;
+        let rgba =
+            rgba.ok_or_else(|| "missing RGBA chunk, the default MagicaVoxel palette isn't supported".to_string())?;
+        if models > 1 {
+            log::warn!("Only the first of {models} models in the .vox file is used");
+        }
+
+        // Palette index i is color i - 1 of the RGBA chunk
+        let mut palette = [[0; 4]; 256];
+        for (index, color) in rgba.chunks_exact(4).take(255).enumerate() {
+            palette[index + 1] = color.try_into().unwrap();
+        }
+
+        let count = u32::from_le_bytes(xyzi.get(0..4).ok_or("file ends early")?.try_into().unwrap()) as usize;
+        let voxels = xyzi[4..]
+            .chunks_exact(4)
+            .take(count)
+            .filter(|voxel| (voxel[0] as u32) < size.x && (voxel[1] as u32) < size.y && (voxel[2] as u32) < size.z)
+            .map(|voxel| {
+                (
+                    Vector3::new(voxel[0] as u32, voxel[2] as u32, size.y - 1 - voxel[1] as u32),
+                    voxel[3],
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            size: Vector3::new(size.x, size.z, size.y),
+            voxels,
+            palette,
+        })
+    }
+
+    // Every voxel as the block of the nearest color of the palette mapping
+    pub fn to_structure(&self, vox_palette: &VoxPalette) -> Structure {
+        let blocks_by_index: Vec<Option<&str>> = self
+            .palette
+            .iter()
+            .map(|[r, g, b, _]| vox_palette.nearest_block([*r, *g, *b]))
+            .collect();
+
+        let mut blocks = vec![None; (self.size.x * self.size.y * self.size.z) as usize];
+        for (pos, index) in &self.voxels {
+            blocks[Structure::index(self.size, pos)] = blocks_by_index[*index as usize].map(|block| block.to_owned());
+        }
+
+        Structure {
+            size: self.size,
+            blocks,
+        }
+    }
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| "file ends early".to_string())
+}
+
+// Grid of block names, None is left as it is when pasted
+#[derive(Clone, Debug)]
+pub struct Structure {
+    pub size: Vector3<u32>,
+    pub blocks: Vec<Option<String>>,
+}
+
+impl Structure {
+    fn index(size: Vector3<u32>, pos: &Vector3<u32>) -> usize {
+        ((pos.y * size.z + pos.z) * size.x + pos.x) as usize
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (Vector3<u32>, &str)> {
+        let size = self.size;
+        (0..size.y)
+            .flat_map(move |y| (0..size.z).flat_map(move |z| (0..size.x).map(move |x| Vector3::new(x, y, z))))
+            .filter_map(move |pos| {
+                self.blocks[Self::index(size, &pos)]
+                    .as_deref()
+                    .map(|block| (pos, block))
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoxPaletteEntry {
+    pub color: [u8; 3],
+    pub block: String,
+}
+
+// Blocks that colors of .vox files are turned into, loaded from `vox_palette.yaml`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoxPalette {
+    pub entries: Vec<VoxPaletteEntry>,
+}
+
+impl VoxPalette {
+    pub fn load() -> Self {
+        match load_resource_string(PathBuf::new().join("vox_palette.yaml")) {
+            Ok(palette_string) => match serde_yaml::from_str(palette_string.as_str()) {
+                Ok(palette) => palette,
+                Err(e) => {
+                    log::warn!("Failed parsing `vox_palette.yaml`, .vox files can't be imported - {e:?}");
+                    Self::default()
+                }
+            },
+            Err(_) => {
+                log::warn!("Missing `vox_palette.yaml`, .vox files can't be imported");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn nearest_block(&self, color: [u8; 3]) -> Option<&str> {
+        let distance = |other: &[u8; 3]| -> u32 {
+            (0..3)
+                .map(|channel| (color[channel] as i32 - other[channel] as i32).pow(2) as u32)
+                .sum()
+        };
+
+        self.entries
+            .iter()
+            .min_by_key(|entry| distance(&entry.color))
+            .map(|entry| entry.block.as_str())
+    }
+}
+
+pub fn load_vox(path: impl AsRef<Path>) -> Option<VoxModel> {
+    let path = path.as_ref();
+
+    let Ok(bytes) = load_binary(path) else {
+        log::warn!("Failed reading .vox file from {}", path.display());
+        return None;
+    };
+    match VoxModel::parse(&bytes) {
+        Ok(model) => Some(model),
+        Err(e) => {
+            log::warn!("Failed parsing .vox file {} - {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend((content.len() as u32).to_le_bytes());
+        out.extend((children.len() as u32).to_le_bytes());
+        out.extend(content);
+        out.extend(children);
+        out
+    }
+
+    // A file with a single model of the given SIZE content, a voxel at 1, 0, 0 with palette index 1 and a red palette
+    fn vox_file(size: &[u8]) -> Vec<u8> {
+        let mut xyzi = 1u32.to_le_bytes().to_vec();
+        xyzi.extend([1, 0, 0, 1]);
+        let rgba = [255, 0, 0, 255].repeat(256);
+
+        let children = [
+            chunk(b"SIZE", size, &[]),
+            chunk(b"XYZI", &xyzi, &[]),
+            chunk(b"RGBA", &rgba, &[]),
+        ]
+        .concat();
+        let mut out = VOX_MAGIC.to_vec();
+        out.extend(150u32.to_le_bytes());
+        out.extend(chunk(b"MAIN", &[], &children));
+        out
+    }
+
+    fn size(x: u32, y: u32, z: u32) -> Vec<u8> {
+        [x, y, z].iter().flat_map(|val| val.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn models_are_turned_y_up() {
+        let model = VoxModel::parse(&vox_file(&size(2, 3, 4))).unwrap();
+        assert_eq!(model.size, Vector3::new(2, 4, 3));
+        assert_eq!(model.voxels, vec![(Vector3::new(1, 0, 2), 1)]);
+        assert_eq!(model.palette[1], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn invalid_sizes_are_refused() {
+        for (x, y, z) in [(0, 3, 4), (2, 257, 4), (u32::MAX, u32::MAX, u32::MAX)] {
+            let error = VoxModel::parse(&vox_file(&size(x, y, z))).unwrap_err();
+            assert!(error.contains("is invalid"), "{error}");
+        }
+        assert!(VoxModel::parse(&vox_file(&size(256, 256, 256))).is_ok());
+
+        // The size has to be inside the SIZE chunk, not read from the chunk after it
+        assert_eq!(
+            VoxModel::parse(&vox_file(&size(2, 3, 4)[..8])).unwrap_err(),
+            "file ends early"
+        );
+    }
+}